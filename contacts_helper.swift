@@ -7,6 +7,7 @@ struct ContactData: Codable {
     let familyName: String
     let phoneNumbers: [String]
     let emailAddresses: [String]
+    let isMe: Bool
 }
 
 let store = CNContactStore()
@@ -18,8 +19,20 @@ let keys =
         CNContactEmailAddressesKey,
     ] as [CNKeyDescriptor]
 
+// Exit code the Rust side reads as "Contacts access denied"
+let accessDeniedExitCode: Int32 = 2
+
+let status = CNContactStore.authorizationStatus(for: .contacts)
+if status == .denied || status == .restricted {
+    fputs("Error: Contacts access denied\n", stderr)
+    exit(accessDeniedExitCode)
+}
+
 var contacts: [ContactData] = []
 
+// The user's own card, if they've set one ("My Card" in Contacts)
+let meIdentifier = (try? store.unifiedMeContactWithKeys(toFetch: []))?.identifier
+
 let fetchRequest = CNContactFetchRequest(keysToFetch: keys)
 
 do {
@@ -28,7 +41,8 @@ do {
             givenName: contact.givenName,
             familyName: contact.familyName,
             phoneNumbers: contact.phoneNumbers.map { $0.value.stringValue },
-            emailAddresses: contact.emailAddresses.map { $0.value as String }
+            emailAddresses: contact.emailAddresses.map { $0.value as String },
+            isMe: contact.identifier == meIdentifier
         )
         contacts.append(contactData)
     }
@@ -42,5 +56,8 @@ do {
     }
 } catch {
     fputs("Error: \(error.localizedDescription)\n", stderr)
+    if (error as NSError).code == CNError.Code.authorizationDenied.rawValue {
+        exit(accessDeniedExitCode)
+    }
     exit(1)
 }
@@ -0,0 +1,31 @@
+#!/usr/bin/env swift
+import Foundation
+import Vision
+
+// Prints the text Vision recognizes in the image at the path given as the only
+// argument, one line of text per line, top to bottom
+guard CommandLine.arguments.count == 2 else {
+    fputs("Usage: ocr_helper <image>\n", stderr)
+    exit(1)
+}
+
+let url = URL(fileURLWithPath: CommandLine.arguments[1])
+let request = VNRecognizeTextRequest()
+request.recognitionLevel = .accurate
+request.usesLanguageCorrection = true
+if #available(macOS 13.0, *) {
+    request.automaticallyDetectsLanguage = true
+}
+
+do {
+    try VNImageRequestHandler(url: url, options: [:]).perform([request])
+} catch {
+    fputs("Error: \(error.localizedDescription)\n", stderr)
+    exit(1)
+}
+
+for observation in request.results ?? [] {
+    if let candidate = observation.topCandidates(1).first {
+        print(candidate.string)
+    }
+}
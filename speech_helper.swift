@@ -0,0 +1,81 @@
+#!/usr/bin/env swift
+import AVFoundation
+import Foundation
+import Speech
+
+// Prints a transcript of the speech in the video at the path given as the only
+// argument. The audio track is exported to a temporary M4A first, since speech
+// recognition only reads audio files.
+guard CommandLine.arguments.count == 2 else {
+    fputs("Usage: speech_helper <video>\n", stderr)
+    exit(1)
+}
+
+// Distinct from other failures, like the Contacts helper's access denied exit code
+let accessDeniedExitCode: Int32 = 2
+
+let semaphore = DispatchSemaphore(value: 0)
+var authorization = SFSpeechRecognizer.authorizationStatus()
+if authorization == .notDetermined {
+    SFSpeechRecognizer.requestAuthorization { status in
+        authorization = status
+        semaphore.signal()
+    }
+    semaphore.wait()
+}
+guard authorization == .authorized else {
+    fputs("Error: Speech Recognition access denied\n", stderr)
+    exit(accessDeniedExitCode)
+}
+
+let video = AVURLAsset(url: URL(fileURLWithPath: CommandLine.arguments[1]))
+let audioURL = FileManager.default.temporaryDirectory
+    .appendingPathComponent(UUID().uuidString)
+    .appendingPathExtension("m4a")
+defer { try? FileManager.default.removeItem(at: audioURL) }
+
+guard let export = AVAssetExportSession(asset: video, presetName: AVAssetExportPresetAppleM4A) else {
+    fputs("Error: video has no audio track that can be exported\n", stderr)
+    exit(1)
+}
+export.outputURL = audioURL
+export.outputFileType = .m4a
+export.exportAsynchronously { semaphore.signal() }
+semaphore.wait()
+guard export.status == .completed else {
+    fputs("Error: \(export.error?.localizedDescription ?? "audio export failed")\n", stderr)
+    exit(1)
+}
+
+guard let recognizer = SFSpeechRecognizer(), recognizer.isAvailable else {
+    fputs("Error: speech recognition is not available for this language\n", stderr)
+    exit(1)
+}
+let request = SFSpeechURLRecognitionRequest(url: audioURL)
+request.shouldReportPartialResults = false
+if recognizer.supportsOnDeviceRecognition {
+    request.requiresOnDeviceRecognition = true
+}
+
+var transcript: String?
+var failure: Error?
+recognizer.recognitionTask(with: request) { result, error in
+    if let error = error {
+        failure = error
+        semaphore.signal()
+    } else if let result = result, result.isFinal {
+        transcript = result.bestTranscription.formattedString
+        semaphore.signal()
+    }
+}
+// Recognition calls back on the main queue, so keep the run loop going until it's done
+while semaphore.wait(timeout: .now()) == .timedOut {
+    RunLoop.main.run(until: Date(timeIntervalSinceNow: 0.1))
+}
+
+if let transcript = transcript {
+    print(transcript)
+} else {
+    fputs("Error: \(failure?.localizedDescription ?? "no speech recognized")\n", stderr)
+    exit(1)
+}
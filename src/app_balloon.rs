@@ -0,0 +1,244 @@
+use imessage_database::message_types::app::AppMessage;
+use imessage_database::message_types::placemark::PlacemarkMessage;
+use imessage_database::message_types::url::URLMessage;
+use imessage_database::message_types::variants::{
+    BalloonProvider, CustomBalloon, URLOverride, Variant,
+};
+use imessage_database::tables::messages::Message;
+use imessage_database::util::plist::parse_ns_keyed_archiver;
+use rusqlite::Connection;
+
+/// Rich content iMessage shows as a card or status line instead of plain text
+pub enum AppBalloon {
+    /// A place shared from Maps
+    Location {
+        name: Option<String>,
+        address: Option<String>,
+        url: Option<String>,
+        coordinates: Option<(f64, f64)>,
+    },
+    /// A location shared through Find My
+    FindMy {
+        caption: Option<String>,
+        url: Option<String>,
+    },
+    /// "Started sharing location" / "Stopped sharing location"
+    LocationSharing { started: bool },
+    /// An Apple Pay / Apple Cash payment or request
+    Payment {
+        /// e.g. "Sent $20 with Apple Cash"
+        summary: String,
+        /// e.g. "Accepted", "Pending"
+        status: Option<String>,
+    },
+    /// The preview Messages fetched for a link
+    Link {
+        url: String,
+        title: Option<String>,
+        summary: Option<String>,
+        /// Site name, or the host of the URL when the page didn't give one
+        site: Option<String>,
+    },
+    /// A message sent from an iMessage app (Game Pigeon, polls, Fitness, ...)
+    App {
+        app_name: String,
+        title: Option<String>,
+        summary: Option<String>,
+        url: Option<String>,
+    },
+}
+
+impl AppBalloon {
+    /// Decodes the balloon payload of a message. Returns `None` for plain messages and
+    /// for app messages this exporter doesn't know how to render.
+    pub fn from_message(db: &Connection, message: &Message) -> Option<Self> {
+        if message.started_sharing_location() {
+            return Some(AppBalloon::LocationSharing { started: true });
+        }
+        if message.stopped_sharing_location() {
+            return Some(AppBalloon::LocationSharing { started: false });
+        }
+
+        let balloon = match message.variant() {
+            Variant::App(balloon) => balloon,
+            _ => return None,
+        };
+
+        let payload = message.payload_data(db)?;
+        let parsed = parse_ns_keyed_archiver(&payload).ok()?;
+
+        match balloon {
+            CustomBalloon::URL => match URLMessage::get_url_message_override(&parsed).ok()? {
+                URLOverride::SharedPlacemark(placemark) => Some(Self::from_placemark(&placemark)),
+                URLOverride::Normal(url) => Self::from_url_message(&url),
+                _ => None,
+            },
+            CustomBalloon::FindMy => {
+                let app = AppMessage::from_map(&parsed).ok()?;
+                Some(AppBalloon::FindMy {
+                    caption: app
+                        .caption
+                        .or(app.ldtext)
+                        .or(app.subcaption)
+                        .map(str::to_string),
+                    url: app.url.map(str::to_string),
+                })
+            }
+            CustomBalloon::ApplePay => {
+                let app = AppMessage::from_map(&parsed).ok()?;
+                let summary = app
+                    .ldtext
+                    .or(app.caption)
+                    .unwrap_or("Apple Pay")
+                    .trim_end_matches('.')
+                    .to_string();
+                let status = [app.subcaption, app.trailing_subcaption]
+                    .into_iter()
+                    .flatten()
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>();
+                Some(AppBalloon::Payment {
+                    summary,
+                    status: (!status.is_empty()).then(|| status.join(" · ")),
+                })
+            }
+            CustomBalloon::Application(bundle_id) => {
+                let app = AppMessage::from_map(&parsed).ok()?;
+                Some(Self::from_app_message(
+                    &app,
+                    app_name_from_bundle_id(bundle_id),
+                ))
+            }
+            CustomBalloon::Fitness => Some(Self::from_app_message(
+                &AppMessage::from_map(&parsed).ok()?,
+                "Fitness",
+            )),
+            CustomBalloon::Slideshow => Some(Self::from_app_message(
+                &AppMessage::from_map(&parsed).ok()?,
+                "Photos",
+            )),
+            CustomBalloon::CheckIn => Some(Self::from_app_message(
+                &AppMessage::from_map(&parsed).ok()?,
+                "Check In",
+            )),
+            _ => None,
+        }
+    }
+
+    /// An app card. A preview image the app links to is left out rather than loaded from
+    /// its server whenever the export is opened; one it attached shows with the message's
+    /// attachments.
+    fn from_app_message(app: &AppMessage, fallback_name: &str) -> Self {
+        let summary = [app.caption, app.subcaption, app.ldtext]
+            .into_iter()
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+
+        AppBalloon::App {
+            app_name: app.app_name.unwrap_or(fallback_name).to_string(),
+            title: app.title.or(app.subtitle).map(str::to_string),
+            summary: (!summary.is_empty()).then(|| summary.join(" · ")),
+            url: app.url.map(str::to_string),
+        }
+    }
+
+    /// A link preview; `None` when the payload has no URL or nothing to preview it with.
+    /// The preview image Messages stored shows with the message's attachments; the image
+    /// URLs in the payload are never loaded.
+    fn from_url_message(url_message: &URLMessage) -> Option<Self> {
+        let url = url_message.url.or(url_message.original_url)?.to_string();
+        let title = url_message.title.filter(|title| !title.is_empty());
+        let summary = url_message.summary.filter(|summary| !summary.is_empty());
+        if title.is_none() && summary.is_none() {
+            return None;
+        }
+
+        Some(AppBalloon::Link {
+            site: url_message
+                .site_name
+                .map(str::to_string)
+                .or_else(|| host_from_url(&url)),
+            title: title.map(str::to_string),
+            summary: summary.map(str::to_string),
+            url,
+        })
+    }
+
+    fn from_placemark(placemark: &PlacemarkMessage) -> Self {
+        let url = placemark.get_url().map(str::to_string);
+        AppBalloon::Location {
+            name: placemark
+                .placemark
+                .name
+                .or(placemark.place_name)
+                .map(str::to_string),
+            address: placemark.placemark.address.map(str::to_string),
+            coordinates: url.as_deref().and_then(coordinates_from_maps_url),
+            url,
+        }
+    }
+}
+
+/// URL schemes a card may link to; anything else from a message payload, like a
+/// `javascript:` or `file:` URL, is shown as text instead
+const LINK_SCHEMES: &[&str] = &["http", "https", "maps"];
+
+/// Whether `url` uses one of the [`LINK_SCHEMES`] and can be put in an `href`
+pub fn is_safe_link(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        LINK_SCHEMES
+            .iter()
+            .any(|allowed| scheme.trim().eq_ignore_ascii_case(allowed))
+    })
+}
+
+/// Pulls latitude/longitude out of the `ll=` parameter of an Apple Maps URL
+fn coordinates_from_maps_url(url: &str) -> Option<(f64, f64)> {
+    let query = url.split_once('?')?.1;
+    let ll = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("ll="))?;
+    let (latitude, longitude) = ll.split_once(',')?;
+    Some((latitude.parse().ok()?, longitude.parse().ok()?))
+}
+
+/// The host of a URL without a leading `www.`, e.g. `example.com` for
+/// `https://www.example.com/page`
+fn host_from_url(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// Best-effort readable name for an app bundle id like `com.gamerdelights.gamepigeon.ext`
+fn app_name_from_bundle_id(bundle_id: &str) -> &str {
+    bundle_id
+        .split('.')
+        .rev()
+        .find(|part| {
+            !matches!(
+                *part,
+                "ext" | "extension" | "MessagesExtension" | "imessage"
+            )
+        })
+        .unwrap_or(bundle_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_links() {
+        assert!(is_safe_link("https://maps.apple.com/?ll=37.33,-122.03"));
+        assert!(is_safe_link("HTTP://example.com"));
+        assert!(is_safe_link("maps://?q=Cupertino"));
+        assert!(!is_safe_link("javascript:alert(1)"));
+        assert!(!is_safe_link("file:///etc/passwd"));
+        assert!(!is_safe_link("data:text/html,<script>"));
+        assert!(!is_safe_link("example.com"));
+    }
+}
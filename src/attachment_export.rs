@@ -0,0 +1,81 @@
+use crate::attachment_store::read_for_export;
+use crate::attachment_timestamp::AttachmentTimestamp;
+use crate::clean_message::CleanMessage;
+use crate::error_log::ErrorLog;
+use crate::events::ExportEvent;
+use crate::filename::FilenameAllocator;
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Extracts every attachment into `<chat>/<year>/<month>/<filename>`, with no HTML
+pub struct AttachmentExport {
+    messages: Vec<CleanMessage>,
+    timestamp: AttachmentTimestamp,
+    strip_metadata: bool,
+}
+
+impl AttachmentExport {
+    pub fn new(
+        messages: Vec<CleanMessage>,
+        timestamp: AttachmentTimestamp,
+        strip_metadata: bool,
+    ) -> Self {
+        Self {
+            messages,
+            timestamp,
+            strip_metadata,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget, errors: &mut ErrorLog) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+
+        // Sorted so that renamed duplicates get the same names when an export is resumed
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+
+        let mut chat_dirs = FilenameAllocator::default();
+        // File names taken so far in each folder
+        let mut folders: HashMap<String, FilenameAllocator> = HashMap::new();
+
+        for chat_key in chat_keys {
+            let chat_dir = chat_dirs.stem(display_name(chat_key));
+            let chat_messages = &grouped_messages[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+
+            for message in chat_messages {
+                for attachment in &message.attachments {
+                    let Some(filename) = attachment.filename.as_deref() else {
+                        continue;
+                    };
+                    let dir = format!("{}/{}", chat_dir, message.date.format("%Y/%m"));
+                    let unique = folders.entry(dir.clone()).or_default().file(filename);
+                    let output_path = format!("{}/{}", dir, unique);
+
+                    if output.resume(&output_path) {
+                        continue;
+                    }
+
+                    let Some(bytes) = read_for_export(attachment, self.strip_metadata, errors)?
+                    else {
+                        continue;
+                    };
+
+                    output.write_modified(
+                        &output_path,
+                        &bytes,
+                        self.timestamp.resolve(message, attachment),
+                    )?;
+                }
+            }
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        Ok(())
+    }
+}
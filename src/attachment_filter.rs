@@ -0,0 +1,105 @@
+use crate::clean_attachment::{CleanAttachment, MediaKind};
+use crate::clean_message::CleanMessage;
+
+/// A size in bytes, written on the command line as e.g. `500K`, `25M` or `2G`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid size '{}' (expected e.g. 500K, 25M or 2G)", s))?;
+        let multiplier = match unit.trim().to_uppercase().trim_end_matches('B') {
+            "" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => return Err(format!("unknown size unit '{}' (expected K, M or G)", unit)),
+        };
+        Ok(ByteSize(number * multiplier))
+    }
+}
+
+impl std::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+        let mut size = self.0 as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{} {}", self.0, UNITS[0])
+        } else {
+            write!(f, "{:.1} {}", size, UNITS[unit])
+        }
+    }
+}
+
+/// An attachment left out of the export, shown as a placeholder instead
+#[derive(Clone, Debug)]
+pub struct SkippedAttachment {
+    pub filename: String,
+    pub size: ByteSize,
+}
+
+impl SkippedAttachment {
+    /// Placeholder text, e.g. "IMG_1234.MOV (52.3 MB) not exported"
+    pub fn description(&self) -> String {
+        format!("{} ({}) not exported", self.filename, self.size)
+    }
+}
+
+/// Which attachments to leave out of the export
+pub struct AttachmentFilter {
+    /// Skip attachments larger than this
+    pub max_size: Option<ByteSize>,
+    pub skip_videos: bool,
+}
+
+impl AttachmentFilter {
+    fn is_active(&self) -> bool {
+        self.max_size.is_some() || self.skip_videos
+    }
+
+    /// Moves every attachment the filter excludes into the message's skipped attachments
+    pub fn apply(&self, messages: &mut [CleanMessage]) {
+        if !self.is_active() {
+            return;
+        }
+
+        for message in messages {
+            let (kept, skipped): (Vec<_>, Vec<_>) = message
+                .attachments
+                .drain(..)
+                .partition(|attachment| !self.excludes(attachment));
+            message.attachments = kept;
+            message
+                .skipped_attachments
+                .extend(skipped.iter().map(|attachment| {
+                    SkippedAttachment {
+                        filename: attachment
+                            .filename
+                            .clone()
+                            .unwrap_or_else(|| "attachment".to_string()),
+                        size: ByteSize(attachment.size),
+                    }
+                }));
+        }
+    }
+
+    fn excludes(&self, attachment: &CleanAttachment) -> bool {
+        if self.skip_videos && attachment.kind == MediaKind::Video {
+            return true;
+        }
+        self.max_size
+            .is_some_and(|max_size| ByteSize(attachment.size) > max_size)
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{Result, anyhow};
+
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// JPEG segments that carry EXIF (including GPS and serial numbers), XMP, IPTC and
+/// comments: APP1, APP13 and COM
+const JPEG_METADATA_MARKERS: [u8; 3] = [0xE1, 0xED, 0xFE];
+
+/// PNG chunks that carry EXIF, text and the modification time
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+/// QuickTime/MP4 boxes holding user data and metadata (location, device make, model and
+/// software), found in `moov` and its tracks
+const MOVIE_METADATA_BOXES: [&[u8; 4]; 2] = [b"udta", b"meta"];
+
+/// UUID of the box Adobe tools put XMP in within MP4 files
+const XMP_UUID: [u8; 16] = [
+    0xBE, 0x7A, 0xCF, 0xCB, 0x97, 0xA9, 0x42, 0xE8, 0x9C, 0x71, 0x99, 0x94, 0x91, 0xE3, 0xAF, 0xAC,
+];
+
+/// Removes EXIF, GPS, XMP and device metadata from a JPEG, PNG, HEIC/HEIF or
+/// QuickTime/MP4 file without re-encoding it. Other files are returned unchanged.
+///
+/// JPEG and PNG metadata segments are dropped. ISO media files keep their layout, since
+/// their boxes point at each other by byte offset: movie metadata boxes are turned into
+/// `free` boxes and HEIF EXIF/XMP items are zeroed in place.
+pub fn strip_metadata(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if bytes.starts_with(&JPEG_SOI) {
+        strip_jpeg(&bytes)
+    } else if bytes.starts_with(&PNG_SIGNATURE) {
+        strip_png(&bytes)
+    } else if bytes.get(4..8) == Some(b"ftyp") {
+        strip_iso_media(bytes)
+    } else {
+        Ok(bytes)
+    }
+}
+
+fn strip_jpeg(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut stripped = JPEG_SOI.to_vec();
+    let mut position = JPEG_SOI.len();
+
+    loop {
+        let Some(&[0xFF, marker]) = bytes.get(position..position + 2) else {
+            return Err(anyhow!("malformed JPEG segment at byte {}", position));
+        };
+
+        // Padding before a marker
+        if marker == 0xFF {
+            position += 1;
+            continue;
+        }
+
+        // Markers without a length: TEM, RSTn and EOI
+        if marker == 0x01 || (0xD0..=0xD9).contains(&marker) {
+            stripped.extend_from_slice(&bytes[position..position + 2]);
+            position += 2;
+            if marker == 0xD9 {
+                break;
+            }
+            continue;
+        }
+
+        let length = read_u16(bytes, position + 2)? as usize;
+        let end = position + 2 + length;
+        if length < 2 || end > bytes.len() {
+            return Err(anyhow!("malformed JPEG segment at byte {}", position));
+        }
+
+        // Everything after the start of scan is image data
+        if marker == 0xDA {
+            stripped.extend_from_slice(&bytes[position..]);
+            return Ok(stripped);
+        }
+
+        if !JPEG_METADATA_MARKERS.contains(&marker) {
+            stripped.extend_from_slice(&bytes[position..end]);
+        }
+        position = end;
+    }
+
+    Ok(stripped)
+}
+
+fn strip_png(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut stripped = PNG_SIGNATURE.to_vec();
+    let mut position = PNG_SIGNATURE.len();
+
+    while position < bytes.len() {
+        let length = read_u32(bytes, position)? as usize;
+        // Length, type, data and CRC
+        let end = position + 12 + length;
+        let chunk_type = bytes
+            .get(position + 4..position + 8)
+            .filter(|_| end <= bytes.len())
+            .ok_or_else(|| anyhow!("malformed PNG chunk at byte {}", position))?;
+
+        if !PNG_METADATA_CHUNKS.iter().any(|&c| c == chunk_type) {
+            stripped.extend_from_slice(&bytes[position..end]);
+        }
+        position = end;
+    }
+
+    Ok(stripped)
+}
+
+/// A box in an ISO base media file (MP4, QuickTime, HEIF)
+pub struct IsoBox {
+    pub kind: [u8; 4],
+    pub start: usize,
+    /// Start of the content, after the header
+    pub content: usize,
+    pub end: usize,
+}
+
+/// Lists the boxes in `bytes[start..end]`
+pub fn iso_boxes(bytes: &[u8], start: usize, end: usize) -> Result<Vec<IsoBox>> {
+    let malformed = |position| anyhow!("malformed box at byte {}", position);
+    let mut boxes = Vec::new();
+    let mut position = start;
+
+    while position + 8 <= end {
+        let size = read_u32(bytes, position)? as usize;
+        let kind: [u8; 4] = bytes[position + 4..position + 8].try_into()?;
+        let (mut content, box_end) = match size {
+            // Extends to the end of the file
+            0 => (position + 8, end),
+            1 => {
+                let size = usize::try_from(read_u64(bytes, position + 8)?)
+                    .map_err(|_| malformed(position))?;
+                (
+                    position + 16,
+                    position.checked_add(size).ok_or(malformed(position))?,
+                )
+            }
+            _ => (position + 8, position + size),
+        };
+        if &kind == b"uuid" {
+            content += 16;
+        }
+        if box_end > end || content > box_end {
+            return Err(malformed(position));
+        }
+
+        boxes.push(IsoBox {
+            kind,
+            start: position,
+            content,
+            end: box_end,
+        });
+        position = box_end;
+    }
+
+    Ok(boxes)
+}
+
+fn strip_iso_media(mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+    for top in iso_boxes(&bytes, 0, bytes.len())? {
+        match &top.kind {
+            b"moov" => strip_movie_metadata(&mut bytes, &top)?,
+            b"meta" => strip_heif_metadata(&mut bytes, &top)?,
+            b"uuid" if bytes[top.start + 8..top.content] == XMP_UUID => {
+                rename_to_free(&mut bytes, &top)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn rename_to_free(bytes: &mut [u8], iso_box: &IsoBox) {
+    bytes[iso_box.start + 4..iso_box.start + 8].copy_from_slice(b"free");
+}
+
+/// Frees the metadata boxes of a movie and each of its tracks
+fn strip_movie_metadata(bytes: &mut [u8], parent: &IsoBox) -> Result<()> {
+    for child in iso_boxes(bytes, parent.content, parent.end)? {
+        if MOVIE_METADATA_BOXES.contains(&&child.kind)
+            || (&child.kind == b"uuid" && bytes[child.start + 8..child.content] == XMP_UUID)
+        {
+            rename_to_free(bytes, &child);
+        } else if &child.kind == b"trak" {
+            strip_movie_metadata(bytes, &child)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Zeroes the data of a HEIF image's EXIF and XMP items. The `meta` box itself can't be
+/// freed, since it also describes the image.
+fn strip_heif_metadata(bytes: &mut [u8], meta: &IsoBox) -> Result<()> {
+    // `meta` is a full box: version and flags come before its children
+    let children = iso_boxes(bytes, meta.content + 4, meta.end)?;
+    let find = |kind: &[u8; 4]| children.iter().find(|child| &child.kind == kind);
+
+    let Some(iinf) = find(b"iinf") else {
+        return Ok(());
+    };
+    let metadata_items = heif_metadata_items(bytes, iinf)?;
+    if metadata_items.is_empty() {
+        return Ok(());
+    }
+
+    let Some(iloc) = find(b"iloc") else {
+        return Ok(());
+    };
+    // Construction method 1 locates data within the `idat` box
+    let idat_start = find(b"idat").map(|idat| idat.content);
+    for (construction_method, offset, length) in heif_item_extents(bytes, iloc, &metadata_items)? {
+        let start = match construction_method {
+            0 => offset,
+            1 => match idat_start {
+                Some(idat_start) => idat_start + offset,
+                None => continue,
+            },
+            // Items built from other items point at data stripped separately
+            _ => continue,
+        };
+        let range = bytes
+            .get_mut(start..start + length)
+            .ok_or_else(|| anyhow!("HEIF item extent at byte {} is out of range", start))?;
+        range.fill(0);
+    }
+
+    Ok(())
+}
+
+/// IDs of the `Exif` and XMP items listed in an `iinf` box
+fn heif_metadata_items(bytes: &[u8], iinf: &IsoBox) -> Result<Vec<u32>> {
+    let version = bytes[iinf.content];
+    let entries_start = iinf.content + if version == 0 { 6 } else { 8 };
+
+    let mut items = Vec::new();
+    for infe in iso_boxes(bytes, entries_start, iinf.end)? {
+        // Versions 0 and 1 predate item types, and with them EXIF items
+        let version = bytes[infe.content];
+        let (item_id, type_position) = match version {
+            2 => (read_u16(bytes, infe.content + 4)? as u32, infe.content + 8),
+            3 => (read_u32(bytes, infe.content + 4)?, infe.content + 10),
+            _ => continue,
+        };
+        let Some(item_type) = bytes.get(type_position..type_position + 4) else {
+            continue;
+        };
+
+        let is_metadata = match item_type {
+            b"Exif" => true,
+            b"mime" => {
+                // Item name, then content type, both null-terminated
+                let mut strings = bytes[type_position + 4..infe.end].split(|&b| b == 0);
+                let content_type = strings.nth(1).unwrap_or_default();
+                content_type == b"application/rdf+xml"
+            }
+            _ => false,
+        };
+        if is_metadata {
+            items.push(item_id);
+        }
+    }
+
+    Ok(items)
+}
+
+/// `(construction method, offset, length)` of each extent of `items` in an `iloc` box
+fn heif_item_extents(
+    bytes: &[u8],
+    iloc: &IsoBox,
+    items: &[u32],
+) -> Result<Vec<(u8, usize, usize)>> {
+    let version = bytes[iloc.content];
+    let mut position = iloc.content + 4;
+
+    let sizes = read_u16(bytes, position)?;
+    let offset_size = (sizes >> 12) as usize;
+    let length_size = ((sizes >> 8) & 0xF) as usize;
+    let base_offset_size = ((sizes >> 4) & 0xF) as usize;
+    let index_size = if version > 0 {
+        (sizes & 0xF) as usize
+    } else {
+        0
+    };
+    position += 2;
+
+    let (item_count, id_size) = if version < 2 {
+        (read_u16(bytes, position)? as usize, 2)
+    } else {
+        (read_u32(bytes, position)? as usize, 4)
+    };
+    position += id_size;
+
+    let mut extents = Vec::new();
+    for _ in 0..item_count {
+        let item_id = read_sized(bytes, position, id_size)? as u32;
+        position += id_size;
+        let construction_method = if version > 0 {
+            position += 2;
+            (read_u16(bytes, position - 2)? & 0xF) as u8
+        } else {
+            0
+        };
+        // Data reference index
+        position += 2;
+        let base_offset = read_sized(bytes, position, base_offset_size)?;
+        position += base_offset_size;
+        let extent_count = read_u16(bytes, position)?;
+        position += 2;
+
+        for _ in 0..extent_count {
+            position += index_size;
+            let offset = read_sized(bytes, position, offset_size)?;
+            position += offset_size;
+            let length = read_sized(bytes, position, length_size)?;
+            position += length_size;
+
+            if items.contains(&item_id) {
+                extents.push((
+                    construction_method,
+                    usize::try_from(base_offset + offset)?,
+                    usize::try_from(length)?,
+                ));
+            }
+        }
+    }
+
+    Ok(extents)
+}
+
+/// Width and height in pixels of a JPEG or PNG image, read from its header; `bytes` need
+/// only hold the start of the file
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.starts_with(&PNG_SIGNATURE) {
+        // IHDR is always the first chunk
+        if bytes.get(12..16) != Some(b"IHDR") {
+            return None;
+        }
+        return Some((read_u32(bytes, 16).ok()?, read_u32(bytes, 20).ok()?));
+    }
+    if !bytes.starts_with(&JPEG_SOI) {
+        return None;
+    }
+
+    let mut position = JPEG_SOI.len();
+    loop {
+        let Some(&[0xFF, marker]) = bytes.get(position..position + 2) else {
+            return None;
+        };
+        if marker == 0xFF {
+            position += 1;
+            continue;
+        }
+        if marker == 0x01 || (0xD0..=0xD9).contains(&marker) {
+            position += 2;
+            continue;
+        }
+        // Start of frame markers; C4, C8 and CC are tables and extensions
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            let height = read_u16(bytes, position + 5).ok()?;
+            let width = read_u16(bytes, position + 7).ok()?;
+            return Some((width as u32, height as u32));
+        }
+        position += 2 + read_u16(bytes, position + 2).ok()? as usize;
+    }
+}
+
+fn read_u16(bytes: &[u8], position: usize) -> Result<u16> {
+    Ok(read_sized(bytes, position, 2)? as u16)
+}
+
+pub fn read_u32(bytes: &[u8], position: usize) -> Result<u32> {
+    Ok(read_sized(bytes, position, 4)? as u32)
+}
+
+pub fn read_u64(bytes: &[u8], position: usize) -> Result<u64> {
+    read_sized(bytes, position, 8)
+}
+
+/// Reads a big-endian integer of `size` bytes (0 to 8)
+fn read_sized(bytes: &[u8], position: usize, size: usize) -> Result<u64> {
+    if size > 8 {
+        return Err(anyhow!(
+            "unsupported {}-byte field at byte {}",
+            size,
+            position
+        ));
+    }
+    let field = bytes
+        .get(position..position + size)
+        .ok_or_else(|| anyhow!("unexpected end of file at byte {}", position))?;
+    Ok(field
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | byte as u64))
+}
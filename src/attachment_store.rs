@@ -0,0 +1,231 @@
+use crate::attachment_metadata::strip_metadata;
+use crate::attachment_timestamp::AttachmentTimestamp;
+use crate::audio;
+use crate::clean_attachment::{CleanAttachment, MediaKind};
+use crate::clean_message::CleanMessage;
+use crate::error_log::ErrorLog;
+use crate::layout::Layout;
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Longest edge, in pixels, of the thumbnails shown inline for image attachments
+const THUMBNAIL_MAX_DIMENSION: u32 = 480;
+
+/// Copies messages' attachment files into an export under `attachments/`, where the
+/// layout puts them, optionally with thumbnails of images and waveforms of audio. Any
+/// output format can link to what it copied.
+pub struct AttachmentStore {
+    layout: Layout,
+    timestamp: AttachmentTimestamp,
+    strip_metadata: bool,
+    thumbnails: bool,
+    audio_waveforms: bool,
+}
+
+/// An attachment copied into the export. Paths are from the export root.
+#[derive(Clone, Debug)]
+pub struct ExtractedAttachment {
+    /// ROWID of the attachment
+    pub rowid: i32,
+    /// The copy, e.g. `attachments/12/34/IMG_0001.jpg`
+    pub path: String,
+    /// Downscaled JPEG of an image, when it could be decoded
+    pub thumbnail: Option<String>,
+    /// SVG waveform of an audio attachment
+    pub waveform: Option<String>,
+    /// Length of an audio attachment, when it could be read
+    pub duration: Option<Duration>,
+}
+
+/// What [`AttachmentStore::extract`] copied, by message GUID
+#[derive(Default)]
+pub struct ExtractedAttachments {
+    by_message: HashMap<String, Vec<ExtractedAttachment>>,
+}
+
+impl ExtractedAttachments {
+    /// The copied attachments of the message with GUID `guid`, in its attachment order
+    pub fn of_message(&self, guid: &str) -> &[ExtractedAttachment] {
+        self.by_message.get(guid).map_or(&[], Vec::as_slice)
+    }
+
+    /// The copy of attachment `rowid` of the message with GUID `guid`
+    pub fn get(&self, guid: &str, rowid: i32) -> Option<&ExtractedAttachment> {
+        self.of_message(guid)
+            .iter()
+            .find(|attachment| attachment.rowid == rowid)
+    }
+}
+
+impl AttachmentStore {
+    pub fn new(layout: Layout, timestamp: AttachmentTimestamp, strip_metadata: bool) -> Self {
+        Self {
+            layout,
+            timestamp,
+            strip_metadata,
+            thumbnails: false,
+            audio_waveforms: false,
+        }
+    }
+
+    /// Also writes a JPEG thumbnail of each image under `thumbnails/`
+    pub fn with_thumbnails(mut self, thumbnails: bool) -> Self {
+        self.thumbnails = thumbnails;
+        self
+    }
+
+    /// Also draws a waveform of each audio attachment under `waveforms/`
+    pub fn with_audio_waveforms(mut self, audio_waveforms: bool) -> Self {
+        self.audio_waveforms = audio_waveforms;
+        self
+    }
+
+    /// Copies the attachments of `messages` whose files are on this computer. Files an
+    /// interrupted run already wrote are kept; files that can't be stripped of their
+    /// metadata are recorded in `errors` and left out.
+    pub fn extract(
+        &self,
+        messages: &[CleanMessage],
+        output: &mut OutputTarget,
+        errors: &mut ErrorLog,
+    ) -> Result<ExtractedAttachments> {
+        let mut extracted = ExtractedAttachments::default();
+        for message in messages {
+            let attachment_subpath = self.layout.attachment_dir(message);
+            let mut copies = Vec::new();
+            for (attachment, filename) in message
+                .attachments
+                .iter()
+                .zip(self.layout.attachment_files(message))
+            {
+                let Some(filename) = filename else {
+                    continue;
+                };
+                let key = format!("{}/{}", attachment_subpath, filename);
+                let mut copy = ExtractedAttachment {
+                    rowid: attachment.rowid,
+                    path: format!("attachments/{}", key),
+                    thumbnail: None,
+                    waveform: None,
+                    duration: None,
+                };
+                let thumbnail_path = format!("thumbnails/{}.jpg", key);
+                let wants_thumbnail = self.thumbnails && attachment.kind == MediaKind::Image;
+                let is_audio = attachment.kind == MediaKind::Audio;
+
+                // Thumbnails and waveforms are written before their original, so an
+                // attachment an interrupted run finished already has them (if it got any)
+                if output.resume(&copy.path) {
+                    if wants_thumbnail && output.resume(&thumbnail_path) {
+                        copy.thumbnail = Some(thumbnail_path);
+                    }
+                    // The length of audio is read again from the file
+                    if is_audio && let Some(bytes) = attachment.read()? {
+                        self.save_audio_details(output, &bytes, &key, &mut copy)?;
+                    }
+                    copies.push(copy);
+                    continue;
+                }
+
+                let Some(bytes) = read_for_export(attachment, self.strip_metadata, errors)? else {
+                    continue;
+                };
+                if wants_thumbnail && save_thumbnail(output, &bytes, &thumbnail_path)? {
+                    copy.thumbnail = Some(thumbnail_path);
+                }
+                if is_audio {
+                    self.save_audio_details(output, &bytes, &key, &mut copy)?;
+                }
+
+                output.write_modified(
+                    &copy.path,
+                    &bytes,
+                    self.timestamp.resolve(message, attachment),
+                )?;
+                copies.push(copy);
+            }
+            if !copies.is_empty() {
+                extracted.by_message.insert(message.guid.clone(), copies);
+            }
+        }
+
+        Ok(extracted)
+    }
+
+    /// Reads the length of an audio attachment and, with waveforms on, writes its
+    /// waveform. `key` is the attachment's `subpath/filename`.
+    fn save_audio_details(
+        &self,
+        output: &mut OutputTarget,
+        bytes: &[u8],
+        key: &str,
+        copy: &mut ExtractedAttachment,
+    ) -> Result<()> {
+        copy.duration = audio::duration(bytes);
+
+        if self.audio_waveforms {
+            let waveform_path = format!("waveforms/{}.svg", key);
+            if output.resume(&waveform_path) {
+                copy.waveform = Some(waveform_path);
+            } else if let Some(levels) =
+                audio::waveform(bytes, key.rsplit('/').next().unwrap_or(key))
+            {
+                output.write(&waveform_path, audio::waveform_svg(&levels).as_bytes())?;
+                copy.waveform = Some(waveform_path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Contents of `attachment`'s file as every format exports it: without its EXIF, GPS
+/// and device metadata when `strip` is set. `None` when the file isn't on this
+/// computer, or when its metadata can't be stripped (e.g. a truncated JPEG), which is
+/// recorded in `errors` so the rest of the export goes ahead.
+pub fn read_for_export(
+    attachment: &CleanAttachment,
+    strip: bool,
+    errors: &mut ErrorLog,
+) -> Result<Option<Vec<u8>>> {
+    let Some(bytes) = attachment.read()? else {
+        return Ok(None);
+    };
+    if !strip {
+        return Ok(Some(bytes));
+    }
+    match strip_metadata(bytes) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) => {
+            errors.record(
+                format!(
+                    "attachment {}",
+                    attachment.filename.as_deref().unwrap_or_default()
+                ),
+                e.context("Unable to strip metadata"),
+            )?;
+            Ok(None)
+        }
+    }
+}
+
+/// Writes a downscaled JPEG copy of an image attachment. Returns false when the image
+/// format can't be decoded (e.g. HEIC), in which case the original is shown.
+fn save_thumbnail(output: &mut OutputTarget, bytes: &[u8], thumbnail_path: &str) -> Result<bool> {
+    let Ok(image) = image::load_from_memory(bytes) else {
+        return Ok(false);
+    };
+
+    let thumbnail = image::DynamicImage::ImageRgb8(
+        image
+            .thumbnail(THUMBNAIL_MAX_DIMENSION, THUMBNAIL_MAX_DIMENSION)
+            .to_rgb8(),
+    );
+    let mut jpeg = std::io::Cursor::new(Vec::new());
+    thumbnail.write_to(&mut jpeg, image::ImageFormat::Jpeg)?;
+    output.write(thumbnail_path, jpeg.get_ref())?;
+
+    Ok(true)
+}
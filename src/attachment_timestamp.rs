@@ -0,0 +1,84 @@
+use crate::clean_attachment::CleanAttachment;
+use crate::clean_message::CleanMessage;
+use crate::recently_deleted::apple_timestamp_to_local;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use imessage_database::tables::attachment::Attachment;
+use imessage_database::tables::table::ATTACHMENT;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::fs;
+
+/// Which time extracted attachment files are dated with, so they sort correctly in
+/// Finder and Photos
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AttachmentTimestamp {
+    /// The original file's modification time
+    #[default]
+    Original,
+    /// The date of the message it was sent with
+    Message,
+    /// The attachment's creation date recorded in the database
+    Created,
+}
+
+impl AttachmentTimestamp {
+    /// The time to date `attachment`'s extracted file with, falling back to the
+    /// message date when the chosen one isn't known
+    pub fn resolve(&self, message: &CleanMessage, attachment: &CleanAttachment) -> DateTime<Local> {
+        let date = match self {
+            AttachmentTimestamp::Original => attachment
+                .file
+                .as_ref()
+                .and_then(|file| fs::metadata(&file.path).ok())
+                .and_then(|metadata| metadata.modified().ok())
+                .map(DateTime::<Local>::from),
+            AttachmentTimestamp::Message => None,
+            AttachmentTimestamp::Created => {
+                message.attachment_created.get(&attachment.rowid).copied()
+            }
+        };
+        date.unwrap_or(message.date)
+    }
+}
+
+impl std::str::FromStr for AttachmentTimestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "original" => Ok(AttachmentTimestamp::Original),
+            "message" => Ok(AttachmentTimestamp::Message),
+            "created" => Ok(AttachmentTimestamp::Created),
+            _ => Err(format!(
+                "unknown attachment timestamp '{}' (expected original, message or created)",
+                s
+            )),
+        }
+    }
+}
+
+/// Reads when each of `attachments` was created, keyed by attachment ROWID
+pub fn created_dates(
+    db: &Connection,
+    attachments: &[Attachment],
+) -> Result<HashMap<i32, DateTime<Local>>> {
+    let mut statement = db.prepare_cached(&format!(
+        "SELECT created_date FROM {} WHERE ROWID = ?1",
+        ATTACHMENT
+    ))?;
+    let database_tz_offset = imessage_database::util::dates::get_offset();
+
+    let mut dates = HashMap::new();
+    for attachment in attachments {
+        let created_date: Option<i64> =
+            statement.query_row([attachment.rowid], |row| row.get(0))?;
+        if let Some(created_date) = created_date.filter(|&date| date != 0)
+            && let Some(date) = apple_timestamp_to_local(created_date, database_tz_offset)
+        {
+            dates.insert(attachment.rowid, date);
+        }
+    }
+
+    Ok(dates)
+}
@@ -0,0 +1,206 @@
+use crate::attachment_metadata::{iso_boxes, read_u32, read_u64};
+use crate::snapshot::DatabaseSnapshot;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Bars drawn in a waveform
+const WAVEFORM_BARS: usize = 48;
+
+/// Sample rate audio is decoded at for its waveform, plenty for the loudness of each bar
+const WAVEFORM_SAMPLE_RATE: u32 = 4000;
+
+/// Length of a voice message (CAF) or other audio file (M4A, MP4), read from its header
+pub fn duration(bytes: &[u8]) -> Option<Duration> {
+    if bytes.starts_with(b"caff") {
+        caf_duration(bytes)
+    } else {
+        mp4_duration(bytes)
+    }
+}
+
+/// `1:05` for 65 seconds
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64().round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// A Core Audio file's length: its valid frames from the packet table, or for constant
+/// bit rate audio its packets of data, over the sample rate
+fn caf_duration(bytes: &[u8]) -> Option<Duration> {
+    let mut sample_rate = None;
+    let mut packet_size = None;
+    let mut valid_frames = None;
+    let mut data_size = None;
+
+    // The file header is followed by chunks with a type and 64-bit size
+    let mut position = 8;
+    while position + 12 <= bytes.len() {
+        let kind = &bytes[position..position + 4];
+        let size = read_u64(bytes, position + 4).ok()? as i64;
+        let content = position + 12;
+        // Only the data chunk may have a size of -1, running to the end of the file
+        let end = if size < 0 {
+            bytes.len()
+        } else {
+            content.checked_add(size as usize)?.min(bytes.len())
+        };
+
+        match kind {
+            b"desc" => {
+                sample_rate = Some(f64::from_bits(read_u64(bytes, content).ok()?));
+                packet_size = Some((
+                    read_u32(bytes, content + 16).ok()?,
+                    read_u32(bytes, content + 20).ok()?,
+                ));
+            }
+            b"pakt" => valid_frames = Some(read_u64(bytes, content + 8).ok()?),
+            // The audio follows a 4-byte edit count
+            b"data" => data_size = Some(end.saturating_sub(content + 4) as u64),
+            _ => {}
+        }
+        position = end;
+    }
+
+    let sample_rate = sample_rate.filter(|sample_rate| *sample_rate > 0.0)?;
+    let frames = match (valid_frames, packet_size, data_size) {
+        (Some(frames), _, _) => frames,
+        (None, Some((bytes_per_packet, frames_per_packet)), Some(data_size))
+            if bytes_per_packet > 0 =>
+        {
+            data_size / bytes_per_packet as u64 * frames_per_packet as u64
+        }
+        _ => return None,
+    };
+    Some(Duration::from_secs_f64(frames as f64 / sample_rate))
+}
+
+/// An MP4 or QuickTime file's length, from the movie header
+fn mp4_duration(bytes: &[u8]) -> Option<Duration> {
+    let top = iso_boxes(bytes, 0, bytes.len()).ok()?;
+    let moov = top.iter().find(|iso_box| &iso_box.kind == b"moov")?;
+    let mvhd = iso_boxes(bytes, moov.content, moov.end)
+        .ok()?
+        .into_iter()
+        .find(|iso_box| &iso_box.kind == b"mvhd")?;
+
+    // Version 1 headers have 64-bit creation and modification times and duration
+    let (timescale, duration) = match bytes.get(mvhd.content)? {
+        1 => (
+            read_u32(bytes, mvhd.content + 20).ok()?,
+            read_u64(bytes, mvhd.content + 24).ok()?,
+        ),
+        _ => (
+            read_u32(bytes, mvhd.content + 12).ok()?,
+            read_u32(bytes, mvhd.content + 16).ok()? as u64,
+        ),
+    };
+    (timescale > 0).then(|| Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+/// Loudness of each bar of a waveform, from 0 to 1. The audio is decoded with `afconvert`
+/// (part of macOS) or `ffmpeg`; `None` when neither is installed or can decode it.
+pub fn waveform(bytes: &[u8], filename: &str) -> Option<Vec<f32>> {
+    let file = DatabaseSnapshot::from_bytes(filename, bytes, "audio").ok()?;
+    let samples = decode_with_afconvert(file.path()).or_else(|| decode_with_ffmpeg(file.path()))?;
+    if samples.is_empty() {
+        return None;
+    }
+
+    let bar_length = samples.len().div_ceil(WAVEFORM_BARS);
+    let levels: Vec<f32> = samples
+        .chunks(bar_length)
+        .map(|bar| {
+            let sum_of_squares: f64 = bar.iter().map(|&sample| (sample as f64).powi(2)).sum();
+            (sum_of_squares / bar.len() as f64).sqrt() as f32
+        })
+        .collect();
+    let loudest = levels.iter().copied().fold(0.0, f32::max);
+    if loudest == 0.0 {
+        return Some(vec![0.0; levels.len()]);
+    }
+    Some(levels.into_iter().map(|level| level / loudest).collect())
+}
+
+/// An SVG of vertical bars, one per level, for showing next to the audio player
+pub fn waveform_svg(levels: &[f32]) -> String {
+    const HEIGHT: f32 = 32.0;
+    let bars: String = levels
+        .iter()
+        .enumerate()
+        .map(|(index, level)| {
+            let height = (level * HEIGHT).max(2.0);
+            format!(
+                r#"<rect x="{}" y="{:.1}" width="2" height="{:.1}" rx="1"/>"#,
+                index * 3,
+                (HEIGHT - height) / 2.0,
+                height
+            )
+        })
+        .collect();
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" width="{}" height="{}" fill="#8e8e93">{}</svg>
+"##,
+        levels.len() * 3,
+        HEIGHT,
+        levels.len() * 3,
+        HEIGHT,
+        bars
+    )
+}
+
+/// Mono 16-bit samples decoded by macOS's `afconvert`, which writes a WAV file
+fn decode_with_afconvert(path: &Path) -> Option<Vec<i16>> {
+    let wav_path = path.with_extension("waveform.wav");
+    let status = Command::new("afconvert")
+        .args(["-f", "WAVE", "-c", "1", "-d"])
+        .arg(format!("LEI16@{}", WAVEFORM_SAMPLE_RATE))
+        .arg(path)
+        .arg(&wav_path)
+        .stderr(Stdio::null())
+        .status()
+        .ok()?;
+    if !status.success() {
+        return None;
+    }
+
+    let wav = fs::read(&wav_path).ok()?;
+    Some(samples(wav_data(&wav)?))
+}
+
+/// Mono 16-bit samples decoded by `ffmpeg`, which writes them raw to stdout
+fn decode_with_ffmpeg(path: &Path) -> Option<Vec<i16>> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "quiet", "-i"])
+        .arg(path)
+        .args(["-f", "s16le", "-ac", "1", "-ar"])
+        .arg(WAVEFORM_SAMPLE_RATE.to_string())
+        .arg("-")
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then(|| samples(&output.stdout))
+}
+
+/// The contents of a WAV file's `data` chunk
+fn wav_data(wav: &[u8]) -> Option<&[u8]> {
+    let mut position = 12;
+    while position + 8 <= wav.len() {
+        let size = u32::from_le_bytes(wav[position + 4..position + 8].try_into().ok()?) as usize;
+        let content = position + 8;
+        if &wav[position..position + 4] == b"data" {
+            return wav.get(content..(content + size).min(wav.len()));
+        }
+        // Chunks are padded to an even length
+        position = content + size + size % 2;
+    }
+    None
+}
+
+/// Little-endian 16-bit samples
+fn samples(pcm: &[u8]) -> Vec<i16> {
+    pcm.chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect()
+}
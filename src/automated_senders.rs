@@ -0,0 +1,192 @@
+use crate::clean_message::CleanMessage;
+use crate::contact_resolver::ContactResolver;
+use crate::message_store::group_messages_by_chat;
+use anyhow::Result;
+use imessage_database::tables::table::{CHAT_HANDLE_JOIN, HANDLE};
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+
+/// Prefix of Apple Messages for Business handles, followed by an opaque ID
+const BUSINESS_PREFIX: &str = "urn:biz:";
+
+/// Phrases of verification code and marketing texts, in lowercase
+const TEMPLATE_PHRASES: &[&str] = &[
+    "verification code",
+    "security code",
+    "confirmation code",
+    "login code",
+    "one-time",
+    "passcode",
+    "do not share",
+    "don't share",
+    "reply stop",
+    "text stop",
+    "stop to opt",
+    "stop to end",
+    "stop to cancel",
+    "opt out",
+    "opt-out",
+    "unsubscribe",
+    "msg&data rates",
+    "msg & data rates",
+    "message and data rates",
+];
+
+/// Whether `identifier` is an SMS short code, the 3 to 6 digit numbers banks, delivery
+/// services and verification codes are sent from
+pub fn is_short_code(identifier: &str) -> bool {
+    let digits = identifier.strip_prefix('+').unwrap_or(identifier);
+    (3..=6).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Whether `identifier` is a business chatting through Apple Messages for Business
+pub fn is_business(identifier: &str) -> bool {
+    identifier.starts_with(BUSINESS_PREFIX)
+}
+
+/// Whether `identifier` is a short code or business rather than a person
+pub fn is_automated(identifier: &str) -> bool {
+    is_short_code(identifier) || is_business(identifier)
+}
+
+/// Readable names for short codes ("Short code 262966") and businesses (their name, when
+/// the database kept it as the handle's uncanonicalized ID), which would otherwise show
+/// as the raw number or `urn:biz:` ID. Asked after the contact sources, so a short code
+/// saved in Contacts keeps its contact name.
+#[derive(Default)]
+pub struct AutomatedSenders {
+    labels: HashMap<String, String>,
+}
+
+impl AutomatedSenders {
+    pub fn read(db: &Connection) -> Result<Self> {
+        let mut statement =
+            db.prepare(&format!("SELECT id, uncanonicalized_id FROM {}", HANDLE))?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        })?;
+
+        let mut labels = HashMap::new();
+        for row in rows {
+            let (identifier, uncanonicalized) = row?;
+            if is_short_code(&identifier) {
+                let label = format!("Short code {}", identifier.trim_start_matches('+'));
+                labels.insert(identifier, label);
+            } else if is_business(&identifier) {
+                let label = uncanonicalized
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty() && !is_business(name) && *name != identifier)
+                    .unwrap_or_else(|| "Business".to_string());
+                labels.insert(identifier, label);
+            }
+        }
+        Ok(Self { labels })
+    }
+}
+
+impl ContactResolver for AutomatedSenders {
+    fn name(&self, identifier: &str) -> Option<String> {
+        self.labels.get(identifier).cloned()
+    }
+}
+
+/// ROWIDs of the chats whose members are all short codes or businesses
+pub fn automated_chats(
+    db: &Connection,
+    handle_cache: &HashMap<i32, String>,
+) -> Result<HashSet<i32>> {
+    let mut statement = db.prepare(&format!(
+        "SELECT chat_id, handle_id FROM {}",
+        CHAT_HANDLE_JOIN
+    ))?;
+    let rows = statement.query_map([], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)))?;
+
+    let mut all_automated: HashMap<i32, bool> = HashMap::new();
+    for row in rows {
+        let (chat_id, handle_id) = row?;
+        let automated = handle_cache
+            .get(&handle_id)
+            .is_some_and(|identifier| is_automated(identifier));
+        *all_automated.entry(chat_id).or_insert(true) &= automated;
+    }
+    Ok(all_automated
+        .into_iter()
+        .filter_map(|(chat_id, automated)| automated.then_some(chat_id))
+        .collect())
+}
+
+/// Whether a message's text reads like a verification code or marketing text: one of
+/// their stock phrases, or a code of 4 to 8 digits next to the word "code"
+pub fn is_template_text(text: &str) -> bool {
+    let text = text.to_lowercase();
+    if TEMPLATE_PHRASES.iter().any(|phrase| text.contains(phrase)) {
+        return true;
+    }
+    text.contains("code")
+        && text
+            .split(|c: char| !c.is_ascii_digit())
+            .any(|digits| (4..=8).contains(&digits.len()))
+}
+
+/// Whether a chat is a one-way stream of verification codes or marketing: I never wrote
+/// or called, and it's from short codes or businesses only, or mostly template text
+pub fn is_automated_chat(messages: &[&CleanMessage]) -> bool {
+    if messages.is_empty()
+        || messages
+            .iter()
+            .any(|message| message.from.is_me() || message.call.is_some())
+    {
+        return false;
+    }
+    let from_automated_senders = messages
+        .iter()
+        .all(|message| message.from.identifier().is_some_and(is_automated));
+    let template_count = messages
+        .iter()
+        .filter(|message| is_template_text(&message.text))
+        .count();
+    from_automated_senders || template_count * 2 > messages.len()
+}
+
+/// Removes the messages of automated chats (see `is_automated_chat`) for
+/// `--skip-automated`, returning the names of the chats removed
+pub fn remove_automated_chats(messages: &mut Vec<CleanMessage>) -> Vec<String> {
+    let mut chats: Vec<String> = Vec::new();
+    let mut guids: HashSet<String> = HashSet::new();
+    for (chat_key, chat_messages) in group_messages_by_chat(messages) {
+        if is_automated_chat(&chat_messages) {
+            guids.extend(chat_messages.iter().map(|message| message.guid.clone()));
+            chats.push(chat_key);
+        }
+    }
+
+    messages.retain(|message| !guids.contains(&message.guid));
+    chats.sort();
+    chats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_automated_senders() {
+        assert!(is_short_code("262966"));
+        assert!(is_short_code("7726"));
+        assert!(!is_short_code("+15555550100"));
+        assert!(!is_short_code("friend@example.com"));
+        assert!(is_business("urn:biz:2c1a9c7e-5f1b-4b0e-9e6d-0f3c6b6f9a11"));
+        assert!(!is_automated("+15555550100"));
+    }
+
+    #[test]
+    fn test_template_text() {
+        assert!(is_template_text("Your verification code is 482913"));
+        assert!(is_template_text("G-552817 is your Google code."));
+        assert!(is_template_text(
+            "20% off this weekend! Reply STOP to opt out"
+        ));
+        assert!(!is_template_text("Running late, there in 10"));
+        assert!(!is_template_text("The door code is on the fridge"));
+    }
+}
@@ -8,6 +8,8 @@ use imessage_database::{
     },
     util::dirs::default_db_path,
 };
+use imessage_extractor::chat_naming::{ChatNames, NamingOptions};
+use imessage_extractor::contacts::ContactMap;
 use std::collections::HashMap;
 
 fn main() -> Result<()> {
@@ -19,6 +21,13 @@ fn main() -> Result<()> {
 
     let chat_data_cache = Chat::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
     let handle_cache = Handle::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
+    let chat_names = ChatNames::load(
+        &db,
+        &chat_data_cache,
+        &handle_cache,
+        &ContactMap::empty(),
+        NamingOptions::default(),
+    )?;
 
     let ralph_handles = vec![740, 713, 789, 801];
 
@@ -79,12 +88,7 @@ fn main() -> Result<()> {
     println!("=== Direct message chats involving Ralph ===\n");
 
     for (chat_id, info) in &ralph_chats {
-        let chat = chat_data_cache.get(chat_id);
-        let identifier = chat
-            .map(|c| c.chat_identifier.as_str())
-            .unwrap_or("[unknown]");
-
-        println!("Chat ID {}: {}", chat_id, identifier);
+        println!("Chat ID {}: {}", chat_id, chat_names.label(**chat_id));
         println!("  From me: {}", info.from_me);
         println!("  From others: {}", info.from_others);
         println!("  Participants: {:?}", info.participants);
@@ -96,33 +100,22 @@ fn main() -> Result<()> {
         println!();
     }
 
-    // Now simulate the grouping logic
-    println!("=== Simulating participant-based grouping ===\n");
+    // The exporter groups messages into conversations by chat name
+    println!("=== Conversations the exporter would produce ===\n");
 
-    let mut participant_groups: HashMap<Vec<String>, Vec<i32>> = HashMap::new();
+    let mut conversations: HashMap<&str, Vec<i32>> = HashMap::new();
 
     for (chat_id, info) in &chat_id_info {
         if info.involves_ralph {
-            let mut participants = info.participants.clone();
-            participants.sort();
-            participants.dedup();
-
-            participant_groups
-                .entry(participants)
+            conversations
+                .entry(chat_names.get(*chat_id).unwrap_or("[unknown]"))
                 .or_default()
                 .push(*chat_id);
         }
     }
 
-    for (participants, chat_ids) in &participant_groups {
-        let key = if participants.len() == 1 {
-            format!("Direct: {}", participants[0])
-        } else {
-            format!("Direct: {}", participants.join(", "))
-        };
-
-        println!("Group: {}", key);
-        println!("  Participant set: {:?}", participants);
+    for (name, chat_ids) in &conversations {
+        println!("Conversation: {}", name);
         println!("  Chat IDs: {:?}", chat_ids);
 
         let total_from_me: usize = chat_ids
@@ -8,6 +8,8 @@ use imessage_database::{
     },
     util::dirs::default_db_path,
 };
+use imessage_extractor::chat_naming::{ChatNames, NamingOptions};
+use imessage_extractor::contacts::ContactMap;
 use std::collections::HashMap;
 
 fn main() -> Result<()> {
@@ -19,21 +21,23 @@ fn main() -> Result<()> {
     // Cache chats and handles
     let chat_data_cache = Chat::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
     let handle_cache = Handle::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
+    let chat_names = ChatNames::load(
+        &db,
+        &chat_data_cache,
+        &handle_cache,
+        &ContactMap::empty(),
+        NamingOptions::default(),
+    )?;
 
     println!("\n=== Looking for Ralph Douglass conversations ===\n");
 
     // Find all chats involving Ralph
     let mut ralph_chats: Vec<(i32, String)> = Vec::new();
-    for (chat_id, chat) in &chat_data_cache {
-        let chat_identifier = &chat.chat_identifier;
-        let display_name = chat.display_name.as_deref().unwrap_or("");
-
-        if chat_identifier.to_lowercase().contains("ralph")
-            || chat_identifier.to_lowercase().contains("douglass")
-            || display_name.to_lowercase().contains("ralph")
-            || display_name.to_lowercase().contains("douglass")
-        {
-            ralph_chats.push((*chat_id, format!("{} ({})", display_name, chat_identifier)));
+    for chat_id in chat_data_cache.keys() {
+        let label = chat_names.label(*chat_id);
+
+        if label.to_lowercase().contains("ralph") || label.to_lowercase().contains("douglass") {
+            ralph_chats.push((*chat_id, label));
         }
     }
 
@@ -8,6 +8,8 @@ use imessage_database::{
     },
     util::dirs::default_db_path,
 };
+use imessage_extractor::chat_naming::{ChatNames, NamingOptions};
+use imessage_extractor::contacts::ContactMap;
 use std::collections::HashMap;
 
 fn main() -> Result<()> {
@@ -25,6 +27,13 @@ fn main() -> Result<()> {
     // Cache chats and handles
     let chat_data_cache = Chat::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
     let handle_cache = Handle::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
+    let chat_names = ChatNames::load(
+        &db,
+        &chat_data_cache,
+        &handle_cache,
+        &ContactMap::empty(),
+        NamingOptions::default(),
+    )?;
 
     println!("=== Step 1: Finding matching handles ===\n");
 
@@ -44,22 +53,15 @@ fn main() -> Result<()> {
 
     // Find chats by chat name/identifier
     let mut named_chats: Vec<(i32, String)> = Vec::new();
-    for (chat_id, chat) in &chat_data_cache {
-        let chat_identifier = &chat.chat_identifier;
-        let display_name = chat.display_name.as_deref().unwrap_or("");
+    for chat_id in chat_data_cache.keys() {
+        let label = chat_names.label(*chat_id);
 
-        let matches = search_terms.iter().any(|term| {
-            chat_identifier.to_lowercase().contains(term)
-                || display_name.to_lowercase().contains(term)
-        });
+        let matches = search_terms
+            .iter()
+            .any(|term| label.to_lowercase().contains(term));
 
         if matches {
-            let name = if display_name.is_empty() {
-                format!("Direct: {}", chat_identifier)
-            } else {
-                format!("{} ({})", display_name, chat_identifier)
-            };
-            named_chats.push((*chat_id, name));
+            named_chats.push((*chat_id, label));
             println!("Found chat {}: {}", chat_id, named_chats.last().unwrap().1);
         }
     }
@@ -155,17 +157,7 @@ fn main() -> Result<()> {
             unique_chats.len()
         );
         for chat_id in unique_chats {
-            let chat_name = chat_data_cache
-                .get(&chat_id)
-                .and_then(|c| c.display_name.as_ref())
-                .cloned()
-                .unwrap_or_else(|| {
-                    chat_data_cache
-                        .get(&chat_id)
-                        .map(|c| format!("[{}]", c.chat_identifier))
-                        .unwrap_or_else(|| "[unknown]".to_string())
-                });
-            println!("  Chat {}: {}", chat_id, chat_name);
+            println!("  Chat {}: {}", chat_id, chat_names.label(chat_id));
         }
         println!();
     }
@@ -176,19 +168,7 @@ fn main() -> Result<()> {
     chat_stats.sort_by_key(|(chat_id, _)| **chat_id);
 
     for (chat_id, (total, from_me, from_others)) in chat_stats {
-        let chat_name = chat_data_cache
-            .get(chat_id)
-            .map(|c| {
-                let display = c.display_name.as_deref().unwrap_or("");
-                if display.is_empty() {
-                    format!("Direct: {}", c.chat_identifier)
-                } else {
-                    format!("{} ({})", display, c.chat_identifier)
-                }
-            })
-            .unwrap_or_else(|| "[unknown chat]".to_string());
-
-        println!("Chat ID {}: {}", chat_id, chat_name);
+        println!("Chat ID {}: {}", chat_id, chat_names.label(*chat_id));
         println!("  Total messages: {}", total);
         println!(
             "  From me: {} ({:.1}%)",
@@ -218,19 +198,7 @@ fn main() -> Result<()> {
     chat_samples.sort_by_key(|(chat_id, _)| **chat_id);
 
     for (chat_id, messages) in chat_samples {
-        let chat_name = chat_data_cache
-            .get(chat_id)
-            .map(|c| {
-                let display = c.display_name.as_deref().unwrap_or("");
-                if display.is_empty() {
-                    format!("Direct: {}", c.chat_identifier)
-                } else {
-                    format!("{} ({})", display, c.chat_identifier)
-                }
-            })
-            .unwrap_or_else(|| "[unknown chat]".to_string());
-
-        println!("Chat ID {}: {}", chat_id, chat_name);
+        println!("Chat ID {}: {}", chat_id, chat_names.label(*chat_id));
         for msg in messages {
             let sender = if msg.is_from_me {
                 "ME".to_string()
@@ -8,6 +8,8 @@ use imessage_database::{
     },
     util::dirs::default_db_path,
 };
+use imessage_extractor::chat_naming::{ChatNames, NamingOptions};
+use imessage_extractor::contacts::ContactMap;
 use std::collections::HashMap;
 
 fn main() -> Result<()> {
@@ -20,6 +22,13 @@ fn main() -> Result<()> {
     // Cache chats and handles
     let chat_data_cache = Chat::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
     let handle_cache = Handle::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
+    let chat_names = ChatNames::load(
+        &db,
+        &chat_data_cache,
+        &handle_cache,
+        &ContactMap::empty(),
+        NamingOptions::default(),
+    )?;
 
     println!("=== Finding chats where Ralph Douglass appears as the only other participant ===\n");
 
@@ -71,15 +80,7 @@ fn main() -> Result<()> {
     );
 
     for (chat_id, info) in &candidates {
-        let chat = chat_data_cache.get(chat_id);
-        let chat_name = chat
-            .and_then(|c| c.display_name.as_ref().cloned())
-            .unwrap_or_default();
-        let chat_identifier = chat
-            .map(|c| c.chat_identifier.as_str())
-            .unwrap_or("[unknown]");
-
-        println!("Chat ID {}: {} ({})", chat_id, chat_name, chat_identifier);
+        println!("Chat ID {}: {}", chat_id, chat_names.label(**chat_id));
         println!("  From you: {}", info.from_me_count);
         println!("  From others: {}", info.from_others_count);
         println!("  Total: {}", info.from_me_count + info.from_others_count);
@@ -121,21 +122,13 @@ fn main() -> Result<()> {
     );
 
     for (chat_id, info) in &ralph_only_chats {
-        let chat = chat_data_cache.get(chat_id);
-        let chat_name = chat
-            .and_then(|c| c.display_name.as_ref().cloned())
-            .unwrap_or_default();
-        let chat_identifier = chat
-            .map(|c| c.chat_identifier.as_str())
-            .unwrap_or("[unknown]");
-
         let handle_id = info.participants.keys().next().unwrap();
         let handle_str = handle_cache
             .get(handle_id)
             .cloned()
             .unwrap_or_else(|| format!("Handle {}", handle_id));
 
-        println!("Chat ID {}: {} ({})", chat_id, chat_name, chat_identifier);
+        println!("Chat ID {}: {}", chat_id, chat_names.label(**chat_id));
         println!("  From you: {}", info.from_me_count);
         println!(
             "  From others: {} (all from {})",
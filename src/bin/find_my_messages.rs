@@ -8,6 +8,8 @@ use imessage_database::{
     },
     util::dirs::default_db_path,
 };
+use imessage_extractor::chat_naming::{ChatNames, NamingOptions};
+use imessage_extractor::contacts::ContactMap;
 use std::collections::{HashMap, HashSet};
 
 fn main() -> Result<()> {
@@ -28,6 +30,13 @@ fn main() -> Result<()> {
     // Cache chats and handles
     let chat_data_cache = Chat::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
     let handle_cache = Handle::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
+    let chat_names = ChatNames::load(
+        &db,
+        &chat_data_cache,
+        &handle_cache,
+        &ContactMap::empty(),
+        NamingOptions::default(),
+    )?;
 
     println!("=== Step 1: Finding handles matching search terms ===\n");
 
@@ -67,19 +76,7 @@ fn main() -> Result<()> {
     );
 
     for chat_id in &chats_with_matching_handles {
-        let chat_name = chat_data_cache
-            .get(chat_id)
-            .map(|c| {
-                let display = c.display_name.as_deref().unwrap_or("");
-                let identifier = &c.chat_identifier;
-                if display.is_empty() {
-                    format!("Chat {}: {}", chat_id, identifier)
-                } else {
-                    format!("Chat {}: {} ({})", chat_id, display, identifier)
-                }
-            })
-            .unwrap_or_else(|| format!("Chat {}: [unknown]", chat_id));
-        println!("  {}", chat_name);
+        println!("  Chat {}: {}", chat_id, chat_names.label(*chat_id));
     }
 
     println!("\n=== Step 3: Analyzing YOUR messages in these chats ===\n");
@@ -130,20 +127,7 @@ fn main() -> Result<()> {
 
     for (chat_id, (from_me, from_others)) in &stats_vec {
         let total = from_me + from_others;
-        let chat_name = chat_data_cache
-            .get(chat_id)
-            .map(|c| {
-                let display = c.display_name.as_deref().unwrap_or("");
-                let identifier = &c.chat_identifier;
-                if display.is_empty() {
-                    identifier.to_string()
-                } else {
-                    format!("{} ({})", display, identifier)
-                }
-            })
-            .unwrap_or_else(|| "[unknown]".to_string());
-
-        println!("Chat {}: {}", chat_id, chat_name);
+        println!("Chat {}: {}", chat_id, chat_names.label(**chat_id));
         println!("  Total: {}", total);
         println!(
             "  From you: {} ({:.1}%)",
@@ -173,20 +157,7 @@ fn main() -> Result<()> {
             continue;
         }
 
-        let chat_name = chat_data_cache
-            .get(chat_id)
-            .map(|c| {
-                let display = c.display_name.as_deref().unwrap_or("");
-                let identifier = &c.chat_identifier;
-                if display.is_empty() {
-                    identifier.to_string()
-                } else {
-                    format!("{} ({})", display, identifier)
-                }
-            })
-            .unwrap_or_else(|| "[unknown]".to_string());
-
-        println!("Chat {}: {}", chat_id, chat_name);
+        println!("Chat {}: {}", chat_id, chat_names.label(**chat_id));
         for msg in *messages {
             println!(
                 "  [ME] date={} handle_id={:?} text=\"{}\"",
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use imessage_extractor::fixture;
+use std::path::PathBuf;
+
+/// Writes the synthetic test database to the directory given, `fixture` by default
+fn main() -> Result<()> {
+    let directory = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("fixture"));
+    let fixture = fixture::generate(&directory)?;
+
+    println!("Wrote {}", fixture.database.display());
+    println!(
+        "Export it with: imessage_extractor --database-path {} --platform macos --attachment-root {}",
+        fixture.database.display(),
+        fixture.attachment_root.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use crate::database_connection::open_read_only;
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Local, TimeZone};
+use rusqlite::types::Value;
+use std::path::{Path, PathBuf};
+
+/// `ZCALLTYPE` values in the call history database
+const CALL_TYPE_FACETIME_VIDEO: i64 = 8;
+const CALL_TYPE_FACETIME_AUDIO: i64 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallKind {
+    Phone,
+    FaceTimeVideo,
+    FaceTimeAudio,
+}
+
+impl std::fmt::Display for CallKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallKind::Phone => write!(f, "Phone call"),
+            CallKind::FaceTimeVideo => write!(f, "FaceTime call"),
+            CallKind::FaceTimeAudio => write!(f, "FaceTime Audio call"),
+        }
+    }
+}
+
+/// One row of the macOS call history
+pub struct CallRecord {
+    pub id: i64,
+    /// Phone number or email of the other party, as stored by the call history
+    pub address: String,
+    pub date: DateTime<Local>,
+    pub duration_seconds: i64,
+    pub answered: bool,
+    pub outgoing: bool,
+    pub kind: CallKind,
+}
+
+impl CallRecord {
+    /// "FaceTime call, 23 minutes" / "Missed FaceTime call"
+    pub fn description(&self) -> String {
+        if !self.answered || self.duration_seconds == 0 {
+            let missed = if self.outgoing {
+                "Unanswered"
+            } else {
+                "Missed"
+            };
+            let kind = match self.kind {
+                CallKind::Phone => "call".to_string(),
+                kind => kind.to_string(),
+            };
+            return format!("{} {}", missed, kind);
+        }
+
+        format!("{}, {}", self.kind, format_duration(self.duration_seconds))
+    }
+}
+
+pub fn default_call_history_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_default();
+    PathBuf::from(home).join("Library/Application Support/CallHistoryDB/CallHistory.storedata")
+}
+
+/// Reads every call from the call history database at `path`
+pub fn fetch_calls(path: &Path) -> Result<Vec<CallRecord>> {
+    let db = open_read_only(path)
+        .with_context(|| format!("Failed to open call history {}", path.display()))?;
+
+    let mut statement = db
+        .prepare(
+            "SELECT Z_PK, ZADDRESS, ZDATE, ZDURATION, ZANSWERED, ZORIGINATED, ZCALLTYPE
+             FROM ZCALLRECORD
+             ORDER BY ZDATE",
+        )
+        .context("Failed to query call history")?;
+
+    let database_tz_offset = imessage_database::util::dates::get_offset();
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Value>(1)?,
+            row.get::<_, Option<f64>>(2)?,
+            row.get::<_, Option<f64>>(3)?,
+            row.get::<_, Option<i64>>(4)?,
+            row.get::<_, Option<i64>>(5)?,
+            row.get::<_, Option<i64>>(6)?,
+        ))
+    })?;
+
+    let mut calls = Vec::new();
+    for row in rows {
+        let (id, address, date, duration, answered, originated, call_type) = row?;
+
+        // ZADDRESS is TEXT on most versions but a BLOB on some
+        let address = match address {
+            Value::Text(text) => text,
+            Value::Blob(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            _ => continue,
+        };
+        let Some(date) = date else {
+            continue;
+        };
+        let date = Local
+            .timestamp_opt(date as i64 + database_tz_offset, 0)
+            .single()
+            .ok_or_else(|| anyhow!("Invalid call date {}", date))?;
+
+        calls.push(CallRecord {
+            id,
+            address,
+            date,
+            duration_seconds: duration.unwrap_or_default().round() as i64,
+            answered: answered.unwrap_or_default() != 0,
+            outgoing: originated.unwrap_or_default() != 0,
+            kind: match call_type.unwrap_or_default() {
+                CALL_TYPE_FACETIME_VIDEO => CallKind::FaceTimeVideo,
+                CALL_TYPE_FACETIME_AUDIO => CallKind::FaceTimeAudio,
+                _ => CallKind::Phone,
+            },
+        });
+    }
+
+    Ok(calls)
+}
+
+fn format_duration(seconds: i64) -> String {
+    if seconds < 60 {
+        return format!("{} second{}", seconds, if seconds == 1 { "" } else { "s" });
+    }
+
+    let minutes = (seconds + 30) / 60;
+    let hours = minutes / 60;
+    let minutes = minutes % 60;
+
+    let plural = |n: i64| if n == 1 { "" } else { "s" };
+    match (hours, minutes) {
+        (0, m) => format!("{} minute{}", m, plural(m)),
+        (h, 0) => format!("{} hour{}", h, plural(h)),
+        (h, m) => format!("{} hour{} {} minute{}", h, plural(h), m, plural(m)),
+    }
+}
@@ -0,0 +1,43 @@
+use crate::chat_participants::group_name;
+use imessage_database::tables::chat::Chat;
+use std::collections::{BTreeSet, HashMap};
+
+/// Names to export group chats under so that chats with the same participants, which
+/// Messages splits into several chats when someone's phone number or email changes,
+/// become one conversation. Keyed by chat ROWID; only chats sharing their participants
+/// with another chat are included.
+///
+/// Participants are compared by resolved contact name. Each set of duplicates takes the
+/// display name of its newest chat that has one, else a name built from the participants.
+pub fn merged_chat_names(
+    chat_data_cache: &HashMap<i32, Chat>,
+    participants: &HashMap<i32, BTreeSet<String>>,
+) -> HashMap<i32, String> {
+    // Direct chats with the same person already share their contact name
+    let mut duplicates: HashMap<&BTreeSet<String>, Vec<i32>> = HashMap::new();
+    for (chat_id, members) in participants {
+        if members.len() > 1 && chat_data_cache.contains_key(chat_id) {
+            duplicates.entry(members).or_default().push(*chat_id);
+        }
+    }
+
+    let mut names = HashMap::new();
+    for (members, mut chat_ids) in duplicates
+        .into_iter()
+        .filter(|(_, chat_ids)| chat_ids.len() > 1)
+    {
+        chat_ids.sort();
+        let canonical_name = chat_ids
+            .iter()
+            .rev()
+            .filter_map(|chat_id| chat_data_cache[chat_id].display_name.as_ref())
+            .find(|display_name| !display_name.is_empty())
+            .cloned()
+            .unwrap_or_else(|| group_name(members));
+        for chat_id in chat_ids {
+            names.insert(chat_id, canonical_name.clone());
+        }
+    }
+
+    names
+}
@@ -0,0 +1,184 @@
+use crate::chat_merge::merged_chat_names;
+use crate::chat_participants::{chat_participants, unnamed_group_names};
+use crate::chat_rename::ChatRenames;
+use crate::contacts::ContactMap;
+use anyhow::{Result, anyhow};
+use imessage_database::tables::chat::Chat;
+use imessage_database::tables::messages::Message;
+use rusqlite::Connection;
+use std::collections::{BTreeSet, HashMap};
+
+/// How [`ChatNames`] names chats
+#[derive(Clone, Debug, Default)]
+pub struct NamingOptions {
+    /// Give group chats with the same participants one name, so they export as one
+    /// conversation; see [`merged_chat_names`]
+    pub merge_duplicate_groups: bool,
+    /// Names chosen with `--rename-chat` and `--chat-names`
+    pub renames: ChatRenames,
+}
+
+/// The name each chat in a database is exported under, which also decides the
+/// conversation its messages are grouped into: the chat's own name, else for an unnamed
+/// group a name built from its members, else the contact name of its identifier.
+/// Merged duplicate groups take their shared name before any of these, and a chosen
+/// name renames the whole conversation the chat would be grouped into.
+#[derive(Clone, Debug, Default)]
+pub struct ChatNames {
+    names: HashMap<i32, String>,
+    identifiers: HashMap<i32, String>,
+}
+
+impl ChatNames {
+    /// Names the chats in `chat_data_cache`, reading their members from `db`
+    pub fn load(
+        db: &Connection,
+        chat_data_cache: &HashMap<i32, Chat>,
+        handle_cache: &HashMap<i32, String>,
+        contact_map: &ContactMap,
+        options: NamingOptions,
+    ) -> Result<Self> {
+        let participants = chat_participants(db, handle_cache, contact_map)?;
+        Ok(Self::new(
+            chat_data_cache,
+            &participants,
+            contact_map,
+            options,
+        ))
+    }
+
+    /// Names the chats in `chat_data_cache`, whose members other than me are
+    /// `participants`, keyed by chat ROWID
+    pub fn new(
+        chat_data_cache: &HashMap<i32, Chat>,
+        participants: &HashMap<i32, BTreeSet<String>>,
+        contact_map: &ContactMap,
+        options: NamingOptions,
+    ) -> Self {
+        let group_names = unnamed_group_names(chat_data_cache, participants);
+        let mut merged_names = if options.merge_duplicate_groups {
+            merged_chat_names(chat_data_cache, participants)
+        } else {
+            HashMap::new()
+        };
+
+        let mut names = HashMap::new();
+        let mut identifiers = HashMap::new();
+        for (chat_id, chat) in chat_data_cache {
+            let name = match merged_names.remove(chat_id) {
+                Some(merged_name) => merged_name,
+                None => chat_name(chat, contact_map, &group_names),
+            };
+            names.insert(*chat_id, name);
+            identifiers.insert(*chat_id, chat.chat_identifier.clone());
+        }
+
+        // Chats exported under the same name are one conversation (e.g. the SMS and
+        // iMessage chats with a contact), so renaming one renames all of them
+        let mut renamed = HashMap::new();
+        for (chat_id, chat) in chat_data_cache {
+            if let Some(new_name) = options.renames.get(&chat.chat_identifier, &names[chat_id]) {
+                renamed.insert(names[chat_id].clone(), new_name.to_string());
+            }
+        }
+        for name in names.values_mut() {
+            if let Some(new_name) = renamed.get(name) {
+                *name = new_name.clone();
+            }
+        }
+
+        Self { names, identifiers }
+    }
+
+    /// The name of the chat with ROWID `chat_id`
+    pub fn get(&self, chat_id: i32) -> Option<&str> {
+        self.names.get(&chat_id).map(String::as_str)
+    }
+
+    /// The name of `message`'s chat; `None` for a message in no chat, an error for one
+    /// whose chat is missing from the database
+    pub fn for_message(&self, message: &Message) -> Result<Option<String>> {
+        match message.chat_id {
+            None => Ok(None),
+            Some(chat_id) => self
+                .get(chat_id)
+                .map(|name| Some(name.to_string()))
+                .ok_or_else(|| anyhow!("Unable to find chat data for chat id {}", chat_id)),
+        }
+    }
+
+    /// The chat's name and, when it isn't named after it, its identifier, e.g.
+    /// "Weekend Plans (chat100000000000000001)", for diagnostic output
+    pub fn label(&self, chat_id: i32) -> String {
+        match (self.names.get(&chat_id), self.identifiers.get(&chat_id)) {
+            (Some(name), Some(identifier)) if name != identifier => {
+                format!("{} ({})", name, identifier)
+            }
+            (Some(name), _) => name.clone(),
+            _ => "[unknown]".to_string(),
+        }
+    }
+}
+
+/// The chat's own name, else the name built from its members for an unnamed group, else
+/// the contact name of its identifier
+fn chat_name(chat: &Chat, contact_map: &ContactMap, group_names: &HashMap<i32, String>) -> String {
+    match chat.display_name.as_ref() {
+        Some(display_name) if !display_name.is_empty() => display_name.clone(),
+        _ if group_names.contains_key(&chat.rowid) => group_names[&chat.rowid].clone(),
+        _ => contact_map
+            .get(&chat.chat_identifier)
+            .unwrap_or_else(|| chat.chat_identifier.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use imessage_database::tables::handle::Handle;
+    use imessage_database::tables::table::Cacheable;
+
+    #[test]
+    fn test_fixture_chat_names() {
+        let directory = std::env::temp_dir().join(format!(
+            "imessage_extractor-{}-chat-naming-test",
+            std::process::id()
+        ));
+        let fixture = fixture::generate(&directory).unwrap();
+        let db = Connection::open(&fixture.database).unwrap();
+        let chat_data_cache = Chat::cache(&db).unwrap();
+        let handle_cache = Handle::cache(&db).unwrap();
+
+        let names = ChatNames::load(
+            &db,
+            &chat_data_cache,
+            &handle_cache,
+            &ContactMap::empty(),
+            NamingOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(names.get(1), Some("+15555550101"));
+        assert_eq!(names.get(2), Some("Weekend Plans"));
+        assert_eq!(names.label(1), "+15555550101");
+        assert_eq!(names.label(2), "Weekend Plans (chat100000000000000001)");
+        assert_eq!(names.label(99), "[unknown]");
+
+        let mut renames = ChatRenames::default();
+        renames.extend(&["chat100000000000000001=Trip".parse().unwrap()]);
+        let names = ChatNames::load(
+            &db,
+            &chat_data_cache,
+            &handle_cache,
+            &ContactMap::empty(),
+            NamingOptions {
+                renames,
+                ..NamingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(names.get(2), Some("Trip"));
+        assert_eq!(names.label(2), "Trip (chat100000000000000001)");
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::contacts::ContactMap;
+use crate::resolved_handle::ResolvedHandle;
+use anyhow::Result;
+use imessage_database::tables::chat::Chat;
+use imessage_database::tables::table::CHAT_HANDLE_JOIN;
+use rusqlite::Connection;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Names shown before the rest of a group's members are counted
+const NAMED_PARTICIPANTS: usize = 2;
+
+/// Members of each chat other than me, resolved to contact names, keyed by chat ROWID
+pub fn chat_participants(
+    db: &Connection,
+    handle_cache: &HashMap<i32, String>,
+    contact_map: &ContactMap,
+) -> Result<HashMap<i32, BTreeSet<String>>> {
+    let mut participants: HashMap<i32, BTreeSet<String>> = HashMap::new();
+    let mut statement = db.prepare(&format!(
+        "SELECT chat_id, handle_id FROM {}",
+        CHAT_HANDLE_JOIN
+    ))?;
+    let rows = statement.query_map([], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)))?;
+    for row in rows {
+        let (chat_id, handle_id) = row?;
+        // My other numbers and emails can be members too
+        if handle_cache
+            .get(&handle_id)
+            .is_some_and(|identifier| contact_map.is_me(identifier))
+        {
+            continue;
+        }
+        participants
+            .entry(chat_id)
+            .or_default()
+            .insert(ResolvedHandle::resolve_handle_to_name(
+                &handle_id,
+                handle_cache,
+                contact_map,
+            ));
+    }
+
+    Ok(participants)
+}
+
+/// Names for group chats without a display name, built from their members, e.g.
+/// "Alice & Bob", "Alice, Bob & Carol" or "Alice, Bob & 2 others", keyed by chat ROWID.
+/// Chats whose names would be the same get ` (2)`, ` (3)` etc. in ROWID order, so they
+/// stay separate conversations.
+pub fn unnamed_group_names(
+    chat_data_cache: &HashMap<i32, Chat>,
+    participants: &HashMap<i32, BTreeSet<String>>,
+) -> HashMap<i32, String> {
+    let mut chat_ids: Vec<&i32> = participants
+        .iter()
+        .filter(|(chat_id, members)| {
+            members.len() > 1
+                && chat_data_cache.get(chat_id).is_some_and(|chat| {
+                    chat.display_name
+                        .as_ref()
+                        .is_none_or(|display_name| display_name.is_empty())
+                })
+        })
+        .map(|(chat_id, _)| chat_id)
+        .collect();
+    chat_ids.sort();
+
+    let mut used = HashSet::new();
+    let mut names = HashMap::new();
+    for chat_id in chat_ids {
+        let base = group_name(&participants[chat_id]);
+        let mut name = base.clone();
+        let mut counter = 2;
+        while !used.insert(name.clone()) {
+            name = format!("{} ({})", base, counter);
+            counter += 1;
+        }
+        names.insert(*chat_id, name);
+    }
+
+    names
+}
+
+/// A group chat name listing `members`
+pub fn group_name(members: &BTreeSet<String>) -> String {
+    let members: Vec<&str> = members.iter().map(String::as_str).collect();
+    match members.as_slice() {
+        [] => String::new(),
+        [only] => only.to_string(),
+        [init @ .., last] if members.len() <= NAMED_PARTICIPANTS + 1 => {
+            format!("{} & {}", init.join(", "), last)
+        }
+        _ => format!(
+            "{} & {} others",
+            members[..NAMED_PARTICIPANTS].join(", "),
+            members.len() - NAMED_PARTICIPANTS
+        ),
+    }
+}
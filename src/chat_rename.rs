@@ -0,0 +1,143 @@
+use crate::markup::csv_fields;
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A `--rename-chat CHAT=NAME` option
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatRename {
+    /// The chat's identifier, e.g. `chat483920112233445566`, or the name it would be
+    /// exported under
+    pub chat: String,
+    pub name: String,
+}
+
+impl FromStr for ChatRename {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((chat, name)) if !chat.trim().is_empty() && !name.trim().is_empty() => Ok(Self {
+                chat: chat.trim().to_string(),
+                name: name.trim().to_string(),
+            }),
+            _ => Err(format!(
+                "'{}' is not in CHAT=NAME form (e.g. chat483920112233445566=College Friends)",
+                s
+            )),
+        }
+    }
+}
+
+/// Names chosen for chats with `--rename-chat` and `--chat-names`, keyed by the chat's
+/// identifier or the name it would otherwise be exported under
+#[derive(Clone, Debug, Default)]
+pub struct ChatRenames {
+    names: HashMap<String, String>,
+}
+
+impl ChatRenames {
+    /// Loads a `--chat-names` file, in the format of an alias file: a table of
+    /// `"chat" = "Name"` pairs for files ending in `.toml`, else CSV with one `chat,name`
+    /// pair per line, skipping blank lines and lines starting with `#`
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read chat names file {}", path.display()))?;
+
+        let is_toml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+
+        let names = if is_toml {
+            toml::from_str(&contents).context("Failed to parse chat names TOML")?
+        } else {
+            parse_chat_names_csv(&contents)?
+        };
+        Ok(Self { names })
+    }
+
+    /// Adds `renames`, replacing the name of any chat already renamed
+    pub fn extend(&mut self, renames: &[ChatRename]) {
+        for rename in renames {
+            self.names.insert(rename.chat.clone(), rename.name.clone());
+        }
+    }
+
+    /// The new name of the chat with identifier `chat_identifier`, which would otherwise
+    /// be exported as `name`. A rename by identifier wins over one by name.
+    pub fn get(&self, chat_identifier: &str, name: &str) -> Option<&str> {
+        self.names
+            .get(chat_identifier)
+            .or_else(|| self.names.get(name))
+            .map(String::as_str)
+    }
+}
+
+fn parse_chat_names_csv(contents: &str) -> Result<HashMap<String, String>> {
+    let mut names = HashMap::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = csv_fields(line).unwrap_or_default();
+        let [chat, name] = fields.as_slice() else {
+            bail!(
+                "Chat names file line {} is not in chat,name form (quote names with commas)",
+                index + 1
+            );
+        };
+        names.insert(chat.trim().to_string(), name.trim().to_string());
+    }
+
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rename() {
+        assert_eq!(
+            "chat483920112233445566=College Friends".parse(),
+            Ok(ChatRename {
+                chat: "chat483920112233445566".to_string(),
+                name: "College Friends".to_string(),
+            })
+        );
+        assert!("College Friends".parse::<ChatRename>().is_err());
+        assert!("chat483920112233445566=".parse::<ChatRename>().is_err());
+    }
+
+    #[test]
+    fn test_rename_by_identifier_before_name() {
+        let mut renames = ChatRenames {
+            names: parse_chat_names_csv("# chat,name\n+15555550101,Alice\nBob,\"Bob Smith\"\n")
+                .unwrap(),
+        };
+        renames.extend(&["+15555550101=Alice Jones".parse().unwrap()]);
+
+        assert_eq!(renames.get("+15555550101", "Bob"), Some("Alice Jones"));
+        assert_eq!(renames.get("bob@example.com", "Bob"), Some("Bob Smith"));
+        assert_eq!(renames.get("bob@example.com", "Robert"), None);
+    }
+
+    #[test]
+    fn test_parse_quoted_names() {
+        let names = parse_chat_names_csv(
+            "\"Alice, Bob & 2 others\",\"Book Club, Tuesdays\"\nchat1,\"The \"\"Crew\"\"\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            names.get("Alice, Bob & 2 others").map(String::as_str),
+            Some("Book Club, Tuesdays")
+        );
+        assert_eq!(names.get("chat1").map(String::as_str), Some("The \"Crew\""));
+        assert!(parse_chat_names_csv("Alice, Bob & 2 others,Book Club\n").is_err());
+    }
+}
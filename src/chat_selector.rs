@@ -0,0 +1,41 @@
+use crate::clean_message::CleanMessage;
+use anyhow::Result;
+use imessage_database::tables::table::CHAT;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+/// The chats to export, picked by resolved name (`--chat`), by the chat's ROWID in the
+/// database (`--chat-id`) or by its GUID (`--chat-guid`). IDs and GUIDs bypass name
+/// resolution, for chats whose names are ambiguous. Everything is exported when no chat
+/// is picked.
+#[derive(Clone, Debug, Default)]
+pub struct ChatSelector {
+    pub names: Vec<String>,
+    pub ids: Vec<i32>,
+    pub guids: Vec<String>,
+}
+
+impl ChatSelector {
+    /// Whether `message` belongs to one of the picked chats
+    pub fn selects(&self, message: &CleanMessage) -> bool {
+        if self.names.is_empty() && self.ids.is_empty() && self.guids.is_empty() {
+            return true;
+        }
+        message
+            .chat_name
+            .as_ref()
+            .is_some_and(|name| self.names.contains(name))
+            || message.chat_id.is_some_and(|id| self.ids.contains(&id))
+            || message
+                .chat_guid
+                .as_ref()
+                .is_some_and(|guid| self.guids.contains(guid))
+    }
+}
+
+/// Reads the GUID of each chat (e.g. `iMessage;-;+15555550100`), keyed by chat ROWID
+pub fn chat_guids(db: &Connection) -> Result<HashMap<i32, String>> {
+    let mut statement = db.prepare(&format!("SELECT ROWID, guid FROM {}", CHAT))?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
@@ -0,0 +1,247 @@
+use crate::clean_message::CleanMessage;
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::word_frequency::Frequencies;
+use chrono::{DateTime, Duration, Local};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+
+/// A silence this long ends a conversation; whoever writes next starts a new one
+const CONVERSATION_GAP_HOURS: i64 = 6;
+
+/// Most words and emoji listed for each chat, and for all chats together
+pub const TOP_TERMS: usize = 10;
+
+/// A second message from the same sender this long after their first, with no reply in
+/// between, is a double text
+const DOUBLE_TEXT_GAP_MINUTES: i64 = 30;
+
+/// How a chat's conversations go: who starts them, how fast each person replies and how
+/// long the chat has gone quiet
+#[derive(Serialize)]
+pub struct ChatStats {
+    pub chat: String,
+    pub message_count: usize,
+    /// Runs of messages with no silence longer than six hours between them
+    pub conversation_count: usize,
+    pub longest_silence: Option<Silence>,
+    /// Most messages first
+    pub participants: Vec<ParticipantStats>,
+    /// Most used first, leaving out common English words
+    pub top_words: Vec<(String, usize)>,
+    pub top_emoji: Vec<(String, usize)>,
+}
+
+/// Every chat's stats, and the words and emoji used most across all of them
+#[derive(Serialize)]
+pub struct StatsReport {
+    pub top_words: Vec<(String, usize)>,
+    pub top_emoji: Vec<(String, usize)>,
+    /// Sorted by chat
+    pub chats: Vec<ChatStats>,
+}
+
+#[derive(Serialize)]
+pub struct ParticipantStats {
+    pub name: String,
+    pub is_me: bool,
+    pub message_count: usize,
+    /// Conversations whose first message they sent
+    pub conversations_started: usize,
+    /// Messages they sent right after someone else's in the same conversation
+    pub replies: usize,
+    /// Median time those replies took
+    pub median_reply_seconds: Option<i64>,
+    pub double_texts: usize,
+}
+
+/// The time between two messages with nothing in between
+#[derive(Serialize)]
+pub struct Silence {
+    pub seconds: i64,
+    #[serde(serialize_with = "rfc3339")]
+    pub from: DateTime<Local>,
+    #[serde(serialize_with = "rfc3339")]
+    pub to: DateTime<Local>,
+}
+
+fn rfc3339<S: Serializer>(date: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&date.to_rfc3339())
+}
+
+impl StatsReport {
+    pub fn new(messages: &[CleanMessage]) -> Self {
+        let grouped_messages = group_messages_by_chat(messages);
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+
+        let all = Frequencies::of(messages.iter().filter(|message| message.call.is_none()));
+        Self {
+            top_words: all.top_words(TOP_TERMS),
+            top_emoji: all.top_emoji(TOP_TERMS),
+            chats: chat_keys
+                .into_iter()
+                .map(|chat_key| ChatStats::new(chat_key, &grouped_messages[chat_key]))
+                .collect(),
+        }
+    }
+}
+
+/// Splits date-ordered messages into conversations at each silence of more than six
+/// hours
+pub fn conversations<'a, 'b>(
+    messages: &'a [&'b CleanMessage],
+) -> impl Iterator<Item = &'a [&'b CleanMessage]> {
+    let gap = Duration::hours(CONVERSATION_GAP_HOURS);
+    messages.chunk_by(move |a, b| b.date - a.date <= gap)
+}
+
+impl ChatStats {
+    /// Stats of one chat's date-ordered messages; calls aren't counted
+    pub fn new(chat_key: &str, chat_messages: &[&CleanMessage]) -> Self {
+        let messages: Vec<&CleanMessage> = chat_messages
+            .iter()
+            .filter(|message| message.call.is_none())
+            .copied()
+            .collect();
+
+        #[derive(Default)]
+        struct Tally {
+            is_me: bool,
+            message_count: usize,
+            conversations_started: usize,
+            reply_seconds: Vec<i64>,
+            double_texts: usize,
+        }
+        let mut tallies: HashMap<String, Tally> = HashMap::new();
+        let double_text_gap = Duration::minutes(DOUBLE_TEXT_GAP_MINUTES);
+
+        let mut conversation_count = 0;
+        for conversation in conversations(&messages) {
+            conversation_count += 1;
+            let starter = tallies.entry(conversation[0].from.to_string()).or_default();
+            starter.conversations_started += 1;
+
+            for (index, message) in conversation.iter().enumerate() {
+                let tally = tallies.entry(message.from.to_string()).or_default();
+                tally.is_me = message.from.is_me();
+                tally.message_count += 1;
+                let Some(previous) = index.checked_sub(1).map(|i| conversation[i]) else {
+                    continue;
+                };
+                if previous.from == message.from {
+                    if message.date - previous.date >= double_text_gap {
+                        tally.double_texts += 1;
+                    }
+                } else {
+                    tally
+                        .reply_seconds
+                        .push((message.date - previous.date).num_seconds());
+                }
+            }
+        }
+        // A double text can also come after a silence long enough to end a conversation
+        for pair in messages.windows(2) {
+            let gap = pair[1].date - pair[0].date;
+            if pair[0].from == pair[1].from
+                && gap > Duration::hours(CONVERSATION_GAP_HOURS)
+                && let Some(tally) = tallies.get_mut(&pair[1].from.to_string())
+            {
+                tally.double_texts += 1;
+            }
+        }
+
+        let longest_silence = messages
+            .windows(2)
+            .max_by_key(|pair| pair[1].date - pair[0].date)
+            .map(|pair| Silence {
+                seconds: (pair[1].date - pair[0].date).num_seconds(),
+                from: pair[0].date,
+                to: pair[1].date,
+            });
+
+        let mut participants: Vec<ParticipantStats> = tallies
+            .into_iter()
+            .map(|(name, mut tally)| ParticipantStats {
+                name,
+                is_me: tally.is_me,
+                message_count: tally.message_count,
+                conversations_started: tally.conversations_started,
+                replies: tally.reply_seconds.len(),
+                median_reply_seconds: median(&mut tally.reply_seconds),
+                double_texts: tally.double_texts,
+            })
+            .collect();
+        participants.sort_by(|a, b| {
+            b.message_count
+                .cmp(&a.message_count)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        let frequencies = Frequencies::of(messages.iter().copied());
+        Self {
+            chat: display_name(chat_key).to_string(),
+            message_count: messages.len(),
+            conversation_count,
+            longest_silence,
+            participants,
+            top_words: frequencies.top_words(TOP_TERMS),
+            top_emoji: frequencies.top_emoji(TOP_TERMS),
+        }
+    }
+}
+
+fn median(values: &mut [i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2
+    } else {
+        values[middle]
+    })
+}
+
+/// Words or emoji with their counts, e.g. `pizza (12), tonight (3)`
+pub fn format_terms(terms: &[(String, usize)]) -> String {
+    terms
+        .iter()
+        .map(|(term, count)| format!("{} ({})", term, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A length of time in its two largest units, e.g. `45 s`, `12 min`, `3 h 5 min` or `4 d 2 h`
+pub fn format_duration(seconds: i64) -> String {
+    let (days, hours, minutes) = (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{} s", seconds),
+        (0, 0, m) => format!("{} min", m),
+        (0, h, 0) => format!("{} h", h),
+        (0, h, m) => format!("{} h {} min", h, m),
+        (d, 0, _) => format!("{} d", d),
+        (d, h, _) => format!("{} d {} h", d, h),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [30, 10, 20]), Some(20));
+        assert_eq!(median(&mut [40, 10, 20, 30]), Some(25));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(45), "45 s");
+        assert_eq!(format_duration(12 * 60 + 5), "12 min");
+        assert_eq!(format_duration(3 * 3600 + 5 * 60), "3 h 5 min");
+        assert_eq!(format_duration(4 * 86_400 + 2 * 3600 + 59), "4 d 2 h");
+        assert_eq!(format_duration(2 * 86_400), "2 d");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::attachment_metadata::image_dimensions;
+use crate::database_source::AttachmentFile;
+use anyhow::Result;
+use imessage_database::tables::attachment::{Attachment, MediaType};
+use std::fs::{self, File};
+use std::io::Read;
+
+/// Bytes read from the start of an image to find its dimensions, enough for the JPEG
+/// EXIF and thumbnail segments that come before the frame header
+const HEADER_BYTES: u64 = 128 * 1024;
+
+/// What an attachment's MIME type says it is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+    Other,
+}
+
+/// An attachment's metadata and file, read when its message is collected, so output
+/// formats don't go back to the database
+#[derive(Clone, Debug)]
+pub struct CleanAttachment {
+    pub rowid: i32,
+    /// Name the attachment was sent with, e.g. `IMG_0001.HEIC`; `None` when it has no file
+    pub filename: Option<String>,
+    pub mime_type: Option<String>,
+    pub kind: MediaKind,
+    /// Size recorded in the database, else the file's size; 0 when neither is known
+    pub size: u64,
+    /// Width and height in pixels of a JPEG or PNG image whose file is on this computer
+    pub dimensions: Option<(u32, u32)>,
+    pub is_sticker: bool,
+    /// Path recorded in the database, e.g. `~/Library/Messages/Attachments/...`
+    pub source_path: Option<String>,
+    /// Where the file is on this computer; `None` when the database has no file for it
+    pub file: Option<AttachmentFile>,
+}
+
+impl CleanAttachment {
+    /// `attachment`, whose file is `file`
+    pub fn new(attachment: Attachment, file: Option<AttachmentFile>) -> Self {
+        let kind = match attachment.mime_type() {
+            MediaType::Image(_) => MediaKind::Image,
+            MediaType::Video(_) => MediaKind::Video,
+            MediaType::Audio(_) => MediaKind::Audio,
+            _ => MediaKind::Other,
+        };
+        let size = if attachment.total_bytes > 0 {
+            attachment.total_bytes as u64
+        } else {
+            file.as_ref()
+                .and_then(|file| fs::metadata(&file.path).ok())
+                .map_or(0, |metadata| metadata.len())
+        };
+        let dimensions = match &file {
+            Some(file) if kind == MediaKind::Image => read_dimensions(file),
+            _ => None,
+        };
+
+        Self {
+            rowid: attachment.rowid,
+            filename: attachment.filename().map(str::to_string),
+            kind,
+            size,
+            dimensions,
+            is_sticker: attachment.is_sticker,
+            source_path: attachment.filename,
+            mime_type: attachment.mime_type,
+            file,
+        }
+    }
+
+    /// Contents of the attachment's file, or `None` when it has no file or the file isn't
+    /// on this computer (e.g. offloaded to iCloud, or a database copied without its
+    /// attachments)
+    pub fn read(&self) -> Result<Option<Vec<u8>>> {
+        match &self.file {
+            Some(file) => file.read(),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the attachment's file is on this computer
+    pub fn is_on_disk(&self) -> bool {
+        self.file.as_ref().is_some_and(|file| file.path.exists())
+    }
+}
+
+/// Dimensions from the start of an image's file. Files in an encrypted backup are left
+/// alone, since they'd have to be decrypted whole.
+fn read_dimensions(file: &AttachmentFile) -> Option<(u32, u32)> {
+    if file.key.is_some() {
+        return None;
+    }
+    let mut header = Vec::new();
+    File::open(&file.path)
+        .ok()?
+        .take(HEADER_BYTES)
+        .read_to_end(&mut header)
+        .ok()?;
+    image_dimensions(&header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_source::{DatabaseSource, SourcePlatform};
+    use crate::fixture;
+    use imessage_database::tables::messages::Message;
+    use imessage_database::tables::table::Table;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_fixture_photo() {
+        let directory = std::env::temp_dir().join(format!(
+            "imessage_extractor-{}-clean-attachment-test",
+            std::process::id()
+        ));
+        let fixture = fixture::generate(&directory).unwrap();
+        let db = Connection::open(&fixture.database).unwrap();
+        let source = DatabaseSource::new(
+            fixture.database.clone(),
+            SourcePlatform::MacOs,
+            Some(fixture.attachment_root.display().to_string()),
+        );
+
+        let mut attachments = Vec::new();
+        Message::stream(&db, |message| {
+            let message = message.unwrap();
+            if message.has_attachments() {
+                attachments.extend(Attachment::from_message(&db, &message).unwrap());
+            }
+            Ok::<(), imessage_database::error::table::TableError>(())
+        })
+        .unwrap();
+        let attachment = attachments.pop().unwrap();
+        let file = source.attachment_file(&attachment).unwrap();
+        let photo = CleanAttachment::new(attachment, file);
+
+        assert_eq!(photo.filename.as_deref(), Some("summit.png"));
+        assert_eq!(photo.kind, MediaKind::Image);
+        assert_eq!(photo.dimensions, Some((4, 4)));
+        assert!(photo.is_on_disk());
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -1,79 +1,286 @@
-use super::contacts::ContactMap;
+use super::app_balloon::AppBalloon;
+use super::attachment_filter::SkippedAttachment;
+use super::attachment_timestamp;
+use super::call_history::CallRecord;
+use super::chat_rename::ChatRenames;
+use super::chat_selector::ChatSelector;
+use super::clean_attachment::CleanAttachment;
+use super::contacts::{ContactMap, normalize_number};
+use super::database_source::DatabaseSource;
+use super::date_range::DateRange;
+use super::error_log::ErrorLog;
+use super::message_flags::MessageFlags;
+use super::message_service::MessageService;
 use super::resolved_handle::ResolvedHandle;
+use super::send_effect::SendEffect;
 use super::tapback_emoji::TapbackEmoji;
+use super::text_fallback::{self, TextFallback};
+use super::text_span::TextSpan;
 use anyhow::{Result, anyhow};
-use chrono::{DateTime, Local, NaiveDate};
-use imessage_database::message_types::variants::{Tapback, TapbackAction};
+use chrono::{DateTime, Local};
+use imessage_database::error::message::MessageError;
+use imessage_database::message_types::variants::TapbackAction;
 use imessage_database::tables::attachment::Attachment;
 use imessage_database::tables::messages::Message;
+use phonenumber::country;
 use rusqlite::Connection;
 use std::collections::HashMap;
 
 pub struct CleanMessage {
     pub guid: String,
     pub text: String,
+    /// Formatted runs of `text`; empty when the message has no attributed text
+    pub text_spans: Vec<TextSpan>,
+    /// Set when the message had no readable text and `text` came from a fallback
+    pub text_fallback: Option<TextFallback>,
     pub from: ResolvedHandle,
+    /// `from` was changed to me by `--repair-sender-heuristics`
+    pub sender_repaired: bool,
     pub chat_id: Option<i32>,
+    /// GUID of the chat, e.g. `iMessage;-;+15555550100`
+    pub chat_guid: Option<String>,
     pub chat_name: Option<String>,
+    /// Display and sort date: the sent time, or the time `--timestamp-source` picks
     pub date: DateTime<Local>,
+    pub date_sent: DateTime<Local>,
+    pub date_delivered: Option<DateTime<Local>>,
+    pub date_read: Option<DateTime<Local>>,
+    /// Read, delivered, downgraded and reply flags, set after the message is cleaned
+    pub flags: MessageFlags,
+    pub service: MessageService,
+    pub send_effect: Option<SendEffect>,
+    /// Card content for location shares and other app messages
+    pub balloon: Option<AppBalloon>,
+    /// The message is in "Recently Deleted" rather than its chat
+    pub deleted: bool,
+    pub deleted_at: Option<DateTime<Local>>,
+    /// The sender unsent (retracted) this message
+    pub unsent: bool,
+    /// Last known text of an unsent message, when the edit history still has it
+    pub unsent_text: Option<String>,
+    /// Set when this entry is a FaceTime or phone call rather than a message
+    pub call: Option<CallRecord>,
     pub tapbacks: HashMap<ResolvedHandle, TapbackEmoji>,
-    pub attachments: Vec<Attachment>,
+    pub attachments: Vec<CleanAttachment>,
+    /// Attachments left out by `--max-attachment-size` or `--skip-videos`
+    pub skipped_attachments: Vec<SkippedAttachment>,
+    /// Creation date of each attachment recorded in the database, keyed by attachment ROWID
+    pub attachment_created: HashMap<i32, DateTime<Local>>,
+    /// Text `--ocr` found in image attachments, keyed by attachment ROWID
+    pub recognized_text: HashMap<i32, String>,
+    /// Speech `--transcribe-videos` found in video attachments, keyed by attachment ROWID
+    pub transcripts: HashMap<i32, String>,
 }
 
 impl CleanMessage {
+    /// Fails when the message can't be dated or its attachments can't be looked up in
+    /// the database or `source`. Text that can't be decoded is recorded in `errors` and
+    /// the message is kept without it.
     pub fn from_message(
         db: &Connection,
+        source: &DatabaseSource,
         handle_cache: &HashMap<i32, String>,
         contact_map: &ContactMap,
+        deletion_dates: &HashMap<i32, DateTime<Local>>,
         chat_name: Option<String>,
         mut message: Message,
+        errors: &mut ErrorLog,
     ) -> Result<Self> {
         let database_tz_offset = imessage_database::util::dates::get_offset();
 
-        // TODO: is this really a result that needs to be checked?
-        let _: Result<_, _> = message.generate_text(db);
-
-        let best_date = if message.date_delivered != 0 {
-            message
-                .date_delivered(&database_tz_offset)
-                .expect("unable to calculate date_delivered")
-        } else if message.date_read != 0 {
-            message
-                .date_read(&database_tz_offset)
-                .expect("unable to calculate date_read")
+        // Messages without any text (attachments, unsent messages) are expected
+        match message.generate_text(db) {
+            Ok(_) | Err(MessageError::NoText) => {}
+            Err(e) => {
+                let error = anyhow!("Unable to decode text: {}", e);
+                // In strict mode the caller records the failure against this message
+                if errors.is_strict() {
+                    return Err(error);
+                }
+                errors.record(format!("message {}", message.guid), error)?;
+            }
+        }
+
+        let date_sent = message
+            .date(&database_tz_offset)
+            .map_err(|e| anyhow!("Unable to calculate date written: {}", e))?;
+        let date_delivered = if message.date_delivered != 0 {
+            Some(
+                message
+                    .date_delivered(&database_tz_offset)
+                    .map_err(|e| anyhow!("Unable to calculate date delivered: {}", e))?,
+            )
+        } else {
+            None
+        };
+        let date_read = if message.date_read != 0 {
+            Some(
+                message
+                    .date_read(&database_tz_offset)
+                    .map_err(|e| anyhow!("Unable to calculate date read: {}", e))?,
+            )
         } else {
-            message
-                .date(&database_tz_offset)
-                .expect("unable to calculate date written")
+            None
         };
 
         let from = ResolvedHandle::from_message_sender(&message, handle_cache, contact_map);
+        let mut text = message.text.as_deref().unwrap_or_default().to_owned();
+        let mut text_spans = TextSpan::from_components(
+            message.text.as_deref().unwrap_or_default(),
+            &message.components,
+            contact_map,
+        );
+        let service = MessageService::from_message_service(message.service());
+        let send_effect = SendEffect::from_expressive(message.get_expressive());
+
+        let balloon = AppBalloon::from_message(db, &message);
+
+        let deleted = message.is_deleted();
+        let deleted_at = deletion_dates.get(&message.rowid).copied();
+
+        let unsent = message.is_fully_unsent();
+        let unsent_text = if unsent {
+            message.edited_parts.as_ref().and_then(|edited| {
+                let texts: Vec<&str> = edited
+                    .parts
+                    .iter()
+                    .filter_map(|part| part.edit_history.last()?.text.as_deref())
+                    .collect();
+                (!texts.is_empty()).then(|| texts.join("\n"))
+            })
+        } else {
+            None
+        };
 
         let attachments = if message.has_attachments() {
-            Attachment::from_message(db, &message).map_err(|e| anyhow!(format!("{}", e)))?
+            Attachment::from_message(db, &message)
+                .map_err(|e| anyhow!("Unable to look up attachments: {}", e))?
         } else {
             Vec::new()
         };
+        let attachment_created = attachment_timestamp::created_dates(db, &attachments)
+            .map_err(|e| anyhow!("Unable to look up attachments: {}", e))?;
+        let attachments = attachments
+            .into_iter()
+            .map(|attachment| {
+                let file = source.attachment_file(&attachment)?;
+                Ok(CleanAttachment::new(attachment, file))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // A plain message that would otherwise be an empty bubble
+        let text_fallback = if text_fallback::is_blank(&text)
+            && attachments.is_empty()
+            && balloon.is_none()
+            && !unsent
+            && message.item_type == 0
+        {
+            let (fallback, source) = text_fallback::fallback_text(db, &message);
+            text = fallback;
+            text_spans = Vec::new();
+            Some(source)
+        } else {
+            None
+        };
 
         Ok(Self {
             guid: message.guid,
-            text: message.text.as_deref().unwrap_or_default().to_owned(),
+            text,
+            text_spans,
+            text_fallback,
             from,
+            sender_repaired: false,
             chat_id: message.chat_id,
-            date: best_date,
+            chat_guid: None,
+            date: date_sent,
+            date_sent,
+            date_delivered,
+            date_read,
+            flags: MessageFlags::default(),
+            service,
+            send_effect,
+            balloon,
+            deleted,
+            deleted_at,
+            unsent,
+            unsent_text,
+            call: None,
             chat_name,
             tapbacks: HashMap::new(),
             attachments,
+            skipped_attachments: Vec::new(),
+            attachment_created,
+            recognized_text: HashMap::new(),
+            transcripts: HashMap::new(),
         })
     }
 
+    /// Builds a timeline entry for a call, named after the direct chat with the other party
+    /// and renamed as that chat is by `renames`
+    pub fn from_call(
+        contact_map: &ContactMap,
+        renames: &ChatRenames,
+        default_region: country::Id,
+        call: CallRecord,
+    ) -> Self {
+        let identifier = if call.address.contains('@') {
+            call.address.to_lowercase()
+        } else {
+            normalize_number(&call.address, default_region).unwrap_or(call.address.clone())
+        };
+        let contact_name = contact_map
+            .get(&identifier)
+            .unwrap_or_else(|| identifier.clone());
+        let chat_name = renames
+            .get(&identifier, &contact_name)
+            .map_or(contact_name.clone(), str::to_string);
+
+        Self {
+            guid: format!("call-{}", call.id),
+            text: String::new(),
+            text_spans: Vec::new(),
+            text_fallback: None,
+            from: ResolvedHandle::from_call(call.outgoing, &contact_name, &identifier),
+            sender_repaired: false,
+            chat_id: None,
+            chat_guid: None,
+            chat_name: Some(chat_name),
+            date: call.date,
+            date_sent: call.date,
+            date_delivered: None,
+            date_read: None,
+            flags: MessageFlags::default(),
+            service: MessageService::Unknown,
+            send_effect: None,
+            balloon: None,
+            deleted: false,
+            deleted_at: None,
+            unsent: false,
+            unsent_text: None,
+            call: Some(call),
+            tapbacks: HashMap::new(),
+            attachments: Vec::new(),
+            skipped_attachments: Vec::new(),
+            attachment_created: HashMap::new(),
+            recognized_text: HashMap::new(),
+            transcripts: HashMap::new(),
+        }
+    }
+
+    /// Number of this message's attachments whose files exist on this computer
+    pub fn attachments_on_disk(&self) -> usize {
+        self.attachments
+            .iter()
+            .filter(|attachment| attachment.is_on_disk())
+            .count()
+    }
+
     pub fn tapback(
         &mut self,
         tapback_action: TapbackAction,
         tapback_handle: ResolvedHandle,
-        tapback: Tapback,
+        tapback_emoji: TapbackEmoji,
     ) {
-        let tapback_emoji = TapbackEmoji::from_message_tapback(tapback);
         match tapback_action {
             TapbackAction::Added => {
                 let _ = self.tapbacks.insert(tapback_handle, tapback_emoji);
@@ -84,30 +291,29 @@ impl CleanMessage {
         }
     }
 
+    /// Whether the message @-mentions me
+    pub fn mentions_me(&self) -> bool {
+        self.text_spans.iter().any(|span| span.mentions_me)
+    }
+
+    /// A message someone else sent that hasn't been read on any of my devices
+    pub fn is_unread(&self) -> bool {
+        !self.from.is_me() && self.call.is_none() && !self.flags.is_read
+    }
+
     pub fn matches(
         &self,
-        on_or_after: &Option<NaiveDate>,
-        before: &Option<NaiveDate>,
-        chat_names: &[String],
+        dates: &DateRange,
+        chats: &ChatSelector,
+        services: &[MessageService],
     ) -> bool {
-        if let Some(on_or_after) = on_or_after
-            && self.date.date_naive() < *on_or_after
-        {
+        if !dates.contains(&self.date) {
             return false;
         }
-        if let Some(before) = before
-            && self.date.date_naive() >= *before
-        {
+        if !services.is_empty() && !services.contains(&self.service) {
             return false;
         }
-        if chat_names.is_empty() {
-            true
-        } else {
-            match &self.chat_name {
-                None => false,
-                Some(message_chat_name) => chat_names.contains(message_chat_name),
-            }
-        }
+        chats.selects(self)
     }
 }
 
@@ -117,7 +323,7 @@ impl std::fmt::Display for CleanMessage {
         if !self.attachments.is_empty() {
             writeln!(f, "Attachments:")?;
             for attachment in &self.attachments {
-                writeln!(f, "  {:?}", attachment.filename())?
+                writeln!(f, "  {:?}", attachment.filename)?
             }
         }
         if !self.tapbacks.is_empty() {
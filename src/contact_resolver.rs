@@ -0,0 +1,24 @@
+/// A source of display names for handle identifiers. Identifiers are given as iMessage
+/// stores them: phone numbers in E.164 form (`+15555550100`) and email addresses.
+///
+/// Resolvers are composed into a `ContactMap`, which asks each in priority order. The
+/// exporter uses `--aliases`, then `--vcards`, then Contacts; embedders with their own
+/// people database can implement this trait and put their resolver wherever it belongs.
+pub trait ContactResolver: Send + Sync {
+    /// Display name for `identifier`, or `None` to let the next resolver answer
+    fn name(&self, identifier: &str) -> Option<String>;
+
+    /// Identifiers this resolver knows are the user's own, e.g. from the "Me" card
+    fn my_handles(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Resolves nothing, leaving every handle shown as its phone number or email
+pub struct NoopResolver;
+
+impl ContactResolver for NoopResolver {
+    fn name(&self, _identifier: &str) -> Option<String> {
+        None
+    }
+}
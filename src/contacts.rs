@@ -1,11 +1,24 @@
+use super::contact_resolver::{ContactResolver, NoopResolver};
+use super::error::ExportError;
+use super::vcard::VCard;
 use anyhow::{Context, Result};
+use phonenumber::{Mode, country};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::process::{Command, Stdio};
+use std::sync::Arc;
 
 const SWIFT_SCRIPT: &str = include_str!("../contacts_helper.swift");
 
+/// Exit code of the helper when Contacts access has been denied
+const CONTACTS_ACCESS_DENIED_EXIT_CODE: i32 = 2;
+
+/// Fewest digits a string can have and still be treated as a phone number
+const MIN_PHONE_NUMBER_DIGITS: usize = 7;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Contact {
@@ -13,6 +26,9 @@ pub struct Contact {
     pub family_name: String,
     pub phone_numbers: Vec<String>,
     pub email_addresses: Vec<String>,
+    /// This is the "Me" card, the user's own contact
+    #[serde(default)]
+    pub is_me: bool,
 }
 
 impl Contact {
@@ -23,60 +39,122 @@ impl Contact {
     }
 }
 
-pub struct ContactMap(HashMap<String, String>);
+/// Names for a fixed set of identifiers, read from Contacts, an alias file or vCards
+#[derive(Default)]
+pub struct NameTable {
+    names: HashMap<String, String>,
+    /// Identifiers on the "Me" card
+    mine: HashSet<String>,
+}
+
+/// Every contact resolver in priority order, plus the user's own handles
+#[derive(Clone)]
+pub struct ContactMap {
+    resolvers: Vec<Arc<dyn ContactResolver>>,
+    /// The user's own phone numbers and emails, from the resolvers, `--my-handle` and the
+    /// database's accounts
+    mine: HashSet<String>,
+}
 
-/// Normalizes a phone number to E.164 format (+1XXXXXXXXXX for US numbers)
+/// Normalizes a phone number to E.164 format, the form iMessage uses for handle identifiers
 ///
-/// Takes numbers in various formats like:
+/// Numbers written with a leading `+` or international prefix keep their own country code;
+/// anything else is interpreted as a national number in `default_region`. With a default
+/// region of `US`, numbers in formats like:
 /// - 555-555-0100
 /// - (555) 555-0101
-/// - (555) 555-0102
+/// - 1 (555) 555-0102
 /// - 555 555 0103
 ///
-/// And converts them to:
-/// - +15555550107
+/// Are converted to:
+/// - +15555550100
 /// - +15555550101
 /// - +15555550102
 /// - +15555550103
-pub fn normalize_number(number: &str) -> Option<String> {
-    // Strip all non-numeric characters
-    let digits: String = number.chars().filter(|c| c.is_ascii_digit()).collect();
+///
+/// Short codes and anything that can't be parsed as a phone number yield `None`.
+pub fn normalize_number(number: &str, default_region: country::Id) -> Option<String> {
+    let digit_count = number.chars().filter(|c| c.is_ascii_digit()).count();
 
-    // Handle empty or too short numbers
-    if digits.is_empty() {
+    // Short codes (e.g. 5-6 digit SMS senders) aren't phone numbers
+    if digit_count < MIN_PHONE_NUMBER_DIGITS {
         return None;
     }
 
-    // Handle different length cases
-    let normalized = match digits.len() {
-        // 10 digits - assume US number, add +1
-        10 => format!("+1{}", digits),
+    let parsed = phonenumber::parse(Some(default_region), number).ok()?;
+    Some(parsed.format().mode(Mode::E164).to_string())
+}
 
-        // 11 digits starting with 1 - US number with country code
-        11 if digits.starts_with('1') => format!("+{}", digits),
+/// Normalizes a handle identifier given by the user (in an alias file or `--my-handle`) so
+/// it matches the keys used for Contacts entries.
+///
+/// Email addresses and identifiers that don't look like phone numbers (short codes,
+/// business ids) are kept as given.
+pub fn normalize_identifier(identifier: &str, default_region: country::Id) -> String {
+    if identifier.contains('@') {
+        identifier.to_string()
+    } else {
+        normalize_number(identifier, default_region).unwrap_or_else(|| identifier.to_string())
+    }
+}
 
-        // 11 digits not starting with 1 - might be international, add +
-        11 => format!("+{}", digits),
+fn parse_aliases_csv(
+    contents: &str,
+    default_region: country::Id,
+) -> Result<HashMap<String, String>> {
+    let mut aliases = HashMap::new();
 
-        // 12+ digits - international number, add +
-        12.. => format!("+{}", digits),
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
 
-        // Less than 10 digits - could be short code or invalid
-        _ => return None,
-    };
+        let (identifier, name) = line.split_once(',').with_context(|| {
+            format!(
+                "Alias file line {} is not in identifier,name form",
+                index + 1
+            )
+        })?;
+        let identifier = identifier.trim().trim_matches('"');
+        let name = name.trim().trim_matches('"');
+
+        aliases.insert(
+            normalize_identifier(identifier, default_region),
+            name.to_string(),
+        );
+    }
 
-    Some(normalized)
+    Ok(aliases)
 }
 
-impl ContactMap {
-    pub fn fetch() -> Result<Self> {
+fn parse_aliases_toml(
+    contents: &str,
+    default_region: country::Id,
+) -> Result<HashMap<String, String>> {
+    let raw: HashMap<String, String> =
+        toml::from_str(contents).context("Failed to parse alias TOML")?;
+
+    Ok(raw
+        .into_iter()
+        .map(|(identifier, name)| (normalize_identifier(&identifier, default_region), name))
+        .collect())
+}
+
+impl NameTable {
+    /// Fetches all contacts, normalizing phone numbers without a country code as
+    /// numbers in `default_region`.
+    pub fn fetch(default_region: country::Id) -> Result<Self> {
         let mut child = Command::new("swift")
             .arg("-")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
-            .context("Failed to spawn swift command")?;
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => anyhow::Error::new(ExportError::SwiftNotFound),
+                _ => anyhow::Error::new(e).context("Failed to spawn swift command"),
+            })?;
 
         if let Some(mut stdin) = child.stdin.take() {
             stdin
@@ -88,6 +166,9 @@ impl ContactMap {
             .wait_with_output()
             .context("Failed to wait for swift command")?;
 
+        if output.status.code() == Some(CONTACTS_ACCESS_DENIED_EXIT_CODE) {
+            return Err(ExportError::ContactsUnavailable.into());
+        }
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
             anyhow::bail!("Contacts helper failed: {}", stderr);
@@ -104,33 +185,152 @@ impl ContactMap {
             contact.phone_numbers = contact
                 .phone_numbers
                 .iter()
-                .filter_map(|num| normalize_number(num))
+                .filter_map(|num| normalize_number(num, default_region))
                 .collect();
         }
 
-        let mut contact_map = HashMap::new();
+        let mut table = NameTable::default();
         for contact in contacts {
             let full_name = contact.full_name();
 
+            if contact.is_me {
+                table.mine.extend(
+                    contact
+                        .phone_numbers
+                        .iter()
+                        .chain(&contact.email_addresses)
+                        .cloned(),
+                );
+            }
+
             for phone_number in contact.phone_numbers {
-                contact_map.insert(phone_number, full_name.clone());
+                table.names.insert(phone_number, full_name.clone());
             }
 
             for email_address in contact.email_addresses {
-                contact_map.insert(email_address, full_name.clone());
+                table.names.insert(email_address, full_name.clone());
             }
         }
 
-        Ok(ContactMap(contact_map))
+        Ok(table)
     }
 
-    pub fn get(&self, identifier: &str) -> Option<&String> {
-        self.0.get(identifier)
+    /// Loads a user-provided alias file mapping handle identifiers to display names.
+    ///
+    /// Files ending in `.toml` are read as a table of `"identifier" = "Name"` pairs.
+    /// Anything else is treated as CSV with one `identifier,name` pair per line;
+    /// blank lines and lines starting with `#` are ignored.
+    pub fn load_aliases(path: &Path, default_region: country::Id) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read alias file {}", path.display()))?;
+
+        let is_toml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+
+        let names = if is_toml {
+            parse_aliases_toml(&contents, default_region)?
+        } else {
+            parse_aliases_csv(&contents, default_region)?
+        };
+        Ok(NameTable {
+            names,
+            mine: HashSet::new(),
+        })
     }
 
-    #[allow(unused)]
-    pub fn len(&self) -> usize {
-        self.0.len()
+    /// Loads the cards in a `.vcf` file, or in every `.vcf` file in a directory, such as
+    /// contacts exported from Contacts or another address book
+    pub fn load_vcards(path: &Path, default_region: country::Id) -> Result<Self> {
+        let files = if path.is_dir() {
+            let mut files: Vec<_> = fs::read_dir(path)
+                .with_context(|| format!("Failed to read vCard directory {}", path.display()))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| VCard::is_vcard(&file.to_string_lossy()))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let mut table = NameTable::default();
+        for file in files {
+            let contents = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read vCard file {}", file.display()))?;
+            for card in VCard::parse_all(&contents) {
+                let Some(name) = card.name.or(card.organization) else {
+                    continue;
+                };
+                for phone in &card.phones {
+                    if let Some(number) = normalize_number(phone, default_region) {
+                        table.names.insert(number, name.clone());
+                    }
+                }
+                for email in card.emails {
+                    table.names.insert(email, name.clone());
+                }
+            }
+        }
+        Ok(table)
+    }
+}
+
+impl ContactResolver for NameTable {
+    fn name(&self, identifier: &str) -> Option<String> {
+        self.names.get(identifier).cloned()
+    }
+
+    fn my_handles(&self) -> Vec<String> {
+        self.mine.iter().cloned().collect()
+    }
+}
+
+impl ContactMap {
+    /// Asks `resolvers` for names in order, the first to know a handle winning
+    pub fn new(resolvers: Vec<Arc<dyn ContactResolver>>) -> Self {
+        let mut contact_map = ContactMap {
+            resolvers,
+            mine: HashSet::new(),
+        };
+        let my_handles: Vec<String> = contact_map
+            .resolvers
+            .iter()
+            .flat_map(|resolver| resolver.my_handles())
+            .collect();
+        contact_map.add_my_handles(my_handles);
+        contact_map
+    }
+
+    /// A map that resolves no names, for when no contact source can or should be read
+    pub fn empty() -> Self {
+        ContactMap::new(vec![Arc::new(NoopResolver)])
+    }
+
+    /// Marks identifiers as the user's own, so messages sent from them are shown as from
+    /// "Me" even when the database doesn't flag them as sent
+    pub fn add_my_handles(&mut self, identifiers: impl IntoIterator<Item = String>) {
+        self.mine.extend(
+            identifiers
+                .into_iter()
+                .map(|identifier| identifier.to_lowercase()),
+        );
+    }
+
+    /// Asks `resolver` after all the others
+    pub fn add_resolver(&mut self, resolver: Arc<dyn ContactResolver>) {
+        self.add_my_handles(resolver.my_handles());
+        self.resolvers.push(resolver);
+    }
+
+    pub fn is_me(&self, identifier: &str) -> bool {
+        self.mine.contains(&identifier.to_lowercase())
+    }
+
+    pub fn get(&self, identifier: &str) -> Option<String> {
+        self.resolvers
+            .iter()
+            .find_map(|resolver| resolver.name(identifier))
     }
 }
 
@@ -138,71 +338,158 @@ impl ContactMap {
 mod tests {
     use super::*;
 
+    // Contacts are only read on macOS
+    #[cfg(target_os = "macos")]
     #[test]
     fn test_fetch() {
-        let contacts = ContactMap::fetch().expect("Failed to fetch contacts");
+        let contacts = NameTable::fetch(country::Id::US).expect("Failed to fetch contacts");
 
         // Verify we got a reasonable number of contacts
         assert!(
-            contacts.len() > 0,
+            !contacts.names.is_empty(),
             "Should have at least one contact, got {}",
-            contacts.len()
+            contacts.names.len()
         );
     }
 
+    #[test]
+    fn test_parse_aliases_csv() {
+        let aliases = parse_aliases_csv(
+            "# old numbers\n(555) 555-0110, Old Work Phone\n\nfriend@example.com,\"Pat\"\n12345,Pharmacy\n",
+            country::Id::US,
+        )
+        .expect("Failed to parse aliases");
+
+        assert_eq!(
+            aliases.get("+15555550110"),
+            Some(&"Old Work Phone".to_string())
+        );
+        assert_eq!(aliases.get("friend@example.com"), Some(&"Pat".to_string()));
+        assert_eq!(aliases.get("12345"), Some(&"Pharmacy".to_string()));
+
+        assert!(parse_aliases_csv("missing a comma", country::Id::US).is_err());
+    }
+
+    #[test]
+    fn test_parse_aliases_toml() {
+        let aliases = parse_aliases_toml("\"555-555-0111\" = \"Dentist\"\n", country::Id::US)
+            .expect("Failed to parse aliases");
+
+        assert_eq!(aliases.get("+15555550111"), Some(&"Dentist".to_string()));
+    }
+
+    #[test]
+    fn test_aliases_override_contacts() {
+        let mut contacts = NameTable::default();
+        contacts
+            .names
+            .insert("+15555550112".to_string(), "Contacts Name".to_string());
+        contacts
+            .names
+            .insert("+15555550116".to_string(), "Only In Contacts".to_string());
+        let mut aliases = NameTable::default();
+        aliases
+            .names
+            .insert("+15555550112".to_string(), "Alias Name".to_string());
+        let contacts = ContactMap::new(vec![Arc::new(aliases), Arc::new(contacts)]);
+
+        assert_eq!(contacts.get("+15555550112"), Some("Alias Name".to_string()));
+        assert_eq!(
+            contacts.get("+15555550116"),
+            Some("Only In Contacts".to_string())
+        );
+        assert_eq!(contacts.get("+15555550117"), None);
+    }
+
+    #[test]
+    fn test_my_handles_ignore_email_case() {
+        let mut contacts = ContactMap::empty();
+        contacts.add_my_handles(["Me@Example.com".to_string(), "+15555550114".to_string()]);
+
+        assert!(contacts.is_me("me@example.com"));
+        assert!(contacts.is_me("+15555550114"));
+        assert!(!contacts.is_me("+15555550115"));
+    }
+
     #[test]
     fn test_normalize_number() {
         // Test various input formats with fictional 555 numbers (555-01xx series reserved for testing)
         assert_eq!(
-            normalize_number("555-555-0100"),
+            normalize_number("555-555-0100", country::Id::US),
             Some("+15555550100".to_string())
         );
         assert_eq!(
-            normalize_number("(555) 555-0101"),
+            normalize_number("(555) 555-0101", country::Id::US),
             Some("+15555550101".to_string())
         );
         assert_eq!(
-            normalize_number("(555) 555-0102"),
+            normalize_number("(555) 555-0102", country::Id::US),
             Some("+15555550102".to_string())
         );
         assert_eq!(
-            normalize_number("555 555 0103"),
+            normalize_number("555 555 0103", country::Id::US),
             Some("+15555550103".to_string())
         );
 
         // Test 11-digit number with leading 1
         assert_eq!(
-            normalize_number("15555550104"),
+            normalize_number("15555550104", country::Id::US),
             Some("+15555550104".to_string())
         );
         assert_eq!(
-            normalize_number("1 (555) 555-0105"),
+            normalize_number("1 (555) 555-0105", country::Id::US),
             Some("+15555550105".to_string())
         );
         assert_eq!(
-            normalize_number("+15555550106"),
+            normalize_number("+15555550106", country::Id::US),
             Some("+15555550106".to_string())
         );
 
         // Test already normalized number
         assert_eq!(
-            normalize_number("+15555550107"),
+            normalize_number("+15555550107", country::Id::US),
             Some("+15555550107".to_string())
         );
 
         // Test edge cases
-        assert_eq!(normalize_number(""), None); // Empty string
-        assert_eq!(normalize_number("123"), None); // Too short
-        assert_eq!(normalize_number("abc-def-ghij"), None); // No digits
+        assert_eq!(normalize_number("", country::Id::US), None); // Empty string
+        assert_eq!(normalize_number("123", country::Id::US), None); // Too short
+        assert_eq!(normalize_number("abc-def-ghij", country::Id::US), None); // No digits
 
         // Test with extra characters
         assert_eq!(
-            normalize_number("+1 (555) 555-0108"),
+            normalize_number("+1 (555) 555-0108", country::Id::US),
             Some("+15555550108".to_string())
         );
         assert_eq!(
-            normalize_number("1-555-555-0109"),
+            normalize_number("1-555-555-0109", country::Id::US),
             Some("+15555550109".to_string())
         );
     }
+
+    #[test]
+    fn test_normalize_number_international() {
+        // National numbers are read in the default region
+        assert_eq!(
+            normalize_number("020 7946 0018", country::Id::GB),
+            Some("+442079460018".to_string())
+        );
+        assert_eq!(
+            normalize_number("030 123456789", country::Id::DE),
+            Some("+4930123456789".to_string())
+        );
+
+        // An explicit country code wins over the default region
+        assert_eq!(
+            normalize_number("+44 20 7946 0018", country::Id::US),
+            Some("+442079460018".to_string())
+        );
+        assert_eq!(
+            normalize_number("+1 (555) 555-0113", country::Id::GB),
+            Some("+15555550113".to_string())
+        );
+
+        // Short codes are not phone numbers in any region
+        assert_eq!(normalize_number("12345", country::Id::GB), None);
+    }
 }
@@ -0,0 +1,76 @@
+use crate::clean_message::CleanMessage;
+use crate::message_store::group_messages_by_chat;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// `--grep`, `--grep-not` and `--guids`: keeps the messages whose text matches any of
+/// `matching` (every message when there are none) and none of `not_matching`, and whose
+/// GUID is one of `guids` when given, along with the `context` messages before and after
+/// each in its chat, so a match reads like an excerpt of the conversation
+#[derive(Debug, Default)]
+pub struct ContentFilter {
+    pub matching: Vec<Regex>,
+    pub not_matching: Vec<Regex>,
+    pub guids: Option<HashSet<String>>,
+    pub context: usize,
+}
+
+/// Reads a `--guids` file: one message GUID per line, ignoring blank lines and lines
+/// starting with `#`
+pub fn read_guids(path: &Path) -> Result<HashSet<String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+impl ContentFilter {
+    pub fn is_empty(&self) -> bool {
+        self.matching.is_empty() && self.not_matching.is_empty() && self.guids.is_none()
+    }
+
+    fn is_match(&self, message: &CleanMessage) -> bool {
+        self.guids
+            .as_ref()
+            .is_none_or(|guids| guids.contains(&message.guid))
+            && (self.matching.is_empty()
+                || self.matching.iter().any(|re| re.is_match(&message.text)))
+            && !self
+                .not_matching
+                .iter()
+                .any(|re| re.is_match(&message.text))
+    }
+
+    /// Filters the date-ordered `messages`. Context is only added around `--grep` and
+    /// `--guids` matches, so `--grep-not` alone can't bring back what it left out.
+    pub fn apply(&self, mut messages: Vec<CleanMessage>) -> Vec<CleanMessage> {
+        if self.is_empty() {
+            return messages;
+        }
+        let context = if self.matching.is_empty() && self.guids.is_none() {
+            0
+        } else {
+            self.context
+        };
+
+        let mut keep: HashSet<String> = HashSet::new();
+        for chat_messages in group_messages_by_chat(&messages).values() {
+            for (index, message) in chat_messages.iter().enumerate() {
+                if self.is_match(message) {
+                    let window = index.saturating_sub(context)
+                        ..(index + context + 1).min(chat_messages.len());
+                    keep.extend(chat_messages[window].iter().map(|m| m.guid.clone()));
+                }
+            }
+        }
+
+        messages.retain(|message| keep.contains(&message.guid));
+        messages
+    }
+}
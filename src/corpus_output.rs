@@ -0,0 +1,201 @@
+use crate::clean_message::CleanMessage;
+use crate::events::ExportEvent;
+use crate::mbox_output::message_body;
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use chrono::Duration;
+use serde::Serialize;
+use std::fmt::Write;
+
+pub const CORPUS_FILENAME: &str = "corpus.jsonl";
+
+/// Default most characters of messages in a chunk, about 500 tokens
+pub const DEFAULT_CHUNK_SIZE: usize = 2000;
+
+/// Default characters of messages a chunk repeats from the end of the one before
+pub const DEFAULT_CHUNK_OVERLAP: usize = 200;
+
+/// A silence this long ends a conversation, and with it a chunk, whatever its size
+const CONVERSATION_GAP_HOURS: i64 = 6;
+
+/// Writes `corpus.jsonl`: each chat cut into chunks of consecutive messages for
+/// embedding, one JSON object per line. A chunk ends before it would pass the chunk size
+/// or at a long silence, never in the middle of a message, and starts with the last
+/// messages of the one before (up to the overlap) when the conversation carries on.
+/// Its text opens with a header naming the chat, participants and dates, so it makes
+/// sense retrieved on its own.
+pub struct CorpusOutput {
+    messages: Vec<CleanMessage>,
+    chunk_size: usize,
+    chunk_overlap: usize,
+}
+
+/// One line of `corpus.jsonl`
+#[derive(Serialize)]
+struct Chunk<'a> {
+    /// GUID of the chunk's first message, which stays the same from one export to the next
+    id: &'a str,
+    chat: &'a str,
+    chat_guid: Option<&'a str>,
+    participants: &'a [String],
+    /// RFC 3339, with the local UTC offset
+    start: String,
+    end: String,
+    message_count: usize,
+    message_guids: Vec<&'a str>,
+    text: String,
+}
+
+/// A message as it reads in a chunk
+struct Line<'a> {
+    message: &'a CleanMessage,
+    text: String,
+    /// Characters in `text`
+    length: usize,
+}
+
+impl CorpusOutput {
+    pub fn new(messages: Vec<CleanMessage>, chunk_size: usize, chunk_overlap: usize) -> Self {
+        Self {
+            messages,
+            chunk_size,
+            chunk_overlap,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+
+        let mut corpus = String::new();
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+
+            let chat = display_name(chat_key);
+            let mut participants: Vec<String> =
+                chat_messages.iter().map(|m| m.from.to_string()).collect();
+            participants.sort();
+            participants.dedup();
+
+            let lines: Vec<Line> = chat_messages
+                .iter()
+                .filter_map(|message| line(message))
+                .collect();
+            for range in self.chunks(&lines) {
+                let chunk_lines = &lines[range];
+                let first = chunk_lines[0].message;
+                let last = chunk_lines[chunk_lines.len() - 1].message;
+
+                let mut text = String::new();
+                writeln!(text, "Chat: {}", chat)?;
+                writeln!(text, "Participants: {}", participants.join(", "))?;
+                writeln!(
+                    text,
+                    "Dates: {} to {}",
+                    first.date.format("%Y-%m-%d %H:%M"),
+                    last.date.format("%Y-%m-%d %H:%M")
+                )?;
+                writeln!(text)?;
+                for line in chunk_lines {
+                    writeln!(text, "{}", line.text)?;
+                }
+
+                let chunk = Chunk {
+                    id: &first.guid,
+                    chat,
+                    chat_guid: first.chat_guid.as_deref(),
+                    participants: &participants,
+                    start: first.date.to_rfc3339(),
+                    end: last.date.to_rfc3339(),
+                    message_count: chunk_lines.len(),
+                    message_guids: chunk_lines
+                        .iter()
+                        .map(|line| line.message.guid.as_str())
+                        .collect(),
+                    text,
+                };
+                corpus.push_str(&serde_json::to_string(&chunk)?);
+                corpus.push('\n');
+            }
+
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        output.write(CORPUS_FILENAME, corpus.as_bytes())
+    }
+
+    /// The ranges of `lines` each chunk holds, in order
+    fn chunks(&self, lines: &[Line]) -> Vec<std::ops::Range<usize>> {
+        let gap = Duration::hours(CONVERSATION_GAP_HOURS);
+        let is_gap = |index: usize| lines[index].message.date - lines[index - 1].message.date > gap;
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < lines.len() {
+            // A message longer than the chunk size is a chunk of its own
+            let mut end = start + 1;
+            let mut length = lines[start].length;
+            while end < lines.len() && !is_gap(end) && length + lines[end].length <= self.chunk_size
+            {
+                length += lines[end].length;
+                end += 1;
+            }
+            chunks.push(start..end);
+
+            if end == lines.len() || is_gap(end) {
+                start = end;
+                continue;
+            }
+            // Repeat the messages at the end that fit in the overlap, leaving room for the
+            // next new message and always moving on by at least one
+            let budget = self
+                .chunk_overlap
+                .min(self.chunk_size.saturating_sub(lines[end].length));
+            let mut next = end;
+            let mut overlap = 0;
+            while next > start + 1 && overlap + lines[next - 1].length <= budget {
+                overlap += lines[next - 1].length;
+                next -= 1;
+            }
+            start = next;
+        }
+        chunks
+    }
+}
+
+/// `[date] Sender: text`, with the names of any attachments, or `None` for a message
+/// with nothing to read
+fn line(message: &CleanMessage) -> Option<Line<'_>> {
+    let mut content = message_body(message);
+    for filename in message
+        .attachments
+        .iter()
+        .filter_map(|a| a.filename.as_deref())
+    {
+        if !content.is_empty() {
+            content.push(' ');
+        }
+        content.push_str(&format!("[attachment: {}]", filename));
+    }
+    if content.is_empty() {
+        return None;
+    }
+
+    let text = format!(
+        "[{}] {}: {}",
+        message.date.format("%Y-%m-%d %H:%M"),
+        message.from,
+        content
+    );
+    Some(Line {
+        message,
+        length: text.chars().count(),
+        text,
+    })
+}
@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// Times a query waits out `SQLITE_BUSY` before giving up, about 9 seconds in all
+const BUSY_RETRIES: i32 = 10;
+
+/// First wait after `SQLITE_BUSY`, doubled on each retry up to [`MAX_BUSY_WAIT`]
+const FIRST_BUSY_WAIT: Duration = Duration::from_millis(25);
+const MAX_BUSY_WAIT: Duration = Duration::from_secs(2);
+
+/// Opens a database another app owns, like Messages.app's `chat.db`, strictly
+/// read-only. A database without a `-wal` file is opened `immutable`, taking no locks at
+/// all; one in WAL mode is opened read-only so the changes still in its WAL are read,
+/// taking only the shared locks Messages.app expects of readers. Either way the
+/// connection refuses writes, and a query that finds the database busy, e.g. while
+/// Messages.app checkpoints, backs off and retries instead of failing.
+pub fn open_read_only(path: &Path) -> Result<Connection> {
+    let immutable = !with_suffix(path, "-wal").exists();
+    let uri = format!(
+        "{}?mode=ro{}",
+        file_uri(&path.to_string_lossy(), cfg!(windows)),
+        if immutable { "&immutable=1" } else { "" }
+    );
+    let db = Connection::open_with_flags(
+        &uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    db.busy_handler(Some(wait_when_busy))?;
+    db.pragma_update(None, "query_only", true)?;
+    Ok(db)
+}
+
+/// Busy handler: sleeps with exponential backoff and asks SQLite to retry, until
+/// [`BUSY_RETRIES`] retries have failed
+fn wait_when_busy(attempt: i32) -> bool {
+    if attempt >= BUSY_RETRIES {
+        return false;
+    }
+    let wait = FIRST_BUSY_WAIT.saturating_mul(1u32 << attempt.clamp(0, 16));
+    thread::sleep(wait.min(MAX_BUSY_WAIT));
+    true
+}
+
+/// `path` as a `file:` URI, with the characters URIs reserve percent-encoded. A
+/// `windows` path has its backslashes turned into slashes, and a drive letter or UNC share
+/// is put after an empty authority, as SQLite expects: `C:\Users\me\chat.db` is
+/// `file:///C:/Users/me/chat.db` and `\\server\share\chat.db` is
+/// `file:////server/share/chat.db`. The `\\?\` prefix of verbatim paths is dropped.
+fn file_uri(path: &str, windows: bool) -> String {
+    let path = if windows {
+        let path = match path.strip_prefix(r"\\?\UNC\") {
+            Some(share) => format!(r"\\{}", share),
+            None => path.strip_prefix(r"\\?\").unwrap_or(path).to_string(),
+        };
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    };
+    let drive = windows
+        && path.as_bytes().get(1) == Some(&b':')
+        && path.as_bytes()[0].is_ascii_alphabetic();
+
+    let mut uri = String::from("file:");
+    if drive {
+        uri.push_str("///");
+    } else if windows && path.starts_with("//") {
+        uri.push_str("//");
+    }
+    for (index, byte) in path.bytes().enumerate() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            b':' if drive && index == 1 => uri.push(':'),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_uri() {
+        assert_eq!(
+            file_uri("/Users/me/Library/Messages/chat.db", false),
+            "file:/Users/me/Library/Messages/chat.db"
+        );
+        assert_eq!(
+            file_uri("/tmp/my chats?#1/chat.db", false),
+            "file:/tmp/my%20chats%3F%231/chat.db"
+        );
+        assert_eq!(
+            file_uri("/tmp/back\\slash:/chat.db", false),
+            "file:/tmp/back%5Cslash%3A/chat.db"
+        );
+    }
+
+    #[test]
+    fn test_windows_file_uri() {
+        assert_eq!(
+            file_uri(r"C:\Users\me\My Chats\chat.db", true),
+            "file:///C:/Users/me/My%20Chats/chat.db"
+        );
+        assert_eq!(
+            file_uri(r"\\server\share\chat.db", true),
+            "file:////server/share/chat.db"
+        );
+        assert_eq!(
+            file_uri(r"\\?\C:\Users\me\chat.db", true),
+            "file:///C:/Users/me/chat.db"
+        );
+        assert_eq!(
+            file_uri(r"\\?\UNC\server\share\chat.db", true),
+            "file:////server/share/chat.db"
+        );
+        assert_eq!(file_uri(r"backup\chat.db", true), "file:backup/chat.db");
+    }
+
+    #[test]
+    fn test_open_read_only_refuses_writes() {
+        let dir = std::env::temp_dir().join(format!(
+            "imessage_extractor-{}-read-only-test",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1);")
+            .unwrap();
+
+        let db = open_read_only(&path).unwrap();
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert!(db.execute("INSERT INTO t VALUES (2)", []).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
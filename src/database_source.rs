@@ -0,0 +1,176 @@
+use crate::ios_backup::{self, EncryptedBackup, FileKey};
+use crate::snapshot::DatabaseSnapshot;
+use anyhow::{Result, anyhow};
+use imessage_database::tables::attachment::Attachment;
+use imessage_database::tables::table::DEFAULT_PATH_IOS;
+use imessage_database::util::platform::Platform;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Which device a database comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourcePlatform {
+    /// A Mac's `chat.db`, whose attachment paths point into `~/Library/Messages/Attachments`
+    MacOs,
+    /// An iOS backup directory, which stores `sms.db` and the attachments under hashed
+    /// names, encrypted when the backup has a password
+    Ios,
+}
+
+impl SourcePlatform {
+    /// iOS for an iOS backup directory (or the `sms.db` inside one), macOS otherwise
+    pub fn detect(path: &Path) -> Self {
+        if path.join(DEFAULT_PATH_IOS).is_file() || path.ends_with(DEFAULT_PATH_IOS) {
+            SourcePlatform::Ios
+        } else {
+            SourcePlatform::MacOs
+        }
+    }
+}
+
+impl std::str::FromStr for SourcePlatform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "macos" => Ok(SourcePlatform::MacOs),
+            "ios" => Ok(SourcePlatform::Ios),
+            _ => Err(format!("unknown platform '{}' (expected macos or ios)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for SourcePlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourcePlatform::MacOs => write!(f, "macos"),
+            SourcePlatform::Ios => write!(f, "ios"),
+        }
+    }
+}
+
+/// An attachment's file on this computer
+#[derive(Clone, Debug)]
+pub struct AttachmentFile {
+    pub path: PathBuf,
+    /// Key the file is encrypted with, for attachments in an encrypted iOS backup
+    pub key: Option<FileKey>,
+}
+
+impl AttachmentFile {
+    /// Contents of the file, decrypted for an encrypted backup, or `None` when it isn't on
+    /// this computer (e.g. offloaded to iCloud, or a database copied without its
+    /// attachments)
+    pub fn read(&self) -> Result<Option<Vec<u8>>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(anyhow!("Unable to read {}: {}", self.path.display(), e)),
+        };
+        match &self.key {
+            Some(key) => ios_backup::decrypt(key, &bytes)
+                .map(Some)
+                .map_err(|e| anyhow!("Unable to decrypt {}: {}", self.path.display(), e)),
+            None => Ok(Some(bytes)),
+        }
+    }
+}
+
+/// An encrypted iOS backup unlocked with its password, and its decrypted `sms.db`
+struct UnlockedBackup {
+    backup: EncryptedBackup,
+    sms_db: DatabaseSnapshot,
+}
+
+/// A database to export from, as given with `--database-path`, and where to find the
+/// files of its attachments
+pub struct DatabaseSource {
+    /// The `chat.db` file, or the root of an iOS backup
+    path: PathBuf,
+    platform: SourcePlatform,
+    /// Replaces `~/Library/Messages/Attachments` in attachment paths, for a database
+    /// copied from another Mac along with its attachments
+    attachment_root: Option<String>,
+    unlocked: Option<UnlockedBackup>,
+}
+
+impl DatabaseSource {
+    pub fn new(path: PathBuf, platform: SourcePlatform, attachment_root: Option<String>) -> Self {
+        // The library resolves iOS attachments from the backup root
+        let path = match platform {
+            SourcePlatform::Ios if path.ends_with(DEFAULT_PATH_IOS) => path
+                .ancestors()
+                .nth(2)
+                .map_or(path.clone(), Path::to_path_buf),
+            _ => path,
+        };
+        Self {
+            path,
+            platform,
+            attachment_root,
+            unlocked: None,
+        }
+    }
+
+    /// Whether this is an encrypted iOS backup, which must be unlocked before exporting
+    pub fn is_encrypted(&self) -> bool {
+        self.platform == SourcePlatform::Ios && EncryptedBackup::is_encrypted(&self.path)
+    }
+
+    /// Unlocks an encrypted iOS backup with its password and decrypts its `sms.db` into
+    /// a temporary file. `index` distinguishes sources unlocked by the same process.
+    pub fn unlock(&mut self, password: &str, index: usize) -> Result<()> {
+        let backup = EncryptedBackup::unlock(&self.path, password, index)?;
+        let sms_db = backup.decrypt_sms_db(&self.path, index)?;
+        self.unlocked = Some(UnlockedBackup { backup, sms_db });
+        Ok(())
+    }
+
+    /// The file the messages are stored in, as given; encrypted for an encrypted backup
+    pub fn stored_file(&self) -> PathBuf {
+        match self.platform {
+            SourcePlatform::MacOs => self.path.clone(),
+            SourcePlatform::Ios => self.path.join(DEFAULT_PATH_IOS),
+        }
+    }
+
+    /// The SQLite file to read the messages from
+    pub fn database_file(&self) -> PathBuf {
+        match &self.unlocked {
+            Some(unlocked) => unlocked.sms_db.path().to_path_buf(),
+            None => self.stored_file(),
+        }
+    }
+
+    /// Where `attachment`'s file is on this computer; `None` for an attachment without a
+    /// file. The file found isn't checked to exist.
+    pub fn attachment_file(&self, attachment: &Attachment) -> Result<Option<AttachmentFile>> {
+        let platform = match self.platform {
+            SourcePlatform::MacOs => Platform::macOS,
+            SourcePlatform::Ios => Platform::iOS,
+        };
+
+        let Some(path) = attachment.resolved_attachment_path(
+            &platform,
+            &self.path,
+            self.attachment_root.as_deref(),
+        ) else {
+            return Ok(None);
+        };
+        let path = PathBuf::from(path);
+
+        // An encrypted backup's files are named by their ID, which finds their key
+        let key = match &self.unlocked {
+            Some(unlocked) => {
+                let file_id = path.file_name().and_then(|name| name.to_str());
+                match file_id.map(|id| unlocked.backup.file_key(id)).transpose()? {
+                    Some(Some(key)) => Some(key),
+                    // Not in the backup
+                    _ => return Ok(None),
+                }
+            }
+            None => None,
+        };
+        Ok(Some(AttachmentFile { path, key }))
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDate};
+
+/// The timezone whose calendar days `--start-date`, `--end-date` and `--since` are
+/// counted in. Defaults to the computer's own, which is also the one dates are shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timezone {
+    #[default]
+    Local,
+    /// A fixed offset from UTC, which doesn't follow daylight saving time
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// The calendar day `date` falls on in this timezone
+    pub fn date_of(&self, date: &DateTime<Local>) -> NaiveDate {
+        match self {
+            Timezone::Local => date.date_naive(),
+            Timezone::Fixed(offset) => date.with_timezone(offset).date_naive(),
+        }
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.date_of(&Local::now())
+    }
+}
+
+impl std::str::FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            format!(
+                "invalid timezone '{}' (expected local, UTC or an offset such as +02:00 or -0800)",
+                s
+            )
+        };
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "local" => return Ok(Timezone::Local),
+            "utc" | "z" => return Ok(Timezone::Fixed(FixedOffset::east_opt(0).ok_or_else(error)?)),
+            _ => {}
+        }
+
+        let (sign, digits) = match s.split_at_checked(1) {
+            Some(("+", digits)) => (1, digits),
+            Some(("-", digits)) => (-1, digits),
+            _ => return Err(error()),
+        };
+        let digits = digits.replace(':', "");
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(error());
+        }
+        let (hours, minutes) = match digits.len() {
+            1 | 2 => (digits.as_str(), "0"),
+            4 => digits.split_at(2),
+            _ => return Err(error()),
+        };
+        let hours: i32 = hours.parse().map_err(|_| error())?;
+        let minutes: i32 = minutes.parse().map_err(|_| error())?;
+        if minutes >= 60 {
+            return Err(error());
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+            .map(Timezone::Fixed)
+            .ok_or_else(error)
+    }
+}
+
+/// The days messages are exported from. A message is in the range when the calendar day
+/// it was sent on, in `timezone`, is on or after `on_or_after` and before `before`; so
+/// the range starts at midnight of `on_or_after` and ends at midnight of `before` in that
+/// timezone, whatever the computer's timezone or daylight saving time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DateRange {
+    pub on_or_after: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+    pub timezone: Timezone,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: &DateTime<Local>) -> bool {
+        let day = self.timezone.date_of(date);
+        self.on_or_after
+            .is_none_or(|on_or_after| day >= on_or_after)
+            && self.before.is_none_or(|before| day < before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(rfc3339: &str) -> DateTime<Local> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        let offset = |seconds| Timezone::Fixed(FixedOffset::east_opt(seconds).unwrap());
+        assert_eq!("local".parse::<Timezone>(), Ok(Timezone::Local));
+        assert_eq!("UTC".parse::<Timezone>(), Ok(offset(0)));
+        assert_eq!("+02:00".parse::<Timezone>(), Ok(offset(2 * 3600)));
+        assert_eq!("-0800".parse::<Timezone>(), Ok(offset(-8 * 3600)));
+        assert_eq!("+5".parse::<Timezone>(), Ok(offset(5 * 3600)));
+        assert_eq!("+05:30".parse::<Timezone>(), Ok(offset(5 * 3600 + 30 * 60)));
+        assert!("Europe/Paris".parse::<Timezone>().is_err());
+        assert!("+05:75".parse::<Timezone>().is_err());
+    }
+
+    #[test]
+    fn test_range_boundaries_follow_timezone() {
+        let range = DateRange {
+            on_or_after: Some(day("2024-03-10")),
+            before: Some(day("2024-03-11")),
+            timezone: "-08:00".parse().unwrap(),
+        };
+        // Midnight at the start is in, midnight at the end is out
+        assert!(range.contains(&at("2024-03-10T00:00:00-08:00")));
+        assert!(!range.contains(&at("2024-03-09T23:59:59-08:00")));
+        assert!(range.contains(&at("2024-03-10T23:59:59-08:00")));
+        assert!(!range.contains(&at("2024-03-11T00:00:00-08:00")));
+        // Already the 11th in UTC, but still the 10th at -08:00
+        assert!(range.contains(&at("2024-03-11T05:00:00Z")));
+
+        let utc = DateRange {
+            timezone: "UTC".parse().unwrap(),
+            ..range
+        };
+        assert!(!utc.contains(&at("2024-03-11T05:00:00Z")));
+    }
+
+    #[test]
+    fn test_open_range_contains_everything() {
+        assert!(DateRange::default().contains(&at("1999-12-31T23:59:59Z")));
+    }
+}
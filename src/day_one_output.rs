@@ -0,0 +1,229 @@
+use crate::attachment_store::read_for_export;
+use crate::clean_message::CleanMessage;
+use crate::error_log::ErrorLog;
+use crate::events::ExportEvent;
+use crate::mbox_output::message_body;
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use chrono::Utc;
+use md5::Md5;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{Cursor, Write};
+use zip::CompressionMethod;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+/// The archive Day One's "Import → Day One (JSON)" reads
+pub const DAY_ONE_FILENAME: &str = "dayone.zip";
+
+/// Name of the journal file inside the archive, which Day One names the new journal after
+const JOURNAL_FILENAME: &str = "iMessage.json";
+
+/// Image types Day One can attach to an entry, by file extension
+const PHOTO_TYPES: &[(&str, &str)] = &[
+    ("jpg", "jpeg"),
+    ("jpeg", "jpeg"),
+    ("png", "png"),
+    ("gif", "gif"),
+    ("heic", "heic"),
+];
+
+/// Writes one journal entry per chat per day, in Day One's JSON import format: a zip
+/// of the journal JSON plus a `photos/` folder with the images the entries show, so
+/// conversations can be imported and then moved into an existing journal
+pub struct DayOneOutput {
+    messages: Vec<CleanMessage>,
+    /// Remove EXIF, GPS and device metadata from the photos
+    strip_metadata: bool,
+}
+
+#[derive(Serialize)]
+struct Journal {
+    metadata: JournalMetadata,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct JournalMetadata {
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    uuid: String,
+    creation_date: String,
+    modified_date: String,
+    text: String,
+    tags: Vec<String>,
+    starred: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    photos: Vec<Photo>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Photo {
+    identifier: String,
+    /// The photo is stored as `photos/<md5>.<type>`
+    md5: String,
+    #[serde(rename = "type")]
+    photo_type: &'static str,
+    order_in_entry: usize,
+}
+
+/// The zip being written, and the photos already in it
+struct Archive {
+    zip: ZipWriter<Cursor<Vec<u8>>>,
+    photos: HashSet<String>,
+}
+
+impl Archive {
+    /// Adds a photo under `photos/`, once however many entries show it
+    fn add_photo(&mut self, filename: String, bytes: &[u8]) -> Result<()> {
+        if self.photos.insert(filename.clone()) {
+            // Photos are already compressed
+            let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+            self.zip
+                .start_file(format!("photos/{}", filename), stored)?;
+            self.zip.write_all(bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl DayOneOutput {
+    pub fn new(messages: Vec<CleanMessage>, strip_metadata: bool) -> Self {
+        Self {
+            messages,
+            strip_metadata,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget, errors: &mut ErrorLog) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+
+        let mut archive = Archive {
+            zip: ZipWriter::new(Cursor::new(Vec::new())),
+            photos: HashSet::new(),
+        };
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let mut entries = Vec::new();
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+
+            for day in chat_messages.chunk_by(|a, b| a.date.date_naive() == b.date.date_naive()) {
+                let mut photos = Vec::new();
+                let text = self.entry_text(chat_key, day, &mut photos, &mut archive, errors)?;
+                let first = day[0];
+                let last = day[day.len() - 1];
+                entries.push(Entry {
+                    uuid: identifier(&format!("{}\n{}", chat_key, first.date.date_naive())),
+                    creation_date: day_one_date(first),
+                    modified_date: day_one_date(last),
+                    text,
+                    tags: vec!["iMessage".to_string(), display_name(chat_key).to_string()],
+                    starred: false,
+                    photos,
+                });
+            }
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        let journal = Journal {
+            metadata: JournalMetadata { version: "1.0" },
+            entries,
+        };
+        let mut zip = archive.zip;
+        zip.start_file(JOURNAL_FILENAME, deflated)?;
+        zip.write_all(serde_json::to_string_pretty(&journal)?.as_bytes())?;
+
+        output.write(DAY_ONE_FILENAME, &zip.finish()?.into_inner())
+    }
+
+    /// Markdown of one chat's messages on one day, with each image attachment added to
+    /// `photos` (and the archive) and shown where it was sent
+    fn entry_text(
+        &self,
+        chat_key: &str,
+        messages: &[&CleanMessage],
+        photos: &mut Vec<Photo>,
+        archive: &mut Archive,
+        errors: &mut ErrorLog,
+    ) -> Result<String> {
+        let mut text = format!("# {}\n", display_name(chat_key));
+        for message in messages {
+            writeln!(
+                text,
+                "\n**{}** · {}",
+                message.from,
+                message.date.format("%I:%M %p")
+            )?;
+            let body = message_body(message);
+            if !body.is_empty() {
+                writeln!(text, "{}", body)?;
+            }
+
+            for attachment in &message.attachments {
+                let Some(filename) = attachment.filename.as_deref() else {
+                    continue;
+                };
+                let photo_type = filename
+                    .rsplit_once('.')
+                    .and_then(|(_, extension)| {
+                        PHOTO_TYPES
+                            .iter()
+                            .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+                    })
+                    .map(|(_, photo_type)| *photo_type);
+                let bytes = match photo_type {
+                    Some(_) => read_for_export(attachment, self.strip_metadata, errors)?,
+                    None => None,
+                };
+                let (Some(photo_type), Some(bytes)) = (photo_type, bytes) else {
+                    writeln!(text, "📎 {}", filename)?;
+                    continue;
+                };
+
+                let md5 = format!("{:x}", Md5::digest(&bytes));
+                let photo_identifier =
+                    identifier(&format!("{}\n{}", message.guid, attachment.rowid));
+                archive.add_photo(format!("{}.{}", md5, photo_type), &bytes)?;
+                writeln!(text, "![](dayone-moment://{})", photo_identifier)?;
+                photos.push(Photo {
+                    identifier: photo_identifier,
+                    md5,
+                    photo_type,
+                    order_in_entry: photos.len(),
+                });
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// A stable 32-digit uppercase hex ID, as Day One uses for entries and photos, so a
+/// re-export imports as the same entries
+fn identifier(seed: &str) -> String {
+    format!("{:X}", Sha256::digest(seed.as_bytes()))[..32].to_string()
+}
+
+/// A message's date in UTC, as Day One's JSON has them
+fn day_one_date(message: &CleanMessage) -> String {
+    message
+        .date
+        .with_timezone(&Utc)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
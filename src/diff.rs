@@ -0,0 +1,152 @@
+use crate::manifest::{Manifest, ManifestChat, ManifestFile};
+use std::collections::{BTreeMap, HashSet};
+
+/// Exported files under this directory are the attachments compared between exports
+const ATTACHMENTS_DIR: &str = "attachments/";
+
+/// Totals of what changed between two exports
+#[derive(Default)]
+struct DiffSummary {
+    chats_added: usize,
+    chats_removed: usize,
+    messages_added: usize,
+    messages_removed: usize,
+    attachments_added: usize,
+    attachments_removed: usize,
+    attachments_changed: usize,
+}
+
+/// Prints what was added (`+`) and removed (`-`) between the `old` and `new` exports,
+/// by chat and by attachment, followed by the totals. With `list_messages`, the GUID of
+/// every added and removed message is printed too.
+pub fn diff_manifests(old: &Manifest, new: &Manifest, list_messages: bool) {
+    println!("--- {}", old.generated_at);
+    println!("+++ {}", new.generated_at);
+
+    let mut summary = DiffSummary::default();
+    diff_chats(&old.chats, &new.chats, list_messages, &mut summary);
+    diff_attachments(&old.files, &new.files, &mut summary);
+
+    println!(
+        "chats: +{} -{}, messages: +{} -{}, attachments: +{} -{} ~{}",
+        summary.chats_added,
+        summary.chats_removed,
+        summary.messages_added,
+        summary.messages_removed,
+        summary.attachments_added,
+        summary.attachments_removed,
+        summary.attachments_changed
+    );
+}
+
+fn diff_chats(
+    old: &[ManifestChat],
+    new: &[ManifestChat],
+    list_messages: bool,
+    summary: &mut DiffSummary,
+) {
+    let mut chats: BTreeMap<&str, (Option<&ManifestChat>, Option<&ManifestChat>)> = BTreeMap::new();
+    for chat in old {
+        chats.entry(&chat.name).or_default().0 = Some(chat);
+    }
+    for chat in new {
+        chats.entry(&chat.name).or_default().1 = Some(chat);
+    }
+
+    for (name, pair) in chats {
+        match pair {
+            (None, Some(new_chat)) => {
+                println!("+ chat \"{}\" ({} messages)", name, new_chat.message_count);
+                summary.chats_added += 1;
+                summary.messages_added += new_chat.message_count;
+                if list_messages {
+                    for guid in &new_chat.messages {
+                        println!("+   {}", guid);
+                    }
+                }
+            }
+            (Some(old_chat), None) => {
+                println!("- chat \"{}\" ({} messages)", name, old_chat.message_count);
+                summary.chats_removed += 1;
+                summary.messages_removed += old_chat.message_count;
+                if list_messages {
+                    for guid in &old_chat.messages {
+                        println!("-   {}", guid);
+                    }
+                }
+            }
+            (Some(old_chat), Some(new_chat)) => {
+                let old_guids: HashSet<&str> =
+                    old_chat.messages.iter().map(String::as_str).collect();
+                let new_guids: HashSet<&str> =
+                    new_chat.messages.iter().map(String::as_str).collect();
+                let added: Vec<&str> = new_chat
+                    .messages
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|guid| !old_guids.contains(guid))
+                    .collect();
+                let removed: Vec<&str> = old_chat
+                    .messages
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|guid| !new_guids.contains(guid))
+                    .collect();
+
+                if added.is_empty() && removed.is_empty() {
+                    continue;
+                }
+
+                println!(
+                    "  chat \"{}\": +{} -{} messages",
+                    name,
+                    added.len(),
+                    removed.len()
+                );
+                summary.messages_added += added.len();
+                summary.messages_removed += removed.len();
+                if list_messages {
+                    for guid in added {
+                        println!("+   {}", guid);
+                    }
+                    for guid in removed {
+                        println!("-   {}", guid);
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+fn diff_attachments(old: &[ManifestFile], new: &[ManifestFile], summary: &mut DiffSummary) {
+    let attachments = |files: &[ManifestFile]| -> BTreeMap<String, String> {
+        files
+            .iter()
+            .filter(|file| file.path.starts_with(ATTACHMENTS_DIR))
+            .map(|file| (file.path.clone(), file.sha256.clone()))
+            .collect()
+    };
+    let old = attachments(old);
+    let new = attachments(new);
+
+    for (path, sha256) in &new {
+        match old.get(path) {
+            None => {
+                println!("+ {}", path);
+                summary.attachments_added += 1;
+            }
+            Some(old_sha256) if old_sha256 != sha256 => {
+                println!("! {}", path);
+                summary.attachments_changed += 1;
+            }
+            Some(_) => {}
+        }
+    }
+    for path in old.keys() {
+        if !new.contains_key(path) {
+            println!("- {}", path);
+            summary.attachments_removed += 1;
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Most emoji a message can have and still be shown large, as Messages.app does
+const JUMBO_EMOJI_MAX: usize = 3;
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+const TEXT_PRESENTATION: char = '\u{FE0E}';
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+const COMBINING_KEYCAP: char = '\u{20E3}';
+
+/// Pictographs before U+1F000 that show as emoji without a variation selector; the rest
+/// of that range (©, ❤, ☺…) are text unless followed by U+FE0F
+const DEFAULT_EMOJI_PRESENTATION: &[(u32, u32)] = &[
+    (0x231A, 0x231B),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F3),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x26FD, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x274E, 0x274E),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+];
+
+/// Other pictographs before U+1F000 that can be emoji
+const TEXT_DEFAULT_PICTOGRAPHS: &[(u32, u32)] = &[
+    (0x00A9, 0x00A9),
+    (0x00AE, 0x00AE),
+    (0x203C, 0x203C),
+    (0x2049, 0x2049),
+    (0x2122, 0x2122),
+    (0x2139, 0x2139),
+    (0x2194, 0x2199),
+    (0x21A9, 0x21AA),
+    (0x2328, 0x2328),
+    (0x23CF, 0x23CF),
+    (0x23ED, 0x23EF),
+    (0x23F1, 0x23F2),
+    (0x23F8, 0x23FA),
+    (0x24C2, 0x24C2),
+    (0x25AA, 0x25AB),
+    (0x25B6, 0x25B6),
+    (0x25C0, 0x25C0),
+    (0x25FB, 0x25FC),
+    (0x2600, 0x27BF),
+    (0x2934, 0x2935),
+    (0x2B05, 0x2B07),
+    (0x3030, 0x3030),
+    (0x303D, 0x303D),
+    (0x3297, 0x3297),
+    (0x3299, 0x3299),
+];
+
+fn in_ranges(c: char, ranges: &[(u32, u32)]) -> bool {
+    let c = c as u32;
+    ranges
+        .iter()
+        .any(|&(start, end)| (start..=end).contains(&c))
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_skin_tone(c: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+}
+
+/// Tag characters, which follow 🏴 in the flags of England, Scotland and Wales
+fn is_tag(c: char) -> bool {
+    ('\u{E0020}'..='\u{E007F}').contains(&c)
+}
+
+/// Whether `c` starts an emoji, and if so whether it shows as one on its own
+fn pictograph(c: char) -> Option<bool> {
+    let supplementary_emoji =
+        ('\u{1F000}'..='\u{1FAFF}').contains(&c) && !is_regional_indicator(c) && !is_skin_tone(c);
+    if supplementary_emoji || in_ranges(c, DEFAULT_EMOJI_PRESENTATION) {
+        Some(true)
+    } else if in_ranges(c, TEXT_DEFAULT_PICTOGRAPHS) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Reads one emoji (a user-perceived character, however many code points it takes) from
+/// the start of `chars`, returning whether it's one
+fn read_emoji(chars: &mut Peekable<Chars>) -> bool {
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    // Flags are a pair of regional indicators
+    if is_regional_indicator(first) {
+        return chars.next_if(|&c| is_regional_indicator(c)).is_some();
+    }
+
+    // Keycaps: a digit, # or *, then U+FE0F and U+20E3
+    if first.is_ascii_digit() || first == '#' || first == '*' {
+        chars.next_if_eq(&EMOJI_PRESENTATION);
+        return chars.next_if_eq(&COMBINING_KEYCAP).is_some();
+    }
+
+    let Some(mut is_emoji) = pictograph(first) else {
+        return false;
+    };
+    loop {
+        match chars.peek().copied() {
+            Some(EMOJI_PRESENTATION) => is_emoji = true,
+            Some(TEXT_PRESENTATION) => is_emoji = false,
+            Some(c) if is_skin_tone(c) => is_emoji = true,
+            Some(c) if is_tag(c) || c == COMBINING_KEYCAP => {}
+            // 👩‍💻, 🏳️‍🌈: sequences joined into one emoji
+            Some(ZERO_WIDTH_JOINER) => {
+                chars.next();
+                match chars.next().and_then(pictograph) {
+                    Some(_) => continue,
+                    None => return false,
+                }
+            }
+            _ => return is_emoji,
+        }
+        chars.next();
+    }
+}
+
+/// How many emoji `text` consists of, ignoring whitespace, or `None` if it has anything
+/// else
+pub fn emoji_count(text: &str) -> Option<usize> {
+    let mut chars = text.chars().peekable();
+    let mut count = 0;
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Some(count);
+        }
+        if !read_emoji(&mut chars) {
+            return None;
+        }
+        count += 1;
+    }
+}
+
+/// Every emoji in `text`, in order, skipping everything else
+pub fn emoji_in(text: &str) -> Vec<&str> {
+    let mut found = Vec::new();
+    let mut rest = text;
+    while let Some(first) = rest.chars().next() {
+        let mut chars = rest.chars().peekable();
+        if read_emoji(&mut chars) {
+            let remaining: usize = chars.map(char::len_utf8).sum();
+            let (emoji, after) = rest.split_at(rest.len() - remaining);
+            found.push(emoji);
+            rest = after;
+        } else {
+            rest = &rest[first.len_utf8()..];
+        }
+    }
+    found
+}
+
+/// Whether `text` is only 1 to 3 emoji, which Messages.app shows large and without a bubble
+pub fn is_jumbo_emoji(text: &str) -> bool {
+    emoji_count(text).is_some_and(|count| (1..=JUMBO_EMOJI_MAX).contains(&count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emoji_count() {
+        assert_eq!(emoji_count("😂"), Some(1));
+        assert_eq!(emoji_count("👍🏽 ❤️"), Some(2));
+        assert_eq!(emoji_count("👩‍👩‍👧‍👦🇯🇵1️⃣"), Some(3));
+        assert_eq!(emoji_count("🏴󠁧󠁢󠁳󠁣󠁴󠁿"), Some(1));
+        assert_eq!(emoji_count("ok 👍"), None);
+        assert_eq!(emoji_count("©"), None);
+        assert_eq!(emoji_count("42"), None);
+    }
+
+    #[test]
+    fn test_emoji_in() {
+        assert_eq!(emoji_in("ok 👍🏽 see you 🇯🇵!"), vec!["👍🏽", "🇯🇵"]);
+        assert_eq!(emoji_in("👩‍💻 and 2 © marks"), vec!["👩‍💻"]);
+        assert!(emoji_in("no emoji here").is_empty());
+    }
+
+    #[test]
+    fn test_jumbo_emoji() {
+        assert!(is_jumbo_emoji("🎉🎉🎉"));
+        assert!(!is_jumbo_emoji("🎉🎉🎉🎉"));
+        assert!(!is_jumbo_emoji(""));
+        assert!(!is_jumbo_emoji("   "));
+    }
+}
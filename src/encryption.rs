@@ -0,0 +1,68 @@
+use age::secrecy::{ExposeSecret, SecretString};
+use anyhow::{Result, anyhow, bail};
+use std::io::Write;
+use std::str::FromStr;
+
+/// Environment variable read for the passphrase before prompting for one
+pub const PASSPHRASE_ENV_VAR: &str = "IMESSAGE_EXTRACTOR_PASSPHRASE";
+
+/// How exported files are encrypted with age
+pub enum Encryption {
+    /// Public keys (`age1...`) that can decrypt the export
+    Recipients(Vec<age::x25519::Recipient>),
+    /// A passphrase, stretched with scrypt
+    Passphrase(SecretString),
+}
+
+impl Encryption {
+    /// Encrypts to `recipients` when any are given, otherwise to a passphrase taken from
+    /// `IMESSAGE_EXTRACTOR_PASSPHRASE` or prompted for on the terminal.
+    pub fn from_args(recipients: &[String]) -> Result<Self> {
+        if !recipients.is_empty() {
+            let recipients = recipients
+                .iter()
+                .map(|recipient| {
+                    age::x25519::Recipient::from_str(recipient)
+                        .map_err(|e| anyhow!("Invalid age recipient '{}': {}", recipient, e))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(Encryption::Recipients(recipients));
+        }
+
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV_VAR)
+            && !passphrase.is_empty()
+        {
+            return Ok(Encryption::Passphrase(SecretString::from(passphrase)));
+        }
+
+        let passphrase = rpassword::prompt_password("Export passphrase: ")?;
+        if passphrase.is_empty() {
+            bail!("An empty passphrase can't be used to encrypt the export");
+        }
+        let confirmation = rpassword::prompt_password("Confirm passphrase: ")?;
+        if passphrase != confirmation {
+            bail!("Passphrases don't match");
+        }
+
+        Ok(Encryption::Passphrase(SecretString::from(passphrase)))
+    }
+
+    pub fn is_passphrase(&self) -> bool {
+        matches!(self, Encryption::Passphrase(_))
+    }
+
+    /// Starts an age stream over `output`. Call `finish()` on the returned writer once
+    /// everything has been written.
+    pub fn wrap<W: Write>(&self, output: W) -> Result<age::stream::StreamWriter<W>> {
+        let encryptor = match self {
+            Encryption::Recipients(recipients) => age::Encryptor::with_recipients(
+                recipients.iter().map(|r| r as &dyn age::Recipient),
+            )?,
+            Encryption::Passphrase(passphrase) => age::Encryptor::with_user_passphrase(
+                SecretString::from(passphrase.expose_secret().to_owned()),
+            ),
+        };
+
+        Ok(encryptor.wrap_output(output)?)
+    }
+}
@@ -0,0 +1,34 @@
+use crate::error::ExportError;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// First bytes of every SQLite database file
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Checks each database can be opened and read before the export starts, so missing
+/// files and privacy restrictions are reported clearly instead of as SQLite errors
+pub fn check_databases(paths: &[PathBuf]) -> Result<(), ExportError> {
+    for path in paths {
+        check_database(path)?;
+    }
+    Ok(())
+}
+
+fn check_database(path: &Path) -> Result<(), ExportError> {
+    let classify = |error: io::Error| match error.kind() {
+        io::ErrorKind::NotFound => ExportError::DatabaseNotFound(path.to_path_buf()),
+        // macOS privacy protection (TCC) denies both the stat and the open with EPERM
+        io::ErrorKind::PermissionDenied => ExportError::PermissionDenied(path.to_path_buf()),
+        _ => ExportError::NotADatabase(path.to_path_buf()),
+    };
+
+    let mut file = File::open(path).map_err(classify)?;
+    let mut header = [0; SQLITE_HEADER.len()];
+    file.read_exact(&mut header).map_err(classify)?;
+    if header != SQLITE_HEADER {
+        return Err(ExportError::NotADatabase(path.to_path_buf()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,507 @@
+use crate::app_balloon::AppBalloon;
+use crate::clean_attachment::{CleanAttachment, MediaKind};
+use crate::clean_message::CleanMessage;
+use crate::events::ExportEvent;
+use crate::filename::{chat_stems, sanitize_filename};
+use crate::markup::html_escape;
+use crate::message_store::group_messages_by_chat;
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use chrono::{Datelike, Utc};
+use std::io::{Cursor, Write};
+use zip::CompressionMethod;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+/// Longest edge, in pixels, of images embedded in a book
+const BOOK_IMAGE_MAX_DIMENSION: u32 = 1200;
+
+const BOOK_CSS: &str = r#"body { font-family: Georgia, serif; margin: 0 1em; }
+h1 { text-align: center; margin: 1.5em 0 1em; }
+h2.day { font-size: 0.85em; color: #8e8e93; text-align: center; font-weight: normal; margin: 1.5em 0 0.5em; }
+.title-page { text-align: center; margin-top: 30%; }
+.title-page .dates { color: #8e8e93; }
+.title-page .participants { margin-top: 2em; font-style: italic; }
+.message { margin: 0.4em 0; overflow: hidden; page-break-inside: avoid; }
+.sender { font-size: 0.75em; color: #8e8e93; margin: 0 0 0.1em 0.8em; }
+.bubble { display: inline-block; max-width: 75%; padding: 0.4em 0.8em; border-radius: 1em; }
+.from-me { text-align: right; }
+.from-me .bubble { background: #007aff; color: #ffffff; text-align: left; }
+.from-others .bubble { background: #e9e9eb; color: #000000; }
+.bubble p { margin: 0; }
+.bubble img { max-width: 100%; border-radius: 0.6em; margin: 0.2em 0; }
+.attachment { font-style: italic; }
+.time { font-size: 0.7em; color: #8e8e93; margin: 0.1em 0.8em 0; }
+.system { text-align: center; font-size: 0.8em; color: #8e8e93; margin: 0.6em 0; }
+"#;
+
+/// Builds a "conversation book": one EPUB per chat (or per chat and year) with a
+/// title page, a table of contents by month and embedded images.
+pub struct EpubOutput {
+    messages: Vec<CleanMessage>,
+    per_year: bool,
+}
+
+/// Image embedded in a book, stored under `OEBPS/images/`
+struct BookImage {
+    filename: String,
+    bytes: Vec<u8>,
+}
+
+/// One month of messages, which becomes a chapter
+struct Chapter<'a> {
+    id: String,
+    title: String,
+    messages: Vec<&'a CleanMessage>,
+}
+
+impl EpubOutput {
+    pub fn new(messages: Vec<CleanMessage>, per_year: bool) -> Self {
+        Self { messages, per_year }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let chat_stems = chat_stems(grouped_messages.keys());
+
+        for (chat_key, chat_messages) in &grouped_messages {
+            let is_group = !chat_key.starts_with("Direct: ");
+            let subdir = if is_group { "groups" } else { "direct" };
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+            if self.per_year {
+                let mut years: Vec<i32> = chat_messages.iter().map(|m| m.date.year()).collect();
+                years.dedup();
+                for year in years {
+                    let messages: Vec<&CleanMessage> = chat_messages
+                        .iter()
+                        .copied()
+                        .filter(|m| m.date.year() == year)
+                        .collect();
+                    let title = format!("{} — {}", chat_key, year);
+                    let path = format!("{}/{} {}.epub", subdir, chat_stems[chat_key], year);
+                    let book = self.build_book(&title, &messages)?;
+                    output.write(&path, &book)?;
+                }
+            } else {
+                let path = format!("{}/{}.epub", subdir, chat_stems[chat_key]);
+                let book = self.build_book(chat_key, chat_messages)?;
+                output.write(&path, &book)?;
+            }
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        Ok(())
+    }
+
+    /// Builds the EPUB file for a set of messages
+    fn build_book(&self, title: &str, messages: &[&CleanMessage]) -> Result<Vec<u8>> {
+        let (Some(first), Some(last)) = (messages.first(), messages.last()) else {
+            return Ok(Vec::new());
+        };
+
+        let mut chapters: Vec<Chapter> = Vec::new();
+        for message in messages {
+            let id = message.date.format("chapter-%Y-%m").to_string();
+            match chapters.last_mut() {
+                Some(chapter) if chapter.id == id => chapter.messages.push(message),
+                _ => chapters.push(Chapter {
+                    id,
+                    title: message.date.format("%B %Y").to_string(),
+                    messages: vec![message],
+                }),
+            }
+        }
+
+        let mut images = Vec::new();
+        let chapter_pages = chapters
+            .iter()
+            .map(|chapter| self.build_chapter(chapter, &mut images))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut participants: Vec<String> = messages
+            .iter()
+            .map(|m| m.from.to_string())
+            .filter(|name| name != "Me")
+            .collect();
+        participants.sort();
+        participants.dedup();
+
+        let date_range = format!(
+            "{} – {}",
+            first.date.format("%B %-d, %Y"),
+            last.date.format("%B %-d, %Y")
+        );
+        let book_id = format!("urn:imessage-extractor:{}", sanitize_filename(title));
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        // The mimetype must be the first entry and uncompressed
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+
+        zip.start_file("OEBPS/style.css", deflated)?;
+        zip.write_all(BOOK_CSS.as_bytes())?;
+
+        zip.start_file("OEBPS/title.xhtml", deflated)?;
+        zip.write_all(
+            xhtml_page(
+                title,
+                &format!(
+                    r#"<div class="title-page">
+<h1>{}</h1>
+<p class="dates">{}</p>
+<p class="participants">{}</p>
+</div>"#,
+                    html_escape(title),
+                    date_range,
+                    html_escape(&participants.join(", "))
+                ),
+            )
+            .as_bytes(),
+        )?;
+
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(build_nav(title, &chapters).as_bytes())?;
+
+        zip.start_file("OEBPS/toc.ncx", deflated)?;
+        zip.write_all(build_ncx(&book_id, title, &chapters).as_bytes())?;
+
+        for (chapter, page) in chapters.iter().zip(&chapter_pages) {
+            zip.start_file(format!("OEBPS/{}.xhtml", chapter.id), deflated)?;
+            zip.write_all(page.as_bytes())?;
+        }
+
+        // JPEGs are already compressed
+        for image in &images {
+            zip.start_file(format!("OEBPS/images/{}", image.filename), stored)?;
+            zip.write_all(&image.bytes)?;
+        }
+
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        zip.write_all(build_opf(&book_id, title, &chapters, &images).as_bytes())?;
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    fn build_chapter(&self, chapter: &Chapter, images: &mut Vec<BookImage>) -> Result<String> {
+        let mut body = format!("<h1>{}</h1>\n", html_escape(&chapter.title));
+        let mut last_day = String::new();
+
+        for message in &chapter.messages {
+            let day = message.date.format("%A, %B %-d").to_string();
+            if day != last_day {
+                body.push_str(&format!("<h2 class=\"day\">{}</h2>\n", day));
+                last_day = day;
+            }
+
+            let time = message.date.format("%I:%M %p");
+
+            if let Some(call) = &message.call {
+                body.push_str(&format!(
+                    "<p class=\"system\">{} · {}</p>\n",
+                    html_escape(&call.description()),
+                    time
+                ));
+                continue;
+            }
+
+            let sender = if message.from.is_me() {
+                "You".to_string()
+            } else {
+                message.from.to_string()
+            };
+
+            if message.unsent {
+                body.push_str(&format!(
+                    "<p class=\"system\">{} unsent a message · {}</p>\n",
+                    html_escape(&sender),
+                    time
+                ));
+                continue;
+            }
+            if let Some(AppBalloon::LocationSharing { started }) = &message.balloon {
+                let action = if *started { "started" } else { "stopped" };
+                body.push_str(&format!(
+                    "<p class=\"system\">{} {} sharing location · {}</p>\n",
+                    html_escape(&sender),
+                    action,
+                    time
+                ));
+                continue;
+            }
+
+            let mut bubble = String::new();
+
+            let text = message.text.replace('\u{FFFC}', "");
+            let text = text.trim();
+            if !text.is_empty() {
+                bubble.push_str(&format!(
+                    "<p>{}</p>",
+                    html_escape(text).replace('\n', "<br/>")
+                ));
+            }
+            if let Some(summary) = message.balloon.as_ref().and_then(balloon_summary) {
+                bubble.push_str(&format!("<p>{}</p>", html_escape(&summary)));
+            }
+
+            for attachment in &message.attachments {
+                let Some(filename) = attachment.filename.as_deref() else {
+                    continue;
+                };
+
+                let embedded = if attachment.kind == MediaKind::Image {
+                    self.embed_image(attachment, images)?
+                } else {
+                    None
+                };
+                match embedded {
+                    Some(image_filename) => bubble.push_str(&format!(
+                        "<img src=\"images/{}\" alt=\"{}\"/>",
+                        image_filename,
+                        html_escape(filename)
+                    )),
+                    None => bubble.push_str(&format!(
+                        "<p class=\"attachment\">📎 {}</p>",
+                        html_escape(filename)
+                    )),
+                }
+            }
+            for skipped in &message.skipped_attachments {
+                bubble.push_str(&format!(
+                    "<p class=\"attachment\">📎 {}</p>",
+                    html_escape(&skipped.description())
+                ));
+            }
+
+            if bubble.is_empty() {
+                continue;
+            }
+
+            let (class, sender_line) = if message.from.is_me() {
+                ("from-me", String::new())
+            } else {
+                (
+                    "from-others",
+                    format!("<p class=\"sender\">{}</p>", html_escape(&sender)),
+                )
+            };
+            body.push_str(&format!(
+                "<div class=\"message {}\">{}<div class=\"bubble\">{}</div><p class=\"time\">{}</p></div>\n",
+                class, sender_line, bubble, time
+            ));
+        }
+
+        Ok(xhtml_page(&chapter.title, &body))
+    }
+
+    /// Downscales an image attachment to a JPEG for the book. Returns the image's file
+    /// name, or `None` when the image can't be decoded (e.g. HEIC).
+    fn embed_image(
+        &self,
+        attachment: &CleanAttachment,
+        images: &mut Vec<BookImage>,
+    ) -> Result<Option<String>> {
+        let Some(bytes) = attachment.read()? else {
+            return Ok(None);
+        };
+        let Ok(image) = image::load_from_memory(&bytes) else {
+            return Ok(None);
+        };
+
+        let resized = image::DynamicImage::ImageRgb8(
+            image
+                .thumbnail(BOOK_IMAGE_MAX_DIMENSION, BOOK_IMAGE_MAX_DIMENSION)
+                .to_rgb8(),
+        );
+        let mut jpeg = Cursor::new(Vec::new());
+        resized.write_to(&mut jpeg, image::ImageFormat::Jpeg)?;
+
+        let filename = format!("image-{}.jpg", images.len() + 1);
+        images.push(BookImage {
+            filename: filename.clone(),
+            bytes: jpeg.into_inner(),
+        });
+        Ok(Some(filename))
+    }
+}
+
+/// One-line text stand-in for app message cards
+fn balloon_summary(balloon: &AppBalloon) -> Option<String> {
+    match balloon {
+        AppBalloon::Location { name, address, .. } => Some(format!(
+            "📍 {}",
+            [name.as_deref(), address.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        AppBalloon::FindMy { caption, .. } => {
+            Some(format!("📍 {}", caption.as_deref().unwrap_or("Find My")))
+        }
+        AppBalloon::Payment { summary, status } => Some(match status {
+            Some(status) => format!("{} ({})", summary, status),
+            None => summary.clone(),
+        }),
+        AppBalloon::App {
+            app_name,
+            title,
+            summary,
+            ..
+        } => Some(
+            [
+                Some(app_name.as_str()),
+                title.as_deref(),
+                summary.as_deref(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(": "),
+        ),
+        AppBalloon::Link { title, site, .. } => title.as_ref().map(|title| match site {
+            Some(site) => format!("🔗 {} ({})", title, site),
+            None => format!("🔗 {}", title),
+        }),
+        AppBalloon::LocationSharing { .. } => None,
+    }
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+<title>{}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{}
+</body>
+</html>
+"#,
+        html_escape(title),
+        body
+    )
+}
+
+fn build_nav(title: &str, chapters: &[Chapter]) -> String {
+    let items: String = chapters
+        .iter()
+        .map(|chapter| {
+            format!(
+                "<li><a href=\"{}.xhtml\">{}</a></li>\n",
+                chapter.id,
+                html_escape(&chapter.title)
+            )
+        })
+        .collect();
+
+    xhtml_page(
+        title,
+        &format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n{}</ol>\n</nav>",
+            items
+        ),
+    )
+}
+
+/// EPUB 2 table of contents, for older readers that don't understand nav.xhtml
+fn build_ncx(book_id: &str, title: &str, chapters: &[Chapter]) -> String {
+    let nav_points: String = chapters
+        .iter()
+        .enumerate()
+        .map(|(i, chapter)| {
+            format!(
+                r#"    <navPoint id="nav-{0}" playOrder="{0}"><navLabel><text>{1}</text></navLabel><content src="{2}.xhtml"/></navPoint>
+"#,
+                i + 1,
+                html_escape(&chapter.title),
+                chapter.id
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head><meta name="dtb:uid" content="{}"/></head>
+  <docTitle><text>{}</text></docTitle>
+  <navMap>
+{}  </navMap>
+</ncx>
+"#,
+        html_escape(book_id),
+        html_escape(title),
+        nav_points
+    )
+}
+
+fn build_opf(book_id: &str, title: &str, chapters: &[Chapter], images: &[BookImage]) -> String {
+    let mut manifest = String::from(
+        r#"    <item id="css" href="style.css" media-type="text/css"/>
+    <item id="title" href="title.xhtml" media-type="application/xhtml+xml"/>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+"#,
+    );
+    let mut spine = String::from(
+        r#"    <itemref idref="title"/>
+    <itemref idref="nav"/>
+"#,
+    );
+
+    for chapter in chapters {
+        manifest.push_str(&format!(
+            "    <item id=\"{0}\" href=\"{0}.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            chapter.id
+        ));
+        spine.push_str(&format!("    <itemref idref=\"{}\"/>\n", chapter.id));
+    }
+    for (i, image) in images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"img-{}\" href=\"images/{}\" media-type=\"image/jpeg\"/>\n",
+            i + 1,
+            image.filename
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:language>en</dc:language>
+    <meta property="dcterms:modified">{}</meta>
+  </metadata>
+  <manifest>
+{}  </manifest>
+  <spine toc="ncx">
+{}  </spine>
+</package>
+"#,
+        html_escape(book_id),
+        html_escape(title),
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        manifest,
+        spine
+    )
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Exit code for any error without a more specific one below
+pub const EXIT_FAILURE: u8 = 1;
+
+/// Failures a wrapper script or embedder may want to handle differently, each with
+/// guidance on how to fix it and its own exit code. Other errors are plain `anyhow`
+/// errors and exit with `EXIT_FAILURE`.
+#[derive(Debug)]
+pub enum ExportError {
+    /// Options that can't be used together, or a subcommand given the wrong arguments
+    InvalidArguments(String),
+    /// No file at the database path
+    DatabaseNotFound(PathBuf),
+    /// The file exists but macOS won't let this process read it
+    PermissionDenied(PathBuf),
+    /// The file is readable but isn't a SQLite database
+    NotADatabase(PathBuf),
+    /// Contacts access was denied to the helper
+    ContactsUnavailable,
+    /// The `swift` command used to read Contacts isn't installed
+    SwiftNotFound,
+    /// The database opened, but a table in it couldn't be read
+    DatabaseRead(String),
+    /// The output directory or archive already holds an export
+    OutputExists(PathBuf),
+    /// Another export is writing to the same output; `pid` is its process ID if known
+    OutputLocked {
+        lock_file: PathBuf,
+        pid: Option<u32>,
+    },
+    /// The export completed, but some messages are missing parts, listed in the `log`
+    /// file, or only on stderr when it's `None`
+    PartialFailure { count: usize, log: Option<PathBuf> },
+    /// `verify` found differences between an export, its manifest and the database
+    VerifyMismatch(PathBuf),
+}
+
+impl ExportError {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            ExportError::InvalidArguments(_) => 2,
+            ExportError::DatabaseNotFound(_) => 3,
+            ExportError::PermissionDenied(_) => 4,
+            ExportError::NotADatabase(_) => 5,
+            ExportError::ContactsUnavailable => 6,
+            ExportError::SwiftNotFound => 7,
+            ExportError::DatabaseRead(_) => 8,
+            ExportError::OutputExists(_) => 9,
+            ExportError::OutputLocked { .. } => 10,
+            ExportError::PartialFailure { .. } => 11,
+            ExportError::VerifyMismatch(_) => 12,
+        })
+    }
+
+    /// Exit code for any error: its `ExportError`'s, or `EXIT_FAILURE`
+    pub fn exit_code_of(error: &anyhow::Error) -> ExitCode {
+        match error.downcast_ref::<ExportError>() {
+            Some(export_error) => export_error.exit_code(),
+            None => ExitCode::from(EXIT_FAILURE),
+        }
+    }
+
+    /// A `DatabaseRead` from the database library's errors, which carry no source chain
+    pub fn database(error: impl fmt::Display) -> Self {
+        ExportError::DatabaseRead(error.to_string())
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::InvalidArguments(message) => write!(f, "{}", message),
+            ExportError::DatabaseNotFound(path) => write!(
+                f,
+                "No database found at {}. Sign in to Messages on this Mac, or point --database-path at a chat.db",
+                path.display()
+            ),
+            ExportError::PermissionDenied(path) => write!(
+                f,
+                "Permission denied reading {}. Grant Full Disk Access to your terminal app in System Settings > Privacy & Security > Full Disk Access, then restart the terminal",
+                path.display()
+            ),
+            ExportError::NotADatabase(path) => write!(
+                f,
+                "{} is not a SQLite database. Point --database-path at a chat.db",
+                path.display()
+            ),
+            ExportError::ContactsUnavailable => write!(
+                f,
+                "Contacts access was denied. Allow your terminal app in System Settings > Privacy & Security > Contacts, then restart the terminal"
+            ),
+            ExportError::SwiftNotFound => write!(
+                f,
+                "The swift command is needed to read Contacts. Install the Xcode Command Line Tools with `xcode-select --install`"
+            ),
+            ExportError::DatabaseRead(message) => {
+                write!(f, "Unable to read the database: {}", message)
+            }
+            ExportError::OutputExists(path) => write!(
+                f,
+                "Output '{}' already exists. Please remove it or specify a different output with --output-directory or --output-archive",
+                path.display()
+            ),
+            ExportError::OutputLocked { lock_file, pid } => write!(
+                f,
+                "Another export is writing to this output (process {}). If none is running, delete {}",
+                pid.map_or_else(|| "unknown".to_string(), |pid| pid.to_string()),
+                lock_file.display()
+            ),
+            ExportError::PartialFailure {
+                count,
+                log: Some(log),
+            } => write!(
+                f,
+                "{} message(s) could not be exported completely; see {}",
+                count,
+                log.display()
+            ),
+            ExportError::PartialFailure { count, log: None } => write!(
+                f,
+                "{} message(s) could not be exported completely; see the errors above",
+                count
+            ),
+            ExportError::VerifyMismatch(path) => write!(
+                f,
+                "Export '{}' does not match its manifest and the database",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
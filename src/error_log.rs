@@ -0,0 +1,65 @@
+use crate::events::{ExportEvent, ExportObserver};
+use anyhow::{Error, Result};
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// Name of the report written at the root of an export that skipped anything
+pub const ERRORS_FILENAME: &str = "errors.log";
+
+/// Failures that only affect a single message, collected so the rest of the export can
+/// go ahead. In strict mode the first failure aborts the export instead.
+pub struct ErrorLog {
+    strict: bool,
+    entries: Vec<String>,
+    /// Told about each failure as a `Warning` as it's recorded
+    observer: Option<Arc<dyn ExportObserver>>,
+}
+
+impl ErrorLog {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            entries: Vec::new(),
+            observer: None,
+        }
+    }
+
+    pub fn set_observer(&mut self, observer: Arc<dyn ExportObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /// Records a failure in `what` (e.g. a message GUID). Returns the error in strict
+    /// mode, so callers can `?` it to abort.
+    pub fn record(&mut self, what: impl Display, error: Error) -> Result<()> {
+        if self.strict {
+            return Err(error.context(what.to_string()));
+        }
+        let entry = format!("{}: {:#}", what, error);
+        if let Some(observer) = &self.observer {
+            observer.on_event(&ExportEvent::Warning {
+                message: entry.clone(),
+            });
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// One failure per line
+    pub fn to_log(&self) -> String {
+        let mut log = self.entries.join("\n");
+        log.push('\n');
+        log
+    }
+}
@@ -0,0 +1,48 @@
+use serde::Serialize;
+use std::io::Write;
+use std::sync::mpsc::Sender;
+
+/// Something that happened during an export, for showing progress or reacting to problems
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ExportEvent {
+    /// An output format started writing a chat
+    ChatStarted { chat: String, message_count: usize },
+    /// A message was written to the export
+    MessageProcessed { chat: String, guid: String },
+    /// An attachment file was written, at `path` relative to the export root
+    AttachmentSaved { path: String, size: u64 },
+    /// Part of a message couldn't be exported and was recorded in `errors.log`
+    Warning { message: String },
+}
+
+/// Receives the events of an export as they happen. Set one on the `OutputTarget` and
+/// `ErrorLog` an export uses. Implemented for closures and for channel senders, so the
+/// events can be handled on another thread.
+pub trait ExportObserver: Send + Sync {
+    fn on_event(&self, event: &ExportEvent);
+}
+
+impl<F: Fn(&ExportEvent) + Send + Sync> ExportObserver for F {
+    fn on_event(&self, event: &ExportEvent) {
+        self(event)
+    }
+}
+
+impl ExportObserver for Sender<ExportEvent> {
+    fn on_event(&self, event: &ExportEvent) {
+        // Nobody listening any more isn't the export's problem
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Prints each event as a line of JSON on stderr, for `--progress`
+pub struct JsonLinesObserver;
+
+impl ExportObserver for JsonLinesObserver {
+    fn on_event(&self, event: &ExportEvent) {
+        if let Ok(json) = serde_json::to_string(event) {
+            let _ = writeln!(std::io::stderr().lock(), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{HashMap, HashSet};
+
+/// Longest file name handed out, in bytes. File systems allow 255; this leaves room for
+/// suffixes like ` (2)` and `.media.html`.
+const MAX_FILENAME_BYTES: usize = 200;
+
+/// Extensions longer than this are treated as part of the name when truncating
+const MAX_EXTENSION_BYTES: usize = 16;
+
+/// Device names Windows reserves, with or without an extension
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Makes `name` safe to use as a file name on macOS, Windows and Linux: characters that
+/// aren't allowed are replaced with `_`, as are leading dots (which would hide the file);
+/// trailing spaces and dots are removed, Windows device names get a `_` and long names
+/// are shortened, keeping their extension.
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            _ => c,
+        })
+        .collect();
+
+    if let Some(rest) = sanitized.strip_prefix('.') {
+        sanitized = format!("_{}", rest);
+    }
+
+    let (stem, extension) = split_extension(&sanitized);
+    let mut stem = stem.to_string();
+    if WINDOWS_RESERVED_NAMES.contains(&stem.trim_end().to_uppercase().as_str()) {
+        stem.push('_');
+    }
+
+    let max_stem_bytes = MAX_FILENAME_BYTES.saturating_sub(extension.len());
+    if stem.len() > max_stem_bytes {
+        let mut end = max_stem_bytes;
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+        stem.truncate(end);
+    }
+
+    let sanitized = format!("{}{}", stem, extension);
+    let sanitized = sanitized.trim_end_matches([' ', '.']);
+    if sanitized.is_empty() {
+        "_".to_string()
+    } else {
+        sanitized.to_string()
+    }
+}
+
+/// Splits `filename` into its stem and extension (with the dot), when it has an
+/// extension short enough to keep
+fn split_extension(filename: &str) -> (&str, &str) {
+    match filename.rfind('.') {
+        Some(dot) if dot > 0 && filename.len() - dot <= MAX_EXTENSION_BYTES => {
+            filename.split_at(dot)
+        }
+        _ => (filename, ""),
+    }
+}
+
+/// Hands out sanitized file names that differ from every other name it handed out,
+/// ignoring case like the default macOS and Windows file systems do
+#[derive(Default)]
+pub struct FilenameAllocator {
+    used: HashSet<String>,
+}
+
+impl FilenameAllocator {
+    /// `filename` sanitized, with ` (2)`, ` (3)` etc. added before its extension when
+    /// that name is taken
+    pub fn file(&mut self, filename: &str) -> String {
+        let filename = sanitize_filename(filename);
+        let (stem, extension) = split_extension(&filename);
+        self.allocate(stem, extension)
+    }
+
+    /// `name` sanitized, with ` (2)`, ` (3)` etc. appended when that name is taken. For
+    /// names the caller adds its own extensions to, such as chat pages.
+    pub fn stem(&mut self, name: &str) -> String {
+        self.allocate(&sanitize_filename(name), "")
+    }
+
+    fn allocate(&mut self, stem: &str, extension: &str) -> String {
+        let mut filename = format!("{}{}", stem, extension);
+        let mut counter = 2;
+        while !self.used.insert(filename.to_lowercase()) {
+            filename = format!("{} ({}){}", stem, counter, extension);
+            counter += 1;
+        }
+        filename
+    }
+}
+
+/// Gives each chat a unique file name stem, so two chats whose names sanitize to the
+/// same string don't overwrite each other. Names are handed out in sorted order, so a
+/// chat keeps its file name from one export to the next.
+pub fn chat_stems<'a>(chat_keys: impl IntoIterator<Item = &'a String>) -> HashMap<String, String> {
+    let mut chat_keys: Vec<&String> = chat_keys.into_iter().collect();
+    chat_keys.sort();
+
+    let mut allocator = FilenameAllocator::default();
+    chat_keys
+        .into_iter()
+        .map(|chat_key| (chat_key.clone(), allocator.stem(chat_key)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_names() {
+        assert_eq!(sanitize_filename("CON"), "CON_");
+        assert_eq!(sanitize_filename("con.txt"), "con_.txt");
+        assert_eq!(sanitize_filename("lpt1 "), "lpt1 _");
+        assert_eq!(sanitize_filename("CONSOLE"), "CONSOLE");
+    }
+
+    #[test]
+    fn test_leading_dots() {
+        assert_eq!(sanitize_filename(".hidden"), "_hidden");
+        assert_eq!(sanitize_filename("..x"), "_.x");
+        assert_eq!(sanitize_filename("..."), "_");
+    }
+
+    #[test]
+    fn test_trailing_spaces_and_dots() {
+        assert_eq!(sanitize_filename("report.pdf "), "report.pdf");
+        assert_eq!(sanitize_filename("name. . "), "name");
+        assert_eq!(sanitize_filename(" . "), "_");
+    }
+
+    #[test]
+    fn test_truncates_at_char_boundary() {
+        let long = format!("a{}", "é".repeat(150));
+        let truncated = sanitize_filename(&long);
+        assert_eq!(truncated, format!("a{}", "é".repeat(99)));
+
+        let truncated = sanitize_filename(&format!("{}.txt", long));
+        assert_eq!(truncated, format!("a{}.txt", "é".repeat(97)));
+        assert!(truncated.len() <= MAX_FILENAME_BYTES);
+    }
+
+    #[test]
+    fn test_case_insensitive_collisions() {
+        let mut allocator = FilenameAllocator::default();
+        assert_eq!(allocator.file("a"), "a");
+        assert_eq!(allocator.file("A"), "A (2)");
+        assert_eq!(allocator.file("Photo.JPG"), "Photo.JPG");
+        assert_eq!(allocator.file("photo.jpg"), "photo (2).jpg");
+        assert_eq!(allocator.stem("a"), "a (3)");
+    }
+}
@@ -0,0 +1,473 @@
+use anyhow::{Context, Result};
+use plist::{Dictionary, Value};
+use rusqlite::{Connection, params};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Name of the synthetic database [`generate`] writes
+pub const FIXTURE_DATABASE: &str = "chat.db";
+/// Folder [`generate`] writes attachment files to, to pass as `--attachment-root`
+pub const FIXTURE_ATTACHMENTS: &str = "Attachments";
+
+/// Seconds between the Unix epoch and 2001-01-01, where Messages dates start
+const APPLE_EPOCH: i64 = 978_307_200;
+/// 2024-03-01 12:00:00 UTC, when the first fixture message was sent
+const FIRST_MESSAGE: i64 = 1_709_294_400;
+
+/// Tables and columns of a macOS 13 `chat.db` that the database library and the
+/// exporter read; columns nothing reads are left out
+const SCHEMA: &str = "
+CREATE TABLE handle (
+    ROWID INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE,
+    id TEXT NOT NULL,
+    country TEXT,
+    service TEXT NOT NULL,
+    uncanonicalized_id TEXT,
+    person_centric_id TEXT
+);
+CREATE TABLE chat (
+    ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+    guid TEXT UNIQUE NOT NULL,
+    style INTEGER,
+    state INTEGER,
+    account_id TEXT,
+    properties BLOB,
+    chat_identifier TEXT,
+    service_name TEXT,
+    room_name TEXT,
+    account_login TEXT,
+    is_archived INTEGER DEFAULT 0,
+    last_addressed_handle TEXT,
+    display_name TEXT,
+    group_id TEXT,
+    is_filtered INTEGER DEFAULT 0,
+    last_read_message_timestamp INTEGER DEFAULT 0
+);
+CREATE TABLE message (
+    ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+    guid TEXT UNIQUE NOT NULL,
+    text TEXT,
+    handle_id INTEGER DEFAULT 0,
+    subject TEXT,
+    attributedBody BLOB,
+    service TEXT,
+    account TEXT,
+    date INTEGER,
+    date_read INTEGER,
+    date_delivered INTEGER,
+    is_delivered INTEGER DEFAULT 0,
+    is_from_me INTEGER DEFAULT 0,
+    is_read INTEGER DEFAULT 0,
+    was_downgraded INTEGER DEFAULT 0,
+    cache_has_attachments INTEGER DEFAULT 0,
+    is_audio_message INTEGER DEFAULT 0,
+    item_type INTEGER DEFAULT 0,
+    other_handle INTEGER DEFAULT 0,
+    group_title TEXT,
+    group_action_type INTEGER DEFAULT 0,
+    share_status INTEGER DEFAULT 0,
+    share_direction INTEGER DEFAULT 0,
+    associated_message_guid TEXT,
+    associated_message_type INTEGER DEFAULT 0,
+    balloon_bundle_id TEXT,
+    payload_data BLOB,
+    expressive_send_style_id TEXT,
+    message_summary_info BLOB,
+    destination_caller_id TEXT,
+    thread_originator_guid TEXT,
+    thread_originator_part TEXT,
+    date_retracted INTEGER DEFAULT 0,
+    date_edited INTEGER DEFAULT 0,
+    part_count INTEGER DEFAULT 0,
+    associated_message_emoji TEXT
+);
+CREATE TABLE attachment (
+    ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+    guid TEXT UNIQUE NOT NULL,
+    created_date INTEGER DEFAULT 0,
+    start_date INTEGER DEFAULT 0,
+    filename TEXT,
+    uti TEXT,
+    mime_type TEXT,
+    transfer_state INTEGER DEFAULT 0,
+    is_outgoing INTEGER DEFAULT 0,
+    transfer_name TEXT,
+    total_bytes INTEGER DEFAULT 0,
+    is_sticker INTEGER DEFAULT 0,
+    sticker_user_info BLOB,
+    attribution_info BLOB,
+    hide_attachment INTEGER DEFAULT 0,
+    emoji_image_short_description TEXT
+);
+CREATE TABLE chat_handle_join (chat_id INTEGER, handle_id INTEGER, UNIQUE(chat_id, handle_id));
+CREATE TABLE chat_message_join (
+    chat_id INTEGER,
+    message_id INTEGER,
+    message_date INTEGER DEFAULT 0,
+    PRIMARY KEY (chat_id, message_id)
+);
+CREATE TABLE message_attachment_join (
+    message_id INTEGER,
+    attachment_id INTEGER,
+    UNIQUE(message_id, attachment_id)
+);
+CREATE TABLE chat_recoverable_message_join (
+    chat_id INTEGER,
+    message_id INTEGER,
+    delete_date INTEGER,
+    ck_sync_state INTEGER DEFAULT 0,
+    PRIMARY KEY (chat_id, message_id)
+);
+";
+
+/// Where [`generate`] wrote the fixture
+pub struct Fixture {
+    pub database: PathBuf,
+    pub attachment_root: PathBuf,
+}
+
+/// Writes a small synthetic `chat.db` to `directory`, with an attachment file under
+/// its `Attachments` folder, so the export pipeline can run without a real Messages
+/// database. It holds three chats:
+///
+/// - A direct iMessage chat with Alice (+15555550101): a question, my answer, Alice's
+///   ❤️ tapback on it and my inline reply to her question
+/// - The "Weekend Plans" group with Alice and Bob (bob@example.com): Bob's question, my
+///   answer, edited once after sending, and Alice's photo
+/// - A direct SMS chat with +15555550103, a shipping notification that was deleted and
+///   is in Recently Deleted
+///
+/// Every date is fixed, so exports of the fixture are the same from run to run.
+pub fn generate(directory: &Path) -> Result<Fixture> {
+    let database = directory.join(FIXTURE_DATABASE);
+    let attachment_root = directory.join(FIXTURE_ATTACHMENTS);
+    if database.exists() {
+        fs::remove_file(&database)
+            .with_context(|| format!("Failed to replace {}", database.display()))?;
+    }
+    fs::create_dir_all(directory)
+        .with_context(|| format!("Failed to create {}", directory.display()))?;
+
+    let db = Connection::open(&database)
+        .with_context(|| format!("Failed to create {}", database.display()))?;
+    db.execute_batch(SCHEMA)?;
+
+    for (id, service) in [
+        ("+15555550101", "iMessage"),
+        ("bob@example.com", "iMessage"),
+        ("+15555550103", "SMS"),
+    ] {
+        db.execute(
+            "INSERT INTO handle (id, country, service, uncanonicalized_id) VALUES (?1, 'us', ?2, ?1)",
+            params![id, service],
+        )?;
+    }
+
+    // Style 45 is a direct chat, 43 a group
+    for (guid, style, identifier, service, display_name, handles) in [
+        (
+            "iMessage;-;+15555550101",
+            45,
+            "+15555550101",
+            "iMessage",
+            None,
+            &[1][..],
+        ),
+        (
+            "iMessage;+;chat100000000000000001",
+            43,
+            "chat100000000000000001",
+            "iMessage",
+            Some("Weekend Plans"),
+            &[1, 2][..],
+        ),
+        (
+            "SMS;-;+15555550103",
+            45,
+            "+15555550103",
+            "SMS",
+            None,
+            &[3][..],
+        ),
+    ] {
+        db.execute(
+            "INSERT INTO chat (guid, style, state, chat_identifier, service_name, display_name, account_login)
+             VALUES (?1, ?2, 3, ?3, ?4, ?5, 'E:me@example.com')",
+            params![guid, style, identifier, service, display_name],
+        )?;
+        let chat_id = db.last_insert_rowid();
+        for handle_id in handles {
+            db.execute(
+                "INSERT INTO chat_handle_join (chat_id, handle_id) VALUES (?1, ?2)",
+                params![chat_id, handle_id],
+            )?;
+        }
+    }
+
+    let messages = [
+        FixtureMessage::new(
+            "FIXTURE-0001",
+            1,
+            1,
+            0,
+            "Hey! Are we still on for Saturday?",
+        ),
+        FixtureMessage::new("FIXTURE-0002", 1, 0, 2, "Yes, 10am at the trailhead"),
+        FixtureMessage {
+            associated_message_guid: Some("p:0/FIXTURE-0002"),
+            associated_message_type: 2000,
+            ..FixtureMessage::new(
+                "FIXTURE-0003",
+                1,
+                1,
+                3,
+                "Loved “Yes, 10am at the trailhead”",
+            )
+        },
+        FixtureMessage {
+            thread_originator_guid: Some("FIXTURE-0001"),
+            ..FixtureMessage::new("FIXTURE-0004", 1, 0, 5, "See you there")
+        },
+        FixtureMessage::new("FIXTURE-0005", 2, 2, 60, "Who's bringing snacks?"),
+        FixtureMessage {
+            edits: &["I'll bring chips", "I'll bring trail mix"],
+            ..FixtureMessage::new("FIXTURE-0006", 2, 0, 61, "I'll bring trail mix")
+        },
+        FixtureMessage {
+            attachment: Some("summit.png"),
+            ..FixtureMessage::new("FIXTURE-0007", 2, 1, 90, "Summit view")
+        },
+        FixtureMessage {
+            service: "SMS",
+            deleted: true,
+            ..FixtureMessage::new("FIXTURE-0008", 3, 3, 24 * 60, "Your package has shipped")
+        },
+    ];
+    for message in &messages {
+        message.insert(&db, &attachment_root)?;
+    }
+
+    Ok(Fixture {
+        database,
+        attachment_root,
+    })
+}
+
+/// A message row, from me when `handle_id` is 0
+struct FixtureMessage {
+    guid: &'static str,
+    chat_id: i64,
+    handle_id: i64,
+    /// Minutes after the first message it was sent
+    minutes: i64,
+    text: &'static str,
+    service: &'static str,
+    associated_message_guid: Option<&'static str>,
+    associated_message_type: i64,
+    thread_originator_guid: Option<&'static str>,
+    /// Every version of the text, oldest first, when it was edited
+    edits: &'static [&'static str],
+    attachment: Option<&'static str>,
+    deleted: bool,
+}
+
+impl FixtureMessage {
+    fn new(
+        guid: &'static str,
+        chat_id: i64,
+        handle_id: i64,
+        minutes: i64,
+        text: &'static str,
+    ) -> Self {
+        Self {
+            guid,
+            chat_id,
+            handle_id,
+            minutes,
+            text,
+            service: "iMessage",
+            associated_message_guid: None,
+            associated_message_type: 0,
+            thread_originator_guid: None,
+            edits: &[],
+            attachment: None,
+            deleted: false,
+        }
+    }
+
+    fn insert(&self, db: &Connection, attachment_root: &Path) -> Result<()> {
+        let date = apple_date(self.minutes);
+        let is_from_me = self.handle_id == 0;
+        let edited = !self.edits.is_empty();
+        db.execute(
+            "INSERT INTO message (guid, text, handle_id, service, account, date, date_read,
+                 date_delivered, is_delivered, is_from_me, is_read, cache_has_attachments,
+                 associated_message_guid, associated_message_type, message_summary_info,
+                 destination_caller_id, thread_originator_guid, thread_originator_part,
+                 date_edited, part_count)
+             VALUES (?1, ?2, ?3, ?4, 'E:me@example.com', ?5, ?6, ?6, 1, ?7, 1, ?8, ?9, ?10, ?11,
+                 'me@example.com', ?12, ?13, ?14, 1)",
+            params![
+                self.guid,
+                self.text,
+                self.handle_id,
+                self.service,
+                date,
+                date + 30_000_000_000,
+                is_from_me,
+                self.attachment.is_some(),
+                self.associated_message_guid,
+                self.associated_message_type,
+                edited.then(|| edit_history(self.edits, date)).transpose()?,
+                self.thread_originator_guid,
+                self.thread_originator_guid.map(|_| "0:0:0"),
+                if edited { date + 60_000_000_000 } else { 0 },
+            ],
+        )?;
+        let message_id = db.last_insert_rowid();
+        // Deleting a message moves it out of its chat into Recently Deleted
+        if self.deleted {
+            db.execute(
+                "INSERT INTO chat_recoverable_message_join (chat_id, message_id, delete_date)
+                 VALUES (?1, ?2, ?3)",
+                params![self.chat_id, message_id, date + 3_600_000_000_000],
+            )?;
+        } else {
+            db.execute(
+                "INSERT INTO chat_message_join (chat_id, message_id, message_date) VALUES (?1, ?2, ?3)",
+                params![self.chat_id, message_id, date],
+            )?;
+        }
+
+        if let Some(filename) = self.attachment {
+            let guid = format!("{}-ATTACHMENT", self.guid);
+            let folder = Path::new("fi/00").join(&guid);
+            let bytes = png()?;
+            fs::create_dir_all(attachment_root.join(&folder))?;
+            fs::write(attachment_root.join(&folder).join(filename), &bytes)?;
+            db.execute(
+                "INSERT INTO attachment (guid, created_date, start_date, filename, uti, mime_type,
+                     transfer_state, is_outgoing, transfer_name, total_bytes)
+                 VALUES (?1, ?2, ?2, ?3, 'public.png', 'image/png', 5, ?4, ?5, ?6)",
+                params![
+                    guid,
+                    date / 1_000_000_000,
+                    format!(
+                        "~/Library/Messages/Attachments/{}/{}",
+                        folder.display(),
+                        filename
+                    ),
+                    is_from_me,
+                    filename,
+                    bytes.len() as i64,
+                ],
+            )?;
+            db.execute(
+                "INSERT INTO message_attachment_join (message_id, attachment_id) VALUES (?1, ?2)",
+                params![message_id, db.last_insert_rowid()],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Nanoseconds since 2001, as Messages stores dates, `minutes` after the first message
+fn apple_date(minutes: i64) -> i64 {
+    (FIRST_MESSAGE - APPLE_EPOCH + minutes * 60) * 1_000_000_000
+}
+
+/// `message_summary_info` for a message edited through `edits`: the text of each
+/// version of its one part, a minute apart
+fn edit_history(edits: &[&str], sent: i64) -> Result<Vec<u8>> {
+    let versions = edits
+        .iter()
+        .zip(0..)
+        .map(|(text, index)| {
+            let mut version = Dictionary::new();
+            version.insert(
+                "d".to_string(),
+                Value::Integer((sent + index * 60_000_000_000).into()),
+            );
+            version.insert("t".to_string(), Value::Data(typedstream(text)));
+            Value::Dictionary(version)
+        })
+        .collect();
+
+    let mut edited_parts = Dictionary::new();
+    edited_parts.insert("0".to_string(), Value::Array(versions));
+    let mut summary = Dictionary::new();
+    summary.insert("ec".to_string(), Value::Dictionary(edited_parts));
+    summary.insert(
+        "ep".to_string(),
+        Value::Array(vec![Value::Integer(0.into())]),
+    );
+
+    let mut bytes = Vec::new();
+    plist::to_writer_binary(&mut bytes, &Value::Dictionary(summary))?;
+    Ok(bytes)
+}
+
+/// `text` as the `NSAttributedString` typedstream Messages writes for unstyled text
+fn typedstream(text: &str) -> Vec<u8> {
+    let mut stream = b"\x04\x0bstreamtyped\x81\xe8\x03\x84\x01@\x84\x84\x84\x12NSAttributedString\x00\x84\x84\x08NSObject\x00\x85\x92\x84\x84\x84\x08NSString\x01\x94\x84\x01+".to_vec();
+    push_length(&mut stream, text.len());
+    stream.extend(text.as_bytes());
+    stream.extend(b"\x86\x84\x02iI\x01");
+    push_length(&mut stream, text.encode_utf16().count());
+    stream.extend(b"\x92\x84\x84\x84\x0cNSDictionary\x00\x94\x84\x01i\x01\x92\x84\x96\x96\x1d__kIMMessagePartAttributeName\x86\x92\x84\x84\x84\x08NSNumber\x00\x84\x84\x07NSValue\x00\x94\x84\x01*\x84\x9b\x9b\x00\x86\x86\x86");
+    stream
+}
+
+/// A typedstream length: one byte, or 0x81 and two little-endian bytes
+fn push_length(stream: &mut Vec<u8>, length: usize) {
+    if length < 0x80 {
+        stream.push(length as u8);
+    } else {
+        stream.push(0x81);
+        stream.extend((length as u16).to_le_bytes());
+    }
+}
+
+/// A small PNG to stand in for a photo
+fn png() -> Result<Vec<u8>> {
+    let image = image::RgbImage::from_fn(4, 4, |x, y| {
+        image::Rgb([(x * 60) as u8, (y * 60) as u8, 180])
+    });
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, image::ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imessage_database::tables::messages::Message;
+    use imessage_database::tables::table::Table;
+
+    #[test]
+    fn test_generate() {
+        let directory = std::env::temp_dir().join(format!(
+            "imessage_extractor-{}-fixture-test",
+            std::process::id()
+        ));
+        let fixture = generate(&directory).unwrap();
+        let db = Connection::open(&fixture.database).unwrap();
+
+        let mut texts = Vec::new();
+        Message::stream(&db, |message| {
+            texts.push(message.unwrap().text.unwrap_or_default());
+            Ok::<(), imessage_database::error::table::TableError>(())
+        })
+        .unwrap();
+        assert_eq!(texts.len(), 8);
+        assert_eq!(texts[0], "Hey! Are we still on for Saturday?");
+        assert!(
+            fixture
+                .attachment_root
+                .join("fi/00/FIXTURE-0007-ATTACHMENT/summit.png")
+                .exists()
+        );
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
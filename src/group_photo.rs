@@ -0,0 +1,65 @@
+use crate::database_source::{AttachmentFile, DatabaseSource};
+use crate::filename::sanitize_filename;
+use chrono::{DateTime, Local};
+use imessage_database::tables::attachment::{Attachment, MediaType};
+use imessage_database::tables::messages::Message;
+use rusqlite::Connection;
+
+/// `item_type` of messages that change a group chat's name or photo
+const GROUP_CHANGE_ITEM_TYPE: i32 = 3;
+/// `group_action_type` of a group change that sets a new photo
+const PHOTO_SET: i32 = 1;
+/// `group_action_type` of a group change that removes the photo
+const PHOTO_REMOVED: i32 = 2;
+
+/// The custom photo a group chat's members gave it
+#[derive(Clone, Debug)]
+pub struct GroupPhoto {
+    /// Sanitized file name of the image
+    pub filename: String,
+    pub file: AttachmentFile,
+}
+
+/// A group chat's photo being set or removed, at `date`
+#[derive(Clone, Debug)]
+pub struct GroupPhotoChange {
+    pub date: DateTime<Local>,
+    pub photo: Option<GroupPhoto>,
+}
+
+impl GroupPhotoChange {
+    /// The change `message` makes to its chat's photo, if it's a photo change. A new photo
+    /// whose file isn't on this computer counts as no photo.
+    pub fn from_message(
+        db: &Connection,
+        source: &DatabaseSource,
+        message: &Message,
+    ) -> Option<Self> {
+        if message.item_type != GROUP_CHANGE_ITEM_TYPE
+            || !matches!(message.group_action_type, PHOTO_SET | PHOTO_REMOVED)
+        {
+            return None;
+        }
+        let database_tz_offset = imessage_database::util::dates::get_offset();
+        let date = message.date(&database_tz_offset).ok()?;
+
+        let photo = if message.group_action_type == PHOTO_SET && message.has_attachments() {
+            Attachment::from_message(db, message)
+                .ok()
+                .and_then(|attachments| {
+                    attachments
+                        .into_iter()
+                        .find(|attachment| matches!(attachment.mime_type(), MediaType::Image(_)))
+                })
+                .and_then(|attachment| {
+                    let filename = sanitize_filename(attachment.filename()?);
+                    let file = source.attachment_file(&attachment).ok()??;
+                    Some(GroupPhoto { filename, file })
+                })
+        } else {
+            None
+        };
+
+        Some(Self { date, photo })
+    }
+}
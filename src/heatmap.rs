@@ -0,0 +1,206 @@
+use crate::clean_message::CleanMessage;
+use crate::markup::html_escape;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Styles for the activity calendar on the index page
+pub const HEATMAP_CSS: &str = r#"
+        .heatmap-box {
+            margin-bottom: 20px;
+            padding: 12px 16px;
+            background: white;
+            border-radius: 12px;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.1);
+            overflow-x: auto;
+        }
+
+        .heatmap-header {
+            display: flex;
+            justify-content: space-between;
+            align-items: center;
+            font-weight: 600;
+            color: #333;
+            margin-bottom: 8px;
+        }
+
+        .heatmap-year {
+            font-size: 0.8em;
+            color: #666;
+            margin: 8px 0 4px;
+        }
+
+        .heatmap-grid {
+            display: grid;
+            grid-template-rows: repeat(7, 11px);
+            grid-auto-flow: column;
+            grid-auto-columns: 11px;
+            gap: 2px;
+        }
+
+        .heatmap-cell {
+            display: block;
+            border-radius: 2px;
+            background-color: #ebedf0;
+        }
+
+        .heatmap-cell.level-1 { background-color: #b3d7ff; }
+        .heatmap-cell.level-2 { background-color: #66afff; }
+        .heatmap-cell.level-3 { background-color: #1a87ff; }
+        .heatmap-cell.level-4 { background-color: #0056b3; }
+
+        html[data-theme="dark"] .heatmap-box {
+            background: #1c1c1e;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.5);
+        }
+
+        html[data-theme="dark"] .heatmap-header {
+            color: #f5f5f7;
+        }
+
+        html[data-theme="dark"] .heatmap-year {
+            color: #98989d;
+        }
+
+        html[data-theme="dark"] .heatmap-cell {
+            background-color: #2c2c2e;
+        }
+"#;
+
+/// Draws one grid per year (a column per week, Sunday at the top) for the chat picked in
+/// the selector, or for all chats combined. Each day with messages links to that day in
+/// the chat, or for all chats, to the chat with the most messages that day.
+const HEATMAP_SCRIPT: &str = r#"
+        function renderHeatmap() {
+            const selected = document.getElementById('heatmapChat').value;
+            const chats = selected === '' ? HEATMAP_DATA : [HEATMAP_DATA[Number(selected)]];
+
+            // Total per day, remembering the busiest chat to link to
+            const days = {};
+            chats.forEach(function(chat) {
+                chat.pages.forEach(function(page) {
+                    Object.keys(page.days).forEach(function(day) {
+                        const count = page.days[day];
+                        const entry = days[day] || (days[day] = { count: 0, busiest: 0, file: '' });
+                        entry.count += count;
+                        if (count > entry.busiest) {
+                            entry.busiest = count;
+                            entry.file = page.file;
+                        }
+                    });
+                });
+            });
+
+            const max = Math.max(1, ...Object.values(days).map(function(d) { return d.count; }));
+            const years = [...new Set(Object.keys(days).map(function(day) { return day.slice(0, 4); }))];
+            years.sort().reverse();
+
+            const container = document.getElementById('heatmap');
+            container.innerHTML = '';
+            years.forEach(function(year) {
+                const label = document.createElement('div');
+                label.className = 'heatmap-year';
+                label.textContent = year;
+                container.appendChild(label);
+
+                const grid = document.createElement('div');
+                grid.className = 'heatmap-grid';
+                const date = new Date(Number(year), 0, 1);
+                for (let i = 0; i < date.getDay(); i++) {
+                    grid.appendChild(document.createElement('span'));
+                }
+                while (date.getFullYear() === Number(year)) {
+                    const key = year + '-' + String(date.getMonth() + 1).padStart(2, '0') + '-' + String(date.getDate()).padStart(2, '0');
+                    const entry = days[key];
+                    const cell = document.createElement(entry ? 'a' : 'span');
+                    cell.className = 'heatmap-cell';
+                    if (entry) {
+                        cell.classList.add('level-' + Math.ceil(4 * entry.count / max));
+                        cell.href = entry.file + '#day-' + key;
+                        cell.title = date.toDateString() + ': ' + entry.count + ' messages';
+                    } else {
+                        cell.title = date.toDateString();
+                    }
+                    grid.appendChild(cell);
+                    date.setDate(date.getDate() + 1);
+                }
+                container.appendChild(grid);
+            });
+        }
+
+        renderHeatmap();
+"#;
+
+#[derive(Serialize)]
+struct HeatmapChat<'a> {
+    pages: Vec<HeatmapPage<'a>>,
+}
+
+#[derive(Serialize)]
+struct HeatmapPage<'a> {
+    /// Transcript page, relative to the index
+    file: &'a str,
+    /// Message count per `YYYY-MM-DD`
+    days: BTreeMap<String, usize>,
+}
+
+/// A transcript page, relative to the index, and the messages on it
+pub type PageMessages<'a, 'b> = (String, &'a [&'b CleanMessage]);
+
+/// Anchor id of the date separator that starts `date`'s messages in a transcript
+pub fn day_anchor(date: &chrono::DateTime<chrono::Local>) -> String {
+    date.format("day-%Y-%m-%d").to_string()
+}
+
+/// The activity calendar section of the index page, for `chats` given as
+/// `(display name, pages)`
+pub fn heatmap_html(chats: &[(&str, Vec<PageMessages>)]) -> String {
+    let data: Vec<HeatmapChat> = chats
+        .iter()
+        .map(|(_, pages)| HeatmapChat {
+            pages: pages
+                .iter()
+                .map(|(file, messages)| {
+                    let mut days = BTreeMap::new();
+                    for message in *messages {
+                        *days
+                            .entry(message.date.format("%Y-%m-%d").to_string())
+                            .or_insert(0) += 1;
+                    }
+                    HeatmapPage { file, days }
+                })
+                .collect(),
+        })
+        .collect();
+
+    let mut options = String::new();
+    for (index, (name, _)) in chats.iter().enumerate() {
+        options.push_str(&format!(
+            r#"                <option value="{}">{}</option>
+"#,
+            index,
+            html_escape(name)
+        ));
+    }
+
+    // Keep a chat name containing "</script>" from ending the script early
+    let json = serde_json::to_string(&data)
+        .unwrap_or_else(|_| "[]".to_string())
+        .replace("</", "<\\/");
+
+    format!(
+        r#"    <div class="heatmap-box">
+        <div class="heatmap-header">
+            <span>Activity</span>
+            <select id="heatmapChat" onchange="renderHeatmap()">
+                <option value="">All chats</option>
+{}            </select>
+        </div>
+        <div id="heatmap"></div>
+    </div>
+    <script>
+        const HEATMAP_DATA = {};
+{}    </script>
+"#,
+        options, json, HEATMAP_SCRIPT
+    )
+}
@@ -1,124 +1,329 @@
+use crate::app_balloon::{AppBalloon, is_safe_link};
+use crate::attachment_store::{ExtractedAttachments, read_for_export};
+use crate::chat_stats::{ChatStats, TOP_TERMS, format_duration, format_terms};
+use crate::clean_attachment::{CleanAttachment, MediaKind};
 use crate::clean_message::CleanMessage;
+use crate::emoji::is_jumbo_emoji;
+use crate::error_log::ErrorLog;
+use crate::events::ExportEvent;
+use crate::filename::{FilenameAllocator, sanitize_filename};
+use crate::group_photo::GroupPhoto;
+use crate::heatmap::{HEATMAP_CSS, day_anchor, heatmap_html};
+use crate::html_render::{
+    AttachmentKind, AttachmentView, Bubble, ChatHeader, Footer, MediaLink, MentionsFilter,
+    PageLink, PageNav, Participants, Render, Tapbacks, file_icon, tapback_image_path,
+};
+use crate::index_sort::IndexSort;
+use crate::layout::{Layout, join};
+use crate::locale::{Locale, Strings};
+use crate::markup::{data_uri, html_escape};
+use crate::membership::{MembershipChange, members};
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::output_target::OutputTarget;
+use crate::search_page::{MATCH_SCRIPT, SEARCH_DATA_FILENAME, search_data_js, search_page_html};
+use crate::split_by::SplitBy;
+use crate::tapback_emoji::TapbackEmoji;
+use crate::text_span::{animation_class, style_tag};
+use crate::theme::Theme;
+use crate::vcard::VCard;
+use crate::word_frequency::Frequencies;
 use anyhow::Result;
-use imessage_database::util::platform::Platform;
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use std::path::Path;
+
+/// Longest edge, in pixels, of the saved copy of a group chat's photo
+const GROUP_PHOTO_MAX_DIMENSION: u32 = 256;
+
+pub const STATS_FILENAME: &str = "stats.html";
+
+pub const ON_THIS_DAY_FILENAME: &str = "on-this-day.html";
+
+/// Directory of the contact pages, one per person who wrote in an exported chat
+const CONTACTS_DIR: &str = "contacts";
+
+/// Search box at the top of a chat page: highlights the messages whose text contains
+/// what's typed, counts them and steps through them; "Only matches" hides the rest
+fn chat_search_html(strings: &Strings) -> String {
+    format!(
+        r#"    <div class="chat-search">
+        <input type="search" id="chatSearchInput" placeholder="{}" oninput="chatSearch()" onkeydown="chatSearchKey(event)">
+        <span id="chatSearchCount" class="chat-search-count"></span>
+        <button type="button" onclick="chatSearchStep(-1)" title="Previous match">↑</button>
+        <button type="button" onclick="chatSearchStep(1)" title="Next match">↓</button>
+        <label><input type="checkbox" id="chatSearchFilter" onchange="chatSearch()"> {}</label>
+    </div>
+"#,
+        strings.search_page_placeholder, strings.only_matches
+    )
+}
+
+const CHAT_SEARCH_SCRIPT: &str = r#"    <script>
+        let chatSearchMatches = [];
+        let chatSearchIndex = -1;
+
+        function chatSearch() {
+            const terms = document.getElementById('chatSearchInput').value.toLowerCase().split(/\s+/).filter(Boolean);
+            const onlyMatches = document.getElementById('chatSearchFilter').checked;
+            chatSearchMatches = [];
+            document.querySelectorAll('.message, .system-event').forEach(function(message) {
+                const texts = message.querySelectorAll('.message-text, .recognized-text div');
+                const text = Array.from(texts).map(function(t) { return t.textContent; }).join('\n').toLowerCase();
+                const matches = terms.length > 0 && terms.every(function(term) { return text.includes(term); });
+                message.classList.toggle('search-match', matches);
+                message.classList.remove('search-current');
+                message.classList.toggle('hidden', terms.length > 0 && onlyMatches && !matches);
+                if (matches) {
+                    chatSearchMatches.push(message);
+                }
+            });
+            document.querySelectorAll('.date-separator').forEach(function(separator) {
+                separator.classList.toggle('hidden', terms.length > 0 && onlyMatches);
+            });
+
+            const count = document.getElementById('chatSearchCount');
+            count.textContent = terms.length === 0 ? '' : chatSearchMatches.length + ' matching';
+            chatSearchIndex = -1;
+            if (chatSearchMatches.length > 0) {
+                chatSearchStep(1);
+            }
+        }
+
+        function chatSearchStep(step) {
+            if (chatSearchMatches.length === 0) {
+                return;
+            }
+            if (chatSearchIndex >= 0) {
+                chatSearchMatches[chatSearchIndex].classList.remove('search-current');
+            }
+            chatSearchIndex = (chatSearchIndex + step + chatSearchMatches.length) % chatSearchMatches.length;
+            const current = chatSearchMatches[chatSearchIndex];
+            current.classList.add('search-current');
+            current.scrollIntoView({ block: 'center' });
+            document.getElementById('chatSearchCount').textContent =
+                (chatSearchIndex + 1) + ' of ' + chatSearchMatches.length;
+        }
+
+        // Enter steps to the next match, Shift+Enter to the previous one
+        function chatSearchKey(event) {
+            if (event.key === 'Enter') {
+                event.preventDefault();
+                chatSearchStep(event.shiftKey ? -1 : 1);
+            }
+        }
+    </script>
+"#;
+
+/// Lightbox for viewing full-size images, then the end of a transcript page
+const TRANSCRIPT_FOOT: &str = r#"    <div id="lightbox" class="lightbox" onclick="closeLightbox()">
+        <img id="lightboxImage" alt="">
+    </div>
+
+    <script>
+        function openLightbox(link) {
+            document.getElementById('lightboxImage').src = link.href;
+            document.getElementById('lightbox').classList.add('open');
+            return false;
+        }
+
+        function closeLightbox() {
+            document.getElementById('lightbox').classList.remove('open');
+            document.getElementById('lightboxImage').removeAttribute('src');
+        }
+
+        document.addEventListener('keydown', function(event) {
+            if (event.key === 'Escape') {
+                closeLightbox();
+            }
+        });
+    </script>
+</body>
+</html>
+"#;
+
+/// Presentation options for the generated HTML
+#[derive(Default)]
+pub struct HtmlOptions {
+    pub theme: Theme,
+    /// Leave delivered/read receipts off sent messages
+    pub skip_receipts: bool,
+    /// Animate an approximation of slam, invisible ink, etc. on bubbles sent with an effect
+    pub animate_effects: bool,
+    /// Show the last known text of unsent messages alongside the placeholder
+    pub recover_unsent: bool,
+    /// Give each chat a directory with one page per year or month, plus an index
+    pub split_by: Option<SplitBy>,
+    /// Also write timeline.html, every chat interleaved in date order
+    pub timeline: bool,
+    /// Day of the year on-this-day.html shows, from the month and day of this date;
+    /// `None` for today
+    pub on_this_day: Option<NaiveDate>,
+    /// Remove EXIF, GPS and device metadata from inline attachments
+    pub strip_metadata: bool,
+    /// Group chats with the same participants were merged; show which chats each came from
+    pub merge_duplicate_groups: bool,
+    /// Show links as plain text rather than as preview cards
+    pub skip_link_previews: bool,
+    /// Embed attachments in the page as data URIs instead of linking to saved files, for
+    /// a single-file excerpt
+    pub inline_attachments: bool,
+    /// Language of the labels and dates
+    pub locale: Locale,
+    /// Where the pages and attachments are written
+    pub layout: Layout,
+    /// Order of the chats on the index page
+    pub index_sort: IndexSort,
+    /// Names of the chats listed first on the index page, in this order
+    pub pinned: Vec<String>,
+}
+
+/// The files the pages show
+struct SavedAttachments<'a> {
+    /// Attachments copied into the export, with their thumbnails and waveforms
+    attachments: &'a ExtractedAttachments,
+    /// Path of each group chat's saved photo, by chat key
+    group_photos: HashMap<String, String>,
+}
+
+/// One transcript page of a chat: the whole chat, or one period when split
+struct ChatPage<'a, 'b> {
+    /// File name within the chat's directory
+    file: String,
+    /// Period the page covers, when split
+    label: Option<String>,
+    messages: &'a [&'b CleanMessage],
+}
+
+impl ChatPage<'_, '_> {
+    fn link(&self) -> PageLink<'_> {
+        PageLink {
+            file: &self.file,
+            label: self.label.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// What a contact's page shows: their direct chat and the other chats they wrote in
+struct ContactProfile<'a, 'b> {
+    name: &'a str,
+    /// Key of their direct chat, when there is one
+    direct_chat: Option<&'a str>,
+    /// Keys of the other chats they wrote in, with how many of the messages are theirs
+    group_chats: Vec<(&'a str, usize)>,
+    /// Their direct chat's messages and their own messages in the other chats
+    messages: Vec<&'b CleanMessage>,
+}
+
+impl ContactProfile<'_, '_> {
+    fn first_date(&self) -> Option<DateTime<Local>> {
+        self.messages.iter().map(|m| m.date).min()
+    }
+
+    fn last_date(&self) -> Option<DateTime<Local>> {
+        self.messages.iter().map(|m| m.date).max()
+    }
+}
 
 pub struct HtmlOutput {
     messages: Vec<CleanMessage>,
-    database_path: PathBuf,
+    options: HtmlOptions,
+    /// File name stem of each chat's pages, by chat key
+    chat_stems: HashMap<String, String>,
+    /// File name stem of each contact's page, by name
+    contact_stems: HashMap<String, String>,
+    /// Group chats' photos, by chat GUID
+    group_photos: HashMap<String, GroupPhoto>,
+    /// Group chats' membership changes, by chat GUID
+    membership_changes: HashMap<String, Vec<MembershipChange>>,
+    /// Attachments an [`AttachmentStore`](crate::attachment_store::AttachmentStore)
+    /// copied into the export
+    attachments: ExtractedAttachments,
 }
 
 impl HtmlOutput {
-    pub fn new(messages: Vec<CleanMessage>, database_path: PathBuf) -> Self {
+    pub fn new(messages: Vec<CleanMessage>, options: HtmlOptions) -> Self {
+        let chat_stems = options
+            .layout
+            .chat_stems(group_messages_by_chat(&messages).keys());
+        let contact_stems = contact_stems(&messages);
         Self {
             messages,
-            database_path,
+            options,
+            chat_stems,
+            contact_stems,
+            group_photos: HashMap::new(),
+            membership_changes: HashMap::new(),
+            attachments: ExtractedAttachments::default(),
         }
     }
 
-    pub fn generate(&self, output_dir: &str) -> Result<()> {
+    /// Shows each group chat's photo, keyed by chat GUID, beside its name
+    pub fn with_group_photos(mut self, group_photos: HashMap<String, GroupPhoto>) -> Self {
+        self.group_photos = group_photos;
+        self
+    }
+
+    /// Lists each group chat's members, with when they were in it, from its membership
+    /// changes keyed by chat GUID
+    pub fn with_membership_changes(
+        mut self,
+        membership_changes: HashMap<String, Vec<MembershipChange>>,
+    ) -> Self {
+        self.membership_changes = membership_changes;
+        self
+    }
+
+    /// Links to the attachments, thumbnails and waveforms in `attachments`, as copied
+    /// into the same export
+    pub fn with_attachments(mut self, attachments: ExtractedAttachments) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget) -> Result<()> {
         // Group messages by chat
-        let grouped_messages = self.group_messages_by_chat();
+        let grouped_messages = group_messages_by_chat(&self.messages);
 
-        // Save all attachments first
-        self.save_attachments(output_dir)?;
+        let mut saved = SavedAttachments {
+            attachments: &self.attachments,
+            group_photos: HashMap::new(),
+        };
+        self.save_tapback_images(output)?;
+        self.save_group_photos(output, &grouped_messages, &mut saved)?;
 
         // Generate individual chat HTML files in subdirectories
         for (chat_key, chat_messages) in &grouped_messages {
-            let is_group = !chat_key.starts_with("Direct: ");
-            let subdir = if is_group { "groups" } else { "direct" };
-            self.generate_chat_html(output_dir, subdir, chat_key, chat_messages)?;
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+            self.generate_chat_html(output, chat_key, chat_messages, &saved)?;
+            output.notify_messages(chat_key, chat_messages);
         }
 
-        // Generate index page
-        self.generate_index_html(output_dir, &grouped_messages)?;
-
-        Ok(())
-    }
-
-    fn group_messages_by_chat(&self) -> HashMap<String, Vec<&CleanMessage>> {
-        let mut grouped: HashMap<String, Vec<&CleanMessage>> = HashMap::new();
-
-        // First pass: collect all chat_ids that are used for direct messages (no chat name)
-        let mut direct_chat_ids: std::collections::HashSet<i32> = std::collections::HashSet::new();
-        for message in &self.messages {
-            if message.chat_name.is_none() {
-                if let Some(chat_id) = message.chat_id {
-                    direct_chat_ids.insert(chat_id);
-                }
-            }
+        if self.options.timeline {
+            self.generate_timeline_html(output, &grouped_messages, &saved)?;
         }
 
-        // Second pass: for each direct chat_id, find all unique participants (excluding "Me")
-        let mut chat_id_to_participants: HashMap<i32, Vec<String>> = HashMap::new();
-        for chat_id in &direct_chat_ids {
-            let mut participants: Vec<String> = self
-                .messages
-                .iter()
-                .filter(|m| m.chat_id == Some(*chat_id))
-                .map(|m| m.from.to_string())
-                .filter(|name| name != "Me")
-                .collect();
-            participants.sort();
-            participants.dedup();
-            chat_id_to_participants.insert(*chat_id, participants);
-        }
-
-        // Third pass: create a mapping from participant set to canonical chat key
-        let mut participant_set_to_key: HashMap<Vec<String>, String> = HashMap::new();
-        for (_chat_id, participants) in &chat_id_to_participants {
-            if !participants.is_empty() {
-                participant_set_to_key
-                    .entry(participants.clone())
-                    .or_insert_with(|| {
-                        if participants.len() == 1 {
-                            format!("Direct: {}", participants[0])
-                        } else {
-                            format!("Direct: {}", participants.join(", "))
-                        }
-                    });
-            }
-        }
-
-        // Fourth pass: group messages using participant-based keys for direct messages
-        for message in &self.messages {
-            let chat_key = match &message.chat_name {
-                Some(name) => name.clone(),
-                None => {
-                    // For direct messages, find participants and use that as the key
-                    if let Some(chat_id) = message.chat_id {
-                        if let Some(participants) = chat_id_to_participants.get(&chat_id) {
-                            if let Some(key) = participant_set_to_key.get(participants) {
-                                key.clone()
-                            } else {
-                                format!("Direct: Unknown ({})", chat_id)
-                            }
-                        } else {
-                            format!("Direct: Unknown ({})", chat_id)
-                        }
-                    } else {
-                        // Fallback for messages with no chat_id
-                        if message.from.to_string() != "Me" {
-                            format!("Direct: {}", message.from)
-                        } else {
-                            "Direct: Unknown".to_string()
-                        }
-                    }
-                }
-            };
-
-            grouped.entry(chat_key).or_default().push(message);
+        // Generate index page, which imessage-exporter's layout doesn't have
+        if self.options.layout == Layout::Default {
+            let profiles = self.contact_profiles(&grouped_messages);
+            self.generate_index_html(output, &grouped_messages, &profiles, &saved)?;
+            self.generate_stats_html(output, &grouped_messages)?;
+            self.generate_on_this_day_html(output, &grouped_messages, &saved)?;
+            self.generate_contact_pages(output, &grouped_messages, &profiles)?;
         }
 
-        grouped
+        Ok(())
     }
 
     fn generate_index_html(
         &self,
-        output_dir: &str,
+        output: &mut OutputTarget,
         grouped_messages: &HashMap<String, Vec<&CleanMessage>>,
+        profiles: &[ContactProfile],
+        saved: &SavedAttachments,
     ) -> Result<()> {
         let mut chat_entries: Vec<_> = grouped_messages
             .iter()
@@ -144,25 +349,40 @@ impl HtmlOutput {
             })
             .collect();
 
-        // Sort alphabetically by chat name for easier finding
-        chat_entries.sort_by(|a, b| a.0.cmp(b.0));
-
-        // Separate into groups and direct messages
-        let mut group_chats: Vec<_> = chat_entries.iter().filter(|e| e.3).collect();
-        let mut direct_chats: Vec<_> = chat_entries.iter().filter(|e| !e.3).collect();
+        // Ties are broken by name, so the order doesn't change between runs
+        match self.options.index_sort {
+            IndexSort::Name => chat_entries.sort_by(|a, b| a.0.cmp(b.0)),
+            IndexSort::Recent => chat_entries.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0))),
+            IndexSort::Count => chat_entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0))),
+        }
 
-        // Sort each category by name
-        group_chats.sort_by(|a, b| a.0.cmp(b.0));
-        direct_chats.sort_by(|a, b| a.0.cmp(b.0));
+        // Pinned chats come first, in the order they were pinned, then the other groups
+        // and direct messages
+        let mut pinned_chats: Vec<_> = chat_entries
+            .iter()
+            .filter(|e| self.pin_position(e.0).is_some())
+            .collect();
+        pinned_chats.sort_by_key(|e| self.pin_position(e.0));
+        let group_chats: Vec<_> = chat_entries
+            .iter()
+            .filter(|e| e.3 && self.pin_position(e.0).is_none())
+            .collect();
+        let direct_chats: Vec<_> = chat_entries
+            .iter()
+            .filter(|e| !e.3 && self.pin_position(e.0).is_none())
+            .collect();
+        let group_count = chat_entries.iter().filter(|e| e.3).count();
 
+        let locale = self.options.locale;
+        let strings = locale.strings();
         let mut html = String::new();
         html.push_str(&format!(
             r#"<!DOCTYPE html>
-<html lang="en">
+<html lang="{}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>iMessage Chats</title>
+    <title>{}</title>
     <style>
         body {{
             font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Helvetica, Arial, sans-serif;
@@ -229,7 +449,7 @@ impl HtmlOutput {
         }}
 
         .chat-item {{
-            display: block;
+            display: flow-root;
             padding: 16px 20px;
             border-bottom: 1px solid #e5e5ea;
             text-decoration: none;
@@ -245,6 +465,15 @@ impl HtmlOutput {
             background-color: #f9f9f9;
         }}
 
+        .chat-photo {{
+            float: left;
+            width: 48px;
+            height: 48px;
+            margin-right: 14px;
+            border-radius: 50%;
+            object-fit: cover;
+        }}
+
         .chat-name {{
             font-size: 1.1em;
             font-weight: 600;
@@ -270,113 +499,203 @@ impl HtmlOutput {
             color: #007aff;
         }}
 
+        .message-results {{
+            background: white;
+            border-radius: 12px;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.1);
+            overflow: hidden;
+            margin-bottom: 20px;
+        }}
+
+        .message-result {{
+            display: block;
+            padding: 12px 20px;
+            border-bottom: 1px solid #e5e5ea;
+            text-decoration: none;
+            color: inherit;
+        }}
+
+        .message-result:hover {{
+            background-color: #f9f9f9;
+        }}
+
+        .message-result-header {{
+            font-size: 0.85em;
+            color: #666;
+            margin-bottom: 4px;
+        }}
+
+        .message-result-text {{
+            white-space: nowrap;
+            overflow: hidden;
+            text-overflow: ellipsis;
+        }}
+
+        .all-message-results {{
+            display: block;
+            padding: 12px 20px;
+            color: #007aff;
+            text-decoration: none;
+        }}
+
         .hidden {{
             display: none;
         }}
-    </style>
-</head>
+{}    </style>
+{}</head>
 <body>
-    <h1>iMessage Chats</h1>
+{}    <h1>{}</h1>
 
     <div class="search-box">
-        <input type="text" id="searchInput" placeholder="Search chats by name..." onkeyup="filterChats()">
+        <input type="text" id="searchInput" placeholder="{}" onkeyup="filterChats(); findMessages()">
+    </div>
+
+    <div id="messageResults" class="message-results hidden">
+        <div class="category-header">{}</div>
+        <div id="messageResultList"></div>
+        <a id="allMessageResults" class="all-message-results" href="search.html"></a>
     </div>
 
     <div class="stats">
-        <span id="totalChats">{}</span> total chats
-        (<span id="groupCount">{}</span> groups, <span id="directCount">{}</span> direct messages)
+        <span id="totalChats">{}</span> {}
+        (<span id="groupCount">{}</span> {}, <span id="directCount">{}</span> {})
     </div>
-"#, chat_entries.len(), group_chats.len(), direct_chats.len()));
+"#, locale.language(), strings.chats_title, HEATMAP_CSS, self.options.theme.head_html(), self.options.theme.toggle_html(), strings.chats_title, strings.search_chats_placeholder, strings.messages_header, chat_entries.len(), strings.total_chats, group_count, strings.groups, chat_entries.len() - group_count, strings.direct_messages));
 
-        // Output group chats
-        if !group_chats.is_empty() {
-            html.push_str(
-                r#"    <div class="chat-list">
-        <div class="category-header">Group Chats</div>
+        html.push_str(&format!(
+            r#"    <div class="stats"><a href="search.html">{}</a></div>
+    <div class="stats"><a href="{}">{}</a></div>
+    <div class="stats"><a href="{}">{}</a></div>
 "#,
-            );
+            strings.search_all_messages,
+            STATS_FILENAME,
+            strings.conversation_stats,
+            ON_THIS_DAY_FILENAME,
+            strings.on_this_day
+        ));
+        if self.options.timeline {
+            html.push_str(&format!(
+                r#"    <div class="stats"><a href="timeline.html">{}</a></div>
+"#,
+                strings.view_timeline
+            ));
+        }
 
-            for (chat_key, message_count, latest_date, _, participants) in group_chats {
-                let filename = format!("groups/{}.html", self.sanitize_filename(chat_key));
+        // Activity calendar and message search, listing chats in the same order as below
+        let heatmap_chats: Vec<_> = pinned_chats
+            .iter()
+            .chain(group_chats.iter())
+            .chain(direct_chats.iter())
+            .map(|(chat_key, _, _, _, _)| {
+                let chat_dir = self.chat_dir(chat_key);
+                let pages = self
+                    .chat_pages(chat_key, &grouped_messages[chat_key.as_str()])
+                    .into_iter()
+                    .map(|page| (join(&chat_dir, &page.file), page.messages))
+                    .collect();
+                (display_name(chat_key), pages)
+            })
+            .collect();
+        html.push_str(&heatmap_html(&heatmap_chats));
+        output.write(
+            SEARCH_DATA_FILENAME,
+            search_data_js(&heatmap_chats).as_bytes(),
+        )?;
+        output.write(
+            "search.html",
+            search_page_html(&self.options.theme).as_bytes(),
+        )?;
+
+        for (header, chats) in [
+            (strings.pinned_header, &pinned_chats),
+            (strings.group_chats_header, &group_chats),
+            (strings.direct_messages_header, &direct_chats),
+        ] {
+            if chats.is_empty() {
+                continue;
+            }
+            html.push_str(&format!(
+                r#"    <div class="chat-list">
+        <div class="category-header">{}</div>
+"#,
+                header
+            ));
+            for (chat_key, message_count, latest_date, _, participants) in chats.iter() {
+                let filename = self.chat_link(chat_key, &grouped_messages[chat_key.as_str()]);
+                // Remove "Direct: " prefix for display
+                let display_name = display_name(chat_key);
                 let members_str = participants.join(", ");
-                let search_text = format!("{} {}", chat_key, members_str).to_lowercase();
-
+                let search_text = format!("{} {}", display_name, members_str).to_lowercase();
                 html.push_str(&format!(
                     r#"        <a href="{}" class="chat-item" data-search="{}">
-            <div class="chat-name">{}</div>
 "#,
                     filename,
-                    self.html_escape(&search_text),
-                    self.html_escape(chat_key)
+                    html_escape(&search_text)
+                ));
+                if let Some(photo) = saved.group_photos.get(chat_key.as_str()) {
+                    html.push_str(&format!(
+                        r#"            <img class="chat-photo" src="{}" alt="">
+"#,
+                        html_escape(photo)
+                    ));
+                }
+                html.push_str(&format!(
+                    r#"            <div class="chat-name">{}</div>
+"#,
+                    html_escape(display_name)
                 ));
-
                 if !participants.is_empty() {
                     html.push_str(&format!(
                         r#"            <div class="chat-members">{}</div>
 "#,
-                        self.html_escape(&members_str)
+                        html_escape(&members_str)
                     ));
                 }
-
                 html.push_str(&format!(
                     r#"            <div class="chat-info">
-                <span class="message-count">{} messages</span>
+                <span class="message-count">{} {}</span>
                 <span class="latest-date">{}</span>
             </div>
         </a>
 "#,
                     message_count,
-                    latest_date.format("%b %d, %Y")
+                    strings.messages,
+                    locale.short_date(latest_date)
                 ));
             }
-
             html.push_str(
                 r#"    </div>
 "#,
             );
         }
-
-        // Output direct messages
-        if !direct_chats.is_empty() {
-            html.push_str(
+        // Output contacts, each linking to their page
+        if !profiles.is_empty() {
+            html.push_str(&format!(
                 r#"    <div class="chat-list">
-        <div class="category-header">Direct Messages</div>
+        <div class="category-header">{}</div>
 "#,
-            );
-
-            for (chat_key, message_count, latest_date, _, participants) in direct_chats {
-                let filename = format!("direct/{}.html", self.sanitize_filename(chat_key));
-                // Remove "Direct: " prefix for display
-                let display_name = chat_key.strip_prefix("Direct: ").unwrap_or(chat_key);
-                let members_str = participants.join(", ");
-                let search_text = format!("{} {}", display_name, members_str).to_lowercase();
+                strings.contacts_header
+            ));
 
+            for profile in profiles {
                 html.push_str(&format!(
                     r#"        <a href="{}" class="chat-item" data-search="{}">
             <div class="chat-name">{}</div>
-"#,
-                    filename,
-                    self.html_escape(&search_text),
-                    self.html_escape(display_name)
-                ));
-
-                if !participants.is_empty() {
-                    html.push_str(&format!(
-                        r#"            <div class="chat-members">{}</div>
-"#,
-                        self.html_escape(&members_str)
-                    ));
-                }
-
-                html.push_str(&format!(
-                    r#"            <div class="chat-info">
-                <span class="message-count">{} messages</span>
+            <div class="chat-info">
+                <span class="message-count">{} {}</span>
                 <span class="latest-date">{}</span>
             </div>
         </a>
 "#,
-                    message_count,
-                    latest_date.format("%b %d, %Y")
+                    self.contact_link(profile.name).unwrap_or_default(),
+                    html_escape(&profile.name.to_lowercase()),
+                    html_escape(profile.name),
+                    profile.messages.len(),
+                    strings.messages,
+                    profile
+                        .last_date()
+                        .map(|date| locale.short_date(&date))
+                        .unwrap_or_default()
                 ));
             }
 
@@ -387,9 +706,57 @@ impl HtmlOutput {
         }
 
         // Add JavaScript for search functionality
+        html.push_str(&format!(
+            r#"
+    <script src="{}"></script>
+    <script>{}"#,
+            SEARCH_DATA_FILENAME, MATCH_SCRIPT
+        ));
         html.push_str(
             r#"
-    <script>
+        // Messages shown under the search box; the rest are on search.html
+        const MAX_MESSAGE_RESULTS = 10;
+
+        function findMessages() {
+            const value = document.getElementById('searchInput').value;
+            const terms = searchTerms(value);
+            const section = document.getElementById('messageResults');
+            const list = document.getElementById('messageResultList');
+            list.innerHTML = '';
+
+            // A single letter would match nearly everything
+            const matches = terms.join('').length < 2 ? [] : matchingMessages(terms);
+            if (matches.length === 0) {
+                section.classList.add('hidden');
+                return;
+            }
+
+            matches.slice(0, MAX_MESSAGE_RESULTS).forEach(function(message) {
+                const link = document.createElement('a');
+                link.className = 'message-result';
+                link.href = message.file + '#msg-' + message.guid;
+
+                const header = document.createElement('div');
+                header.className = 'message-result-header';
+                header.textContent = SEARCH_DATA.chats[message.chat] + ' · ' + message.sender + ' · ' + message.date;
+                link.appendChild(header);
+
+                const text = document.createElement('div');
+                text.className = 'message-result-text';
+                text.textContent = message.text || message.media_text;
+                link.appendChild(text);
+
+                list.appendChild(link);
+            });
+
+            const all = document.getElementById('allMessageResults');
+            all.href = 'search.html?q=' + encodeURIComponent(value);
+            all.textContent = matches.length > MAX_MESSAGE_RESULTS
+                ? 'See all ' + matches.length + ' matching messages →'
+                : 'Open in message search →';
+            section.classList.remove('hidden');
+        }
+
         function filterChats() {
             const searchInput = document.getElementById('searchInput');
             const filter = searchInput.value.toLowerCase();
@@ -423,88 +790,727 @@ impl HtmlOutput {
 "#,
         );
 
-        let index_path = format!("{}/index.html", output_dir);
-        fs::write(&index_path, html)?;
+        output.write("index.html", html.as_bytes())?;
 
         Ok(())
     }
 
     fn generate_chat_html(
         &self,
-        output_dir: &str,
-        subdir: &str,
+        output: &mut OutputTarget,
         chat_key: &str,
         messages: &[&CleanMessage],
+        saved: &SavedAttachments,
     ) -> Result<()> {
-        // Create subdirectory
-        let chat_dir = format!("{}/{}", output_dir, subdir);
-        fs::create_dir_all(&chat_dir)?;
+        let chat_dir = self.chat_dir(chat_key);
+        let pages = self.chat_pages(chat_key, messages);
+
+        for index in 0..pages.len() {
+            let html = self.build_chat_html(chat_key, messages, &pages, index, saved);
+            let output_path = join(&chat_dir, &pages[index].file);
+            output.write(&output_path, html.as_bytes())?;
+        }
+
+        if self.options.split_by.is_some() {
+            let index_html = self.build_chat_index_html(chat_key, messages, &pages);
+            output.write(&format!("{}/index.html", chat_dir), index_html.as_bytes())?;
+        }
+
+        if self.options.layout == Layout::Default && self.media_count(messages) > 0 {
+            let media_html = self.build_media_html(chat_key, messages, saved);
+            let media_path = join(&chat_dir, &self.media_file(chat_key));
+            output.write(&media_path, media_html.as_bytes())?;
+        }
 
-        let html = self.build_chat_html(chat_key, messages);
-        let output_path = format!("{}/{}.html", chat_dir, self.sanitize_filename(chat_key));
-        fs::write(&output_path, html)?;
         Ok(())
     }
 
-    fn save_attachments(&self, output_dir: &str) -> Result<()> {
-        use anyhow::anyhow;
+    /// Writes timeline.html: every message from every chat in date order, each labelled
+    /// with (and linking to) the chat it belongs to
+    fn generate_timeline_html(
+        &self,
+        output: &mut OutputTarget,
+        grouped_messages: &HashMap<String, Vec<&CleanMessage>>,
+        saved: &SavedAttachments,
+    ) -> Result<()> {
+        let chat_keys: HashMap<&str, &str> = grouped_messages
+            .iter()
+            .flat_map(|(chat_key, messages)| {
+                messages
+                    .iter()
+                    .map(move |message| (message.guid.as_str(), chat_key.as_str()))
+            })
+            .collect();
+
+        let locale = self.options.locale;
+        let mut html = self.transcript_head(locale.strings().all_chats);
+        html.push_str(&format!(
+            r#"    <a href="index.html" class="back-link">{}</a>
+    <h1>{}</h1>
+"#,
+            locale.strings().back_to_chats,
+            locale.strings().all_chats
+        ));
 
+        let mut last_date = String::new();
         for message in &self.messages {
-            if !message.attachments.is_empty() {
-                let attachment_subpath = self.get_attachment_path(&message.guid);
-                let message_dir = format!("{}/attachments/{}", output_dir, attachment_subpath);
-                fs::create_dir_all(&message_dir)?;
-
-                for attachment in &message.attachments {
-                    if let Some(filename) = attachment.filename()
-                        && let Some(bytes) = attachment
-                            .as_bytes(&Platform::macOS, &self.database_path, None)
-                            .map_err(|e| anyhow!(e))?
-                    {
-                        let output_path = format!("{}/{}", message_dir, filename);
-                        fs::write(&output_path, bytes)?;
-                    }
-                }
+            let Some(chat_key) = chat_keys.get(message.guid.as_str()) else {
+                continue;
+            };
+
+            let message_date = locale.long_date(&message.date);
+            if message_date != last_date {
+                html.push_str(&format!(
+                    r#"    <div class="date-separator" id="{}">{}</div>
+"#,
+                    day_anchor(&message.date),
+                    message_date
+                ));
+                last_date = message_date;
             }
+
+            let chat_label = self.chat_label(chat_key, message);
+            html.push_str(&self.render_message(message, saved, "", Some(&chat_label)));
         }
 
+        html.push_str(TRANSCRIPT_FOOT);
+        output.write("timeline.html", html.as_bytes())?;
+
         Ok(())
     }
 
-    fn get_attachment_path(&self, guid: &str) -> String {
-        // Extract first 4 characters from GUID for two-level directory structure
-        // Example: "FE718EBE-BB92-4650-A656-D59ACB15619C" -> "FE/71/FE718EBE-BB92-4650-A656-D59ACB15619C"
-        let level1 = &guid[0..2];
-        let level2 = &guid[2..4];
-        format!("{}/{}/{}", level1, level2, guid)
+    /// Writes on-this-day.html: the messages from every chat sent on the same month and
+    /// day as the chosen date (today by default) in any year, most recent year first
+    fn generate_on_this_day_html(
+        &self,
+        output: &mut OutputTarget,
+        grouped_messages: &HashMap<String, Vec<&CleanMessage>>,
+        saved: &SavedAttachments,
+    ) -> Result<()> {
+        let day = self
+            .options
+            .on_this_day
+            .unwrap_or_else(|| Local::now().date_naive());
+        let mut messages: Vec<(&str, &CleanMessage)> = grouped_messages
+            .iter()
+            .flat_map(|(chat_key, messages)| {
+                messages
+                    .iter()
+                    .map(move |message| (chat_key.as_str(), *message))
+            })
+            .filter(|(_, message)| {
+                message.date.month() == day.month() && message.date.day() == day.day()
+            })
+            .collect();
+        messages.sort_by(|a, b| {
+            b.1.date
+                .year()
+                .cmp(&a.1.date.year())
+                .then_with(|| a.1.date.cmp(&b.1.date))
+        });
+
+        let locale = self.options.locale;
+        let strings = locale.strings();
+        let mut html = self.transcript_head(strings.on_this_day);
+        html.push_str(&format!(
+            r#"    <a href="index.html" class="back-link">{}</a>
+    <h1>{}</h1>
+"#,
+            strings.back_to_chats, strings.on_this_day
+        ));
+        if messages.is_empty() {
+            html.push_str(&format!(
+                r#"    <div class="merged-chats">{}</div>
+"#,
+                strings.no_messages_on_this_day
+            ));
+        }
+
+        for year in messages.chunk_by(|a, b| a.1.date.year() == b.1.date.year()) {
+            html.push_str(&format!(
+                r#"    <div class="date-separator">{}</div>
+"#,
+                locale.long_date(&year[0].1.date)
+            ));
+            for (chat_key, message) in year {
+                let chat_label = self.chat_label(chat_key, message);
+                html.push_str(&self.render_message(message, saved, "", Some(&chat_label)));
+            }
+        }
+
+        html.push_str(TRANSCRIPT_FOOT);
+        output.write(ON_THIS_DAY_FILENAME, html.as_bytes())
     }
 
-    fn sanitize_filename(&self, name: &str) -> String {
-        name.chars()
-            .map(|c| match c {
-                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
-                _ => c,
-            })
-            .collect()
+    /// The chat's name on a bubble in a page mixing chats, linking to the message in the
+    /// chat's own transcript
+    fn chat_label(&self, chat_key: &str, message: &CleanMessage) -> String {
+        format!(
+            r#"<a href="{}#msg-{}" class="chat-label">{}</a>"#,
+            join(
+                &self.chat_dir(chat_key),
+                &self.page_file(chat_key, &message.date)
+            ),
+            message.guid,
+            html_escape(display_name(chat_key))
+        )
     }
 
-    fn build_chat_html(&self, chat_name: &str, messages: &[&CleanMessage]) -> String {
-        let mut html = String::new();
+    /// Writes stats.html: for each chat, busiest first, who starts its conversations,
+    /// how fast each person replies, how often they double text and its longest silence
+    fn generate_stats_html(
+        &self,
+        output: &mut OutputTarget,
+        grouped_messages: &HashMap<String, Vec<&CleanMessage>>,
+    ) -> Result<()> {
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort_by(|a, b| {
+            grouped_messages[*b]
+                .len()
+                .cmp(&grouped_messages[*a].len())
+                .then_with(|| a.cmp(b))
+        });
+
+        let locale = self.options.locale;
+        let strings = locale.strings();
+        let mut html = self.transcript_head(strings.conversation_stats);
+        html.push_str(&format!(
+            r#"    <a href="index.html" class="back-link">{}</a>
+    <h1>{}</h1>
+"#,
+            strings.back_to_chats, strings.conversation_stats
+        ));
 
-        // Extract unique participants (excluding "Me")
-        let is_group_chat = !chat_name.starts_with("Direct: ");
-        let mut participants: Vec<String> = messages
-            .iter()
-            .map(|m| m.from.to_string())
-            .filter(|name| name != "Me")
-            .collect();
-        participants.sort();
-        participants.dedup();
+        let all = Frequencies::of(
+            self.messages
+                .iter()
+                .filter(|message| message.call.is_none()),
+        );
+        html.push_str(&format!(
+            r#"    <details class="members" open>
+        <summary>{}</summary>
+{}    </details>
+"#,
+            strings.all_chats,
+            top_terms_html(
+                strings,
+                &all.top_words(TOP_TERMS),
+                &all.top_emoji(TOP_TERMS)
+            )
+        ));
+
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            let stats = ChatStats::new(chat_key, chat_messages);
+            html.push_str(&format!(
+                r#"    <details class="members" open>
+        <summary><a href="{}">{}</a> · {} {} · {} {}</summary>
+"#,
+                self.chat_link(chat_key, chat_messages),
+                html_escape(&stats.chat),
+                stats.message_count,
+                strings.messages,
+                stats.conversation_count,
+                strings.conversations
+            ));
+            if let Some(silence) = &stats.longest_silence {
+                html.push_str(&format!(
+                    r#"        <div class="member-dates">{}: {} ({} – {})</div>
+"#,
+                    strings.longest_silence,
+                    format_duration(silence.seconds),
+                    locale.short_date(&silence.from),
+                    locale.short_date(&silence.to)
+                ));
+            }
+            html.push_str(&format!(
+                r#"        <table>
+            <tr><td></td><td class="member-count">{}</td><td class="member-count">{}</td><td class="member-count">{}</td><td class="member-count">{}</td></tr>
+"#,
+                strings.messages_header,
+                strings.started,
+                strings.median_reply,
+                strings.double_texts
+            ));
+            for participant in &stats.participants {
+                html.push_str(&format!(
+                    r#"            <tr class="member"><td class="member-name">{}</td><td class="member-count">{}</td><td class="member-count">{}</td><td class="member-count">{}</td><td class="member-count">{}</td></tr>
+"#,
+                    html_escape(&participant.name),
+                    participant.message_count,
+                    participant.conversations_started,
+                    participant
+                        .median_reply_seconds
+                        .map(format_duration)
+                        .unwrap_or_else(|| "–".to_string()),
+                    participant.double_texts
+                ));
+            }
+            html.push_str(
+                r#"        </table>
+"#,
+            );
+            html.push_str(&top_terms_html(strings, &stats.top_words, &stats.top_emoji));
+            html.push_str(
+                r#"    </details>
+"#,
+            );
+        }
+
+        html.push_str(TRANSCRIPT_FOOT);
+        output.write(STATS_FILENAME, html.as_bytes())
+    }
+
+    /// Each contact's direct chat and the other chats they wrote in, by name
+    fn contact_profiles<'a, 'b>(
+        &'a self,
+        grouped_messages: &'a HashMap<String, Vec<&'b CleanMessage>>,
+    ) -> Vec<ContactProfile<'a, 'b>> {
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+        let mut names: Vec<&String> = self.contact_stems.keys().collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .map(|name| {
+                let direct_key = format!("Direct: {}", name);
+                let (direct_chat, mut messages) = match grouped_messages.get_key_value(&direct_key)
+                {
+                    Some((chat_key, chat_messages)) => {
+                        (Some(chat_key.as_str()), chat_messages.clone())
+                    }
+                    None => (None, Vec::new()),
+                };
+
+                let mut group_chats = Vec::new();
+                for chat_key in chat_keys
+                    .iter()
+                    .filter(|chat_key| ***chat_key != direct_key)
+                {
+                    let theirs: Vec<&CleanMessage> = grouped_messages[*chat_key]
+                        .iter()
+                        .filter(|m| m.call.is_none() && m.from.to_string() == *name)
+                        .copied()
+                        .collect();
+                    if !theirs.is_empty() {
+                        group_chats.push((chat_key.as_str(), theirs.len()));
+                        messages.extend(theirs);
+                    }
+                }
+
+                ContactProfile {
+                    name,
+                    direct_chat,
+                    group_chats,
+                    messages,
+                }
+            })
+            .collect()
+    }
+
+    /// Writes a page for each contact under contacts/: their direct chat, the other
+    /// chats they wrote in, how many messages and photos and videos you've shared and
+    /// when you first and last talked
+    fn generate_contact_pages(
+        &self,
+        output: &mut OutputTarget,
+        grouped_messages: &HashMap<String, Vec<&CleanMessage>>,
+        profiles: &[ContactProfile],
+    ) -> Result<()> {
+        let locale = self.options.locale;
+        let strings = locale.strings();
+        let chat_row = |chat_key: &str, count: usize| {
+            format!(
+                r#"            <tr class="member"><td class="member-name"><a href="../{}">{}</a></td><td class="member-count">{} {}</td></tr>
+"#,
+                self.chat_link(chat_key, &grouped_messages[chat_key]),
+                html_escape(display_name(chat_key)),
+                count,
+                strings.messages
+            )
+        };
+
+        for profile in profiles {
+            let mut html = self.transcript_head(profile.name);
+            html.push_str(&format!(
+                r#"    <a href="../index.html" class="back-link">{}</a>
+    <h1>{}</h1>
+    <div class="members">
+        <table>
+            <tr><td>{}</td><td class="member-count">{}</td></tr>
+            <tr><td>{}</td><td class="member-count">{}</td></tr>
+"#,
+                strings.back_to_chats,
+                html_escape(profile.name),
+                strings.messages_header,
+                profile.messages.len(),
+                strings.shared_media,
+                self.media_count(&profile.messages)
+            ));
+            if let (Some(first), Some(last)) = (profile.first_date(), profile.last_date()) {
+                html.push_str(&format!(
+                    r#"            <tr><td>{}</td><td class="member-count">{}</td></tr>
+            <tr><td>{}</td><td class="member-count">{}</td></tr>
+"#,
+                    strings.first_message,
+                    locale.short_date(&first),
+                    strings.last_message,
+                    locale.short_date(&last)
+                ));
+            }
+            html.push_str(
+                r#"        </table>
+    </div>
+"#,
+            );
+
+            if let Some(chat_key) = profile.direct_chat {
+                html.push_str(&format!(
+                    r#"    <details class="members" open>
+        <summary>{}</summary>
+        <table>
+{}        </table>
+    </details>
+"#,
+                    strings.direct_messages_header,
+                    chat_row(chat_key, grouped_messages[chat_key].len())
+                ));
+            }
+            if !profile.group_chats.is_empty() {
+                html.push_str(&format!(
+                    r#"    <details class="members" open>
+        <summary>{}</summary>
+        <table>
+"#,
+                    strings.group_chats_header
+                ));
+                for &(chat_key, count) in &profile.group_chats {
+                    html.push_str(&chat_row(chat_key, count));
+                }
+                html.push_str(
+                    r#"        </table>
+    </details>
+"#,
+                );
+            }
+
+            html.push_str(TRANSCRIPT_FOOT);
+            output.write(
+                &format!("{}/{}.html", CONTACTS_DIR, self.contact_stems[profile.name]),
+                html.as_bytes(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Contact page of the person named `name`, relative to the export root; only the
+    /// default layout has them
+    fn contact_link(&self, name: &str) -> Option<String> {
+        match self.options.layout {
+            Layout::Default => self
+                .contact_stems
+                .get(name)
+                .map(|stem| format!("{}/{}.html", CONTACTS_DIR, stem)),
+            Layout::ImessageExporter => None,
+        }
+    }
+
+    /// Position of a chat among the `--pin` options, which name it with or without the
+    /// "Direct: " prefix of its key; `None` when it isn't pinned
+    fn pin_position(&self, chat_key: &str) -> Option<usize> {
+        let name = display_name(chat_key);
+        self.options
+            .pinned
+            .iter()
+            .position(|pin| pin == chat_key || pin == name)
+    }
+
+    /// Directory a chat's pages are written to, relative to the export root
+    fn chat_dir(&self, chat_key: &str) -> String {
+        let subdir = self.options.layout.chat_dir(chat_key);
+        match self.options.split_by {
+            Some(_) => format!("{}/{}", subdir, self.chat_stems[chat_key]),
+            None => subdir.to_string(),
+        }
+    }
+
+    /// File name, within the chat's directory, of the transcript page showing `date`
+    fn page_file(&self, chat_key: &str, date: &DateTime<Local>) -> String {
+        match self.options.split_by {
+            Some(split_by) => format!("{}.html", split_by.period(date)),
+            None => format!("{}.html", self.chat_stems[chat_key]),
+        }
+    }
+
+    /// File name, within the chat's directory, of the media gallery
+    fn media_file(&self, chat_key: &str) -> String {
+        match self.options.split_by {
+            Some(_) => "media.html".to_string(),
+            None => format!("{}.media.html", self.chat_stems[chat_key]),
+        }
+    }
+
+    /// Page the index links to for a chat, relative to the export root
+    fn chat_link(&self, chat_key: &str, messages: &[&CleanMessage]) -> String {
+        match (self.options.split_by, messages.first()) {
+            (None, Some(first)) => join(
+                &self.chat_dir(chat_key),
+                &self.page_file(chat_key, &first.date),
+            ),
+            _ => format!("{}/index.html", self.chat_dir(chat_key)),
+        }
+    }
 
-        // HTML header with CSS
+    /// Relative path from a chat's pages back to the export root
+    fn root_prefix(&self) -> &'static str {
+        match (self.options.split_by, self.options.layout) {
+            (Some(_), _) => "../../",
+            (None, Layout::Default) => "../",
+            (None, Layout::ImessageExporter) => "",
+        }
+    }
+
+    /// Splits a chat's (date-ordered) messages into its transcript pages
+    fn chat_pages<'a, 'b>(
+        &self,
+        chat_key: &str,
+        messages: &'a [&'b CleanMessage],
+    ) -> Vec<ChatPage<'a, 'b>> {
+        match self.options.split_by {
+            Some(split_by) => messages
+                .chunk_by(|a, b| split_by.period(&a.date) == split_by.period(&b.date))
+                .map(|page_messages| ChatPage {
+                    file: self.page_file(chat_key, &page_messages[0].date),
+                    label: Some(split_by.label(&page_messages[0].date, self.options.locale)),
+                    messages: page_messages,
+                })
+                .collect(),
+            None => vec![ChatPage {
+                file: format!("{}.html", self.chat_stems[chat_key]),
+                label: None,
+                messages,
+            }],
+        }
+    }
+
+    /// Number of photo and video attachments in a chat, i.e. what shows on its media page
+    fn media_count(&self, messages: &[&CleanMessage]) -> usize {
+        messages
+            .iter()
+            .flat_map(|m| &m.attachments)
+            .filter(|a| a.filename.is_some())
+            .filter(|a| matches!(a.kind, MediaKind::Image | MediaKind::Video))
+            .count()
+    }
+
+    /// Saves the images of sticker and custom emoji tapbacks
+    fn save_tapback_images(&self, output: &mut OutputTarget) -> Result<()> {
+        for message in &self.messages {
+            for image in message.tapbacks.values().filter_map(TapbackEmoji::image) {
+                let output_path = tapback_image_path(image);
+                if output.resume(&output_path) {
+                    continue;
+                }
+                if let Some(bytes) = image.file.read()? {
+                    output.write(&output_path, &bytes)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// An attachment's file as a `data:` URI, stripped of metadata with
+    /// `--strip-metadata`; `None` when the file isn't on this computer
+    fn attachment_data_uri(&self, attachment: &CleanAttachment) -> Option<String> {
+        // An excerpt leaves out what it can't embed rather than reporting it
+        let mut errors = ErrorLog::new(false);
+        let bytes = read_for_export(attachment, self.options.strip_metadata, &mut errors)
+            .ok()
+            .flatten()?;
+        let mime_type = attachment
+            .mime_type
+            .as_deref()
+            .unwrap_or("application/octet-stream");
+        Some(data_uri(mime_type, &bytes))
+    }
+
+    /// A standalone page of `self.messages`, an excerpt of one chat, with the message
+    /// `highlight` picked out. Attachments are only embedded with `inline_attachments`.
+    pub fn excerpt_html(&self, highlight: &str) -> String {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let chat_name = grouped_messages
+            .keys()
+            .next()
+            .map(|chat_key| display_name(chat_key))
+            .unwrap_or("Excerpt");
+        let saved = SavedAttachments {
+            attachments: &self.attachments,
+            group_photos: HashMap::new(),
+        };
+
+        let mut html = self.transcript_head(chat_name);
         html.push_str(&format!(
+            r#"    <style>
+        [id="msg-{}"] {{
+            outline: 3px solid #ffcc00;
+            outline-offset: 3px;
+        }}
+    </style>
+    <h1>{}</h1>
+"#,
+            highlight,
+            html_escape(chat_name)
+        ));
+
+        let mut last_date = String::new();
+        for message in &self.messages {
+            let message_date = self.options.locale.long_date(&message.date);
+            if message_date != last_date {
+                html.push_str(&format!(
+                    r#"    <div class="date-separator">{}</div>
+"#,
+                    message_date
+                ));
+                last_date = message_date;
+            }
+            html.push_str(&self.render_message(message, &saved, "", None));
+        }
+
+        html.push_str(TRANSCRIPT_FOOT);
+        html
+    }
+
+    /// The Members panel of a group chat: everyone who was in it, when, and how much
+    /// they wrote
+    fn members_html(&self, chat_messages: &[&CleanMessage]) -> String {
+        let mut chat_guids: Vec<&String> = chat_messages
+            .iter()
+            .filter_map(|m| m.chat_guid.as_ref())
+            .collect();
+        chat_guids.sort();
+        chat_guids.dedup();
+        let changes: Vec<MembershipChange> = chat_guids
+            .iter()
+            .filter_map(|chat_guid| self.membership_changes.get(*chat_guid))
+            .flatten()
+            .cloned()
+            .collect();
+
+        let locale = self.options.locale;
+        let strings = locale.strings();
+        let members = members(chat_messages, &changes);
+        let mut html = format!(
+            r#"    <details class="members">
+        <summary>{} ({})</summary>
+        <table>
+"#,
+            strings.members,
+            members.len()
+        );
+        for member in &members {
+            let until = match member.until {
+                Some(until) => locale.short_date(&until),
+                None => strings.present.to_string(),
+            };
+            html.push_str(&format!(
+                r#"            <tr class="{}"><td class="member-name">{}</td><td class="member-dates">{} – {}</td><td class="member-count">{} {}</td></tr>
+"#,
+                if member.until.is_some() {
+                    "member-left"
+                } else {
+                    "member"
+                },
+                html_escape(&member.name),
+                locale.short_date(&member.since),
+                until,
+                member.message_count,
+                if member.message_count == 1 {
+                    strings.message
+                } else {
+                    strings.messages
+                }
+            ));
+        }
+        html.push_str(
+            r#"        </table>
+    </details>
+"#,
+        );
+        html
+    }
+
+    /// Saves the photo of each group chat that has one under `group_photos/`: a small JPEG
+    /// copy, or the original when it can't be decoded but browsers can show it. A chat
+    /// merged from several takes the photo of its latest.
+    fn save_group_photos(
+        &self,
+        output: &mut OutputTarget,
+        grouped_messages: &HashMap<String, Vec<&CleanMessage>>,
+        saved: &mut SavedAttachments,
+    ) -> Result<()> {
+        for (chat_key, messages) in grouped_messages {
+            if chat_key.starts_with("Direct: ") {
+                continue;
+            }
+            let Some(photo) = messages.iter().rev().find_map(|message| {
+                message
+                    .chat_guid
+                    .as_ref()
+                    .and_then(|chat_guid| self.group_photos.get(chat_guid))
+            }) else {
+                continue;
+            };
+
+            let stem = &self.chat_stems[chat_key];
+            let jpeg_path = format!("group_photos/{}.jpg", stem);
+            let original_path = Path::new(&photo.filename)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(str::to_lowercase)
+                .filter(|extension| {
+                    matches!(extension.as_str(), "jpg" | "jpeg" | "png" | "gif" | "webp")
+                })
+                .map(|extension| format!("group_photos/{}.{}", stem, extension));
+
+            let resumed = [Some(&jpeg_path), original_path.as_ref()]
+                .into_iter()
+                .flatten()
+                .find(|path| output.resume(path));
+            if let Some(path) = resumed {
+                saved.group_photos.insert(chat_key.clone(), path.clone());
+                continue;
+            }
+
+            let Some(bytes) = photo.file.read()? else {
+                continue;
+            };
+            if let Ok(image) = image::load_from_memory(&bytes) {
+                let small = image::DynamicImage::ImageRgb8(
+                    image
+                        .thumbnail(GROUP_PHOTO_MAX_DIMENSION, GROUP_PHOTO_MAX_DIMENSION)
+                        .to_rgb8(),
+                );
+                let mut jpeg = std::io::Cursor::new(Vec::new());
+                small.write_to(&mut jpeg, image::ImageFormat::Jpeg)?;
+                output.write(&jpeg_path, jpeg.get_ref())?;
+                saved.group_photos.insert(chat_key.clone(), jpeg_path);
+            } else if let Some(original_path) = original_path {
+                output.write(&original_path, &bytes)?;
+                saved.group_photos.insert(chat_key.clone(), original_path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Everything up to and including `<body>` for a page of message bubbles, with the
+    /// bubble styles shared by chat transcripts and the timeline
+    fn transcript_head(&self, title: &str) -> String {
+        format!(
             r#"<!DOCTYPE html>
-<html lang="en">
+<html lang="{}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
@@ -533,6 +1539,84 @@ impl HtmlOutput {
             background-color: #0051d5;
         }}
 
+        .mentions-filter {{
+            border: none;
+            font: inherit;
+            cursor: pointer;
+        }}
+
+        body.only-mentions .mentions-filter {{
+            background-color: #0051d5;
+        }}
+
+        body.only-mentions .message:not(.mentions-me),
+        body.only-mentions .date-separator {{
+            display: none;
+        }}
+
+        .chat-header-photo {{
+            width: 56px;
+            height: 56px;
+            margin-right: 12px;
+            border-radius: 50%;
+            object-fit: cover;
+            vertical-align: middle;
+        }}
+
+        .chat-search {{
+            display: flex;
+            align-items: center;
+            gap: 8px;
+            position: sticky;
+            top: 0;
+            z-index: 10;
+            margin-bottom: 20px;
+            padding: 10px 12px;
+            background: white;
+            border-radius: 12px;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.1);
+            font-size: 0.9em;
+        }}
+
+        #chatSearchInput {{
+            flex: 1;
+            padding: 8px;
+            font-size: 1em;
+            border: 2px solid #e5e5ea;
+            border-radius: 8px;
+        }}
+
+        .chat-search-count {{
+            color: #666;
+            white-space: nowrap;
+        }}
+
+        .chat-search button {{
+            border: none;
+            background-color: #e5e5ea;
+            border-radius: 6px;
+            padding: 6px 10px;
+            cursor: pointer;
+        }}
+
+        .message.search-match {{
+            box-shadow: 0 0 0 2px #ffcc00;
+        }}
+
+        .message.search-current, .system-event.search-current {{
+            box-shadow: 0 0 0 3px #ff9500;
+        }}
+
+        .hidden {{
+            display: none !important;
+        }}
+
+        /* The message a search result or link jumped to */
+        .message:target, .system-event:target {{
+            outline: 3px solid #ffcc00;
+            outline-offset: 3px;
+        }}
+
         h1 {{
             text-align: center;
             color: #333;
@@ -569,6 +1653,59 @@ impl HtmlOutput {
             font-size: 0.9em;
         }}
 
+        a.participant {{
+            text-decoration: none;
+        }}
+
+        a.participant:hover {{
+            background-color: #d1d1d6;
+        }}
+
+        .members {{
+            background: white;
+            border-radius: 12px;
+            padding: 16px 20px;
+            margin-bottom: 20px;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.1);
+        }}
+
+        .members summary {{
+            font-weight: 600;
+            color: #333;
+            font-size: 0.95em;
+            cursor: pointer;
+        }}
+
+        .members table {{
+            width: 100%;
+            margin-top: 10px;
+            border-collapse: collapse;
+            font-size: 0.9em;
+        }}
+
+        .members td {{
+            padding: 4px 8px 4px 0;
+            color: #333;
+        }}
+
+        .member-dates, .member-count {{
+            color: #666;
+        }}
+
+        .member-count {{
+            text-align: right;
+        }}
+
+        .member-left .member-name {{
+            color: #8e8e93;
+        }}
+
+        .merged-chats {{
+            color: #666;
+            font-size: 0.85em;
+            margin-bottom: 20px;
+        }}
+
         .message {{
             margin: 15px 0;
             padding: 12px 16px;
@@ -585,6 +1722,10 @@ impl HtmlOutput {
             margin-right: 0;
         }}
 
+        .message.from-me.service-sms {{
+            background-color: #34c759;
+        }}
+
         .message.from-others {{
             background-color: #e5e5ea;
             color: black;
@@ -612,12 +1753,258 @@ impl HtmlOutput {
             line-height: 1.4;
         }}
 
+        .message.jumbo-emoji {{
+            background-color: transparent !important;
+            color: #8e8e93 !important;
+            padding: 0 4px;
+        }}
+
+        .message.jumbo-emoji .message-header {{
+            color: inherit;
+        }}
+
+        .message.jumbo-emoji .message-text {{
+            font-size: 3em;
+            line-height: 1.15;
+        }}
+
+        .mention {{
+            font-weight: 600;
+            padding: 0 2px;
+            border-radius: 4px;
+            background-color: rgba(0, 122, 255, 0.15);
+        }}
+
+        .message.from-me .mention {{
+            background-color: rgba(255, 255, 255, 0.25);
+        }}
+
+        .text-big {{
+            font-size: 1.6em;
+        }}
+
+        .text-small {{
+            font-size: 0.75em;
+        }}
+
+        .text-shake,
+        .text-nod,
+        .text-jitter,
+        .text-explode,
+        .text-ripple,
+        .text-bloom,
+        .text-animated {{
+            display: inline-block;
+        }}
+
+        .text-shake {{
+            animation: text-shake 0.5s ease-in-out 3;
+        }}
+
+        @keyframes text-shake {{
+            0%, 100% {{ transform: translateX(0); }}
+            25% {{ transform: translateX(-3px); }}
+            75% {{ transform: translateX(3px); }}
+        }}
+
+        .text-nod {{
+            animation: text-nod 0.6s ease-in-out 3;
+        }}
+
+        @keyframes text-nod {{
+            0%, 100% {{ transform: translateY(0); }}
+            50% {{ transform: translateY(4px); }}
+        }}
+
+        .text-jitter {{
+            animation: text-shake 0.15s linear 10;
+        }}
+
+        .text-explode,
+        .text-bloom {{
+            animation: text-bloom 0.8s ease-out;
+        }}
+
+        @keyframes text-bloom {{
+            0% {{ transform: scale(0.3); opacity: 0; }}
+            70% {{ transform: scale(1.2); opacity: 1; }}
+            100% {{ transform: scale(1); }}
+        }}
+
+        .text-ripple {{
+            animation: text-nod 1s ease-in-out 2;
+        }}
+
         .message-footer {{
             font-size: 0.75em;
             margin-top: 6px;
             opacity: 0.7;
         }}
 
+        .unread-dot {{
+            display: inline-block;
+            width: 7px;
+            height: 7px;
+            margin-right: 5px;
+            border-radius: 50%;
+            background-color: #007aff;
+            vertical-align: middle;
+        }}
+
+        .message.deleted {{
+            opacity: 0.6;
+            outline: 2px dashed #ff3b30;
+            outline-offset: 2px;
+        }}
+
+        .deleted-label {{
+            font-size: 0.75em;
+            margin-top: 6px;
+            font-weight: 600;
+        }}
+
+        .repaired-label {{
+            font-size: 0.75em;
+            margin-top: 6px;
+            font-style: italic;
+            opacity: 0.8;
+        }}
+
+        .send-effect {{
+            font-size: 0.75em;
+            margin-top: 6px;
+            font-style: italic;
+            opacity: 0.8;
+        }}
+
+        .effect-slam {{
+            animation: effect-slam 0.6s ease-out;
+        }}
+
+        @keyframes effect-slam {{
+            0% {{ transform: scale(2.5); opacity: 0; }}
+            60% {{ transform: scale(0.9); opacity: 1; }}
+            100% {{ transform: scale(1); }}
+        }}
+
+        .effect-loud {{
+            animation: effect-loud 0.8s ease-in-out;
+        }}
+
+        @keyframes effect-loud {{
+            0%, 100% {{ transform: scale(1); }}
+            30% {{ transform: scale(1.3) rotate(-2deg); }}
+            60% {{ transform: scale(1.15) rotate(2deg); }}
+        }}
+
+        .effect-gentle {{
+            animation: effect-gentle 1.5s ease-out;
+        }}
+
+        @keyframes effect-gentle {{
+            0% {{ transform: scale(0.6); opacity: 0.3; }}
+            100% {{ transform: scale(1); opacity: 1; }}
+        }}
+
+        .effect-invisible-ink .message-text,
+        .effect-invisible-ink .attachments {{
+            filter: blur(6px);
+            transition: filter 0.4s;
+        }}
+
+        .effect-invisible-ink:hover .message-text,
+        .effect-invisible-ink:hover .attachments {{
+            filter: none;
+        }}
+
+        .effect-screen {{
+            animation: effect-screen 2s ease-in-out 2;
+        }}
+
+        @keyframes effect-screen {{
+            0%, 100% {{ box-shadow: 0 0 0 rgba(255, 204, 0, 0); }}
+            50% {{ box-shadow: 0 0 24px rgba(255, 204, 0, 0.9); }}
+        }}
+
+        .message-receipt {{
+            font-size: 0.7em;
+            margin-top: 2px;
+            opacity: 0.7;
+            text-align: right;
+        }}
+
+        .app-card {{
+            margin-top: 8px;
+            padding: 10px 12px;
+            border-radius: 12px;
+            background-color: rgba(0, 0, 0, 0.06);
+        }}
+
+        .message.from-me .app-card {{
+            background-color: rgba(255, 255, 255, 0.2);
+        }}
+
+        .link-card {{
+            display: block;
+            color: inherit;
+            text-decoration: none;
+        }}
+
+        .audio-details {{
+            display: flex;
+            align-items: center;
+            gap: 8px;
+            font-size: 0.8em;
+            opacity: 0.8;
+        }}
+
+        .audio-waveform {{
+            height: 32px;
+        }}
+
+        .recognized-text {{
+            font-size: 0.8em;
+            margin-top: 4px;
+            opacity: 0.8;
+        }}
+
+        .recognized-text summary {{
+            cursor: pointer;
+        }}
+
+        .contact-card a {{
+            color: inherit;
+        }}
+
+        .payment-card {{
+            border-left: 4px solid #34c759;
+        }}
+
+        .app-card-app {{
+            font-size: 0.75em;
+            text-transform: uppercase;
+            letter-spacing: 0.5px;
+            opacity: 0.7;
+            margin-bottom: 4px;
+        }}
+
+        .app-card-title {{
+            font-weight: 600;
+            margin-bottom: 4px;
+        }}
+
+        .app-card-body {{
+            font-size: 0.9em;
+            opacity: 0.85;
+        }}
+
+        .app-card-link {{
+            display: inline-block;
+            margin-top: 6px;
+            font-size: 0.85em;
+            color: inherit;
+        }}
+
         .attachments {{
             margin-top: 10px;
         }}
@@ -629,6 +2016,26 @@ impl HtmlOutput {
             display: block;
         }}
 
+        .lightbox {{
+            display: none;
+            position: fixed;
+            inset: 0;
+            background-color: rgba(0, 0, 0, 0.9);
+            align-items: center;
+            justify-content: center;
+            cursor: zoom-out;
+            z-index: 100;
+        }}
+
+        .lightbox.open {{
+            display: flex;
+        }}
+
+        .lightbox img {{
+            max-width: 95vw;
+            max-height: 95vh;
+        }}
+
         .attachment-link {{
             display: inline-block;
             padding: 8px 12px;
@@ -645,6 +2052,11 @@ impl HtmlOutput {
             color: white;
         }}
 
+        .attachment-skipped {{
+            font-style: italic;
+            opacity: 0.8;
+        }}
+
         .attachment-link:hover {{
             opacity: 0.8;
         }}
@@ -679,6 +2091,13 @@ impl HtmlOutput {
             margin-right: 4px;
         }}
 
+        .tapback-image {{
+            width: 1.4em;
+            height: 1.4em;
+            object-fit: contain;
+            vertical-align: middle;
+        }}
+
         .tapback-name {{
             opacity: 0.8;
         }}
@@ -690,220 +2109,1238 @@ impl HtmlOutput {
             margin: 20px 0;
             font-weight: 500;
         }}
+
+        .system-event {{
+            text-align: center;
+            color: #8e8e93;
+            font-size: 0.8em;
+            font-style: italic;
+            margin: 12px 0;
+        }}
+
+        .recovered-text {{
+            display: inline-block;
+            margin-top: 4px;
+            padding: 6px 12px;
+            border: 1px dashed #c7c7cc;
+            border-radius: 12px;
+            font-style: normal;
+            white-space: pre-wrap;
+        }}
+
+        .page-nav {{
+            display: flex;
+            justify-content: space-between;
+            gap: 8px;
+        }}
+
+        .chat-label {{
+            display: block;
+            font-size: 0.75em;
+            font-weight: 600;
+            color: inherit;
+            opacity: 0.7;
+            text-decoration: none;
+            margin-bottom: 4px;
+        }}
+
+        .system-event .chat-label {{
+            display: inline;
+        }}
     </style>
-</head>
+{}{}</head>
 <body>
-    <a href="../index.html" class="back-link">← Back to Chats</a>
-    <h1>{}</h1>
-"#,
-            chat_name, chat_name
-        ));
+{}"#,
+            self.options.locale.language(),
+            html_escape(title),
+            self.options.theme.head_html(),
+            print_css(title, self.options.locale),
+            self.options.theme.toggle_html()
+        )
+    }
 
-        // Add participants section for group chats
-        if is_group_chat && !participants.is_empty() {
-            html.push_str(
-                r#"    <div class="participants">
-        <div class="participants-header">Participants:</div>
-        <div class="participants-list">
-"#,
-            );
-            for participant in &participants {
-                html.push_str(&format!(
-                    r#"            <span class="participant">{}</span>
-"#,
-                    self.html_escape(participant)
-                ));
+    /// Renders `pages[page_index]` of a chat. `chat_messages` is the whole chat, for the
+    /// participant list and media count.
+    fn build_chat_html(
+        &self,
+        chat_name: &str,
+        chat_messages: &[&CleanMessage],
+        pages: &[ChatPage],
+        page_index: usize,
+        saved: &SavedAttachments,
+    ) -> String {
+        let mut html = String::new();
+        let page = &pages[page_index];
+        let messages = page.messages;
+        let title = match &page.label {
+            Some(label) => format!("{} ({})", chat_name, label),
+            None => chat_name.to_string(),
+        };
+
+        // Extract unique participants (excluding "Me")
+        let is_group_chat = !chat_name.starts_with("Direct: ");
+        let mut participants: Vec<String> = chat_messages
+            .iter()
+            .map(|m| m.from.to_string())
+            .filter(|name| name != "Me")
+            .collect();
+        participants.sort();
+        participants.dedup();
+
+        let locale = self.options.locale;
+        let strings = locale.strings();
+        html.push_str(&self.transcript_head(&title));
+        let media_count = self.media_count(chat_messages);
+        let mention_count = chat_messages.iter().filter(|m| m.mentions_me()).count();
+        html.push_str(
+            &ChatHeader {
+                title: &title,
+                photo: saved.group_photos.get(chat_name).map(String::as_str),
+                root_prefix: self.root_prefix(),
+                // imessage-exporter's layout has no index to go back to
+                back_to_chats: (self.options.layout == Layout::Default)
+                    .then_some(strings.back_to_chats),
+                // Link to the neighbouring periods and the chat's own index when split
+                page_nav: page.label.is_some().then(|| PageNav {
+                    previous: page_index.checked_sub(1).map(|i| pages[i].link()),
+                    next: pages.get(page_index + 1).map(ChatPage::link),
+                }),
+                // Link to the media gallery when the chat has photos or videos
+                media: (self.options.layout == Layout::Default && media_count > 0).then(|| {
+                    MediaLink {
+                        file: self.media_file(chat_name),
+                        label: strings.media,
+                        count: media_count,
+                    }
+                }),
+                // Let group chats filter down to the messages that mention me
+                mentions: (is_group_chat && mention_count > 0).then_some(MentionsFilter {
+                    label: strings.mentions_of_me,
+                    count: mention_count,
+                }),
             }
+            .render(),
+        );
+
+        if is_group_chat {
             html.push_str(
-                r#"        </div>
-    </div>
-"#,
+                &Participants {
+                    header: strings.participants,
+                    root_prefix: self.root_prefix(),
+                    participants: participants
+                        .iter()
+                        .map(|participant| (participant.as_str(), self.contact_link(participant)))
+                        .collect(),
+                }
+                .render(),
             );
+            html.push_str(&self.members_html(chat_messages));
+        }
+
+        // Note the chats a merged group chat was put together from
+        let mut chat_ids: Vec<i32> = chat_messages.iter().filter_map(|m| m.chat_id).collect();
+        chat_ids.sort();
+        chat_ids.dedup();
+        if self.options.merge_duplicate_groups && is_group_chat && chat_ids.len() > 1 {
+            html.push_str(&format!(
+                r#"    <div class="merged-chats">Merged from chats {}</div>
+"#,
+                chat_ids
+                    .iter()
+                    .map(i32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
         }
 
+        html.push_str(&chat_search_html(strings));
+
         // Group messages by date
         let mut last_date = String::new();
 
         for message in messages {
-            let message_date = message.date.format("%B %d, %Y").to_string();
+            let message_date = locale.long_date(&message.date);
 
-            // Add date separator if date changed
+            // Add date separator if date changed, as the anchor the index's calendar links to
             if message_date != last_date {
                 html.push_str(&format!(
-                    r#"    <div class="date-separator">{}</div>
+                    r#"    <div class="date-separator" id="{}">{}</div>
 "#,
+                    day_anchor(&message.date),
                     message_date
                 ));
                 last_date = message_date;
             }
 
-            // Determine message class
-            let message_class = if message.from.to_string() == "Me" {
-                "from-me"
-            } else {
-                "from-others"
-            };
+            html.push_str(&self.render_message(message, saved, self.root_prefix(), None));
+        }
 
+        html.push_str(CHAT_SEARCH_SCRIPT);
+        html.push_str(TRANSCRIPT_FOOT);
+
+        html
+    }
+
+    /// One message as a bubble or system row. `root_prefix` leads from the page back to the
+    /// export root, and `chat_label` is a link to the message's chat, shown on the timeline.
+    fn render_message(
+        &self,
+        message: &CleanMessage,
+        saved: &SavedAttachments,
+        root_prefix: &str,
+        chat_label: Option<&str>,
+    ) -> String {
+        let mut html = String::new();
+
+        // System rows get the timeline's chat label on a line of their own
+        let is_system_row = message.unsent
+            || message.call.is_some()
+            || matches!(message.balloon, Some(AppBalloon::LocationSharing { .. }));
+        if is_system_row && let Some(chat_label) = chat_label {
             html.push_str(&format!(
-                r#"    <div class="message {}">
+                r#"    <div class="system-event">{}</div>
 "#,
-                message_class
+                chat_label
             ));
+        }
 
-            // Message header (sender name for others)
-            if message_class == "from-others" {
-                html.push_str(&format!(
-                    r#"        <div class="message-header">{}</div>
+        // Unsent messages become a system row instead of a bubble
+        if message.unsent {
+            html.push_str(&self.render_unsent(message));
+            return html;
+        }
+
+        // Calls from the call history are system rows too
+        if let Some(call) = &message.call {
+            let icon = if call.answered { "📞" } else { "📵" };
+            html.push_str(&format!(
+                r#"    <div class="system-event call-event" id="msg-{}">{} {} · {}</div>
 "#,
-                    self.html_escape(&message.from.to_string())
-                ));
-            }
+                message.guid,
+                icon,
+                html_escape(&call.description()),
+                self.options.locale.time(&message.date)
+            ));
+            return html;
+        }
 
-            // Message text
-            if !message.text.is_empty() {
-                html.push_str(&format!(
-                    r#"        <div class="message-text">{}</div>
+        // Location sharing status changes are system rows too
+        if let Some(AppBalloon::LocationSharing { started }) = &message.balloon {
+            let sender = if message.from.is_me() {
+                self.options.locale.strings().you.to_string()
+            } else {
+                message.from.to_string()
+            };
+            let action = if *started { "started" } else { "stopped" };
+            html.push_str(&format!(
+                r#"    <div class="system-event" id="msg-{}">📍 {} {} sharing location · {}</div>
 "#,
-                    self.html_escape(&message.text)
-                ));
-            }
+                message.guid,
+                html_escape(&sender),
+                action,
+                self.options.locale.time(&message.date)
+            ));
+            return html;
+        }
 
-            // Attachments
-            if !message.attachments.is_empty() {
-                html.push_str(
-                    r#"        <div class="attachments">
+        let from_me = message.from.is_me();
+        let effect_class = match &message.send_effect {
+            Some(effect) if self.options.animate_effects => effect.css_class(),
+            _ => "",
+        };
+        let deleted_class = if message.deleted { "deleted" } else { "" };
+        // Like Messages.app, a message of just a few emoji is shown large, without a bubble
+        let jumbo_class = if message.attachments.is_empty()
+            && message.balloon.is_none()
+            && is_jumbo_emoji(&message.text)
+        {
+            "jumbo-emoji"
+        } else {
+            ""
+        };
+
+        let mut contents = String::new();
+
+        // Message text. A shared location's text is just its Maps URL, shown on the card,
+        // as is a previewed link's, and other app messages only carry an object placeholder
+        // character
+        let text_on_card = match &message.balloon {
+            Some(AppBalloon::Location { .. }) => true,
+            Some(AppBalloon::Link { url, .. }) => {
+                !self.options.skip_link_previews && message.text.trim() == url
+            }
+            Some(_) => message
+                .text
+                .trim_matches(|c: char| c == '\u{FFFC}' || c.is_whitespace())
+                .is_empty(),
+            None => false,
+        };
+        if !message.text.is_empty() && !text_on_card {
+            contents.push_str(&format!(
+                r#"        <div class="message-text">{}</div>
 "#,
-                );
+                self.render_message_text(message)
+            ));
+        }
 
-                for attachment in &message.attachments {
-                    if let Some(filename) = attachment.filename() {
-                        let attachment_subpath = self.get_attachment_path(&message.guid);
-                        let attachment_path =
-                            format!("../attachments/{}/{}", attachment_subpath, filename);
-
-                        // Use MIME type to determine how to display the attachment
-                        use imessage_database::tables::attachment::MediaType;
-                        match attachment.mime_type() {
-                            MediaType::Image(_) => {
-                                html.push_str(&format!(
-                                    r#"            <img src="{}" alt="{}" class="attachment-image">
-"#,
-                                    attachment_path,
-                                    self.html_escape(filename)
-                                ));
-                            }
-                            MediaType::Video(_) => {
-                                html.push_str(&format!(
-                                    r#"            <video src="{}" controls class="attachment-image">
-                Your browser does not support the video tag.
-            </video>
-"#,
-                                    attachment_path
-                                ));
-                            }
-                            MediaType::Audio(_) => {
-                                html.push_str(&format!(
-                                    r#"            <audio src="{}" controls class="attachment-link">
-                Your browser does not support the audio tag.
-            </audio>
-"#,
-                                    attachment_path
-                                ));
-                            }
-                            _ => {
-                                // For other files (text, application, other), create a download link
-                                let icon = self.get_file_icon(filename);
-                                html.push_str(&format!(
-                                    r#"            <a href="{}" class="attachment-link" download>
-                <span class="attachment-icon">{}</span>{}
-            </a>
-"#,
-                                    attachment_path,
-                                    icon,
-                                    self.html_escape(filename)
-                                ));
-                            }
-                        }
-                    }
-                }
+        // App message card
+        if let Some(balloon) = &message.balloon
+            && !(self.options.skip_link_previews && matches!(balloon, AppBalloon::Link { .. }))
+        {
+            contents.push_str(&self.render_balloon(balloon));
+        }
 
-                html.push_str(
-                    r#"        </div>
+        if !message.attachments.is_empty() {
+            contents.push_str(
+                r#"        <div class="attachments">
 "#,
-                );
+            );
+            for attachment in self.attachment_views(message, saved, root_prefix) {
+                contents.push_str(&attachment.render());
             }
-
-            // Tapbacks
-            if !message.tapbacks.is_empty() {
-                html.push_str(
-                    r#"        <div class="tapbacks">
+            contents.push_str(
+                r#"        </div>
 "#,
-                );
+            );
+        }
+
+        // Placeholders for attachments left out of the export
+        for skipped in &message.skipped_attachments {
+            contents.push_str(&format!(
+                r#"        <div class="attachment-link attachment-skipped">
+            <span class="attachment-icon">{}</span>{}
+        </div>
+"#,
+                file_icon(&skipped.filename),
+                html_escape(&skipped.description())
+            ));
+        }
+
+        contents.push_str(
+            &Tapbacks {
+                tapbacks: message
+                    .tapbacks
+                    .iter()
+                    .map(|(handle, emoji)| (handle.to_string(), emoji))
+                    .collect(),
+                root_prefix,
+                inline_images: self.options.inline_attachments,
+            }
+            .render(),
+        );
+
+        // Deleted marker for recoverable messages
+        if message.deleted {
+            let locale = self.options.locale;
+            let deleted_label = match message.deleted_at {
+                Some(deleted_at) => {
+                    format!(
+                        "{} {}",
+                        locale.strings().deleted,
+                        locale.short_date(&deleted_at)
+                    )
+                }
+                None => locale.strings().deleted.to_string(),
+            };
+            contents.push_str(&format!(
+                r#"        <div class="deleted-label">🗑 {}</div>
+"#,
+                deleted_label
+            ));
+        }
+
+        // Marker for messages --repair-sender-heuristics reassigned to me
+        if message.sender_repaired {
+            contents.push_str(
+                r#"        <div class="repaired-label">Sender inferred: not marked as sent in the database</div>
+"#,
+            );
+        }
+
+        // Send effect badge
+        if let Some(effect) = &message.send_effect {
+            contents.push_str(&format!(
+                r#"        <div class="send-effect">sent with {}</div>
+"#,
+                html_escape(&effect.to_string())
+            ));
+        }
+
+        contents.push_str(
+            &Footer::new(
+                message,
+                self.options.locale,
+                from_me && !self.options.skip_receipts,
+            )
+            .render(),
+        );
+
+        html.push_str(
+            &Bubble {
+                guid: &message.guid,
+                classes: [
+                    if from_me { "from-me" } else { "from-others" },
+                    message.service.css_class(),
+                    effect_class,
+                    deleted_class,
+                    jumbo_class,
+                ],
+                chat_label,
+                sender: (!from_me).then(|| message.from.to_string()),
+                mentions_me: message.mentions_me(),
+                contents,
+            }
+            .render(),
+        );
+
+        html
+    }
+
+    /// How each of `message`'s attachments that has a file is shown. `root_prefix` leads
+    /// from the page back to the export root.
+    fn attachment_views<'a>(
+        &self,
+        message: &'a CleanMessage,
+        saved: &SavedAttachments,
+        root_prefix: &str,
+    ) -> Vec<AttachmentView<'a>> {
+        let attachment_subpath = self.options.layout.attachment_dir(message);
+        message
+            .attachments
+            .iter()
+            .zip(self.options.layout.attachment_files(message))
+            .filter_map(|(attachment, file)| {
+                let (Some(filename), Some(file)) = (attachment.filename.as_deref(), file) else {
+                    return None;
+                };
+                let key = format!("{}/{}", attachment_subpath, file);
+                let src = if self.options.inline_attachments {
+                    self.attachment_data_uri(attachment).unwrap_or_default()
+                } else {
+                    format!("{}attachments/{}", root_prefix, key)
+                };
+
+                // Use MIME type to determine how to display the attachment
+                let copy = saved.attachments.get(&message.guid, attachment.rowid);
+                let kind = match attachment.kind {
+                    MediaKind::Image => AttachmentKind::Image {
+                        thumbnail: copy
+                            .and_then(|copy| copy.thumbnail.as_ref())
+                            .map(|thumbnail| format!("{}{}", root_prefix, thumbnail)),
+                        recognized_text: message
+                            .recognized_text
+                            .get(&attachment.rowid)
+                            .map(String::as_str),
+                    },
+                    MediaKind::Video => AttachmentKind::Video {
+                        transcript: message
+                            .transcripts
+                            .get(&attachment.rowid)
+                            .map(String::as_str),
+                    },
+                    MediaKind::Audio => AttachmentKind::Audio {
+                        waveform: copy
+                            .and_then(|copy| copy.waveform.as_ref())
+                            .map(|waveform| format!("{}{}", root_prefix, waveform)),
+                        duration: copy.and_then(|copy| copy.duration),
+                    },
+                    // Shared contact cards show the contact above the download
+                    MediaKind::Other => AttachmentKind::File {
+                        card: VCard::is_vcard(filename)
+                            .then(|| attachment.read().ok().flatten())
+                            .flatten()
+                            .and_then(|bytes| VCard::parse(&String::from_utf8_lossy(&bytes))),
+                    },
+                };
+                Some(AttachmentView {
+                    filename,
+                    src,
+                    kind,
+                    locale: self.options.locale,
+                })
+            })
+            .collect()
+    }
+
+    /// Escaped message text, with @-mentions highlighted and bold/italic/etc. styling
+    /// applied when the message has attributed text ranges
+    fn render_message_text(&self, message: &CleanMessage) -> String {
+        if message.text_spans.is_empty() {
+            return html_escape(&message.text);
+        }
 
-                for (handle, emoji) in &message.tapbacks {
+        message
+            .text_spans
+            .iter()
+            .map(|span| {
+                let mut rendered = html_escape(&span.text);
+
+                for style in &span.styles {
+                    let tag = style_tag(style);
+                    rendered = format!("<{}>{}</{}>", tag, rendered, tag);
+                }
+
+                if let Some(animation) = &span.animation {
+                    rendered = format!(
+                        r#"<span class="{}">{}</span>"#,
+                        animation_class(animation),
+                        rendered
+                    );
+                }
+
+                if let Some(name) = &span.mention {
+                    rendered = format!(
+                        r#"<span class="mention" title="{}">{}</span>"#,
+                        html_escape(name),
+                        rendered
+                    );
+                }
+
+                rendered
+            })
+            .collect()
+    }
+
+    fn render_balloon(&self, balloon: &AppBalloon) -> String {
+        match balloon {
+            AppBalloon::Location {
+                name,
+                address,
+                url,
+                coordinates,
+            } => {
+                let mut html = format!(
+                    r#"        <div class="app-card location-card">
+            <div class="app-card-title">📍 {}</div>
+"#,
+                    html_escape(name.as_deref().unwrap_or("Shared Location"))
+                );
+                if let Some(address) = address {
                     html.push_str(&format!(
-                        r#"            <div class="tapback">
-                <span class="tapback-emoji">{}</span>
-                <span class="tapback-name">{}</span>
-            </div>
+                        r#"            <div class="app-card-body">{}</div>
 "#,
-                        emoji,
-                        self.html_escape(&handle.to_string())
+                        html_escape(address)
                     ));
                 }
-
+                if let Some((latitude, longitude)) = coordinates {
+                    html.push_str(&format!(
+                        r#"            <div class="app-card-body">{:.6}, {:.6}</div>
+"#,
+                        latitude, longitude
+                    ));
+                }
+                let maps_url = url.clone().or_else(|| {
+                    coordinates.map(|(latitude, longitude)| {
+                        format!("https://maps.apple.com/?ll={},{}", latitude, longitude)
+                    })
+                });
+                if let Some(maps_url) = maps_url {
+                    html.push_str(&card_link(&maps_url, "Open in Maps"));
+                }
+                html.push_str(
+                    r#"        </div>
+"#,
+                );
+                html
+            }
+            AppBalloon::FindMy { caption, url } => {
+                let mut html = format!(
+                    r#"        <div class="app-card findmy-card">
+            <div class="app-card-title">📍 Find My</div>
+            <div class="app-card-body">{}</div>
+"#,
+                    html_escape(caption.as_deref().unwrap_or("Shared location"))
+                );
+                if let Some(url) = url {
+                    html.push_str(&card_link(url, "Open"));
+                }
+                html.push_str(
+                    r#"        </div>
+"#,
+                );
+                html
+            }
+            AppBalloon::Payment { summary, status } => {
+                let mut html = format!(
+                    r#"        <div class="app-card payment-card">
+            <div class="app-card-title">💵 {}</div>
+"#,
+                    html_escape(summary)
+                );
+                if let Some(status) = status {
+                    html.push_str(&format!(
+                        r#"            <div class="app-card-body">{}</div>
+"#,
+                        html_escape(status)
+                    ));
+                }
+                html.push_str(
+                    r#"        </div>
+"#,
+                );
+                html
+            }
+            AppBalloon::App {
+                app_name,
+                title,
+                summary,
+                url,
+            } => {
+                let mut html = format!(
+                    r#"        <div class="app-card">
+            <div class="app-card-app">{}</div>
+"#,
+                    html_escape(app_name)
+                );
+                if let Some(title) = title {
+                    html.push_str(&format!(
+                        r#"            <div class="app-card-title">{}</div>
+"#,
+                        html_escape(title)
+                    ));
+                }
+                if let Some(summary) = summary {
+                    html.push_str(&format!(
+                        r#"            <div class="app-card-body">{}</div>
+"#,
+                        html_escape(summary)
+                    ));
+                }
+                if let Some(url) = url {
+                    html.push_str(&card_link(url, "Open"));
+                }
                 html.push_str(
                     r#"        </div>
 "#,
                 );
+                html
+            }
+            AppBalloon::Link {
+                url,
+                title,
+                summary,
+                site,
+            } => {
+                let safe = is_safe_link(url);
+                let mut html = if safe {
+                    format!(
+                        r#"        <a href="{}" class="app-card link-card" target="_blank" rel="noopener">
+"#,
+                        html_escape(url)
+                    )
+                } else {
+                    r#"        <div class="app-card link-card">
+"#
+                    .to_string()
+                };
+                html.push_str(&format!(
+                    r#"            <div class="app-card-title">{}</div>
+"#,
+                    html_escape(title.as_deref().unwrap_or(url))
+                ));
+                if let Some(summary) = summary {
+                    html.push_str(&format!(
+                        r#"            <div class="app-card-body">{}</div>
+"#,
+                        html_escape(summary)
+                    ));
+                }
+                if let Some(site) = site {
+                    html.push_str(&format!(
+                        r#"            <div class="app-card-app">{}</div>
+"#,
+                        html_escape(site)
+                    ));
+                }
+                html.push_str(if safe {
+                    r#"        </a>
+"#
+                } else {
+                    r#"        </div>
+"#
+                });
+                html
             }
+            // Rendered as a system row rather than inside a bubble
+            AppBalloon::LocationSharing { .. } => String::new(),
+        }
+    }
+
+    fn render_unsent(&self, message: &CleanMessage) -> String {
+        let sender = if message.from.is_me() {
+            self.options.locale.strings().you.to_string()
+        } else {
+            message.from.to_string()
+        };
+
+        let mut html = format!(
+            r#"    <div class="system-event" id="msg-{}">{} unsent a message · {}
+"#,
+            message.guid,
+            html_escape(&sender),
+            self.options.locale.time(&message.date)
+        );
 
-            // Message footer (timestamp)
+        if self.options.recover_unsent
+            && let Some(text) = &message.unsent_text
+        {
             html.push_str(&format!(
-                r#"        <div class="message-footer">{}</div>
+                r#"        <div class="recovered-text">{}</div>
 "#,
-                message.date.format("%I:%M %p")
+                html_escape(text)
             ));
+        }
 
-            html.push_str(
-                r#"    </div>
+        html.push_str(
+            r#"    </div>
 "#,
-            );
+        );
+        html
+    }
+
+    /// The page listing a split chat's periods, with message counts and date ranges
+    fn build_chat_index_html(
+        &self,
+        chat_name: &str,
+        chat_messages: &[&CleanMessage],
+        pages: &[ChatPage],
+    ) -> String {
+        let locale = self.options.locale;
+        let strings = locale.strings();
+        let mut html = String::new();
+
+        html.push_str(&format!(
+            r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{}</title>
+    <style>
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Helvetica, Arial, sans-serif;
+            max-width: 800px;
+            margin: 0 auto;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }}
+
+        .back-link {{
+            display: inline-block;
+            margin-bottom: 20px;
+            margin-right: 8px;
+            padding: 8px 16px;
+            background-color: #007aff;
+            color: white;
+            text-decoration: none;
+            border-radius: 8px;
+            transition: background-color 0.2s;
+        }}
+
+        .back-link:hover {{
+            background-color: #0051d5;
+        }}
+
+        h1 {{
+            text-align: center;
+            color: #333;
+            border-bottom: 2px solid #007aff;
+            padding-bottom: 10px;
+        }}
+
+        .chat-list {{
+            background: white;
+            border-radius: 12px;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.1);
+            overflow: hidden;
+        }}
+
+        .chat-item {{
+            display: block;
+            padding: 16px 20px;
+            border-bottom: 1px solid #e5e5ea;
+            text-decoration: none;
+            color: inherit;
+        }}
+
+        .chat-item:last-child {{
+            border-bottom: none;
+        }}
+
+        .chat-item:hover {{
+            background-color: #f9f9f9;
+        }}
+
+        .chat-name {{
+            font-size: 1.1em;
+            font-weight: 600;
+            color: #000;
+            margin-bottom: 4px;
+        }}
+
+        .chat-info {{
+            font-size: 0.9em;
+            color: #666;
+            display: flex;
+            justify-content: space-between;
+        }}
+
+        .message-count {{
+            color: #007aff;
+        }}
+    </style>
+{}</head>
+<body>
+{}    <a href="{}index.html" class="back-link">{}</a>
+"#,
+            locale.language(),
+            html_escape(chat_name),
+            self.options.theme.head_html(),
+            self.options.theme.toggle_html(),
+            self.root_prefix(),
+            strings.back_to_chats
+        ));
+
+        let media_count = self.media_count(chat_messages);
+        if self.options.layout == Layout::Default && media_count > 0 {
+            html.push_str(&format!(
+                r#"    <a href="{}" class="back-link">📷 {} ({})</a>
+"#,
+                self.media_file(chat_name),
+                strings.media,
+                media_count
+            ));
+        }
+
+        html.push_str(&format!(
+            r#"    <h1>{}</h1>
+    <div class="chat-list">
+"#,
+            html_escape(chat_name)
+        ));
+
+        for page in pages {
+            let (Some(first), Some(last)) = (page.messages.first(), page.messages.last()) else {
+                continue;
+            };
+            html.push_str(&format!(
+                r#"        <a href="{}" class="chat-item">
+            <div class="chat-name">{}</div>
+            <div class="chat-info">
+                <span class="message-count">{} {}</span>
+                <span>{} – {}</span>
+            </div>
+        </a>
+"#,
+                page.file,
+                page.label.as_deref().unwrap_or_default(),
+                page.messages.len(),
+                strings.messages,
+                locale.short_date(&first.date),
+                locale.short_date(&last.date)
+            ));
+        }
+
+        html.push_str(
+            r#"    </div>
+</body>
+</html>
+"#,
+        );
+
+        html
+    }
+
+    fn build_media_html(
+        &self,
+        chat_name: &str,
+        messages: &[&CleanMessage],
+        saved: &SavedAttachments,
+    ) -> String {
+        // Back to the chat's own index when split, otherwise to its only page
+        let chat_page = match self.options.split_by {
+            Some(_) => "index.html".to_string(),
+            None => format!("{}.html", self.chat_stems[chat_name]),
+        };
+        let mut html = String::new();
+
+        html.push_str(&format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{} - Media</title>
+    <style>
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Helvetica, Arial, sans-serif;
+            max-width: 1100px;
+            margin: 0 auto;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }}
+
+        .back-link {{
+            display: inline-block;
+            margin-bottom: 20px;
+            margin-right: 8px;
+            padding: 8px 16px;
+            background-color: #007aff;
+            color: white;
+            text-decoration: none;
+            border-radius: 8px;
+            transition: background-color 0.2s;
+        }}
+
+        .back-link:hover {{
+            background-color: #0051d5;
+        }}
+
+        h1 {{
+            text-align: center;
+            color: #333;
+            border-bottom: 2px solid #007aff;
+            padding-bottom: 10px;
+        }}
+
+        .media-grid {{
+            display: grid;
+            grid-template-columns: repeat(auto-fill, minmax(180px, 1fr));
+            gap: 12px;
+        }}
+
+        .media-item {{
+            display: block;
+            text-decoration: none;
+            color: inherit;
+        }}
+
+        .media-item img,
+        .media-item video {{
+            width: 100%;
+            aspect-ratio: 1;
+            object-fit: cover;
+            border-radius: 8px;
+            display: block;
+            background-color: #e5e5ea;
+        }}
+
+        .media-date {{
+            font-size: 0.8em;
+            color: #666;
+            margin-top: 4px;
+            text-align: center;
+        }}
+    </style>
+{}</head>
+<body>
+{}    <a href="{}" class="back-link">{}</a>
+    <a href="{}index.html" class="back-link">{}</a>
+    <h1>{}</h1>
+    <div class="media-grid">
+"#,
+            html_escape(chat_name),
+            self.options.theme.head_html(),
+            self.options.theme.toggle_html(),
+            chat_page,
+            self.options.locale.strings().back_to_chat,
+            self.root_prefix(),
+            self.options.locale.strings().all_chats,
+            html_escape(chat_name)
+        ));
+
+        for message in messages {
+            for (attachment, file) in message
+                .attachments
+                .iter()
+                .zip(self.options.layout.attachment_files(message))
+            {
+                let (Some(filename), Some(file)) = (attachment.filename.as_deref(), file) else {
+                    continue;
+                };
+
+                let attachment_subpath = self.options.layout.attachment_dir(message);
+                let attachment_path = format!(
+                    "{}attachments/{}/{}",
+                    self.root_prefix(),
+                    attachment_subpath,
+                    file
+                );
+                let message_link = format!(
+                    "{}#msg-{}",
+                    self.page_file(chat_name, &message.date),
+                    message.guid
+                );
+
+                let preview = match attachment.kind {
+                    MediaKind::Image => {
+                        let thumbnail = saved
+                            .attachments
+                            .get(&message.guid, attachment.rowid)
+                            .and_then(|copy| copy.thumbnail.as_ref());
+                        let image_src = match thumbnail {
+                            Some(thumbnail) => format!("{}{}", self.root_prefix(), thumbnail),
+                            None => attachment_path,
+                        };
+                        format!(
+                            r#"<img src="{}" alt="{}" loading="lazy">"#,
+                            image_src,
+                            html_escape(filename)
+                        )
+                    }
+                    MediaKind::Video => {
+                        format!(
+                            r#"<video src="{}" preload="metadata" muted></video>"#,
+                            attachment_path
+                        )
+                    }
+                    _ => continue,
+                };
+
+                html.push_str(&format!(
+                    r#"        <a href="{}" class="media-item" title="{}">
+            {}
+            <div class="media-date">{}</div>
+        </a>
+"#,
+                    message_link,
+                    html_escape(filename),
+                    preview,
+                    self.options.locale.short_date(&message.date)
+                ));
+            }
         }
 
-        // Close HTML
         html.push_str(
-            r#"</body>
+            r#"    </div>
+</body>
 </html>
 "#,
         );
 
         html
     }
+}
+
+/// File name each of `message`'s attachments is saved under, or `None` for attachments
+/// without a file: sanitized, and unique among the message's attachments
+pub fn attachment_filenames(message: &CleanMessage) -> Vec<Option<String>> {
+    let mut allocator = FilenameAllocator::default();
+    message
+        .attachments
+        .iter()
+        .map(|attachment| Some(allocator.file(attachment.filename.as_deref()?)))
+        .collect()
+}
+
+/// Styles for printing a transcript page, e.g. to PDF from the browser: black text on
+/// white with outlined bubbles instead of colored ones, each day on a new sheet, and
+/// `title` and the page number in the page margins. Comes after the theme so it
+/// overrides dark mode too.
+fn print_css(title: &str, locale: Locale) -> String {
+    let strings = locale.strings();
+    // A CSS string can't hold a raw quote, backslash or newline, nor may "</" end the
+    // style element
+    let title: String = title
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' | '<' => format!("\\{:x} ", c as u32),
+            '\n' | '\r' => " ".to_string(),
+            c => c.to_string(),
+        })
+        .collect();
+    format!(
+        r#"    <style>
+        @page {{
+            margin: 2cm 1.5cm;
+            @top-center {{
+                content: "{}";
+                font-size: 9pt;
+                color: #666;
+            }}
+            @bottom-center {{
+                content: "{} " counter(page) " {} " counter(pages);
+                font-size: 9pt;
+                color: #666;
+            }}
+        }}
+
+        @media print {{
+            html body {{
+                max-width: none;
+                padding: 0;
+                background: white !important;
+                color: black !important;
+            }}
+
+            .theme-toggle, .back-link, .page-nav, .chat-search, .lightbox {{
+                display: none !important;
+            }}
+
+            .message {{
+                break-inside: avoid;
+                box-shadow: none !important;
+                outline: none !important;
+            }}
+
+            .message.from-me, .message.from-others {{
+                background: white !important;
+                color: black !important;
+                border: 1px solid #999;
+            }}
+
+            .message.from-me {{
+                border-width: 2px;
+            }}
+
+            .message a, .message-text, .message .sender {{
+                color: black !important;
+            }}
+
+            .participants, .members {{
+                box-shadow: none;
+                border: 1px solid #ccc;
+            }}
+
+            .date-separator ~ .date-separator {{
+                break-before: page;
+            }}
+
+            .date-separator {{
+                break-after: avoid;
+                color: black !important;
+            }}
+        }}
+    </style>
+"#,
+        title, strings.page, strings.page_of
+    )
+}
+
+/// A link at the bottom of an app card, or its URL as plain text when the scheme isn't
+/// one a card may link to
+fn card_link(url: &str, label: &str) -> String {
+    if is_safe_link(url) {
+        format!(
+            r#"            <a href="{}" class="app-card-link" target="_blank" rel="noopener">{}</a>
+"#,
+            html_escape(url),
+            label
+        )
+    } else {
+        format!(
+            r#"            <div class="app-card-body">{}</div>
+"#,
+            html_escape(url)
+        )
+    }
+}
 
-    fn html_escape(&self, text: &str) -> String {
-        text.replace('&', "&amp;")
-            .replace('<', "&lt;")
-            .replace('>', "&gt;")
-            .replace('"', "&quot;")
-            .replace('\'', "&#39;")
+/// File name stem of each contact's page, by name: everyone other than me who wrote in
+/// an exported chat and whose handle is known. Handles a contact card merged share a name,
+/// and so a page.
+fn contact_stems(messages: &[CleanMessage]) -> HashMap<String, String> {
+    let mut names: Vec<String> = messages
+        .iter()
+        .filter(|m| m.call.is_none() && !m.from.is_me() && !m.from.is_unknown())
+        .map(|m| m.from.to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut allocator = FilenameAllocator::default();
+    names
+        .into_iter()
+        .map(|name| {
+            let stem = allocator.stem(&name);
+            (name, stem)
+        })
+        .collect()
+}
+
+/// The top words and emoji lines of a stats page section
+fn top_terms_html(
+    strings: &Strings,
+    words: &[(String, usize)],
+    emoji: &[(String, usize)],
+) -> String {
+    let mut html = String::new();
+    for (label, terms) in [(strings.top_words, words), (strings.top_emoji, emoji)] {
+        if !terms.is_empty() {
+            html.push_str(&format!(
+                r#"        <div class="member-dates">{}: {}</div>
+"#,
+                label,
+                html_escape(&format_terms(terms))
+            ));
+        }
     }
+    html
+}
+
+/// Directory a message's attachments are saved in, spread over two levels named after
+/// the start of its GUID, e.g. "FE718EBE-BB92-4650-A656-D59ACB15619C" ->
+/// "FE/71/FE718EBE-BB92-4650-A656-D59ACB15619C". GUIDs that are too short or don't start
+/// with four letters or digits are spread by a hash of the GUID instead.
+pub fn attachment_subpath(guid: &str) -> String {
+    let shard = match guid.get(0..4) {
+        Some(prefix) if prefix.chars().all(|c| c.is_ascii_alphanumeric()) => prefix.to_string(),
+        _ => format!("{:x}", Sha256::digest(guid.as_bytes()))[0..4].to_string(),
+    };
+    format!(
+        "{}/{}/{}",
+        &shard[0..2],
+        &shard[2..4],
+        sanitize_filename(guid)
+    )
+}
 
-    fn get_file_icon(&self, filename: &str) -> &str {
-        let lower = filename.to_lowercase();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if lower.ends_with(".pdf") {
-            "📄"
-        } else if lower.ends_with(".mp4") || lower.ends_with(".mov") || lower.ends_with(".avi") {
-            "🎥"
-        } else if lower.ends_with(".mp3") || lower.ends_with(".m4a") || lower.ends_with(".wav") {
-            "🎵"
-        } else if lower.ends_with(".zip") || lower.ends_with(".tar") || lower.ends_with(".gz") {
-            "📦"
-        } else if lower.ends_with(".doc") || lower.ends_with(".docx") {
-            "📝"
-        } else {
-            "📎"
+    #[test]
+    fn test_attachment_subpath() {
+        assert_eq!(
+            attachment_subpath("FE718EBE-BB92-4650-A656-D59ACB15619C"),
+            "FE/71/FE718EBE-BB92-4650-A656-D59ACB15619C"
+        );
+        assert_eq!(attachment_subpath("ABCD"), "AB/CD/ABCD");
+    }
+
+    #[test]
+    fn test_attachment_subpath_malformed_guids() {
+        for guid in [
+            "",
+            "A",
+            "AB",
+            "ABC",
+            "é",
+            "日本語のGUID",
+            "../../etc",
+            "p:0/ABC",
+            "AB/CD",
+        ] {
+            let subpath = attachment_subpath(guid);
+            let parts: Vec<&str> = subpath.split('/').collect();
+
+            assert_eq!(parts.len(), 3, "{:?} -> {:?}", guid, subpath);
+            assert!(
+                parts[..2]
+                    .iter()
+                    .all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_hexdigit())),
+                "{:?} -> {:?}",
+                guid,
+                subpath
+            );
+            assert!(
+                !parts[2].is_empty() && parts[2] != "..",
+                "{:?} -> {:?}",
+                guid,
+                subpath
+            );
+            // The same GUID always lands in the same directory
+            assert_eq!(subpath, attachment_subpath(guid));
         }
     }
 }
@@ -0,0 +1,741 @@
+use crate::audio;
+use crate::clean_message::CleanMessage;
+use crate::html_output::attachment_subpath;
+use crate::locale::Locale;
+use crate::markup::{data_uri, html_escape};
+use crate::message_service::MessageService;
+use crate::tapback_emoji::{TapbackEmoji, TapbackImage};
+use crate::vcard::VCard;
+use std::time::Duration;
+
+/// A part of a transcript page that renders itself, so it can be built and tested apart
+/// from the page around it
+pub trait Render {
+    fn render(&self) -> String;
+}
+
+/// The top of a chat page: the link back to the index, the title beside the group's
+/// photo, and links to the neighbouring pages and the media page
+pub struct ChatHeader<'a> {
+    pub title: &'a str,
+    /// Path of the group chat's photo from the export root
+    pub photo: Option<&'a str>,
+    /// Relative path from the page back to the export root
+    pub root_prefix: &'a str,
+    /// Label of the link back to the index, when there is an index
+    pub back_to_chats: Option<&'a str>,
+    /// The pages before and after this one, when the chat is split into pages
+    pub page_nav: Option<PageNav<'a>>,
+    /// The chat's media page, when it has photos or videos
+    pub media: Option<MediaLink<'a>>,
+    /// Button showing only the messages that mention me, when some do
+    pub mentions: Option<MentionsFilter<'a>>,
+}
+
+/// Links to the previous and next pages of a split chat, around one to its own index
+pub struct PageNav<'a> {
+    pub previous: Option<PageLink<'a>>,
+    pub next: Option<PageLink<'a>>,
+}
+
+pub struct PageLink<'a> {
+    /// File name within the chat's directory
+    pub file: &'a str,
+    /// Period the page covers
+    pub label: &'a str,
+}
+
+pub struct MediaLink<'a> {
+    pub file: String,
+    pub label: &'a str,
+    /// Number of photos and videos on the media page
+    pub count: usize,
+}
+
+pub struct MentionsFilter<'a> {
+    pub label: &'a str,
+    /// Number of messages in the chat that mention me
+    pub count: usize,
+}
+
+impl Render for ChatHeader<'_> {
+    fn render(&self) -> String {
+        let mut html = String::new();
+        if let Some(label) = self.back_to_chats {
+            html.push_str(&format!(
+                r#"    <a href="{}index.html" class="back-link">{}</a>
+"#,
+                self.root_prefix, label
+            ));
+        }
+
+        let photo = self
+            .photo
+            .map(|photo| {
+                format!(
+                    r#"<img class="chat-header-photo" src="{}{}" alt="">"#,
+                    self.root_prefix,
+                    html_escape(photo)
+                )
+            })
+            .unwrap_or_default();
+        html.push_str(&format!(
+            r#"    <h1>{}{}</h1>
+"#,
+            photo,
+            html_escape(self.title)
+        ));
+
+        if let Some(page_nav) = &self.page_nav {
+            html.push_str(&page_nav.render());
+        }
+        if let Some(media) = &self.media {
+            html.push_str(&format!(
+                r#"    <a href="{}" class="back-link">📷 {} ({})</a>
+"#,
+                media.file, media.label, media.count
+            ));
+        }
+        if let Some(mentions) = &self.mentions {
+            html.push_str(&format!(
+                r#"    <button type="button" class="back-link mentions-filter" onclick="document.body.classList.toggle('only-mentions')">@ {} ({})</button>
+"#,
+                mentions.label, mentions.count
+            ));
+        }
+        html
+    }
+}
+
+impl Render for PageNav<'_> {
+    fn render(&self) -> String {
+        let link = |page: &Option<PageLink>, arrow_before: &str, arrow_after: &str| {
+            page.as_ref()
+                .map(|page| {
+                    format!(
+                        r#"<a href="{}" class="back-link">{}{}{}</a>"#,
+                        page.file, arrow_before, page.label, arrow_after
+                    )
+                })
+                .unwrap_or_else(|| "<span></span>".to_string())
+        };
+        format!(
+            r#"    <div class="page-nav">
+        {}
+        <a href="index.html" class="back-link">All Pages</a>
+        {}
+    </div>
+"#,
+            link(&self.previous, "← ", ""),
+            link(&self.next, "", " →")
+        )
+    }
+}
+
+/// A group chat's participants, each linked to their contact page when they have one
+pub struct Participants<'a> {
+    /// "Participants", in the page's language
+    pub header: &'a str,
+    pub root_prefix: &'a str,
+    /// Each participant's name and the path of their contact page from the export root
+    pub participants: Vec<(&'a str, Option<String>)>,
+}
+
+impl Render for Participants<'_> {
+    fn render(&self) -> String {
+        if self.participants.is_empty() {
+            return String::new();
+        }
+
+        let mut html = format!(
+            r#"    <div class="participants">
+        <div class="participants-header">{}</div>
+        <div class="participants-list">
+"#,
+            self.header
+        );
+        for (name, link) in &self.participants {
+            match link {
+                Some(link) => html.push_str(&format!(
+                    r#"            <a class="participant" href="{}{}">{}</a>
+"#,
+                    self.root_prefix,
+                    html_escape(link),
+                    html_escape(name)
+                )),
+                None => html.push_str(&format!(
+                    r#"            <span class="participant">{}</span>
+"#,
+                    html_escape(name)
+                )),
+            }
+        }
+        html.push_str(
+            r#"        </div>
+    </div>
+"#,
+        );
+        html
+    }
+}
+
+/// A message's bubble around its rendered contents, with the sender's name above them
+/// for messages from others and, on the timeline, the chat it's from
+pub struct Bubble<'a> {
+    pub guid: &'a str,
+    /// `from-me` or `from-others`, then the service's class and any effect, deleted and
+    /// jumbo emoji classes
+    pub classes: [&'a str; 5],
+    /// Link to the message's chat, shown on the timeline
+    pub chat_label: Option<&'a str>,
+    /// Sender shown above messages from others
+    pub sender: Option<String>,
+    /// Kept when the chat page shows only messages that mention me
+    pub mentions_me: bool,
+    /// The bubble's text, cards, attachments, tapbacks and footer
+    pub contents: String,
+}
+
+impl Render for Bubble<'_> {
+    fn render(&self) -> String {
+        let [from, service, effect, deleted, jumbo] = self.classes;
+        let mut html = format!(
+            r#"    <div class="message {} {} {} {} {}{}" id="msg-{}">
+"#,
+            from,
+            service,
+            effect,
+            deleted,
+            jumbo,
+            if self.mentions_me { " mentions-me" } else { "" },
+            self.guid
+        );
+        if let Some(chat_label) = self.chat_label {
+            html.push_str(&format!(
+                r#"        {}
+"#,
+                chat_label
+            ));
+        }
+        if let Some(sender) = &self.sender {
+            html.push_str(&format!(
+                r#"        <div class="message-header">{}</div>
+"#,
+                html_escape(sender)
+            ));
+        }
+        html.push_str(&self.contents);
+        html.push_str(
+            r#"    </div>
+"#,
+        );
+        html
+    }
+}
+
+/// One attachment in a bubble
+pub struct AttachmentView<'a> {
+    pub filename: &'a str,
+    /// Path of the saved file from the page, or the file as a `data:` URI
+    pub src: String,
+    pub kind: AttachmentKind<'a>,
+    /// Language of the labels of the recognized text and transcript
+    pub locale: Locale,
+}
+
+/// How an attachment is shown, by its type
+pub enum AttachmentKind<'a> {
+    /// A picture linking to the full-size image, with the text `--ocr` found in it
+    Image {
+        thumbnail: Option<String>,
+        recognized_text: Option<&'a str>,
+    },
+    /// A player, with the speech `--transcribe-videos` found in it
+    Video { transcript: Option<&'a str> },
+    /// A player, with the recording's waveform image and length when known
+    Audio {
+        waveform: Option<String>,
+        duration: Option<Duration>,
+    },
+    /// A download link, below the contact when the file is a contact card
+    File { card: Option<VCard> },
+}
+
+impl Render for AttachmentView<'_> {
+    fn render(&self) -> String {
+        let strings = self.locale.strings();
+        let mut html = String::new();
+        match &self.kind {
+            AttachmentKind::Image {
+                thumbnail,
+                recognized_text,
+            } => {
+                html.push_str(&format!(
+                    r#"            <a href="{}" class="lightbox-link" onclick="return openLightbox(this)">
+            <img src="{}" alt="{}" class="attachment-image" loading="lazy">
+        </a>
+"#,
+                    html_escape(&self.src),
+                    html_escape(thumbnail.as_deref().unwrap_or(&self.src)),
+                    html_escape(self.filename)
+                ));
+                if let Some(text) = recognized_text {
+                    html.push_str(&format!(
+                        r#"            <details class="recognized-text">
+                <summary>{}</summary>
+                <div>{}</div>
+            </details>
+"#,
+                        strings.text_in_image,
+                        html_escape(text).replace('\n', "<br>")
+                    ));
+                }
+            }
+            AttachmentKind::Video { transcript } => {
+                html.push_str(&format!(
+                    r#"            <video src="{}" controls preload="metadata" class="attachment-image">
+            Your browser does not support the video tag.
+        </video>
+"#,
+                    html_escape(&self.src)
+                ));
+                if let Some(transcript) = transcript {
+                    html.push_str(&format!(
+                        r#"            <details class="recognized-text">
+                <summary>{}</summary>
+                <div>{}</div>
+            </details>
+"#,
+                        strings.transcript,
+                        html_escape(transcript)
+                    ));
+                }
+            }
+            AttachmentKind::Audio { waveform, duration } => {
+                html.push_str(&format!(
+                    r#"            <audio src="{}" controls class="attachment-link">
+            Your browser does not support the audio tag.
+        </audio>
+"#,
+                    html_escape(&self.src)
+                ));
+                html.push_str(&audio_details(waveform.as_deref(), *duration));
+            }
+            AttachmentKind::File { card } => {
+                if let Some(card) = card {
+                    html.push_str(&card.render());
+                }
+                html.push_str(&format!(
+                    r#"            <a href="{}" class="attachment-link" download>
+            <span class="attachment-icon">{}</span>{}
+        </a>
+"#,
+                    html_escape(&self.src),
+                    file_icon(self.filename),
+                    html_escape(self.filename)
+                ));
+            }
+        }
+        html
+    }
+}
+
+/// The waveform and length shown under an audio player, when known
+fn audio_details(waveform: Option<&str>, duration: Option<Duration>) -> String {
+    if waveform.is_none() && duration.is_none() {
+        return String::new();
+    }
+
+    let mut html = String::from(
+        r#"            <div class="audio-details">
+"#,
+    );
+    if let Some(waveform) = waveform {
+        html.push_str(&format!(
+            r#"                <img src="{}" alt="" class="audio-waveform" loading="lazy">
+"#,
+            html_escape(waveform)
+        ));
+    }
+    if let Some(duration) = duration {
+        html.push_str(&format!(
+            r#"                <span class="audio-duration">{}</span>
+"#,
+            audio::format_duration(duration)
+        ));
+    }
+    html.push_str(
+        r#"            </div>
+"#,
+    );
+    html
+}
+
+impl Render for VCard {
+    fn render(&self) -> String {
+        let mut html = format!(
+            r#"            <div class="app-card contact-card">
+                <div class="app-card-title">👤 {}</div>
+"#,
+            html_escape(self.name.as_deref().unwrap_or("Contact"))
+        );
+        if let Some(organization) = &self.organization {
+            html.push_str(&format!(
+                r#"                <div class="app-card-body">{}</div>
+"#,
+                html_escape(organization)
+            ));
+        }
+        for phone in &self.phones {
+            html.push_str(&format!(
+                r#"                <div class="app-card-body"><a href="tel:{}">{}</a></div>
+"#,
+                html_escape(&phone.replace(' ', "")),
+                html_escape(phone)
+            ));
+        }
+        for email in &self.emails {
+            html.push_str(&format!(
+                r#"                <div class="app-card-body"><a href="mailto:{}">{}</a></div>
+"#,
+                html_escape(email),
+                html_escape(email)
+            ));
+        }
+        html.push_str(
+            r#"            </div>
+"#,
+        );
+        html
+    }
+}
+
+/// The tapbacks on a message, each with who left it
+pub struct Tapbacks<'a> {
+    pub tapbacks: Vec<(String, &'a TapbackEmoji)>,
+    pub root_prefix: &'a str,
+    /// Embed sticker and custom emoji images as `data:` URIs instead of linking to them
+    pub inline_images: bool,
+}
+
+impl Render for Tapbacks<'_> {
+    fn render(&self) -> String {
+        if self.tapbacks.is_empty() {
+            return String::new();
+        }
+
+        let mut html = String::from(
+            r#"        <div class="tapbacks">
+"#,
+        );
+        for (name, emoji) in &self.tapbacks {
+            let emoji_html = match emoji.image() {
+                Some(image) if self.inline_images => format!(
+                    r#"<img src="{}" alt="{}" class="tapback-image">"#,
+                    image
+                        .file
+                        .read()
+                        .ok()
+                        .flatten()
+                        .map(|bytes| data_uri("image/png", &bytes))
+                        .unwrap_or_default(),
+                    html_escape(&emoji.to_string())
+                ),
+                Some(image) => format!(
+                    r#"<img src="{}{}" alt="{}" class="tapback-image" loading="lazy">"#,
+                    self.root_prefix,
+                    html_escape(&tapback_image_path(image)),
+                    html_escape(&emoji.to_string())
+                ),
+                None => emoji.to_string(),
+            };
+            html.push_str(&format!(
+                r#"            <div class="tapback">
+            <span class="tapback-emoji">{}</span>
+            <span class="tapback-name">{}</span>
+        </div>
+"#,
+                emoji_html,
+                html_escape(name)
+            ));
+        }
+        html.push_str(
+            r#"        </div>
+"#,
+        );
+        html
+    }
+}
+
+/// The line under a bubble: an unread dot, the time, the service when it isn't iMessage
+/// and flags worth noting, then the delivery receipt of a message I sent
+pub struct Footer {
+    pub time: String,
+    pub unread: bool,
+    pub labels: Vec<String>,
+    pub receipt: Option<String>,
+}
+
+impl Footer {
+    /// `message`'s footer, with its receipt when `show_receipt`
+    pub fn new(message: &CleanMessage, locale: Locale, show_receipt: bool) -> Self {
+        let strings = locale.strings();
+        let mut labels = Vec::new();
+        match message.service {
+            MessageService::IMessage | MessageService::Unknown => {}
+            ref service => labels.push(service.to_string()),
+        }
+        if message.flags.was_downgraded {
+            labels.push(strings.sent_as_text_message.to_string());
+        }
+        if message.flags.is_audio_message {
+            labels.push(strings.audio_message.to_string());
+        }
+        if message.flags.has_replies {
+            labels.push(strings.replied_to.to_string());
+        }
+
+        Self {
+            time: locale.time(&message.date),
+            unread: message.is_unread(),
+            labels,
+            receipt: if show_receipt {
+                receipt_text(message, locale)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl Render for Footer {
+    fn render(&self) -> String {
+        let unread_marker = if self.unread {
+            r#"<span class="unread-dot" title="Unread"></span>"#
+        } else {
+            ""
+        };
+        let mut html = format!(
+            r#"        <div class="message-footer">{}{}{}</div>
+"#,
+            unread_marker,
+            self.time,
+            self.labels
+                .iter()
+                .map(|label| format!(" · {}", html_escape(label)))
+                .collect::<String>()
+        );
+        if let Some(receipt) = &self.receipt {
+            html.push_str(&format!(
+                r#"        <div class="message-receipt">{}</div>
+"#,
+                receipt
+            ));
+        }
+        html
+    }
+}
+
+/// "Read at 3:42 PM" / "Delivered" footer for a sent message, with the date
+/// included when it was read on a later day than it was sent
+fn receipt_text(message: &CleanMessage, locale: Locale) -> Option<String> {
+    let strings = locale.strings();
+    match (message.date_read, message.date_delivered) {
+        (Some(read), _) => {
+            if read.date_naive() == message.date_sent.date_naive() {
+                Some(format!("{} {}", strings.read_at, locale.time(&read)))
+            } else {
+                Some(format!("{} {}", strings.read, locale.date_time(&read)))
+            }
+        }
+        (None, Some(_)) => Some(strings.delivered.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// Icon shown beside a download link, by the file's extension
+pub fn file_icon(filename: &str) -> &'static str {
+    let lower = filename.to_lowercase();
+
+    if lower.ends_with(".pdf") {
+        "📄"
+    } else if lower.ends_with(".mp4") || lower.ends_with(".mov") || lower.ends_with(".avi") {
+        "🎥"
+    } else if lower.ends_with(".mp3") || lower.ends_with(".m4a") || lower.ends_with(".wav") {
+        "🎵"
+    } else if lower.ends_with(".zip") || lower.ends_with(".tar") || lower.ends_with(".gz") {
+        "📦"
+    } else if lower.ends_with(".doc") || lower.ends_with(".docx") {
+        "📝"
+    } else {
+        "📎"
+    }
+}
+
+/// Where a sticker or custom emoji tapback's image is saved, next to the attachments of
+/// the tapback message
+pub fn tapback_image_path(image: &TapbackImage) -> String {
+    format!(
+        "attachments/{}/{}",
+        attachment_subpath(&image.guid),
+        image.filename
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imessage_database::message_types::variants::Tapback;
+
+    #[test]
+    fn test_chat_header() {
+        let header = ChatHeader {
+            title: "Weekend Plans & <b>Trips</b> (2024)",
+            photo: Some("group-photos/plans.jpg"),
+            root_prefix: "../../",
+            back_to_chats: Some("← Back to chats"),
+            page_nav: Some(PageNav {
+                previous: None,
+                next: Some(PageLink {
+                    file: "2025.html",
+                    label: "2025",
+                }),
+            }),
+            media: None,
+            mentions: Some(MentionsFilter {
+                label: "Mentions of me",
+                count: 3,
+            }),
+        }
+        .render();
+        assert!(header.starts_with(r#"    <a href="../../index.html" class="back-link">"#));
+        assert!(header.contains(
+            r#"<h1><img class="chat-header-photo" src="../../group-photos/plans.jpg" alt="">Weekend Plans &amp; &lt;b&gt;Trips&lt;/b&gt; (2024)</h1>"#
+        ));
+        assert!(header.contains("<span></span>"));
+        assert!(header.contains(r#"<a href="2025.html" class="back-link">2025 →</a>"#));
+        assert!(!header.contains("📷"));
+        assert!(header.contains(">@ Mentions of me (3)</button>"));
+    }
+
+    #[test]
+    fn test_participants() {
+        let participants = Participants {
+            header: "Participants",
+            root_prefix: "../",
+            participants: vec![
+                ("Alice", Some("contacts/alice.html".to_string())),
+                ("<Bob>", None),
+            ],
+        }
+        .render();
+        assert!(
+            participants
+                .contains(r#"<a class="participant" href="../contacts/alice.html">Alice</a>"#)
+        );
+        assert!(participants.contains(r#"<span class="participant">&lt;Bob&gt;</span>"#));
+
+        let empty = Participants {
+            header: "Participants",
+            root_prefix: "../",
+            participants: Vec::new(),
+        };
+        assert_eq!(empty.render(), "");
+    }
+
+    #[test]
+    fn test_bubble() {
+        let bubble = Bubble {
+            guid: "ABC",
+            classes: ["from-others", "service-sms", "", "", ""],
+            chat_label: None,
+            sender: Some("Tom & Jerry".to_string()),
+            mentions_me: false,
+            contents: "        <div class=\"message-text\">Hi</div>\n".to_string(),
+        }
+        .render();
+        assert_eq!(
+            bubble,
+            r#"    <div class="message from-others service-sms   " id="msg-ABC">
+        <div class="message-header">Tom &amp; Jerry</div>
+        <div class="message-text">Hi</div>
+    </div>
+"#
+        );
+    }
+
+    #[test]
+    fn test_attachment_view() {
+        let image = AttachmentView {
+            filename: "beach.jpg",
+            src: "../attachments/AB/CD/ABCD/beach.jpg".to_string(),
+            kind: AttachmentKind::Image {
+                thumbnail: Some("../thumbnails/AB/CD/ABCD/beach.jpg.jpg".to_string()),
+                recognized_text: Some("OPEN\nDAILY"),
+            },
+            locale: Locale::German,
+        }
+        .render();
+        assert!(image.contains(r#"<a href="../attachments/AB/CD/ABCD/beach.jpg""#));
+        assert!(image.contains(r#"<img src="../thumbnails/AB/CD/ABCD/beach.jpg.jpg""#));
+        assert!(image.contains("<summary>Text im Bild</summary>"));
+        assert!(image.contains("<div>OPEN<br>DAILY</div>"));
+
+        let audio = AttachmentView {
+            filename: "memo.m4a",
+            src: "memo.m4a".to_string(),
+            kind: AttachmentKind::Audio {
+                waveform: None,
+                duration: None,
+            },
+            locale: Locale::English,
+        }
+        .render();
+        assert!(!audio.contains("audio-details"));
+
+        let file = AttachmentView {
+            filename: "report.pdf",
+            src: "report.pdf".to_string(),
+            kind: AttachmentKind::File { card: None },
+            locale: Locale::English,
+        }
+        .render();
+        assert!(file.contains(r#"<span class="attachment-icon">📄</span>report.pdf"#));
+    }
+
+    #[test]
+    fn test_tapbacks() {
+        let liked = TapbackEmoji::from_message_tapback(Tapback::Liked);
+        let tapbacks = Tapbacks {
+            tapbacks: vec![("Alice".to_string(), &liked)],
+            root_prefix: "../",
+            inline_images: false,
+        }
+        .render();
+        assert!(tapbacks.contains(r#"<span class="tapback-emoji">👍</span>"#));
+        assert!(tapbacks.contains(r#"<span class="tapback-name">Alice</span>"#));
+    }
+
+    #[test]
+    fn test_footer() {
+        let footer = Footer {
+            time: "3:42 PM".to_string(),
+            unread: true,
+            labels: vec!["SMS".to_string(), "Replied to".to_string()],
+            receipt: Some("Delivered".to_string()),
+        }
+        .render();
+        assert_eq!(
+            footer,
+            r#"        <div class="message-footer"><span class="unread-dot" title="Unread"></span>3:42 PM · SMS · Replied to</div>
+        <div class="message-receipt">Delivered</div>
+"#
+        );
+    }
+
+    #[test]
+    fn test_file_icon() {
+        assert_eq!(file_icon("Report.PDF"), "📄");
+        assert_eq!(file_icon("notes.txt"), "📎");
+    }
+}
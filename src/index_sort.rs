@@ -0,0 +1,37 @@
+/// Order of the chats on the index page
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexSort {
+    /// Alphabetically by chat name
+    #[default]
+    Name,
+    /// Most recent message first
+    Recent,
+    /// Most messages first
+    Count,
+}
+
+impl std::str::FromStr for IndexSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(IndexSort::Name),
+            "recent" => Ok(IndexSort::Recent),
+            "count" => Ok(IndexSort::Count),
+            _ => Err(format!(
+                "unknown sort '{}' (expected name, recent or count)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for IndexSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexSort::Name => write!(f, "name"),
+            IndexSort::Recent => write!(f, "recent"),
+            IndexSort::Count => write!(f, "count"),
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use crate::snapshot::DatabaseSnapshot;
+use aes::Aes256;
+use aes::cipher::{BlockDecrypt, KeyInit};
+use anyhow::{Context, Result, anyhow, bail};
+use imessage_database::tables::table::DEFAULT_PATH_IOS;
+use plist::{Dictionary, Value};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use sha1::Sha1;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable read for the backup password before prompting for one
+pub const BACKUP_PASSWORD_ENV_VAR: &str = "IMESSAGE_EXTRACTOR_BACKUP_PASSWORD";
+
+const MANIFEST_PLIST: &str = "Manifest.plist";
+const MANIFEST_DB: &str = "Manifest.db";
+
+/// Keybag class keys of this type are wrapped with the key derived from the password
+const WRAP_PASSCODE: u32 = 2;
+
+/// Tags that belong to the class key started by the preceding `UUID` tag
+const CLASS_KEY_TAGS: [&[u8; 4]; 5] = [b"CLAS", b"WRAP", b"WPKY", b"KTYP", b"PBKY"];
+
+/// Initial value RFC 3394 key wrapping checks for after unwrapping
+const KEY_WRAP_IV: u64 = 0xA6A6_A6A6_A6A6_A6A6;
+
+/// The AES key of a file in an encrypted backup
+pub type FileKey = [u8; 32];
+
+/// An encrypted iOS backup, unlocked with its password. Every file in it is encrypted
+/// with its own key, wrapped by a class key from the backup's keybag, which in turn is
+/// wrapped by a key derived from the password.
+pub struct EncryptedBackup {
+    /// Decrypted copy of Manifest.db, the index of the backup's files
+    manifest: Connection,
+    _manifest_snapshot: DatabaseSnapshot,
+    class_keys: HashMap<u32, FileKey>,
+}
+
+impl EncryptedBackup {
+    /// Whether the backup at `root` is encrypted, according to its Manifest.plist
+    pub fn is_encrypted(root: &Path) -> bool {
+        read_manifest_plist(root).is_ok_and(|manifest| {
+            manifest
+                .get("IsEncrypted")
+                .and_then(Value::as_boolean)
+                .unwrap_or(false)
+        })
+    }
+
+    /// The backup password, taken from `IMESSAGE_EXTRACTOR_BACKUP_PASSWORD` or prompted
+    /// for on the terminal
+    pub fn password() -> Result<String> {
+        if let Ok(password) = std::env::var(BACKUP_PASSWORD_ENV_VAR)
+            && !password.is_empty()
+        {
+            return Ok(password);
+        }
+        Ok(rpassword::prompt_password("iOS backup password: ")?)
+    }
+
+    /// Unlocks the backup at `root` and decrypts its Manifest.db. `index` distinguishes
+    /// backups opened by the same process.
+    pub fn unlock(root: &Path, password: &str, index: usize) -> Result<Self> {
+        let manifest = read_manifest_plist(root)?;
+        let keybag = manifest
+            .get("BackupKeyBag")
+            .and_then(Value::as_data)
+            .context("Manifest.plist has no BackupKeyBag")?;
+        let class_keys = unlock_keybag(keybag, password)?;
+
+        let manifest_key = manifest
+            .get("ManifestKey")
+            .and_then(Value::as_data)
+            .context("Manifest.plist has no ManifestKey")?;
+        let manifest_key = unwrap_file_key(&class_keys, manifest_key)?;
+
+        let encrypted = fs::read(root.join(MANIFEST_DB))
+            .with_context(|| format!("Failed to read {}", root.join(MANIFEST_DB).display()))?;
+        let snapshot = DatabaseSnapshot::from_bytes(
+            MANIFEST_DB,
+            &decrypt(&manifest_key, &encrypted)?,
+            &format!("manifest-{}", index),
+        )?;
+        let manifest =
+            Connection::open_with_flags(snapshot.path(), OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .context("Failed to open the decrypted Manifest.db")?;
+
+        Ok(Self {
+            manifest,
+            _manifest_snapshot: snapshot,
+            class_keys,
+        })
+    }
+
+    /// The key of the file the backup stores as `file_id` (the SHA-1 of its domain and
+    /// path, which is also its name in the backup), or `None` if the backup doesn't
+    /// have that file
+    pub fn file_key(&self, file_id: &str) -> Result<Option<FileKey>> {
+        let blob: Option<Vec<u8>> = self
+            .manifest
+            .prepare_cached("SELECT file FROM Files WHERE fileID = ?1")?
+            .query_row([file_id], |row| row.get(0))
+            .optional()?;
+        let Some(blob) = blob else {
+            return Ok(None);
+        };
+
+        let wrapped = archived_encryption_key(&blob)
+            .with_context(|| format!("Manifest.db entry for {} has no encryption key", file_id))?;
+        unwrap_file_key(&self.class_keys, &wrapped).map(Some)
+    }
+
+    /// Decrypts the backup's `sms.db` into a temporary database snapshot
+    pub fn decrypt_sms_db(&self, root: &Path, index: usize) -> Result<DatabaseSnapshot> {
+        let file_id = DEFAULT_PATH_IOS
+            .rsplit('/')
+            .next()
+            .unwrap_or(DEFAULT_PATH_IOS);
+        let key = self
+            .file_key(file_id)?
+            .with_context(|| format!("The backup has no file {}", file_id))?;
+        let path = file_path(root, file_id);
+        let encrypted =
+            fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        DatabaseSnapshot::from_bytes(
+            "sms.db",
+            &decrypt(&key, &encrypted)?,
+            &format!("sms-{}", index),
+        )
+    }
+}
+
+fn read_manifest_plist(root: &Path) -> Result<Dictionary> {
+    let path = root.join(MANIFEST_PLIST);
+    let value =
+        Value::from_file(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    value
+        .into_dictionary()
+        .with_context(|| format!("{} is not a dictionary", path.display()))
+}
+
+/// Derives the password key and unwraps the keybag's class keys with it
+fn unlock_keybag(keybag: &[u8], password: &str) -> Result<HashMap<u32, FileKey>> {
+    let mut attributes: HashMap<[u8; 4], Vec<u8>> = HashMap::new();
+    let mut class_keys: Vec<HashMap<[u8; 4], Vec<u8>>> = Vec::new();
+
+    // A list of tag, big-endian length, value; the first UUID is the keybag's own, each
+    // following UUID starts a class key
+    let mut position = 0;
+    let mut seen_uuid = false;
+    while position + 8 <= keybag.len() {
+        let tag: [u8; 4] = keybag[position..position + 4].try_into()?;
+        let length = u32::from_be_bytes(keybag[position + 4..position + 8].try_into()?) as usize;
+        let value = keybag
+            .get(position + 8..position + 8 + length)
+            .context("Truncated backup keybag")?
+            .to_vec();
+        position += 8 + length;
+
+        if &tag == b"UUID" {
+            if seen_uuid {
+                class_keys.push(HashMap::new());
+            }
+            seen_uuid = true;
+        }
+        match class_keys.last_mut() {
+            Some(class_key) if CLASS_KEY_TAGS.contains(&&tag) => {
+                class_key.insert(tag, value);
+            }
+            _ => {
+                attributes.entry(tag).or_insert(value);
+            }
+        }
+    }
+
+    let number = |attributes: &HashMap<[u8; 4], Vec<u8>>, tag: &[u8; 4]| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            attributes.get(tag)?.as_slice().try_into().ok()?,
+        ))
+    };
+    let salt = attributes
+        .get(b"SALT")
+        .context("Backup keybag has no salt")?;
+    let iterations =
+        number(&attributes, b"ITER").context("Backup keybag has no iteration count")?;
+
+    // Backups from iOS 10.2 on first stretch the password with PBKDF2-SHA256
+    let mut password_key = password.as_bytes().to_vec();
+    if let (Some(salt), Some(iterations)) = (attributes.get(b"DPSL"), number(&attributes, b"DPIC"))
+    {
+        let mut stretched = [0; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(&password_key, salt, iterations, &mut stretched);
+        password_key = stretched.to_vec();
+    }
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2_hmac::<Sha1>(&password_key, salt, iterations, &mut key);
+
+    let mut unwrapped = HashMap::new();
+    for class_key in &class_keys {
+        let (Some(class), Some(wrapped)) = (number(class_key, b"CLAS"), class_key.get(b"WPKY"))
+        else {
+            continue;
+        };
+        if number(class_key, b"WRAP").is_some_and(|wrap| wrap & WRAP_PASSCODE != 0) {
+            let key = unwrap_key(&key, wrapped).context("Wrong iOS backup password")?;
+            unwrapped.insert(class, key);
+        }
+    }
+
+    if unwrapped.is_empty() {
+        bail!("The backup keybag has no keys protected by the password");
+    }
+    Ok(unwrapped)
+}
+
+/// Unwraps a file key stored as a little-endian protection class followed by the key
+/// wrapped with that class's key
+fn unwrap_file_key(class_keys: &HashMap<u32, FileKey>, stored: &[u8]) -> Result<FileKey> {
+    let (class, wrapped) = stored
+        .split_first_chunk::<4>()
+        .context("Encryption key is too short")?;
+    let class = u32::from_le_bytes(*class);
+    let class_key = class_keys.get(&class).with_context(|| {
+        format!(
+            "The backup keybag has no key for protection class {}",
+            class
+        )
+    })?;
+    unwrap_key(class_key, wrapped)
+}
+
+/// AES key unwrap (RFC 3394) of a wrapped 256-bit key
+fn unwrap_key(kek: &FileKey, wrapped: &[u8]) -> Result<FileKey> {
+    if wrapped.len() != 40 {
+        bail!("Wrapped key is {} bytes, expected 40", wrapped.len());
+    }
+    let cipher = Aes256::new(kek.into());
+
+    let mut a = u64::from_be_bytes(wrapped[0..8].try_into()?);
+    let mut r: Vec<[u8; 8]> = wrapped[8..]
+        .chunks(8)
+        .map(|chunk| chunk.try_into())
+        .collect::<Result<_, _>>()?;
+    let n = r.len();
+
+    for j in (0..6).rev() {
+        for i in (0..n).rev() {
+            let t = (n * j + i + 1) as u64;
+            let mut block = [0u8; 16];
+            block[0..8].copy_from_slice(&(a ^ t).to_be_bytes());
+            block[8..16].copy_from_slice(&r[i]);
+            cipher.decrypt_block((&mut block).into());
+            a = u64::from_be_bytes(block[0..8].try_into()?);
+            r[i] = block[8..16].try_into()?;
+        }
+    }
+
+    if a != KEY_WRAP_IV {
+        bail!("Key unwrap integrity check failed");
+    }
+    r.concat()
+        .try_into()
+        .map_err(|_| anyhow!("Unwrapped key has the wrong length"))
+}
+
+/// AES-256-CBC decryption with a zero IV, as backups use, removing PKCS#7 padding
+pub fn decrypt(key: &FileKey, encrypted: &[u8]) -> Result<Vec<u8>> {
+    if !encrypted.len().is_multiple_of(16) {
+        bail!("Encrypted file is not a whole number of AES blocks");
+    }
+    let cipher = Aes256::new(key.into());
+
+    let mut decrypted = Vec::with_capacity(encrypted.len());
+    let mut previous = [0u8; 16];
+    for chunk in encrypted.chunks(16) {
+        let mut block: [u8; 16] = chunk.try_into()?;
+        cipher.decrypt_block((&mut block).into());
+        decrypted.extend(block.iter().zip(previous).map(|(byte, mask)| byte ^ mask));
+        previous.copy_from_slice(chunk);
+    }
+
+    if let Some(&padding) = decrypted.last()
+        && (1..=16).contains(&padding)
+        && decrypted.len() >= padding as usize
+        && decrypted[decrypted.len() - padding as usize..]
+            .iter()
+            .all(|&byte| byte == padding)
+    {
+        decrypted.truncate(decrypted.len() - padding as usize);
+    }
+    Ok(decrypted)
+}
+
+/// The wrapped key in a Manifest.db `file` column, an NSKeyedArchiver plist of an
+/// `MBFile` whose `EncryptionKey` refers to an `NSData`
+fn archived_encryption_key(blob: &[u8]) -> Option<Vec<u8>> {
+    let archive = Value::from_reader(std::io::Cursor::new(blob)).ok()?;
+    let archive = archive.as_dictionary()?;
+    let objects = archive.get("$objects")?.as_array()?;
+    let object = |value: &Value| objects.get(value.as_uid()?.get() as usize);
+
+    let root = object(archive.get("$top")?.as_dictionary()?.get("root")?)?.as_dictionary()?;
+    match object(root.get("EncryptionKey")?)? {
+        Value::Data(data) => Some(data.clone()),
+        Value::Dictionary(data) => data.get("NS.data")?.as_data().map(<[u8]>::to_vec),
+        _ => None,
+    }
+}
+
+/// Where a backup stores the file named `file_id`
+fn file_path(root: &Path, file_id: &str) -> PathBuf {
+    root.join(file_id.get(0..2).unwrap_or_default())
+        .join(file_id)
+}
@@ -0,0 +1,104 @@
+use crate::clean_message::CleanMessage;
+use crate::mbox_output::message_body;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// One line of `--format jsonl`
+#[derive(Serialize)]
+struct JsonlMessage<'a> {
+    guid: &'a str,
+    chat: Option<&'a str>,
+    chat_guid: Option<&'a str>,
+    sender: String,
+    /// Phone number or email address the message came from; absent for my own
+    sender_id: Option<&'a str>,
+    is_from_me: bool,
+    /// RFC 3339, with the local UTC offset
+    date: String,
+    service: String,
+    text: String,
+    attachments: Vec<JsonlAttachment<'a>>,
+    unsent: bool,
+    deleted: bool,
+}
+
+#[derive(Serialize)]
+struct JsonlAttachment<'a> {
+    filename: Option<&'a str>,
+    mime_type: Option<&'a str>,
+    /// Where the file is on this computer, if it is
+    path: Option<String>,
+}
+
+/// Writes each message as a line of JSON as soon as it's read, for `--format jsonl`, so
+/// nothing but the GUIDs already written (to skip a message found again in another
+/// database) is kept in memory
+pub struct JsonlWriter {
+    writer: Box<dyn Write>,
+    written: HashSet<String>,
+}
+
+impl JsonlWriter {
+    pub fn new(writer: Box<dyn Write>) -> Self {
+        Self {
+            writer,
+            written: HashSet::new(),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(Box::new(BufWriter::new(std::io::stdout())))
+    }
+
+    pub fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self::new(Box::new(BufWriter::new(file))))
+    }
+
+    pub fn write(&mut self, message: &CleanMessage) -> Result<()> {
+        if !self.written.insert(message.guid.clone()) {
+            return Ok(());
+        }
+
+        let line = JsonlMessage {
+            guid: &message.guid,
+            chat: message.chat_name.as_deref(),
+            chat_guid: message.chat_guid.as_deref(),
+            sender: message.from.to_string(),
+            sender_id: message.from.identifier(),
+            is_from_me: message.from.is_me(),
+            date: message.date.to_rfc3339(),
+            service: message.service.to_string(),
+            text: message_body(message),
+            attachments: message
+                .attachments
+                .iter()
+                .map(|attachment| JsonlAttachment {
+                    filename: attachment.filename.as_deref(),
+                    mime_type: attachment.mime_type.as_deref(),
+                    path: attachment
+                        .file
+                        .as_ref()
+                        .filter(|file| file.path.exists())
+                        .map(|file| file.path.display().to_string()),
+                })
+                .collect(),
+            unsent: message.unsent,
+            deleted: message.deleted,
+        };
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flushes what's left, returning how many messages were written
+    pub fn finish(mut self) -> Result<usize> {
+        self.writer.flush()?;
+        Ok(self.written.len())
+    }
+}
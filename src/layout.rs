@@ -0,0 +1,118 @@
+use crate::clean_message::CleanMessage;
+use crate::filename::{FilenameAllocator, chat_stems, sanitize_filename};
+use crate::html_output::{attachment_filenames, attachment_subpath};
+use crate::message_store::display_name;
+use std::collections::HashMap;
+
+/// Where transcripts and attachments go in the output directory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Transcripts under `groups/` and `direct/`, attachments by message GUID, plus an
+    /// index page
+    #[default]
+    Default,
+    /// imessage-exporter's layout: one transcript per chat at the top level, named after
+    /// the chat, and attachments under `attachments/<chat ROWID>/<attachment ROWID>.<ext>`
+    ImessageExporter,
+}
+
+impl Layout {
+    /// File name stem of each chat's transcript, by chat key
+    pub fn chat_stems<'a>(
+        &self,
+        chat_keys: impl IntoIterator<Item = &'a String>,
+    ) -> HashMap<String, String> {
+        match self {
+            Layout::Default => chat_stems(chat_keys),
+            Layout::ImessageExporter => {
+                let mut chat_keys: Vec<&String> = chat_keys.into_iter().collect();
+                chat_keys.sort();
+
+                // Named for the chat alone, so a direct chat is just the person's name
+                let mut allocator = FilenameAllocator::default();
+                chat_keys
+                    .into_iter()
+                    .map(|chat_key| {
+                        let name = display_name(chat_key);
+                        (chat_key.clone(), allocator.stem(name))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Directory a chat's transcript is written to, relative to the export root; empty
+    /// for the top level
+    pub fn chat_dir(&self, chat_key: &str) -> &'static str {
+        match self {
+            Layout::Default if chat_key.starts_with("Direct: ") => "direct",
+            Layout::Default => "groups",
+            Layout::ImessageExporter => "",
+        }
+    }
+
+    /// Directory under `attachments/` that `message`'s attachments are saved in
+    pub fn attachment_dir(&self, message: &CleanMessage) -> String {
+        match self {
+            Layout::Default => attachment_subpath(&message.guid),
+            Layout::ImessageExporter => match message.chat_id {
+                Some(chat_id) => chat_id.to_string(),
+                None => "orphaned".to_string(),
+            },
+        }
+    }
+
+    /// File name each of `message`'s attachments is saved under in its
+    /// [`attachment_dir`](Self::attachment_dir), or `None` for attachments without a file
+    pub fn attachment_files(&self, message: &CleanMessage) -> Vec<Option<String>> {
+        match self {
+            Layout::Default => attachment_filenames(message),
+            Layout::ImessageExporter => message
+                .attachments
+                .iter()
+                .map(|attachment| {
+                    let filename = attachment.filename.as_deref()?;
+                    Some(match filename.rsplit_once('.') {
+                        Some((_, extension)) => {
+                            sanitize_filename(&format!("{}.{}", attachment.rowid, extension))
+                        }
+                        None => attachment.rowid.to_string(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// `file` within `dir`, which may be empty for the top level
+pub fn join(dir: &str, file: &str) -> String {
+    if dir.is_empty() {
+        file.to_string()
+    } else {
+        format!("{}/{}", dir, file)
+    }
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(Layout::Default),
+            "imessage-exporter" => Ok(Layout::ImessageExporter),
+            _ => Err(format!(
+                "unknown layout '{}' (expected default or imessage-exporter)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Layout::Default => write!(f, "default"),
+            Layout::ImessageExporter => write!(f, "imessage-exporter"),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Exports iMessage conversations from a Messages database (`chat.db`) to HTML, text,
+//! mbox, EPUB, Obsidian, Day One, Matrix, a RAG corpus, JSONL, Parquet or a review
+//! load file, with names resolved from Contacts.
+//!
+//! The `imessage_extractor` binary is built on these modules. Embedders can reuse them,
+//! e.g. to resolve names from their own people database with a
+//! [`contact_resolver::ContactResolver`], or show an export's progress with an
+//! [`events::ExportObserver`].
+
+pub mod app_balloon;
+pub mod attachment_export;
+pub mod attachment_filter;
+pub mod attachment_metadata;
+pub mod attachment_store;
+pub mod attachment_timestamp;
+pub mod audio;
+pub mod automated_senders;
+pub mod call_history;
+pub mod chat_merge;
+pub mod chat_naming;
+pub mod chat_participants;
+pub mod chat_rename;
+pub mod chat_selector;
+pub mod chat_stats;
+pub mod clean_attachment;
+pub mod clean_message;
+pub mod contact_resolver;
+pub mod contacts;
+pub mod content_filter;
+pub mod corpus_output;
+pub mod database_connection;
+pub mod database_source;
+pub mod date_range;
+pub mod day_one_output;
+pub mod diff;
+pub mod emoji;
+pub mod encryption;
+pub mod environment;
+pub mod epub_output;
+pub mod error;
+pub mod error_log;
+pub mod events;
+pub mod filename;
+pub mod fixture;
+pub mod group_photo;
+pub mod heatmap;
+pub mod html_output;
+pub mod html_render;
+pub mod index_sort;
+pub mod ios_backup;
+pub mod jsonl_output;
+pub mod layout;
+pub mod load_file_output;
+pub mod locale;
+pub mod manifest;
+pub mod markup;
+pub mod matrix_output;
+pub mod mbox_output;
+pub mod membership;
+pub mod message_flags;
+pub mod message_service;
+pub mod message_store;
+pub mod my_handles;
+pub mod obsidian_output;
+pub mod ocr;
+pub mod output_format;
+pub mod output_target;
+pub mod parquet_output;
+pub mod pdf;
+pub mod recently_deleted;
+pub mod relative_date;
+pub mod resolved_handle;
+pub mod run_state;
+pub mod sampling;
+pub mod schema;
+pub mod search_index;
+pub mod search_page;
+pub mod send_effect;
+pub mod sender_repair;
+pub mod signing;
+pub mod sms_duplicates;
+pub mod snapshot;
+pub mod split_by;
+pub mod summary;
+pub mod swift_helper;
+pub mod tapback_emoji;
+pub mod text_fallback;
+pub mod text_span;
+pub mod theme;
+pub mod timestamp_source;
+pub mod transcription;
+pub mod txt_output;
+pub mod vcard;
+pub mod verify;
+pub mod word_frequency;
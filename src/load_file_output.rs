@@ -0,0 +1,361 @@
+use crate::attachment_store::read_for_export;
+use crate::clean_attachment::CleanAttachment;
+use crate::clean_message::CleanMessage;
+use crate::error_log::ErrorLog;
+use crate::events::ExportEvent;
+use crate::mbox_output::message_body;
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::output_target::OutputTarget;
+use crate::pdf::{PAGE_LINES, PdfPage, replaced_chars, text_pdf, wrap};
+use anyhow::Result;
+use md5::{Digest, Md5};
+use std::collections::BTreeSet;
+
+pub const DAT_FILENAME: &str = "DATA/loadfile.dat";
+pub const OPT_FILENAME: &str = "DATA/loadfile.opt";
+
+pub const DEFAULT_BATES_PREFIX: &str = "IMSG";
+pub const DEFAULT_BATES_DIGITS: usize = 8;
+
+/// Volume every image is listed under in the OPT file
+const VOLUME: &str = "VOL001";
+
+/// Concordance delimiters: each field is quoted with þ and separated by ASCII 20, and
+/// line breaks within a field become ®
+const QUOTE: char = 'þ';
+const SEPARATOR: char = '\u{14}';
+const NEWLINE: char = '®';
+
+const DAT_FIELDS: [&str; 22] = [
+    "BEGBATES",
+    "ENDBATES",
+    "BEGATTACH",
+    "ENDATTACH",
+    "PARENTBATES",
+    "PGCOUNT",
+    "DOCTYPE",
+    "CHAT",
+    "CHATGUID",
+    "MESSAGEGUID",
+    "FROM",
+    "TO",
+    "DATESENT",
+    "TIMESENT",
+    "TIMEZONE",
+    "SERVICE",
+    "FILENAME",
+    "FILESIZE",
+    "MD5HASH",
+    "NATIVELINK",
+    "TEXTLINK",
+    "REPLACEDCHARS",
+];
+
+/// How production pages are numbered: a prefix and a zero-padded number, e.g.
+/// `IMSG00000001`
+#[derive(Clone, Debug)]
+pub struct Bates {
+    pub prefix: String,
+    pub start: u64,
+    pub digits: usize,
+}
+
+impl Bates {
+    fn number(&self, page: u64) -> String {
+        format!("{}{:0width$}", self.prefix, page, width = self.digits)
+    }
+}
+
+/// Writes a Concordance/Relativity-style production: every message is a document with
+/// a PDF rendering in `IMAGES/` and its text in `TEXT/`, each attachment a child
+/// document with its native file in `NATIVES/` and a slip sheet image, every page
+/// Bates numbered, and `DATA/loadfile.dat` and `DATA/loadfile.opt` to load them
+pub struct LoadFileOutput {
+    messages: Vec<CleanMessage>,
+    bates: Bates,
+    strip_metadata: bool,
+}
+
+/// A produced document's Bates range and where its files are, as the DAT file links them
+struct Document {
+    begin: String,
+    end: String,
+    page_count: usize,
+    native_link: String,
+    text_link: String,
+    /// Characters its image shows as `?`, which only the text file has
+    replaced_chars: usize,
+}
+
+impl LoadFileOutput {
+    pub fn new(messages: Vec<CleanMessage>, bates: Bates, strip_metadata: bool) -> Self {
+        Self {
+            messages,
+            bates,
+            strip_metadata,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget, errors: &mut ErrorLog) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+
+        let mut dat = format!("\u{feff}{}\r\n", dat_row(DAT_FIELDS.iter().copied()));
+        let mut opt = String::new();
+        let mut next_page = self.bates.start;
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+
+            let chat = display_name(chat_key);
+            let participants: BTreeSet<String> = chat_messages
+                .iter()
+                .map(|m| m.from.to_string())
+                .chain(std::iter::once("Me".to_string()))
+                .collect();
+
+            for message in chat_messages {
+                let from = message.from.to_string();
+                let to: Vec<&str> = participants
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|name| *name != from)
+                    .collect();
+                let body = message_body(message);
+                let filenames: Vec<&str> = message
+                    .attachments
+                    .iter()
+                    .filter_map(|attachment| attachment.filename.as_deref())
+                    .collect();
+
+                let mut lines = vec![
+                    format!("Chat: {}", chat),
+                    format!("From: {}", from),
+                    format!("To: {}", to.join(", ")),
+                    format!("Sent: {}", message.date.format("%Y-%m-%d %H:%M:%S %:z")),
+                    format!("Service: {}", message.service),
+                ];
+                if !filenames.is_empty() {
+                    lines.push(format!("Attachments: {}", filenames.join(", ")));
+                }
+                lines.push(String::new());
+                lines.extend(wrap(&body));
+
+                let parent =
+                    self.write_document(output, &mut opt, &mut next_page, &lines, &body, None)?;
+                let mut family = vec![(parent, "Message", None)];
+                for attachment in &message.attachments {
+                    let Some(filename) = attachment.filename.as_deref() else {
+                        continue;
+                    };
+                    let native = self.native(attachment, filename, errors)?;
+                    let mut slip_sheet = vec![
+                        "Produced in native format".to_string(),
+                        String::new(),
+                        format!("File name: {}", filename),
+                    ];
+                    if native.is_none() {
+                        slip_sheet.push("The file was not available to produce".to_string());
+                    }
+                    let text = message
+                        .recognized_text
+                        .get(&attachment.rowid)
+                        .or_else(|| message.transcripts.get(&attachment.rowid))
+                        .cloned()
+                        .unwrap_or_default();
+                    let child = self.write_document(
+                        output,
+                        &mut opt,
+                        &mut next_page,
+                        &slip_sheet,
+                        &text,
+                        native
+                            .as_ref()
+                            .map(|(extension, bytes)| (extension.as_str(), bytes.as_slice())),
+                    )?;
+                    family.push((child, "Attachment", Some((filename, native))));
+                }
+
+                let begin_attach = family[0].0.begin.clone();
+                let end_attach = family[family.len() - 1].0.end.clone();
+                for (document, doc_type, attachment) in family {
+                    let (filename, file_size, md5) = match &attachment {
+                        Some((filename, Some((_, bytes)))) => (
+                            filename.to_string(),
+                            bytes.len().to_string(),
+                            format!("{:x}", Md5::digest(bytes)),
+                        ),
+                        Some((filename, None)) => {
+                            (filename.to_string(), String::new(), String::new())
+                        }
+                        None => (String::new(), String::new(), String::new()),
+                    };
+                    let parent_bates = if attachment.is_some() {
+                        begin_attach.clone()
+                    } else {
+                        String::new()
+                    };
+                    let fields = [
+                        document.begin,
+                        document.end,
+                        begin_attach.clone(),
+                        end_attach.clone(),
+                        parent_bates,
+                        document.page_count.to_string(),
+                        doc_type.to_string(),
+                        chat.to_string(),
+                        message.chat_guid.clone().unwrap_or_default(),
+                        message.guid.clone(),
+                        from.clone(),
+                        to.join("; "),
+                        message.date.format("%m/%d/%Y").to_string(),
+                        message.date.format("%H:%M:%S").to_string(),
+                        message.date.format("%:z").to_string(),
+                        message.service.to_string(),
+                        filename,
+                        file_size,
+                        md5,
+                        document.native_link,
+                        document.text_link,
+                        document.replaced_chars.to_string(),
+                    ];
+                    dat.push_str(&dat_row(fields.iter().map(String::as_str)));
+                    dat.push_str("\r\n");
+                }
+            }
+
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        output.write(DAT_FILENAME, dat.as_bytes())?;
+        output.write(OPT_FILENAME, opt.as_bytes())
+    }
+
+    /// The extension and contents of an attachment's file, or `None` when it isn't on
+    /// this computer
+    fn native(
+        &self,
+        attachment: &CleanAttachment,
+        filename: &str,
+        errors: &mut ErrorLog,
+    ) -> Result<Option<(String, Vec<u8>)>> {
+        let Some(bytes) = read_for_export(attachment, self.strip_metadata, errors)? else {
+            return Ok(None);
+        };
+        let extension = match filename.rsplit_once('.') {
+            Some((_, extension)) if extension.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                format!(".{}", extension.to_lowercase())
+            }
+            _ => String::new(),
+        };
+        Ok(Some((extension, bytes)))
+    }
+
+    /// Numbers the pages of one document, writes its image, text and native file and
+    /// lists its pages in the OPT file
+    fn write_document(
+        &self,
+        output: &mut OutputTarget,
+        opt: &mut String,
+        next_page: &mut u64,
+        lines: &[String],
+        text: &str,
+        native: Option<(&str, &[u8])>,
+    ) -> Result<Document> {
+        let first_page = *next_page;
+        let pages: Vec<PdfPage> = lines
+            .chunks(PAGE_LINES)
+            .map(|page_lines| {
+                let page = PdfPage {
+                    lines: page_lines.to_vec(),
+                    stamp: self.bates.number(*next_page),
+                };
+                *next_page += 1;
+                page
+            })
+            .collect();
+        let begin = self.bates.number(first_page);
+        let end = self.bates.number(*next_page - 1);
+
+        let image = format!("IMAGES/{}.pdf", begin);
+        output.write(&image, &text_pdf(&pages))?;
+        for (index, page) in pages.iter().enumerate() {
+            let first = index == 0;
+            opt.push_str(&format!(
+                "{},{},{},{},,,{}\r\n",
+                page.stamp,
+                VOLUME,
+                image.replace('/', "\\"),
+                if first { "Y" } else { "" },
+                if first {
+                    pages.len().to_string()
+                } else {
+                    String::new()
+                }
+            ));
+        }
+
+        let text_link = format!("TEXT/{}.txt", begin);
+        output.write(&text_link, text.as_bytes())?;
+        let native_link = match native {
+            Some((extension, bytes)) => {
+                let path = format!("NATIVES/{}{}", begin, extension);
+                output.write(&path, bytes)?;
+                path.replace('/', "\\")
+            }
+            None => String::new(),
+        };
+
+        Ok(Document {
+            begin,
+            end,
+            page_count: pages.len(),
+            native_link,
+            text_link: text_link.replace('/', "\\"),
+            replaced_chars: lines.iter().map(|line| replaced_chars(line)).sum(),
+        })
+    }
+}
+
+/// A DAT line: each value quoted and separated, with its line breaks turned into ®
+fn dat_row<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values
+        .map(|value| {
+            let value: String = value
+                .replace("\r\n", "\n")
+                .chars()
+                .filter(|c| *c != QUOTE && *c != SEPARATOR)
+                .map(|c| if c == '\n' || c == '\r' { NEWLINE } else { c })
+                .collect();
+            format!("{}{}{}", QUOTE, value, QUOTE)
+        })
+        .collect::<Vec<_>>()
+        .join(&SEPARATOR.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bates_number() {
+        let bates = Bates {
+            prefix: "ABC".to_string(),
+            start: 1,
+            digits: 6,
+        };
+        assert_eq!(bates.number(42), "ABC000042");
+    }
+
+    #[test]
+    fn test_dat_row() {
+        assert_eq!(
+            dat_row(["a", "two\r\nlines", "þx"].into_iter()),
+            "þaþ\u{14}þtwo®linesþ\u{14}þxþ"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use chrono::{DateTime, Local};
+
+/// Language of the HTML pages' labels and dates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    English,
+    German,
+    French,
+    Spanish,
+}
+
+/// The labels of the index and chat pages in one language
+pub struct Strings {
+    pub chats_title: &'static str,
+    pub search_chats_placeholder: &'static str,
+    pub messages_header: &'static str,
+    /// "total chats", after the number of chats
+    pub total_chats: &'static str,
+    pub groups: &'static str,
+    pub direct_messages: &'static str,
+    pub search_all_messages: &'static str,
+    pub view_timeline: &'static str,
+    pub group_chats_header: &'static str,
+    pub direct_messages_header: &'static str,
+    pub pinned_header: &'static str,
+    /// "message" and "messages", after a count
+    pub message: &'static str,
+    pub messages: &'static str,
+    pub back_to_chats: &'static str,
+    /// Link from a chat's media page back to the chat
+    pub back_to_chat: &'static str,
+    pub media: &'static str,
+    /// Button on a group chat showing only the messages that @-mention me
+    pub mentions_of_me: &'static str,
+    pub participants: &'static str,
+    pub members: &'static str,
+    /// End of the dates of a member still in the chat
+    pub present: &'static str,
+    pub search_page_placeholder: &'static str,
+    pub only_matches: &'static str,
+    pub deleted: &'static str,
+    pub delivered: &'static str,
+    /// "Read" before a date, "Read at" before a time
+    pub read: &'static str,
+    pub read_at: &'static str,
+    pub conversation_stats: &'static str,
+    /// "conversations", after a count
+    pub conversations: &'static str,
+    pub longest_silence: &'static str,
+    /// Conversations someone started
+    pub started: &'static str,
+    pub median_reply: &'static str,
+    pub double_texts: &'static str,
+    pub all_chats: &'static str,
+    pub top_words: &'static str,
+    pub top_emoji: &'static str,
+    pub contacts_header: &'static str,
+    pub shared_media: &'static str,
+    pub first_message: &'static str,
+    pub last_message: &'static str,
+    pub on_this_day: &'static str,
+    pub no_messages_on_this_day: &'static str,
+    /// Summary of the text `--ocr` found in an image
+    pub text_in_image: &'static str,
+    /// Summary of the speech `--transcribe-videos` found in a video
+    pub transcript: &'static str,
+    pub sent_as_text_message: &'static str,
+    pub audio_message: &'static str,
+    /// Label of a message with replies in a thread
+    pub replied_to: &'static str,
+    /// Me, as the subject of a system row
+    pub you: &'static str,
+    /// "Page" and "of" in the printed pages' "Page 1 of 9" footer
+    pub page: &'static str,
+    pub page_of: &'static str,
+}
+
+const ENGLISH: Strings = Strings {
+    chats_title: "iMessage Chats",
+    search_chats_placeholder: "Search chats and messages...",
+    messages_header: "Messages",
+    total_chats: "total chats",
+    groups: "groups",
+    direct_messages: "direct messages",
+    search_all_messages: "Search all messages",
+    view_timeline: "View every chat as one timeline",
+    group_chats_header: "Group Chats",
+    direct_messages_header: "Direct Messages",
+    pinned_header: "Pinned",
+    message: "message",
+    messages: "messages",
+    back_to_chats: "← Back to Chats",
+    back_to_chat: "← Back to Chat",
+    media: "Media",
+    mentions_of_me: "Mentions of me",
+    participants: "Participants:",
+    members: "Members",
+    present: "present",
+    search_page_placeholder: "Search this page...",
+    only_matches: "Only matches",
+    deleted: "Deleted",
+    delivered: "Delivered",
+    read: "Read",
+    read_at: "Read at",
+    conversation_stats: "Conversation stats",
+    conversations: "conversations",
+    longest_silence: "Longest silence",
+    started: "Started",
+    median_reply: "Median reply",
+    double_texts: "Double texts",
+    all_chats: "All chats",
+    top_words: "Top words",
+    top_emoji: "Top emoji",
+    contacts_header: "Contacts",
+    shared_media: "Shared media",
+    first_message: "First message",
+    last_message: "Last message",
+    on_this_day: "On this day",
+    no_messages_on_this_day: "No messages from this day in any year",
+    text_in_image: "Text in image",
+    transcript: "Transcript",
+    sent_as_text_message: "Sent as text message",
+    audio_message: "Audio message",
+    replied_to: "Replied to",
+    you: "You",
+    page: "Page",
+    page_of: "of",
+};
+
+const GERMAN: Strings = Strings {
+    chats_title: "iMessage-Chats",
+    search_chats_placeholder: "Chats und Nachrichten durchsuchen...",
+    messages_header: "Nachrichten",
+    total_chats: "Chats insgesamt",
+    groups: "Gruppen",
+    direct_messages: "Direktnachrichten",
+    search_all_messages: "Alle Nachrichten durchsuchen",
+    view_timeline: "Alle Chats als eine Zeitleiste anzeigen",
+    group_chats_header: "Gruppenchats",
+    direct_messages_header: "Direktnachrichten",
+    pinned_header: "Angeheftet",
+    message: "Nachricht",
+    messages: "Nachrichten",
+    back_to_chats: "← Zurück zu den Chats",
+    back_to_chat: "← Zurück zum Chat",
+    media: "Medien",
+    mentions_of_me: "Erwähnungen von mir",
+    participants: "Teilnehmer:",
+    members: "Mitglieder",
+    present: "heute",
+    search_page_placeholder: "Diese Seite durchsuchen...",
+    only_matches: "Nur Treffer",
+    deleted: "Gelöscht am",
+    delivered: "Zugestellt",
+    read: "Gelesen am",
+    read_at: "Gelesen um",
+    conversation_stats: "Gesprächsstatistik",
+    conversations: "Gespräche",
+    longest_silence: "Längste Funkstille",
+    started: "Begonnen",
+    median_reply: "Mittlere Antwortzeit",
+    double_texts: "Doppelnachrichten",
+    all_chats: "Alle Chats",
+    top_words: "Häufigste Wörter",
+    top_emoji: "Häufigste Emoji",
+    contacts_header: "Kontakte",
+    shared_media: "Geteilte Medien",
+    first_message: "Erste Nachricht",
+    last_message: "Letzte Nachricht",
+    on_this_day: "An diesem Tag",
+    no_messages_on_this_day: "Keine Nachrichten von diesem Tag in irgendeinem Jahr",
+    text_in_image: "Text im Bild",
+    transcript: "Transkript",
+    sent_as_text_message: "Als SMS gesendet",
+    audio_message: "Sprachnachricht",
+    replied_to: "Beantwortet",
+    you: "Du",
+    page: "Seite",
+    page_of: "von",
+};
+
+const FRENCH: Strings = Strings {
+    chats_title: "Conversations iMessage",
+    search_chats_placeholder: "Rechercher des conversations et des messages...",
+    messages_header: "Messages",
+    total_chats: "conversations au total",
+    groups: "groupes",
+    direct_messages: "messages directs",
+    search_all_messages: "Rechercher dans tous les messages",
+    view_timeline: "Voir toutes les conversations en une chronologie",
+    group_chats_header: "Conversations de groupe",
+    direct_messages_header: "Messages directs",
+    pinned_header: "Épinglées",
+    message: "message",
+    messages: "messages",
+    back_to_chats: "← Retour aux conversations",
+    back_to_chat: "← Retour à la conversation",
+    media: "Médias",
+    mentions_of_me: "Mentions de moi",
+    participants: "Participants :",
+    members: "Membres",
+    present: "aujourd’hui",
+    search_page_placeholder: "Rechercher dans cette page...",
+    only_matches: "Résultats seulement",
+    deleted: "Supprimé le",
+    delivered: "Distribué",
+    read: "Lu le",
+    read_at: "Lu à",
+    conversation_stats: "Statistiques des conversations",
+    conversations: "conversations",
+    longest_silence: "Plus long silence",
+    started: "Lancées",
+    median_reply: "Délai de réponse médian",
+    double_texts: "Relances",
+    all_chats: "Toutes les conversations",
+    top_words: "Mots les plus utilisés",
+    top_emoji: "Emoji les plus utilisés",
+    contacts_header: "Contacts",
+    shared_media: "Médias partagés",
+    first_message: "Premier message",
+    last_message: "Dernier message",
+    on_this_day: "Ce jour-là",
+    no_messages_on_this_day: "Aucun message de ce jour, quelle que soit l’année",
+    text_in_image: "Texte dans l’image",
+    transcript: "Transcription",
+    sent_as_text_message: "Envoyé par SMS",
+    audio_message: "Message audio",
+    replied_to: "Avec réponses",
+    you: "Vous",
+    page: "Page",
+    page_of: "sur",
+};
+
+const SPANISH: Strings = Strings {
+    chats_title: "Chats de iMessage",
+    search_chats_placeholder: "Buscar chats y mensajes...",
+    messages_header: "Mensajes",
+    total_chats: "chats en total",
+    groups: "grupos",
+    direct_messages: "mensajes directos",
+    search_all_messages: "Buscar en todos los mensajes",
+    view_timeline: "Ver todos los chats como una cronología",
+    group_chats_header: "Chats de grupo",
+    direct_messages_header: "Mensajes directos",
+    pinned_header: "Fijados",
+    message: "mensaje",
+    messages: "mensajes",
+    back_to_chats: "← Volver a los chats",
+    back_to_chat: "← Volver al chat",
+    media: "Multimedia",
+    mentions_of_me: "Menciones de mí",
+    participants: "Participantes:",
+    members: "Miembros",
+    present: "actualidad",
+    search_page_placeholder: "Buscar en esta página...",
+    only_matches: "Solo coincidencias",
+    deleted: "Eliminado el",
+    delivered: "Entregado",
+    read: "Leído el",
+    read_at: "Leído a las",
+    conversation_stats: "Estadísticas de conversación",
+    conversations: "conversaciones",
+    longest_silence: "Silencio más largo",
+    started: "Iniciadas",
+    median_reply: "Respuesta mediana",
+    double_texts: "Mensajes dobles",
+    all_chats: "Todos los chats",
+    top_words: "Palabras más usadas",
+    top_emoji: "Emoji más usados",
+    contacts_header: "Contactos",
+    shared_media: "Archivos compartidos",
+    first_message: "Primer mensaje",
+    last_message: "Último mensaje",
+    on_this_day: "Un día como hoy",
+    no_messages_on_this_day: "No hay mensajes de este día en ningún año",
+    text_in_image: "Texto en la imagen",
+    transcript: "Transcripción",
+    sent_as_text_message: "Enviado como SMS",
+    audio_message: "Mensaje de audio",
+    replied_to: "Con respuestas",
+    you: "Tú",
+    page: "Página",
+    page_of: "de",
+};
+
+impl Locale {
+    pub fn strings(&self) -> &'static Strings {
+        match self {
+            Locale::English => &ENGLISH,
+            Locale::German => &GERMAN,
+            Locale::French => &FRENCH,
+            Locale::Spanish => &SPANISH,
+        }
+    }
+
+    /// Language tag for the pages' `lang` attribute
+    pub fn language(&self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::German => "de",
+            Locale::French => "fr",
+            Locale::Spanish => "es",
+        }
+    }
+
+    fn chrono_locale(&self) -> chrono::Locale {
+        match self {
+            Locale::English => chrono::Locale::en_US,
+            Locale::German => chrono::Locale::de_DE,
+            Locale::French => chrono::Locale::fr_FR,
+            Locale::Spanish => chrono::Locale::es_ES,
+        }
+    }
+
+    fn format(&self, date: &DateTime<Local>, pattern: &str) -> String {
+        date.format_localized(pattern, self.chrono_locale())
+            .to_string()
+    }
+
+    /// Date of the separators between days, e.g. `May 04, 2021` or `4. Mai 2021`
+    pub fn long_date(&self, date: &DateTime<Local>) -> String {
+        let pattern = match self {
+            Locale::English => "%B %d, %Y",
+            Locale::German => "%-d. %B %Y",
+            Locale::French => "%-d %B %Y",
+            Locale::Spanish => "%-d de %B de %Y",
+        };
+        self.format(date, pattern)
+    }
+
+    /// Compact date, e.g. `May 04, 2021` or `04.05.2021`
+    pub fn short_date(&self, date: &DateTime<Local>) -> String {
+        let pattern = match self {
+            Locale::English => "%b %d, %Y",
+            Locale::German => "%d.%m.%Y",
+            Locale::French | Locale::Spanish => "%d/%m/%Y",
+        };
+        self.format(date, pattern)
+    }
+
+    /// Time of day, e.g. `03:42 PM` or `15:42`
+    pub fn time(&self, date: &DateTime<Local>) -> String {
+        let pattern = match self {
+            Locale::English => "%I:%M %p",
+            Locale::German | Locale::French | Locale::Spanish => "%H:%M",
+        };
+        self.format(date, pattern)
+    }
+
+    /// Short date followed by the time, e.g. `May 04, 2021 at 03:42 PM`
+    pub fn date_time(&self, date: &DateTime<Local>) -> String {
+        let at = match self {
+            Locale::English => "at",
+            Locale::German => "um",
+            Locale::French => "à",
+            Locale::Spanish => "a las",
+        };
+        format!("{} {} {}", self.short_date(date), at, self.time(date))
+    }
+
+    /// Month and year, e.g. `May 2021` or `Mai 2021`
+    pub fn month_year(&self, date: &DateTime<Local>) -> String {
+        self.format(date, "%B %Y")
+    }
+}
+
+impl std::str::FromStr for Locale {
+    type Err = String;
+
+    /// Accepts a language ("de") or a language and region ("de-DE", "de_AT")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['-', '_']).next().unwrap_or(s).to_lowercase();
+        match language.as_str() {
+            "en" => Ok(Locale::English),
+            "de" => Ok(Locale::German),
+            "fr" => Ok(Locale::French),
+            "es" => Ok(Locale::Spanish),
+            _ => Err(format!(
+                "unknown locale '{}' (expected en, de, fr or es)",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_locale() {
+        assert_eq!("de".parse::<Locale>(), Ok(Locale::German));
+        assert_eq!("fr-CA".parse::<Locale>(), Ok(Locale::French));
+        assert_eq!("en_US".parse::<Locale>(), Ok(Locale::English));
+        assert!("xx".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn test_localized_dates() {
+        let date = Local.with_ymd_and_hms(2021, 5, 4, 15, 42, 0).unwrap();
+        assert_eq!(Locale::English.long_date(&date), "May 04, 2021");
+        assert_eq!(Locale::English.time(&date), "03:42 PM");
+        assert_eq!(Locale::German.long_date(&date), "4. Mai 2021");
+        assert_eq!(Locale::German.date_time(&date), "04.05.2021 um 15:42");
+        assert_eq!(Locale::Spanish.long_date(&date), "4 de mayo de 2021");
+    }
+}
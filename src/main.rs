@@ -1,30 +1,94 @@
-mod clean_message;
-mod contacts;
-mod html_output;
-mod message_store;
-mod resolved_handle;
-mod tapback_emoji;
-
-use anyhow::{Result, anyhow};
-use chrono::NaiveDate;
+use anyhow::{Context, Result, anyhow};
+use attachment_export::AttachmentExport;
+use attachment_filter::{AttachmentFilter, ByteSize};
+use attachment_store::AttachmentStore;
+use attachment_timestamp::AttachmentTimestamp;
+use automated_senders::AutomatedSenders;
+use chat_naming::{ChatNames, NamingOptions};
+use chat_rename::{ChatRename, ChatRenames};
+use chat_selector::ChatSelector;
+use chat_stats::StatsReport;
+use chrono::{DateTime, Local, NaiveDate};
 use clean_message::CleanMessage;
-use contacts::ContactMap;
+use contact_resolver::{ContactResolver, NoopResolver};
+use contacts::{ContactMap, NameTable, normalize_identifier};
+use content_filter::{ContentFilter, read_guids};
+use corpus_output::{CorpusOutput, DEFAULT_CHUNK_OVERLAP, DEFAULT_CHUNK_SIZE};
+use database_source::{DatabaseSource, SourcePlatform};
+use date_range::{DateRange, Timezone};
+use day_one_output::DayOneOutput;
+use encryption::Encryption;
+use epub_output::EpubOutput;
+use error::ExportError;
+use error_log::{ERRORS_FILENAME, ErrorLog};
+use events::{ExportObserver, JsonLinesObserver};
+use group_photo::{GroupPhoto, GroupPhotoChange};
 use gumdrop::Options;
-use html_output::HtmlOutput;
+use html_output::{HtmlOptions, HtmlOutput};
 use imessage_database::{
     error::table::TableError,
     tables::{
         chat::Chat,
         handle::Handle,
         messages::Message,
-        table::{Cacheable, Table, get_connection},
+        table::{Cacheable, Table},
     },
     util::dirs::default_db_path,
 };
+use imessage_extractor::{
+    attachment_export, attachment_filter, attachment_store, attachment_timestamp,
+    automated_senders, call_history, chat_naming, chat_rename, chat_selector, chat_stats,
+    clean_message, contact_resolver, contacts, content_filter, corpus_output, database_connection,
+    database_source, date_range, day_one_output, diff, encryption, environment, epub_output, error,
+    error_log, events, group_photo, html_output, index_sort, ios_backup, jsonl_output, layout,
+    load_file_output, locale, manifest, matrix_output, mbox_output, membership, message_flags,
+    message_service, message_store, my_handles, obsidian_output, ocr, output_format, output_target,
+    parquet_output, recently_deleted, relative_date, resolved_handle, run_state, sampling, schema,
+    search_index, sender_repair, signing, sms_duplicates, snapshot, split_by, summary,
+    tapback_emoji, text_fallback, theme, timestamp_source, transcription, txt_output, verify,
+    word_frequency,
+};
+use index_sort::IndexSort;
+use ios_backup::EncryptedBackup;
+use jsonl_output::JsonlWriter;
+use layout::Layout;
+use load_file_output::{Bates, DEFAULT_BATES_DIGITS, DEFAULT_BATES_PREFIX, LoadFileOutput};
+use locale::Locale;
+use manifest::{MANIFEST_FILENAME, Manifest, ManifestChat, ManifestRecognizedText, ManifestSource};
+use matrix_output::{MatrixOutput, MatrixUsers};
+use mbox_output::MboxOutput;
+use membership::MembershipChange;
+use message_service::MessageService;
 use message_store::MessageStore;
+use obsidian_output::ObsidianOutput;
+use ocr::TextRecognizer;
+use output_format::OutputFormat;
+use output_target::OutputTarget;
+use parquet_output::ParquetOutput;
+use phonenumber::country;
+use regex::Regex;
+use relative_date::RelativeDate;
 use resolved_handle::ResolvedHandle;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use run_state::{ExportLock, LOCK_FILENAME, RUN_STATE_FILENAME, RunState};
+use rusqlite::Connection;
+use sampling::SampleFrom;
+use schema::{DatabaseSchema, SchemaFeature};
+use search_index::SEARCH_DB_FILENAME;
+use signing::SIGNATURE_FILENAME;
+use snapshot::DatabaseSnapshot;
+use split_by::SplitBy;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use summary::{AttachmentCounts, RunSummary, SUMMARY_FILENAME};
+use tapback_emoji::TapbackEmoji;
+use theme::Theme;
+use timestamp_source::TimestampSource;
+use transcription::Transcriber;
+use txt_output::TxtOutput;
+use word_frequency::{Frequencies, WORDCLOUD_CSV_FILENAME, WORDCLOUD_JSON_FILENAME};
 
 #[derive(Debug, Options)]
 struct Args {
@@ -32,145 +96,1804 @@ struct Args {
     start_date: Option<NaiveDate>,
     #[options(help = "Limit export to messages before this date")]
     end_date: Option<NaiveDate>,
+    #[options(
+        no_short,
+        meta = "WHEN",
+        help = "Limit export to messages on or after a date relative to today: 30d, 2w, 6m, 1y, yesterday, this/last week, month or year"
+    )]
+    since: Option<RelativeDate>,
+    #[options(
+        no_short,
+        meta = "TZ",
+        help = "Timezone whose days the date options count in: local, UTC or an offset such as +02:00 (default: local, the one dates are shown in)"
+    )]
+    timezone: Option<Timezone>,
+    #[options(
+        no_short,
+        help = "Only export received messages that haven't been read, for triage"
+    )]
+    unread_only: bool,
+    #[options(
+        no_short,
+        meta = "SOURCE",
+        help = "Which time messages are shown with and sorted by: sent, delivered or read (default: sent)"
+    )]
+    timestamp_source: Option<TimestampSource>,
     #[options(
         help = "Chat to export. Defaults to all if no --chat flag given.  May be given multiple times"
     )]
     chat: Vec<String>,
-    #[options(help = "Override the default database path")]
-    database_path: Option<PathBuf>,
+    #[options(
+        no_short,
+        meta = "ID",
+        help = "Chat to export by its database ID, as shown by list-chats.  May be given multiple times"
+    )]
+    chat_id: Vec<i32>,
+    #[options(
+        no_short,
+        meta = "GUID",
+        help = "Chat to export by its GUID (e.g. iMessage;-;+15555550100), as shown by list-chats.  May be given multiple times"
+    )]
+    chat_guid: Vec<String>,
+    #[options(
+        no_short,
+        help = "Export group chats with the same participants, which Messages splits when someone's number or email changes, as one conversation"
+    )]
+    merge_duplicate_groups: bool,
+    #[options(
+        no_short,
+        meta = "CHAT=NAME",
+        help = "Export a chat, picked by identifier (as --list-chats shows it) or name, under a new name.  May be given multiple times"
+    )]
+    rename_chat: Vec<ChatRename>,
+    #[options(
+        no_short,
+        meta = "PATH",
+        help = "CSV or TOML file of chat,name pairs to rename chats with, as --rename-chat does; --rename-chat wins"
+    )]
+    chat_names: Option<PathBuf>,
+    #[options(
+        no_short,
+        meta = "SERVICE",
+        help = "Only export messages sent via this service (imessage, sms, rcs, satellite).  May be given multiple times"
+    )]
+    service: Vec<MessageService>,
+    #[options(
+        no_short,
+        help = "Leave out chats with only SMS short codes (e.g. 262966) and Messages for Business accounts"
+    )]
+    skip_businesses: bool,
+    #[options(
+        no_short,
+        help = "Leave out one-way chats of verification codes and marketing texts"
+    )]
+    skip_automated: bool,
+    #[options(
+        no_short,
+        meta = "REGEX",
+        help = "Only export messages whose text matches this regular expression ((?i) for case-insensitive).  May be given multiple times"
+    )]
+    grep: Vec<Regex>,
+    #[options(
+        no_short,
+        meta = "REGEX",
+        help = "Leave out messages whose text matches this regular expression.  May be given multiple times"
+    )]
+    grep_not: Vec<Regex>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "With --grep or --guids, also export the N messages before and after each match in its chat"
+    )]
+    grep_context: Option<usize>,
+    #[options(
+        no_short,
+        meta = "FILE",
+        help = "Only export the messages whose GUIDs this file lists, one per line"
+    )]
+    guids: Option<PathBuf>,
+    #[options(
+        no_short,
+        help = "Include recently deleted messages that are still recoverable, marked as deleted"
+    )]
+    include_deleted: bool,
+    #[options(
+        no_short,
+        help = "Add FaceTime and phone calls from the call history to direct-message timelines"
+    )]
+    include_calls: bool,
+    #[options(
+        no_short,
+        help = "Override the default call history database path (used with --include-calls)"
+    )]
+    call_history_path: Option<PathBuf>,
+    #[options(
+        help = "Override the default database path: a chat.db file, or an iOS backup directory.  May be given multiple times to merge databases"
+    )]
+    database_path: Vec<PathBuf>,
+    #[options(
+        no_short,
+        meta = "PLATFORM",
+        help = "Device the databases come from: macos or ios (default: detected from each --database-path)"
+    )]
+    platform: Option<SourcePlatform>,
+    #[options(
+        no_short,
+        meta = "DIR",
+        help = "Attachments folder of a chat.db copied from a Mac (default: ~/Library/Messages/Attachments). Give it once per --database-path, in the same order, for databases with their own folders"
+    )]
+    attachment_root: Vec<String>,
+    #[options(
+        no_short,
+        help = "Read the live database directly instead of a temporary copy (the copy avoids locking and WAL issues while Messages is running)"
+    )]
+    no_snapshot: bool,
+    #[options(
+        no_short,
+        help = "In chats where no message is marked as sent by you, show messages with no sender or with a delivery receipt as yours"
+    )]
+    repair_sender_heuristics: bool,
+    #[options(
+        no_short,
+        help = "Keep both copies of messages delivered over iMessage and again over SMS, which are otherwise merged into one"
+    )]
+    keep_sms_duplicates: bool,
+    #[options(
+        no_short,
+        help = "Abort on the first message that can't be exported instead of logging it to errors.log"
+    )]
+    strict: bool,
+    #[options(
+        no_short,
+        help = "Print progress events (chats, messages, attachments, warnings) to stderr as JSON lines"
+    )]
+    progress: bool,
+    #[options(
+        no_short,
+        help = "For scheduled runs: exit without exporting if the database hasn't changed since the last run into the output, and replace that run's export if it has"
+    )]
+    if_changed: bool,
+    #[options(
+        no_short,
+        help = "Print the run summary (also written to summary.json) as one line of JSON at the end"
+    )]
+    print_summary: bool,
     #[options(help = "Output directory for HTML and attachments (default: output)")]
     output_directory: Option<PathBuf>,
+    #[options(
+        no_short,
+        meta = "ARCHIVE",
+        help = "Write the export into a .zip or .tar.zst archive instead of a directory"
+    )]
+    output_archive: Option<PathBuf>,
+    #[options(
+        no_short,
+        help = "Encrypt the export with age, to --recipient keys or a passphrase (prompted, or from IMESSAGE_EXTRACTOR_PASSPHRASE)"
+    )]
+    encrypt: bool,
+    #[options(
+        no_short,
+        meta = "AGE-KEY",
+        help = "age public key (age1...) to encrypt to with --encrypt.  May be given multiple times"
+    )]
+    recipient: Vec<String>,
+    #[options(
+        no_short,
+        meta = "FORMAT",
+        help = "Output format: html, txt, mbox, epub, obsidian, dayone, matrix, corpus, jsonl, parquet or loadfile (default: html). jsonl streams messages as they're read, without tapbacks"
+    )]
+    format: Option<OutputFormat>,
+    #[options(
+        no_short,
+        meta = "FILE",
+        help = "With --format jsonl, write the messages to FILE instead of stdout, and any errors to errors.log beside it"
+    )]
+    output_file: Option<PathBuf>,
+    #[options(
+        no_short,
+        meta = "FILE",
+        help = "With --format matrix, TOML file mapping handles (and \"me\") to Matrix IDs"
+    )]
+    matrix_users: Option<PathBuf>,
+    #[options(
+        no_short,
+        meta = "SERVER",
+        help = "With --format matrix, give handles missing from --matrix-users an @imessage_...:SERVER ID"
+    )]
+    matrix_server: Option<String>,
+    #[options(
+        no_short,
+        meta = "CHARS",
+        help = "With --format corpus, most characters of messages per chunk (default: 2000)"
+    )]
+    chunk_size: Option<usize>,
+    #[options(
+        no_short,
+        meta = "CHARS",
+        help = "With --format corpus, characters of messages each chunk repeats from the one before (default: 200)"
+    )]
+    chunk_overlap: Option<usize>,
+    #[options(
+        no_short,
+        meta = "PREFIX",
+        help = "With --format loadfile, letters before each Bates number (default: IMSG)"
+    )]
+    bates_prefix: Option<String>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "With --format loadfile, number of the first page (default: 1)"
+    )]
+    bates_start: Option<u64>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "With --format loadfile, digits each Bates number is padded to (default: 8)"
+    )]
+    bates_digits: Option<usize>,
+    #[options(
+        no_short,
+        meta = "LAYOUT",
+        help = "Where html and txt transcripts and attachments go: default or imessage-exporter"
+    )]
+    layout: Option<Layout>,
+    #[options(
+        no_short,
+        meta = "PERIOD",
+        help = "Split each HTML chat into one page per year or month, with an index per chat"
+    )]
+    split_by: Option<SplitBy>,
+    #[options(
+        no_short,
+        meta = "ORDER",
+        help = "Order of the chats on the HTML index: name, recent or count (default: name)"
+    )]
+    sort: Option<IndexSort>,
+    #[options(
+        no_short,
+        meta = "CHAT",
+        help = "List this chat, by name, first on the HTML index.  May be given multiple times, in the order to list them"
+    )]
+    pin: Vec<String>,
+    #[options(
+        no_short,
+        help = "Also write timeline.html, interleaving every chat in date order"
+    )]
+    timeline: bool,
+    #[options(
+        no_short,
+        meta = "DATE",
+        help = "Show this date's month and day, in every year, on on-this-day.html (default: today)"
+    )]
+    on_this_day: Option<NaiveDate>,
+    #[options(
+        no_short,
+        help = "Also write search.db, an SQLite full-text (FTS5) index of every message"
+    )]
+    search_db: bool,
+    #[options(
+        no_short,
+        help = "Also write wordcloud.json and wordcloud.csv, the words and emoji each chat uses most"
+    )]
+    wordcloud: bool,
+    #[options(
+        no_short,
+        meta = "KEYID",
+        help = "Sign manifest.json with this GPG key, writing the detached signature to manifest.json.asc"
+    )]
+    sign_key: Option<String>,
+    #[options(
+        no_short,
+        help = "Record a Merkle root over every exported file's checksum in manifest.json"
+    )]
+    merkle_root: bool,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "Leave out chats with fewer than N messages, e.g. wrong numbers and one-off spam"
+    )]
+    min_messages: Option<usize>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "Keep at most N messages of each chat, for a quick preview export"
+    )]
+    max_messages_per_chat: Option<usize>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "Keep only N messages in total, for a quick preview export"
+    )]
+    sample: Option<usize>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "Keep only the newest N messages in total"
+    )]
+    last_n: Option<usize>,
+    #[options(
+        no_short,
+        meta = "FROM",
+        help = "Which messages --sample and --max-messages-per-chat keep: random, head (oldest) or tail (newest) (default: random)"
+    )]
+    sample_from: Option<SampleFrom>,
+    #[options(
+        no_short,
+        meta = "SIZE",
+        help = "Leave out attachments larger than SIZE (e.g. 25M), showing a placeholder instead"
+    )]
+    max_attachment_size: Option<ByteSize>,
+    #[options(
+        no_short,
+        help = "Leave out video attachments, showing a placeholder instead"
+    )]
+    skip_videos: bool,
+    #[options(
+        no_short,
+        help = "Only extract attachments, into <chat>/<year>/<month>/ folders, without any transcripts"
+    )]
+    attachments_only: bool,
+    #[options(
+        no_short,
+        meta = "TIME",
+        help = "Date extracted attachment files with their original modification time (default), the message date, or the attachment's creation date: original, message or created"
+    )]
+    attachment_timestamps: Option<AttachmentTimestamp>,
+    #[options(
+        no_short,
+        help = "Remove EXIF, GPS and device metadata from extracted photos and videos, for exports meant for sharing"
+    )]
+    strip_metadata: bool,
+    #[options(
+        no_short,
+        help = "With --format epub, write one book per chat and year instead of per chat"
+    )]
+    epub_per_year: bool,
+    #[options(
+        no_short,
+        help = "CSV or TOML file mapping phone numbers/emails to display names. Overrides Contacts"
+    )]
+    aliases: Option<PathBuf>,
+    #[options(
+        no_short,
+        meta = "FILE|DIR",
+        help = "vCard file, or directory of them, to read names from. Overrides Contacts, overridden by --aliases.  May be given multiple times"
+    )]
+    vcards: Vec<PathBuf>,
+    #[options(
+        no_short,
+        help = "Don't read names from Contacts, only from --aliases and --vcards (always the case outside macOS)"
+    )]
+    no_contacts: bool,
+    #[options(
+        no_short,
+        meta = "REGION",
+        help = "Two-letter region (e.g. GB) for contact numbers without a country code (default: US)"
+    )]
+    default_region: Option<country::Id>,
+    #[options(
+        no_short,
+        meta = "HANDLE",
+        help = "Phone number or email of yours, so messages sent from it show as yours even when the database doesn't mark them as sent.  May be given multiple times"
+    )]
+    my_handle: Vec<String>,
+    #[options(
+        no_short,
+        meta = "NAME|CSS-FILE",
+        help = "Color theme: auto, light, dark, or a CSS file to add to every page (default: auto)"
+    )]
+    theme: Option<String>,
+    #[options(
+        no_short,
+        meta = "LOCALE",
+        help = "Language of the HTML pages' labels and dates: en, de, fr or es (default: en)"
+    )]
+    locale: Option<Locale>,
+    #[options(
+        no_short,
+        help = "Show full-size images inline instead of generating thumbnails"
+    )]
+    no_thumbnails: bool,
+    #[options(no_short, help = "Don't show delivered/read receipts on sent messages")]
+    no_receipts: bool,
+    #[options(
+        no_short,
+        help = "Draw a waveform next to each audio attachment (needs afconvert, part of macOS, or ffmpeg)"
+    )]
+    audio_waveforms: bool,
+    #[options(
+        no_short,
+        help = "Recognize text in image attachments so screenshots can be searched (needs the Xcode Command Line Tools or tesseract)"
+    )]
+    ocr: bool,
+    #[options(
+        no_short,
+        help = "Transcribe the speech in video attachments so it can be searched (needs the Xcode Command Line Tools or whisper)"
+    )]
+    transcribe_videos: bool,
+    #[options(
+        no_short,
+        meta = "SIZE",
+        help = "Only transcribe videos up to SIZE (default: 200M)"
+    )]
+    transcribe_max_size: Option<ByteSize>,
+    #[options(
+        no_short,
+        meta = "MINUTES",
+        help = "Only transcribe videos up to MINUTES long (default: 10)"
+    )]
+    transcribe_max_minutes: Option<u64>,
+    #[options(
+        no_short,
+        help = "Show links as plain text instead of preview cards with their title and image"
+    )]
+    no_link_previews: bool,
+    #[options(
+        no_short,
+        help = "Animate a CSS approximation of send effects (slam, invisible ink, ...)"
+    )]
+    animate_effects: bool,
+    #[options(
+        no_short,
+        help = "Show the last known text of unsent messages when it is still available"
+    )]
+    recover_unsent: bool,
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(command)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Options)]
+enum Command {
+    #[options(help = "Check an export against its manifest and the current database")]
+    Verify(VerifyArgs),
+    #[options(help = "Show messages, chats and attachments added or removed between two exports")]
+    Diff(DiffArgs),
+    #[options(help = "List every chat with its ID and GUID, for --chat-id and --chat-guid")]
+    ListChats(ListChatsArgs),
+    #[options(help = "Write one message and the messages around it to a standalone HTML file")]
+    Excerpt(ExcerptArgs),
+    #[options(help = "Show reply times, who starts conversations and other stats of each chat")]
+    Stats(StatsArgs),
+}
+
+#[derive(Debug, Options)]
+struct VerifyArgs {
+    #[options(free, help = "Export directory to verify (default: output)")]
+    export: Vec<PathBuf>,
     #[options(help = "print help message")]
     help: bool,
 }
 
 impl Args {
-    pub fn database_path(&self) -> PathBuf {
-        match &self.database_path {
-            None => default_db_path(),
+    /// The databases to export, defaulting to this Mac's own
+    pub fn database_sources(&self) -> Result<Vec<DatabaseSource>> {
+        let paths = if !self.database_path.is_empty() {
+            self.database_path.clone()
+        } else if cfg!(target_os = "macos") {
+            vec![default_db_path()]
+        } else {
+            return Err(ExportError::InvalidArguments(
+                "There is no Messages database on this computer; pass a copied chat.db or iOS backup with --database-path".to_string(),
+            )
+            .into());
+        };
+
+        // One folder for every database, or one for each
+        let attachment_roots: Vec<Option<String>> = match self.attachment_root.as_slice() {
+            [] => vec![None; paths.len()],
+            [root] => vec![Some(root.clone()); paths.len()],
+            roots if roots.len() == paths.len() => roots.iter().cloned().map(Some).collect(),
+            _ => {
+                return Err(ExportError::InvalidArguments(
+                    "Give --attachment-root once, or once for each --database-path".to_string(),
+                )
+                .into());
+            }
+        };
+
+        let mut sources: Vec<DatabaseSource> = paths
+            .into_iter()
+            .zip(attachment_roots)
+            .map(|(path, attachment_root)| {
+                let platform = self
+                    .platform
+                    .unwrap_or_else(|| SourcePlatform::detect(&path));
+                DatabaseSource::new(path, platform, attachment_root)
+            })
+            .collect();
+
+        // Asked for once, up front, and used for every encrypted backup
+        let mut password = None;
+        for (index, source) in sources.iter_mut().enumerate() {
+            if source.is_encrypted() {
+                if password.is_none() {
+                    password = Some(EncryptedBackup::password()?);
+                }
+                source.unlock(password.as_deref().unwrap_or_default(), index)?;
+            }
+        }
+
+        Ok(sources)
+    }
+
+    pub fn chat_selector(&self) -> ChatSelector {
+        ChatSelector {
+            names: self.chat.clone(),
+            ids: self.chat_id.clone(),
+            guids: self.chat_guid.clone(),
+        }
+    }
+
+    pub fn call_history_path(&self) -> PathBuf {
+        match &self.call_history_path {
+            None => call_history::default_call_history_path(),
             Some(path) => path.clone(),
         }
     }
 
+    pub fn default_region(&self) -> country::Id {
+        self.default_region.unwrap_or(country::Id::US)
+    }
+
+    pub fn theme(&self) -> Result<Theme> {
+        match &self.theme {
+            None => Ok(Theme::default()),
+            Some(theme) => Theme::from_arg(theme),
+        }
+    }
+
     pub fn output_directory(&self) -> PathBuf {
         match &self.output_directory {
             None => PathBuf::from("output"),
             Some(path) => path.clone(),
         }
     }
+
+    /// `--start-date`, or the date `--since` stands for today; the later one when both are
+    /// given
+    pub fn start_date(&self) -> Option<NaiveDate> {
+        let today = self.timezone.unwrap_or_default().today();
+        let since = self.since.map(|since| since.resolve(today));
+        self.start_date.max(since)
+    }
+
+    pub fn date_range(&self) -> DateRange {
+        DateRange {
+            on_or_after: self.start_date(),
+            before: self.end_date,
+            timezone: self.timezone.unwrap_or_default(),
+        }
+    }
+
+    /// Copies attachments where `--layout` puts them, for the formats that link to them
+    fn attachment_store(&self) -> AttachmentStore {
+        AttachmentStore::new(
+            self.layout.unwrap_or_default(),
+            self.attachment_timestamps.unwrap_or_default(),
+            self.strip_metadata,
+        )
+    }
+
+    fn naming_options(&self) -> Result<NamingOptions> {
+        let mut renames = match &self.chat_names {
+            Some(path) => ChatRenames::load(path)?,
+            None => ChatRenames::default(),
+        };
+        renames.extend(&self.rename_chat);
+        Ok(NamingOptions {
+            merge_duplicate_groups: self.merge_duplicate_groups,
+            renames,
+        })
+    }
+
+    fn html_options(&self) -> Result<HtmlOptions> {
+        Ok(HtmlOptions {
+            theme: self.theme()?,
+            skip_receipts: self.no_receipts,
+            animate_effects: self.animate_effects,
+            recover_unsent: self.recover_unsent,
+            split_by: self.split_by,
+            timeline: self.timeline,
+            on_this_day: self.on_this_day,
+            strip_metadata: self.strip_metadata,
+            merge_duplicate_groups: self.merge_duplicate_groups,
+            skip_link_previews: self.no_link_previews,
+            inline_attachments: false,
+            locale: self.locale.unwrap_or_default(),
+            layout: self.layout.unwrap_or_default(),
+            index_sort: self.sort.unwrap_or_default(),
+            pinned: self.pin.clone(),
+        })
+    }
+
+    fn content_filter(&self) -> Result<ContentFilter> {
+        Ok(ContentFilter {
+            matching: self.grep.clone(),
+            not_matching: self.grep_not.clone(),
+            guids: self.guids.as_deref().map(read_guids).transpose()?,
+            context: self.grep_context.unwrap_or(0),
+        })
+    }
+
+    /// Applies `--min-messages`, then `--grep` and `--guids`, then
+    /// `--max-messages-per-chat`, then `--sample`, then `--last-n` to the date-ordered
+    /// messages
+    pub fn select_messages(&self, mut messages: Vec<CleanMessage>) -> Result<Vec<CleanMessage>> {
+        let from = self.sample_from.unwrap_or_default();
+        if let Some(min) = self.min_messages {
+            messages = sampling::drop_small_chats(messages, min);
+        }
+        messages = self.content_filter()?.apply(messages);
+        if let Some(max) = self.max_messages_per_chat {
+            messages = sampling::limit_per_chat(messages, max, from);
+        }
+        if let Some(count) = self.sample {
+            messages = sampling::sample(messages, count, from);
+        }
+        if let Some(count) = self.last_n {
+            messages = sampling::sample(messages, count, SampleFrom::Tail);
+        }
+        Ok(messages)
+    }
 }
 
-fn resolve_chat_name(
-    message: &Message,
-    chat_data_cache: &HashMap<i32, Chat>,
-    contact_map: &ContactMap,
-) -> Option<String> {
-    match message.chat_id {
-        None => None,
-        Some(chat_id) => {
-            let chat = chat_data_cache
-                .get(&chat_id)
-                .expect("Unable to find chat data for a chat id");
-
-            if let Some(display_name) = chat.display_name.as_ref()
-                && !display_name.is_empty()
-            {
-                Some(display_name.clone())
-            } else {
-                Some(
-                    contact_map
-                        .get(&chat.chat_identifier)
-                        .unwrap_or(&chat.chat_identifier)
-                        .clone(),
-                )
+/// Names from `--aliases`, then `--vcards`, then Contacts
+fn load_contact_map(args: &Args) -> Result<ContactMap> {
+    let mut resolvers: Vec<Arc<dyn ContactResolver>> = Vec::new();
+    if let Some(aliases_path) = &args.aliases {
+        resolvers.push(Arc::new(NameTable::load_aliases(
+            aliases_path,
+            args.default_region(),
+        )?));
+    }
+    for vcards_path in &args.vcards {
+        resolvers.push(Arc::new(NameTable::load_vcards(
+            vcards_path,
+            args.default_region(),
+        )?));
+    }
+    // The Swift helper that reads Contacts only runs on macOS
+    if !args.no_contacts && cfg!(target_os = "macos") {
+        resolvers.push(Arc::new(NameTable::fetch(args.default_region())?));
+    }
+    if resolvers.is_empty() {
+        resolvers.push(Arc::new(NoopResolver));
+    }
+
+    let mut contact_map = ContactMap::new(resolvers);
+    contact_map.add_my_handles(
+        args.my_handle
+            .iter()
+            .map(|handle| normalize_identifier(handle, args.default_region())),
+    );
+    Ok(contact_map)
+}
+
+/// `contact_map` plus what a database knows about its handles: my accounts, and labels
+/// for short codes and businesses
+fn with_database_handles(contact_map: &ContactMap, db: &Connection) -> Result<ContactMap> {
+    let mut contact_map = contact_map.clone();
+    contact_map.add_my_handles(my_handles::account_handles(db)?);
+    contact_map.add_resolver(Arc::new(AutomatedSenders::read(db)?));
+    Ok(contact_map)
+}
+
+fn collect_messages(
+    args: &Args,
+    sources: &[DatabaseSource],
+    errors: &mut ErrorLog,
+) -> Result<MessageStore> {
+    collect_messages_into(args, sources, MessageStore::new(), errors)
+}
+
+fn collect_messages_into(
+    args: &Args,
+    sources: &[DatabaseSource],
+    mut message_store: MessageStore,
+    errors: &mut ErrorLog,
+) -> Result<MessageStore> {
+    let contact_map = load_contact_map(args)?;
+
+    // Messages found in more than one database are merged by GUID
+    for (index, source) in sources.iter().enumerate() {
+        let database_file = source.database_file();
+        if args.no_snapshot {
+            collect_database_messages(
+                args,
+                &database_file,
+                source,
+                &contact_map,
+                &mut message_store,
+                errors,
+            )?;
+        } else {
+            let snapshot = DatabaseSnapshot::create(&database_file, index)?;
+            collect_database_messages(
+                args,
+                snapshot.path(),
+                source,
+                &contact_map,
+                &mut message_store,
+                errors,
+            )?;
+        }
+    }
+
+    if args.include_calls {
+        let chats = args.chat_selector();
+        let date_range = args.date_range();
+        let renames = args.naming_options()?.renames;
+        environment::check_databases(&[args.call_history_path()])?;
+        for call in call_history::fetch_calls(&args.call_history_path())? {
+            let clean_message =
+                CleanMessage::from_call(&contact_map, &renames, args.default_region(), call);
+            if clean_message.matches(&date_range, &chats, &args.service) {
+                message_store.insert(clean_message)?;
             }
         }
     }
+
+    Ok(message_store)
+}
+
+/// The messages an export holds, and what was left out of it on the way
+struct ExportMessages {
+    messages: Vec<CleanMessage>,
+    group_photos: HashMap<String, GroupPhoto>,
+    membership_changes: HashMap<String, Vec<MembershipChange>>,
+    /// Messages `--repair-sender-heuristics` reassigned to me
+    repaired_senders: usize,
+    /// Second copies of messages delivered over both iMessage and SMS
+    sms_duplicates: usize,
+    /// Chats `--skip-automated` left out
+    automated_chats: Vec<String>,
+}
+
+/// Collects the messages of `sources` and makes the selection an export with `args`
+/// does: senders repaired, SMS duplicates and automated chats removed, then the
+/// options picking chats and messages applied. The export, `verify` and `stats` all
+/// use it, so they agree on what an export holds.
+fn export_messages(
+    args: &Args,
+    sources: &[DatabaseSource],
+    errors: &mut ErrorLog,
+) -> Result<ExportMessages> {
+    let mut message_store = collect_messages(args, sources, errors)?;
+    let group_photos = message_store.take_group_photos();
+    let membership_changes = message_store.take_membership_changes();
+
+    let mut messages = message_store.drain_to_sorted_vector();
+    let repaired_senders = if args.repair_sender_heuristics {
+        sender_repair::repair_senders(&mut messages)
+    } else {
+        0
+    };
+    let sms_duplicates = if args.keep_sms_duplicates {
+        0
+    } else {
+        sms_duplicates::remove_sms_duplicates(&mut messages)
+    };
+    let automated_chats = if args.skip_automated {
+        automated_senders::remove_automated_chats(&mut messages)
+    } else {
+        Vec::new()
+    };
+
+    Ok(ExportMessages {
+        messages: args.select_messages(messages)?,
+        group_photos,
+        membership_changes,
+        repaired_senders,
+        sms_duplicates,
+        automated_chats,
+    })
 }
 
-fn collect_messages(args: &Args) -> Result<MessageStore> {
-    let db = get_connection(&args.database_path()).map_err(|e| anyhow!(format!("{}", e)))?;
+/// The SQLite file of each database to export
+fn database_files(sources: &[DatabaseSource]) -> Vec<PathBuf> {
+    sources.iter().map(DatabaseSource::database_file).collect()
+}
+
+fn collect_database_messages(
+    args: &Args,
+    database_path: &Path,
+    source: &DatabaseSource,
+    contact_map: &ContactMap,
+    message_store: &mut MessageStore,
+    errors: &mut ErrorLog,
+) -> Result<()> {
+    let db = database_connection::open_read_only(database_path)
+        .map_err(|e| ExportError::database(format!("{:#}", e)))?;
+    let contact_map = &with_database_handles(contact_map, &db)?;
+
+    let chat_data_cache = Chat::cache(&db).map_err(ExportError::database)?;
+    let chat_guids = chat_selector::chat_guids(&db)?;
+    let chats = args.chat_selector();
+    let handle_cache = Handle::cache(&db).map_err(ExportError::database)?;
 
-    let chat_data_cache = Chat::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
-    let handle_cache = Handle::cache(&db).map_err(|e| anyhow!(format!("{}", e)))?;
-    let contact_map = ContactMap::fetch()?;
+    let schema = DatabaseSchema::detect(&db, database_path)?;
+    let deletion_dates = if args.include_deleted && schema.supports(SchemaFeature::RecentlyDeleted)
+    {
+        recently_deleted::deletion_dates(&db)?
+    } else {
+        HashMap::new()
+    };
 
-    let mut message_store = MessageStore::new();
+    let chat_names = ChatNames::load(
+        &db,
+        &chat_data_cache,
+        &handle_cache,
+        contact_map,
+        args.naming_options()?,
+    )?;
+
+    let skipped_chats = if args.skip_businesses {
+        automated_senders::automated_chats(&db, &handle_cache)?
+    } else {
+        HashSet::new()
+    };
+
+    let flags = message_flags::message_flags(&db)?;
+    message_store.add_membership_changes(membership::membership_changes(
+        &db,
+        &handle_cache,
+        contact_map,
+    )?);
+
+    let timestamp_source = args.timestamp_source.unwrap_or_default();
+    let clean = |message: Message, errors: &mut ErrorLog| -> Result<Option<CleanMessage>> {
+        let guid = message.guid.clone();
+        let rowid = message.rowid;
+        let cleaned = chat_names.for_message(&message).and_then(|chat_name| {
+            CleanMessage::from_message(
+                &db,
+                source,
+                &handle_cache,
+                contact_map,
+                &deletion_dates,
+                chat_name,
+                message,
+                errors,
+            )
+        });
+        match cleaned {
+            Ok(mut clean_message) => {
+                clean_message.flags = flags.get(&rowid).copied().unwrap_or_default();
+                clean_message.date = timestamp_source.resolve(&clean_message);
+                clean_message.chat_guid = clean_message
+                    .chat_id
+                    .and_then(|chat_id| chat_guids.get(&chat_id).cloned());
+                Ok(Some(clean_message))
+            }
+            Err(e) => errors.record(format!("message {}", guid), e).map(|()| None),
+        }
+    };
+    let date_range = args.date_range();
+    let matches_filters = |message: &CleanMessage| {
+        message.matches(&date_range, &chats, &args.service)
+            && (!args.unread_only || message.is_unread())
+            && !message
+                .chat_id
+                .is_some_and(|chat_id| skipped_chats.contains(&chat_id))
+    };
+
+    // The stream carries on past callback errors, so the first strict-mode failure is
+    // kept here and the remaining rows skipped
+    let mut failure = None;
 
     // Iterate over a stream of messages
     Message::stream(&db, |message_result| {
+        if failure.is_some() {
+            return Ok(());
+        }
+
         match message_result {
-            Ok(message) => {
+            Ok(mut message) => {
+                // Recoverable deleted messages are no longer joined to a chat; put them
+                // back in the chat they were deleted from, or skip them
+                if message.is_deleted() {
+                    if !args.include_deleted {
+                        return Ok(());
+                    }
+                    message.chat_id = message.chat_id.or(message.deleted_from);
+                }
+
+                if let Some(change) = GroupPhotoChange::from_message(&db, source, &message)
+                    && let Some(chat_guid) =
+                        message.chat_id.and_then(|chat_id| chat_guids.get(&chat_id))
+                {
+                    message_store.group_photo_change(chat_guid.clone(), change);
+                }
+
                 use imessage_database::message_types::variants::Variant::*;
                 match message.variant() {
-                    Normal => {
-                        let chat_name = resolve_chat_name(&message, &chat_data_cache, &contact_map);
-
-                        let clean_message = CleanMessage::from_message(
-                            &db,
-                            &handle_cache,
-                            &contact_map,
-                            chat_name,
-                            message,
-                        )
-                        .expect("unable to clean message");
-
-                        if clean_message.matches(&args.start_date, &args.end_date, &args.chat) {
-                            message_store.insert(clean_message)
+                    Normal => match clean(message, errors) {
+                        Ok(Some(clean_message)) => {
+                            if matches_filters(&clean_message) {
+                                failure = message_store.insert(clean_message).err();
+                            }
                         }
-                    }
-                    Edited => (),
+                        Ok(None) => {}
+                        Err(e) => failure = Some(e),
+                    },
+                    Edited => match clean(message, errors) {
+                        Ok(Some(clean_message)) => {
+                            // Keep unsent messages so the timeline can show a placeholder
+                            if clean_message.unsent && matches_filters(&clean_message) {
+                                failure = message_store.insert(clean_message).err();
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => failure = Some(e),
+                    },
+                    App(_) => match clean(message, errors) {
+                        Ok(Some(clean_message)) => {
+                            // Keep app messages we can render as a card or, failing that,
+                            // as text
+                            if (clean_message.balloon.is_some()
+                                || !text_fallback::is_blank(&clean_message.text))
+                                && matches_filters(&clean_message)
+                            {
+                                failure = message_store.insert(clean_message).err();
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => failure = Some(e),
+                    },
                     Tapback(_body_id, action, tapback) => {
                         if let Some((_, associated_id)) = message.clean_associated_guid() {
                             let tapback_handle = ResolvedHandle::from_message_sender(
                                 &message,
                                 &handle_cache,
-                                &contact_map,
+                                contact_map,
                             );
+                            let tapback_emoji = TapbackEmoji::from_message_tapback(tapback)
+                                .with_image(&db, source, &message);
                             message_store.tapback(
                                 associated_id.to_string(),
                                 action,
                                 tapback_handle,
-                                tapback,
+                                tapback_emoji,
                             );
                         }
                     }
-                    App(_) | SharePlay | Vote | PollUpdate | Unknown(_) => (),
+                    SharePlay | Vote | PollUpdate | Unknown(_) => (),
+                }
+            }
+            Err(e) => {
+                if let Err(e) = errors.record("message row", ExportError::database(e).into()) {
+                    failure = Some(e);
                 }
             }
-            Err(e) => return Err(e),
         };
 
         Ok::<(), TableError>(())
     })
-    .map_err(|e| anyhow!(format!("{}", e)))?;
+    .map_err(ExportError::database)?;
 
-    Ok(message_store)
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Re-runs the selection an export was made with and compares the result against the
+/// export's manifest. Options given before `verify` (e.g. --database-path) override the
+/// ones recorded in the manifest.
+fn verify_export(args: &Args, verify_args: &VerifyArgs) -> Result<()> {
+    let export_dir = match verify_args.export.as_slice() {
+        [] => args.output_directory(),
+        [export_dir] => export_dir.clone(),
+        _ => {
+            return Err(ExportError::InvalidArguments(
+                "verify takes a single export directory".to_string(),
+            )
+            .into());
+        }
+    };
+    let manifest = Manifest::load(&export_dir)?;
+
+    let mut export_args = Args::parse_args_default(&manifest.arguments)
+        .map_err(|e| anyhow!("Unable to parse the export's recorded arguments: {}", e))?;
+    if !args.database_path.is_empty() {
+        export_args.database_path = args.database_path.clone();
+        export_args.platform = args.platform;
+        export_args.attachment_root = args.attachment_root.clone();
+    }
+    // A relative --since counted back from the day of the export, not from today
+    if let Some(since) = export_args.since {
+        let generated_at = DateTime::parse_from_rfc3339(&manifest.generated_at)
+            .with_context(|| format!("Invalid manifest date {}", manifest.generated_at))?;
+        let export_day = export_args
+            .timezone
+            .unwrap_or_default()
+            .date_of(&generated_at.with_timezone(&Local));
+        export_args.since = Some(RelativeDate::Date(since.resolve(export_day)));
+    }
+    let sources = export_args.database_sources()?;
+    environment::check_databases(&database_files(&sources))?;
+
+    let mut errors = ErrorLog::new(export_args.strict);
+    let chat_messages = export_messages(&export_args, &sources, &mut errors)?.messages;
+    let database_chats = ManifestChat::from_messages(&chat_messages);
+
+    let problems = verify::verify_export(&export_dir, &manifest, &database_chats)?;
+    if problems > 0 {
+        return Err(ExportError::VerifyMismatch(export_dir).into());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Options)]
+struct DiffArgs {
+    #[options(
+        free,
+        help = "Old and new export directories (or their manifest.json files)"
+    )]
+    exports: Vec<PathBuf>,
+    #[options(no_short, help = "List the GUID of every added and removed message")]
+    list_messages: bool,
+    #[options(help = "print help message")]
+    help: bool,
+}
+
+fn diff_exports(diff_args: &DiffArgs) -> Result<()> {
+    let [old, new] = diff_args.exports.as_slice() else {
+        return Err(ExportError::InvalidArguments(
+            "diff takes two exports: the old one and the new one".to_string(),
+        )
+        .into());
+    };
+
+    diff::diff_manifests(
+        &Manifest::load(old)?,
+        &Manifest::load(new)?,
+        diff_args.list_messages,
+    );
+    Ok(())
+}
+
+#[derive(Debug, Options)]
+struct ListChatsArgs {
+    #[options(help = "print help message")]
+    help: bool,
+}
+
+#[derive(Debug, Options)]
+struct StatsArgs {
+    #[options(no_short, help = "Print the stats as JSON")]
+    json: bool,
+    #[options(help = "print help message")]
+    help: bool,
+}
+
+/// `stats`: prints each chat's conversation dynamics, from the messages an export with
+/// the same options would hold
+fn print_stats(args: &Args, stats_args: &StatsArgs) -> Result<()> {
+    let sources = args.database_sources()?;
+    environment::check_databases(&database_files(&sources))?;
+    let mut errors = ErrorLog::new(args.strict);
+    let messages = export_messages(args, &sources, &mut errors)?.messages;
+    let report = StatsReport::new(&messages);
+
+    if stats_args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    println!("All chats:");
+    println!(
+        "  Top words: {}",
+        chat_stats::format_terms(&report.top_words)
+    );
+    println!(
+        "  Top emoji: {}",
+        chat_stats::format_terms(&report.top_emoji)
+    );
+    for chat in &report.chats {
+        let silence = chat
+            .longest_silence
+            .as_ref()
+            .map(|silence| {
+                format!(
+                    ", longest silence {} ({} to {})",
+                    chat_stats::format_duration(silence.seconds),
+                    silence.from.format("%Y-%m-%d"),
+                    silence.to.format("%Y-%m-%d")
+                )
+            })
+            .unwrap_or_default();
+        println!(
+            "{}: {} messages, {} conversations{}",
+            chat.chat, chat.message_count, chat.conversation_count, silence
+        );
+        for participant in &chat.participants {
+            println!(
+                "  {}: {} messages, started {}, median reply {}, {} double texts",
+                participant.name,
+                participant.message_count,
+                participant.conversations_started,
+                participant
+                    .median_reply_seconds
+                    .map(chat_stats::format_duration)
+                    .unwrap_or_else(|| "-".to_string()),
+                participant.double_texts
+            );
+        }
+        println!("  Top words: {}", chat_stats::format_terms(&chat.top_words));
+        println!("  Top emoji: {}", chat_stats::format_terms(&chat.top_emoji));
+    }
+
+    Ok(())
+}
+
+/// `--format jsonl`: writes each message as a line of JSON as soon as it's read, to
+/// stdout or --output-file, without collecting them first. Options that need every
+/// message before they can pick any aren't available, and both copies of messages
+/// delivered over iMessage and SMS are written.
+fn stream_jsonl(args: &Args, sources: &[DatabaseSource]) -> Result<()> {
+    let needs_every_message = [
+        ("--min-messages", args.min_messages.is_some()),
+        (
+            "--max-messages-per-chat",
+            args.max_messages_per_chat.is_some(),
+        ),
+        ("--sample", args.sample.is_some()),
+        ("--last-n", args.last_n.is_some()),
+        ("--grep", !args.grep.is_empty() || !args.grep_not.is_empty()),
+        ("--guids", args.guids.is_some()),
+        ("--skip-automated", args.skip_automated),
+        ("--repair-sender-heuristics", args.repair_sender_heuristics),
+        ("--output-archive", args.output_archive.is_some()),
+        ("--encrypt", args.encrypt),
+        ("--search-db", args.search_db),
+        ("--wordcloud", args.wordcloud),
+        ("--sign-key", args.sign_key.is_some()),
+        ("--merkle-root", args.merkle_root),
+    ];
+    if let Some((option, _)) = needs_every_message.iter().find(|(_, given)| *given) {
+        return Err(ExportError::InvalidArguments(format!(
+            "--format jsonl streams messages as they're read, so it can't be combined with {}",
+            option
+        ))
+        .into());
+    }
+
+    let writer = match &args.output_file {
+        Some(path) => JsonlWriter::create(path)?,
+        None => JsonlWriter::stdout(),
+    };
+    let mut errors = ErrorLog::new(args.strict);
+    let message_store =
+        collect_messages_into(args, sources, MessageStore::streaming(writer), &mut errors)?;
+    let count = message_store.finish_stream()?;
+
+    // stdout may be the messages, so everything else goes to stderr
+    eprintln!("Wrote {} message(s)", count);
+    if !errors.is_empty() {
+        eprint!("{}", errors.to_log());
+        // Beside --output-file, like errors.log in an export's directory
+        let log = match &args.output_file {
+            Some(path) => {
+                let log = path.with_file_name(ERRORS_FILENAME);
+                std::fs::write(&log, errors.to_log())
+                    .with_context(|| format!("Failed to write {}", log.display()))?;
+                Some(log)
+            }
+            None => None,
+        };
+        return Err(ExportError::PartialFailure {
+            count: errors.len(),
+            log,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Prints each chat's ID, GUID and resolved name, one per line, for picking chats with
+/// --chat-id and --chat-guid
+fn list_chats(args: &Args) -> Result<()> {
+    let sources = args.database_sources()?;
+    environment::check_databases(&database_files(&sources))?;
+    let contact_map = load_contact_map(args)?;
+
+    for source in &sources {
+        let db = database_connection::open_read_only(&source.database_file())
+            .map_err(|e| ExportError::database(format!("{:#}", e)))?;
+        let contact_map = with_database_handles(&contact_map, &db)?;
+        let chat_data_cache = Chat::cache(&db).map_err(ExportError::database)?;
+        let chat_guids = chat_selector::chat_guids(&db)?;
+        let handle_cache = Handle::cache(&db).map_err(ExportError::database)?;
+        let chat_names = ChatNames::load(
+            &db,
+            &chat_data_cache,
+            &handle_cache,
+            &contact_map,
+            args.naming_options()?,
+        )?;
+
+        // IDs are only unique within one database
+        if sources.len() > 1 {
+            println!("{}:", source.stored_file().display());
+        }
+        let mut chat_ids: Vec<&i32> = chat_data_cache.keys().collect();
+        chat_ids.sort();
+        for chat_id in chat_ids {
+            println!(
+                "{}\t{}\t{}",
+                chat_id,
+                chat_guids.get(chat_id).map_or("", String::as_str),
+                chat_names.get(*chat_id).unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Options)]
+struct ExcerptArgs {
+    #[options(
+        free,
+        help = "GUID of the message to excerpt, e.g. from a search.html link"
+    )]
+    guid: Vec<String>,
+    #[options(
+        no_short,
+        meta = "REGEX",
+        help = "Excerpt the newest message whose text matches this regular expression instead"
+    )]
+    search: Option<Regex>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "Messages before it to include (default: 10)"
+    )]
+    before: Option<usize>,
+    #[options(
+        no_short,
+        meta = "N",
+        help = "Messages after it to include (default: 10)"
+    )]
+    after: Option<usize>,
+    #[options(
+        no_short,
+        meta = "FILE",
+        help = "HTML file to write (default: excerpt.html)"
+    )]
+    output: Option<PathBuf>,
+    #[options(help = "print help message")]
+    help: bool,
+}
+
+/// Writes a message and its neighbours in its chat to a single HTML file with the
+/// attachments embedded, for sharing one exchange. Options given before `excerpt` (e.g.
+/// --database-path, --chat) pick the messages searched.
+fn write_excerpt(args: &Args, excerpt_args: &ExcerptArgs) -> Result<()> {
+    let output = excerpt_args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("excerpt.html"));
+    if output.exists() {
+        return Err(ExportError::OutputExists(output).into());
+    }
+
+    let sources = args.database_sources()?;
+    environment::check_databases(&database_files(&sources))?;
+    let mut errors = ErrorLog::new(args.strict);
+    let messages = collect_messages(args, &sources, &mut errors)?.drain_to_sorted_vector();
+
+    let target = match (excerpt_args.guid.as_slice(), &excerpt_args.search) {
+        ([guid], None) => messages.iter().find(|message| &message.guid == guid),
+        ([], Some(search)) => messages
+            .iter()
+            .rev()
+            .find(|message| search.is_match(&message.text)),
+        _ => {
+            return Err(ExportError::InvalidArguments(
+                "excerpt takes either a message GUID or --search".to_string(),
+            )
+            .into());
+        }
+    };
+    let Some(target) = target else {
+        return Err(ExportError::InvalidArguments(
+            "No exported message matches the GUID or search".to_string(),
+        )
+        .into());
+    };
+    let target_guid = target.guid.clone();
+
+    let window: HashSet<String> = message_store::group_messages_by_chat(&messages)
+        .into_values()
+        .find_map(|chat_messages| {
+            let index = chat_messages
+                .iter()
+                .position(|message| message.guid == target_guid)?;
+            let start = index.saturating_sub(excerpt_args.before.unwrap_or(10));
+            let end = (index + excerpt_args.after.unwrap_or(10) + 1).min(chat_messages.len());
+            Some(
+                chat_messages[start..end]
+                    .iter()
+                    .map(|message| message.guid.clone())
+                    .collect(),
+            )
+        })
+        .unwrap_or_default();
+    let excerpt: Vec<CleanMessage> = messages
+        .into_iter()
+        .filter(|message| window.contains(&message.guid))
+        .collect();
+    let excerpt_count = excerpt.len();
+
+    let html_options = HtmlOptions {
+        split_by: None,
+        timeline: false,
+        inline_attachments: true,
+        ..args.html_options()?
+    };
+    let html = HtmlOutput::new(excerpt, html_options).excerpt_html(&target_guid);
+    std::fs::write(&output, html)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    println!(
+        "Wrote {} message(s) around {} to {}",
+        excerpt_count,
+        target_guid,
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExportError::exit_code_of(&e)
+        }
+    }
+}
+
+/// Prints a line about the run's progress: to stdout, or to stderr with `--print-summary`
+/// so stdout holds only the summary's JSON
+macro_rules! progress {
+    ($args:expr, $($arg:tt)*) => {
+        if $args.print_summary {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
 }
 
-fn main() -> Result<()> {
+fn run() -> Result<()> {
+    let started = Instant::now();
     let args = Args::parse_args_default_or_exit();
 
-    let database_path = args.database_path();
+    match &args.command {
+        Some(Command::Verify(verify_args)) => return verify_export(&args, verify_args),
+        Some(Command::Diff(diff_args)) => return diff_exports(diff_args),
+        Some(Command::ListChats(_)) => return list_chats(&args),
+        Some(Command::Stats(stats_args)) => return print_stats(&args, stats_args),
+        Some(Command::Excerpt(excerpt_args)) => return write_excerpt(&args, excerpt_args),
+        None => {}
+    }
+
+    if args.attachments_only && args.format.is_some() {
+        return Err(ExportError::InvalidArguments(
+            "--attachments-only writes no transcripts, so it can't be combined with --format"
+                .to_string(),
+        )
+        .into());
+    }
+
+    if args.layout == Some(Layout::ImessageExporter) {
+        if !matches!(
+            args.format,
+            None | Some(OutputFormat::Html | OutputFormat::Txt)
+        ) {
+            return Err(ExportError::InvalidArguments(
+                "--layout applies to --format html and txt only".to_string(),
+            )
+            .into());
+        }
+        if args.split_by.is_some() || args.timeline {
+            return Err(ExportError::InvalidArguments(
+                "--layout imessage-exporter writes one page per chat and no index, so it can't be combined with --split-by or --timeline"
+                    .to_string(),
+            )
+            .into());
+        }
+    }
+
+    let chunk_size = args.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+    let chunk_overlap = args.chunk_overlap.unwrap_or(DEFAULT_CHUNK_OVERLAP);
+    if chunk_size == 0 || chunk_overlap >= chunk_size {
+        return Err(ExportError::InvalidArguments(format!(
+            "--chunk-overlap ({}) must be less than --chunk-size ({})",
+            chunk_overlap, chunk_size
+        ))
+        .into());
+    }
+
+    let bates = Bates {
+        prefix: args
+            .bates_prefix
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BATES_PREFIX.to_string()),
+        start: args.bates_start.unwrap_or(1),
+        digits: args.bates_digits.unwrap_or(DEFAULT_BATES_DIGITS),
+    };
+    // Bates numbers name the production's files
+    if !bates
+        .prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        || !(1..=20).contains(&bates.digits)
+    {
+        return Err(ExportError::InvalidArguments(
+            "--bates-prefix takes letters, digits, _ and -, and --bates-digits 1 to 20".to_string(),
+        )
+        .into());
+    }
+
+    let matrix_users = match (&args.matrix_users, args.format) {
+        (Some(path), Some(OutputFormat::Matrix)) => Some(MatrixUsers::load(
+            path,
+            args.matrix_server.clone(),
+            args.default_region(),
+        )?),
+        (None, Some(OutputFormat::Matrix)) => {
+            return Err(ExportError::InvalidArguments(
+                "--format matrix needs --matrix-users to map handles to Matrix IDs".to_string(),
+            )
+            .into());
+        }
+        _ => None,
+    };
+
+    let sources = args.database_sources()?;
+    environment::check_databases(&database_files(&sources))?;
+    if args.format == Some(OutputFormat::Jsonl) {
+        return stream_jsonl(&args, &sources);
+    }
+    let schemas = sources
+        .iter()
+        .map(|source| {
+            let path = source.database_file();
+            let db = database_connection::open_read_only(&path)?;
+            DatabaseSchema::detect(&db, &path)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for schema in &schemas {
+        progress!(
+            args,
+            "Database schema {} ({})",
+            schema.schema_version,
+            schema.os_version
+        );
+        for note in schema.notes(args.include_deleted) {
+            progress!(args, "  Note: {}", note);
+        }
+    }
     let output_directory = args.output_directory();
 
-    // Check if output directory already exists
-    if output_directory.exists() {
-        return Err(anyhow!(
-            "Output directory '{}' already exists. Please remove it or specify a different output directory with --output-directory",
+    let output_path = args.output_archive.as_ref().unwrap_or(&output_directory);
+    let is_archive = args.output_archive.is_some();
+    let _lock = ExportLock::acquire(run_state::state_path(
+        output_path,
+        is_archive,
+        LOCK_FILENAME,
+    ))?;
+
+    let run_state_path = run_state::state_path(output_path, is_archive, RUN_STATE_FILENAME);
+    let source_files: Vec<PathBuf> = sources.iter().map(DatabaseSource::stored_file).collect();
+    let current_state = RunState::current(&source_files)?;
+    let previous_state = if args.if_changed {
+        RunState::load(&run_state_path)
+    } else {
+        None
+    };
+    if let Some(previous) = &previous_state
+        && previous.is_unchanged(&current_state)
+    {
+        progress!(
+            args,
+            "No changes since the export started at {}; nothing to do",
+            previous.started
+        );
+        return Ok(());
+    }
+
+    // Check if the output already exists, unless it's an interrupted export to resume or
+    // the last scheduled run's export to replace
+    let resuming = !is_archive && OutputTarget::has_checkpoint(output_path);
+    if resuming {
+        progress!(
+            args,
+            "Resuming interrupted export in '{}'",
             output_directory.display()
-        ));
+        );
+    } else if previous_state.is_some() {
+        progress!(
+            args,
+            "The database has changed; replacing the export in '{}'",
+            output_path.display()
+        );
+        run_state::remove_export(output_path, is_archive)?;
+    } else if run_state::has_export(output_path, is_archive) {
+        return Err(ExportError::OutputExists(output_path.clone()).into());
+    }
+
+    let html_options = args.html_options()?;
+
+    // Ask for the passphrase up front rather than after a long export
+    let encryption = if args.encrypt {
+        Some(Encryption::from_args(&args.recipient)?)
+    } else {
+        None
+    };
+    if args.output_archive.is_none() && encryption.as_ref().is_some_and(Encryption::is_passphrase) {
+        // scrypt takes about a second per file, far too slow for a directory of attachments
+        return Err(ExportError::InvalidArguments(
+            "Passphrase encryption needs --output-archive; use --recipient to encrypt a directory"
+                .to_string(),
+        )
+        .into());
+    }
+
+    // Compile the OCR and speech helpers up front rather than failing after a long export
+    let text_recognizer = if args.ocr {
+        Some(TextRecognizer::new()?)
+    } else {
+        None
+    };
+    let transcriber = if args.transcribe_videos {
+        Some(Transcriber::new(
+            args.transcribe_max_size
+                .unwrap_or(transcription::DEFAULT_MAX_SIZE),
+            Duration::from_secs(
+                60 * args
+                    .transcribe_max_minutes
+                    .unwrap_or(transcription::DEFAULT_MAX_MINUTES),
+            ),
+        )?)
+    } else {
+        None
+    };
+
+    let observer: Option<Arc<dyn ExportObserver>> = args
+        .progress
+        .then(|| Arc::new(JsonLinesObserver) as Arc<dyn ExportObserver>);
+
+    let mut errors = ErrorLog::new(args.strict);
+    if let Some(observer) = &observer {
+        errors.set_observer(observer.clone());
+    }
+    let ExportMessages {
+        messages: mut chat_messages,
+        group_photos,
+        membership_changes,
+        repaired_senders,
+        sms_duplicates,
+        automated_chats,
+    } = export_messages(&args, &sources, &mut errors)?;
+    if repaired_senders > 0 {
+        progress!(
+            args,
+            "Reassigned {} message(s) to you as their sender",
+            repaired_senders
+        );
+    }
+    if sms_duplicates > 0 {
+        progress!(
+            args,
+            "Removed {} message(s) delivered again over another service",
+            sms_duplicates
+        );
+    }
+    if !automated_chats.is_empty() {
+        progress!(args, "Skipped {} automated chat(s)", automated_chats.len());
     }
 
-    let message_store = collect_messages(&args)?;
+    AttachmentFilter {
+        max_size: args.max_attachment_size,
+        skip_videos: args.skip_videos,
+    }
+    .apply(&mut chat_messages);
 
-    // Collect messages for all chats
-    let chat_messages: Vec<_> = message_store.drain_to_sorted_vector();
+    if let Some(recognizer) = &text_recognizer {
+        let recognized = ocr::recognize_attachments(&mut chat_messages, recognizer)?;
+        progress!(args, "Recognized text in {} image(s)", recognized);
+    }
+    if let Some(transcriber) = &transcriber {
+        let transcribed = transcription::transcribe_attachments(&mut chat_messages, transcriber)?;
+        progress!(args, "Transcribed {} video(s)", transcribed);
+    }
 
-    // Generate HTML output (which will also save attachments)
+    let fallback_count = chat_messages
+        .iter()
+        .filter(|message| message.text_fallback.is_some())
+        .count();
+
+    let mut summary = None;
     if !chat_messages.is_empty() {
-        let html_generator = HtmlOutput::new(chat_messages, database_path);
-        html_generator.generate(output_directory.to_str().unwrap())?;
+        let manifest_sources = sources
+            .iter()
+            .map(|source| ManifestSource::from_database(&source.stored_file()))
+            .collect::<Result<Vec<_>>>()?;
+        let manifest_chats = ManifestChat::from_messages(&chat_messages);
+        let attachment_counts = AttachmentCounts::from_messages(&chat_messages);
+        let manifest_text = ManifestRecognizedText::from_messages(&chat_messages, |message| {
+            &message.recognized_text
+        });
+        let manifest_transcripts =
+            ManifestRecognizedText::from_messages(&chat_messages, |message| &message.transcripts);
+
+        let mut output = match &args.output_archive {
+            Some(archive) => OutputTarget::archive(archive, encryption)?,
+            None => OutputTarget::directory(&output_directory, encryption)?,
+        };
+        if let Some(observer) = observer {
+            output.set_observer(observer);
+        }
+
+        if args.search_db {
+            let search_db = search_index::build(&chat_messages)?;
+            output.write(SEARCH_DB_FILENAME, &search_db)?;
+        }
+        if args.wordcloud {
+            let chats: Vec<(String, Frequencies)> =
+                message_store::group_messages_by_chat(&chat_messages)
+                    .into_iter()
+                    .map(|(chat_key, messages)| {
+                        let messages = messages.into_iter().filter(|m| m.call.is_none());
+                        (chat_key, Frequencies::of(messages))
+                    })
+                    .collect();
+            let (json, csv) = word_frequency::wordcloud_files(&chats)?;
+            output.write(WORDCLOUD_JSON_FILENAME, json.as_bytes())?;
+            output.write(WORDCLOUD_CSV_FILENAME, csv.as_bytes())?;
+        }
+
+        match args.format.unwrap_or_default() {
+            _ if args.attachments_only => {
+                let attachment_export = AttachmentExport::new(
+                    chat_messages,
+                    args.attachment_timestamps.unwrap_or_default(),
+                    args.strip_metadata,
+                );
+                attachment_export.generate(&mut output, &mut errors)?;
+            }
+            OutputFormat::Html => {
+                let attachments = args
+                    .attachment_store()
+                    .with_thumbnails(!args.no_thumbnails)
+                    .with_audio_waveforms(args.audio_waveforms)
+                    .extract(&chat_messages, &mut output, &mut errors)?;
+                let html_generator = HtmlOutput::new(chat_messages, html_options)
+                    .with_group_photos(group_photos)
+                    .with_membership_changes(membership_changes)
+                    .with_attachments(attachments);
+                html_generator.generate(&mut output)?;
+            }
+            OutputFormat::Txt => {
+                let attachments =
+                    args.attachment_store()
+                        .extract(&chat_messages, &mut output, &mut errors)?;
+                let txt_generator =
+                    TxtOutput::new(chat_messages, args.layout.unwrap_or_default(), attachments);
+                txt_generator.generate(&mut output)?;
+            }
+            OutputFormat::Mbox => {
+                let mbox_generator = MboxOutput::new(chat_messages, args.strip_metadata);
+                mbox_generator.generate(&mut output, &mut errors)?;
+            }
+            OutputFormat::Epub => {
+                let epub_generator = EpubOutput::new(chat_messages, args.epub_per_year);
+                epub_generator.generate(&mut output)?;
+            }
+            OutputFormat::Obsidian => {
+                let obsidian_generator = ObsidianOutput::new(
+                    chat_messages,
+                    args.attachment_timestamps.unwrap_or_default(),
+                    args.strip_metadata,
+                );
+                obsidian_generator.generate(&mut output, &mut errors)?;
+            }
+            OutputFormat::DayOne => {
+                let day_one_generator = DayOneOutput::new(chat_messages, args.strip_metadata);
+                day_one_generator.generate(&mut output, &mut errors)?;
+            }
+            OutputFormat::Matrix => {
+                let users = matrix_users.expect("--format matrix loads --matrix-users");
+                let matrix_generator = MatrixOutput::new(chat_messages, users, args.strip_metadata);
+                matrix_generator.generate(&mut output, &mut errors)?;
+            }
+            OutputFormat::Corpus => {
+                let corpus_generator = CorpusOutput::new(chat_messages, chunk_size, chunk_overlap);
+                corpus_generator.generate(&mut output)?;
+            }
+            OutputFormat::Parquet => {
+                let parquet_generator = ParquetOutput::new(chat_messages);
+                parquet_generator.generate(&mut output)?;
+            }
+            OutputFormat::LoadFile => {
+                let load_file_generator =
+                    LoadFileOutput::new(chat_messages, bates, args.strip_metadata);
+                load_file_generator.generate(&mut output, &mut errors)?;
+            }
+            // Streamed by stream_jsonl before any messages are collected
+            OutputFormat::Jsonl => unreachable!("--format jsonl is streamed"),
+        }
+
+        if !errors.is_empty() {
+            output.write(ERRORS_FILENAME, errors.to_log().as_bytes())?;
+        }
+        let run_summary = RunSummary::new(
+            &manifest_chats,
+            schemas.clone(),
+            attachment_counts,
+            errors.len(),
+            started.elapsed(),
+            output.files(),
+            automated_chats.clone(),
+            sms_duplicates,
+            fallback_count,
+        );
+        output.write(SUMMARY_FILENAME, run_summary.to_json()?.as_bytes())?;
+        summary = Some(run_summary);
+
+        let mut manifest = Manifest::new(
+            manifest_sources,
+            manifest_chats,
+            manifest_text,
+            manifest_transcripts,
+            output.files(),
+        );
+        if args.merkle_root {
+            manifest.merkle_root = Some(signing::merkle_root(&manifest.files));
+        }
+        let manifest_json = manifest.to_json()?;
+        output.write(MANIFEST_FILENAME, manifest_json.as_bytes())?;
+        if let Some(key_id) = &args.sign_key {
+            let signature = signing::sign(manifest_json.as_bytes(), key_id)?;
+            output.write(SIGNATURE_FILENAME, &signature)?;
+        }
+
+        output.finish()?;
+        current_state.save(&run_state_path)?;
+    }
+
+    if fallback_count > 0 {
+        progress!(
+            args,
+            "{} message(s) had no readable text and were exported with fallback text",
+            fallback_count
+        );
+    }
+    if args.print_summary {
+        let summary = summary.unwrap_or_else(|| RunSummary {
+            warnings: errors.len(),
+            duration_seconds: started.elapsed().as_secs_f64(),
+            automated_chats,
+            sms_duplicates,
+            text_fallbacks: fallback_count,
+            databases: schemas,
+            ..RunSummary::default()
+        });
+        println!("{}", serde_json::to_string(&summary)?);
+    }
+    if !errors.is_empty() {
+        return Err(ExportError::PartialFailure {
+            count: errors.len(),
+            log: Some(PathBuf::from(ERRORS_FILENAME)),
+        }
+        .into());
     }
 
     Ok(())
@@ -0,0 +1,195 @@
+use crate::clean_message::CleanMessage;
+use crate::html_output::attachment_filenames;
+use crate::message_store::group_messages_by_chat;
+use anyhow::{Context, Result, bail};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Name of the manifest written at the root of every export
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// Provenance and contents of an export: where it came from, how it was made, and a
+/// checksum of every file, so an archive can be reproduced and checked later.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub tool_version: String,
+    /// RFC 3339 time the export was generated
+    pub generated_at: String,
+    /// Databases the export was read from, merged in this order
+    pub sources: Vec<ManifestSource>,
+    /// Command line the export was run with
+    pub arguments: Vec<String>,
+    pub chats: Vec<ManifestChat>,
+    /// Text `--ocr` recognized in image attachments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recognized_text: Vec<ManifestRecognizedText>,
+    /// Speech `--transcribe-videos` recognized in video attachments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transcripts: Vec<ManifestRecognizedText>,
+    pub files: Vec<ManifestFile>,
+    /// `--merkle-root` over `files`, so one hash stands for every file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+}
+
+/// A chat.db the export was read from
+#[derive(Serialize, Deserialize)]
+pub struct ManifestSource {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestChat {
+    pub name: String,
+    pub message_count: usize,
+    pub first_message: Option<String>,
+    pub last_message: Option<String>,
+    /// GUIDs of the exported messages, in timeline order
+    pub messages: Vec<String>,
+}
+
+/// Text found in an image attachment, or speech in a video
+#[derive(Serialize, Deserialize)]
+pub struct ManifestRecognizedText {
+    /// GUID of the message the image was attached to
+    pub message: String,
+    /// File name the attachment was saved under
+    pub attachment: String,
+    pub text: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Path relative to the root of the export
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn new(
+        sources: Vec<ManifestSource>,
+        chats: Vec<ManifestChat>,
+        recognized_text: Vec<ManifestRecognizedText>,
+        transcripts: Vec<ManifestRecognizedText>,
+        files: &[ManifestFile],
+    ) -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at: Local::now().to_rfc3339(),
+            sources,
+            arguments: std::env::args().skip(1).collect(),
+            chats,
+            recognized_text,
+            transcripts,
+            files: files.to_vec(),
+            merkle_root: None,
+        }
+    }
+
+    /// Loads the manifest of an export directory, or a manifest file given directly
+    pub fn load(path: &Path) -> Result<Self> {
+        let manifest_path = if path.is_dir() {
+            path.join(MANIFEST_FILENAME)
+        } else {
+            path.to_path_buf()
+        };
+        if !manifest_path.exists() {
+            if path.join(format!("{}.age", MANIFEST_FILENAME)).exists() {
+                bail!("{} is encrypted; decrypt it first", path.display());
+            }
+            bail!(
+                "No {} in {}; only exported directories have a readable manifest",
+                MANIFEST_FILENAME,
+                path.display()
+            );
+        }
+
+        let contents = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", manifest_path.display()))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl ManifestSource {
+    /// Records the path, size and SHA-256 of the source database
+    pub fn from_database(database_path: &Path) -> Result<Self> {
+        let mut file = File::open(database_path)
+            .with_context(|| format!("Failed to open {}", database_path.display()))?;
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut file, &mut hasher)?;
+
+        Ok(Self {
+            path: database_path.display().to_string(),
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+impl ManifestChat {
+    /// Summarizes each chat in the export, sorted by name
+    pub fn from_messages(messages: &[CleanMessage]) -> Vec<Self> {
+        let mut chats: Vec<Self> = group_messages_by_chat(messages)
+            .into_iter()
+            .map(|(name, messages)| Self {
+                name,
+                message_count: messages.len(),
+                first_message: messages.first().map(|m| m.date.to_rfc3339()),
+                last_message: messages.last().map(|m| m.date.to_rfc3339()),
+                messages: messages.iter().map(|m| m.guid.clone()).collect(),
+            })
+            .collect();
+        chats.sort_by(|a, b| a.name.cmp(&b.name));
+        chats
+    }
+}
+
+impl ManifestRecognizedText {
+    /// The text `text_of` a message holds for its attachments, by attachment ROWID, in
+    /// timeline order
+    pub fn from_messages(
+        messages: &[CleanMessage],
+        text_of: impl Fn(&CleanMessage) -> &HashMap<i32, String>,
+    ) -> Vec<Self> {
+        let text_of = &text_of;
+        messages
+            .iter()
+            .filter(|message| !text_of(message).is_empty())
+            .flat_map(|message| {
+                message
+                    .attachments
+                    .iter()
+                    .zip(attachment_filenames(message))
+                    .filter_map(move |(attachment, filename)| {
+                        Some(Self {
+                            message: message.guid.clone(),
+                            attachment: filename?,
+                            text: text_of(message).get(&attachment.rowid)?.clone(),
+                        })
+                    })
+            })
+            .collect()
+    }
+}
+
+impl ManifestFile {
+    pub fn new(path: &str, bytes: &[u8]) -> Self {
+        Self {
+            path: path.to_string(),
+            size: bytes.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(bytes)),
+        }
+    }
+}
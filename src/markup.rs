@@ -0,0 +1,100 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+/// Escapes text for use in HTML content and attribute values
+pub fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// `value` in double quotes, with its backslashes and quotes escaped, as YAML and email
+/// headers take it
+pub fn double_quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `value` quoted for CSV when it has a comma, quote or line break
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The fields of a CSV line, with quoted fields unquoted, so `"Smith, Jones",x` is two
+/// fields; `None` when a quote is left open
+pub fn csv_fields(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+/// `bytes` as a `data:` URI, for embedding a file in a page
+pub fn data_uri(mime_type: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", mime_type, BASE64.encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_escape() {
+        assert_eq!(
+            html_escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_double_quoted() {
+        assert_eq!(double_quoted(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("Family"), "Family");
+        assert_eq!(csv_field("Smith, Jones"), "\"Smith, Jones\"");
+    }
+
+    #[test]
+    fn test_csv_fields() {
+        assert_eq!(
+            csv_fields(r#"chat1,"Alice, Bob & 2 others""#),
+            Some(vec![
+                "chat1".to_string(),
+                "Alice, Bob & 2 others".to_string()
+            ])
+        );
+        assert_eq!(
+            csv_fields(r#""say ""hi""",x"#),
+            Some(vec![r#"say "hi""#.to_string(), "x".to_string()])
+        );
+        assert_eq!(csv_fields(r#"a,"open"#), None);
+    }
+}
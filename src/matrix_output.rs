@@ -0,0 +1,324 @@
+use crate::attachment_store::read_for_export;
+use crate::clean_attachment::MediaKind;
+use crate::clean_message::CleanMessage;
+use crate::contacts::normalize_identifier;
+use crate::error::ExportError;
+use crate::error_log::ErrorLog;
+use crate::events::ExportEvent;
+use crate::filename::{FilenameAllocator, chat_stems};
+use crate::mbox_output::message_body;
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::output_target::OutputTarget;
+use crate::resolved_handle::ResolvedHandle;
+use anyhow::{Context, Result};
+use phonenumber::country;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+const ROOMS_FOLDER: &str = "rooms";
+const MEDIA_FOLDER: &str = "media";
+
+/// Key of my own Matrix ID in the `--matrix-users` file
+const ME_KEY: &str = "me";
+
+/// Prefix of the localpart given to handles the `--matrix-users` file doesn't list
+const PUPPET_PREFIX: &str = "imessage_";
+
+/// Matrix IDs for the people in the export, from the `--matrix-users` TOML file of
+/// `"identifier" = "@user:server"` lines (and `me = "@me:server"` for my own messages).
+/// Handles it doesn't list get a `@imessage_<handle>:<server>` ID when `--matrix-server`
+/// is given, for an appservice to register as puppets.
+pub struct MatrixUsers {
+    me: String,
+    users: HashMap<String, String>,
+    server: Option<String>,
+    default_region: country::Id,
+}
+
+impl MatrixUsers {
+    pub fn load(path: &Path, server: Option<String>, default_region: country::Id) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read Matrix user file {}", path.display()))?;
+        Self::parse(&contents, server, default_region)
+    }
+
+    fn parse(contents: &str, server: Option<String>, default_region: country::Id) -> Result<Self> {
+        let mut raw: HashMap<String, String> =
+            toml::from_str(contents).context("Failed to parse Matrix user TOML")?;
+
+        if let Some(mxid) = raw.values().find(|mxid| !is_mxid(mxid)) {
+            return Err(ExportError::InvalidArguments(format!(
+                "'{}' in the Matrix user file is not a Matrix ID like @user:example.org",
+                mxid
+            ))
+            .into());
+        }
+        let me = raw.remove(ME_KEY).ok_or_else(|| {
+            ExportError::InvalidArguments(format!(
+                "The Matrix user file needs a {} = \"@user:server\" line for your own messages",
+                ME_KEY
+            ))
+        })?;
+        let users = raw
+            .into_iter()
+            .map(|(identifier, mxid)| (normalize_identifier(&identifier, default_region), mxid))
+            .collect();
+
+        Ok(Self {
+            me,
+            users,
+            server,
+            default_region,
+        })
+    }
+
+    /// The Matrix ID `handle`'s messages are sent as
+    fn mxid(&self, handle: &ResolvedHandle) -> Result<String> {
+        if handle.is_me() {
+            return Ok(self.me.clone());
+        }
+        let identifier = handle.identifier().unwrap_or("unknown");
+        if let Some(mxid) = self
+            .users
+            .get(&normalize_identifier(identifier, self.default_region))
+        {
+            return Ok(mxid.clone());
+        }
+        match &self.server {
+            Some(server) => Ok(format!(
+                "@{}{}:{}",
+                PUPPET_PREFIX,
+                localpart(identifier),
+                server
+            )),
+            None => Err(ExportError::InvalidArguments(format!(
+                "{} ({}) has no Matrix ID in the Matrix user file; add one or give --matrix-server",
+                handle, identifier
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Writes one JSON file per chat under `rooms/` describing a Matrix room to create (its
+/// name, members and whether it's a direct chat) and the `m.room.message` events to
+/// send into it, oldest first, with attachments under `media/` for the importer to
+/// upload before sending the event that shows them. Timestamps are the original send
+/// times, for an appservice to pass as `ts` so the history keeps its dates.
+pub struct MatrixOutput {
+    messages: Vec<CleanMessage>,
+    users: MatrixUsers,
+    strip_metadata: bool,
+}
+
+#[derive(Serialize)]
+struct Room {
+    name: String,
+    is_direct: bool,
+    /// Everyone to invite, me first
+    members: Vec<Member>,
+    events: Vec<Event>,
+}
+
+#[derive(Serialize)]
+struct Member {
+    user_id: String,
+    displayname: String,
+}
+
+#[derive(Serialize)]
+struct Event {
+    #[serde(rename = "type")]
+    event_type: &'static str,
+    sender: String,
+    /// Milliseconds since the Unix epoch
+    origin_server_ts: i64,
+    /// Unique per event, so an importer that's rerun doesn't send it twice
+    txn_id: String,
+    content: Content,
+    /// File under `media/` to upload and set as `content.url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    media_file: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Content {
+    msgtype: &'static str,
+    body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    info: Option<MediaInfo>,
+}
+
+#[derive(Serialize)]
+struct MediaInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mimetype: Option<String>,
+    size: usize,
+}
+
+impl MatrixOutput {
+    pub fn new(messages: Vec<CleanMessage>, users: MatrixUsers, strip_metadata: bool) -> Self {
+        Self {
+            messages,
+            users,
+            strip_metadata,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget, errors: &mut ErrorLog) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+        let chat_stems = chat_stems(grouped_messages.keys());
+
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            let stem = &chat_stems[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+
+            let mut members = BTreeMap::new();
+            let mut events = Vec::new();
+            let mut media = FilenameAllocator::default();
+            for message in chat_messages {
+                let sender = self.users.mxid(&message.from)?;
+                members
+                    .entry(sender.clone())
+                    .or_insert_with(|| message.from.to_string());
+
+                let body = message_body(message);
+                if !body.is_empty() {
+                    events.push(Event {
+                        event_type: "m.room.message",
+                        sender: sender.clone(),
+                        origin_server_ts: message.date.timestamp_millis(),
+                        txn_id: format!("imessage-{}", message.guid),
+                        content: Content {
+                            msgtype: if message.call.is_some() {
+                                "m.notice"
+                            } else {
+                                "m.text"
+                            },
+                            body,
+                            info: None,
+                        },
+                        media_file: None,
+                    });
+                }
+
+                for attachment in &message.attachments {
+                    let Some(filename) = attachment.filename.as_deref() else {
+                        continue;
+                    };
+                    let path = format!("{}/{}/{}", MEDIA_FOLDER, stem, media.file(filename));
+                    let Some(bytes) = read_for_export(attachment, self.strip_metadata, errors)?
+                    else {
+                        continue;
+                    };
+                    output.write(&path, &bytes)?;
+
+                    events.push(Event {
+                        event_type: "m.room.message",
+                        sender: sender.clone(),
+                        origin_server_ts: message.date.timestamp_millis(),
+                        txn_id: format!("imessage-{}-{}", message.guid, attachment.rowid),
+                        content: Content {
+                            msgtype: match attachment.kind {
+                                MediaKind::Image => "m.image",
+                                MediaKind::Video => "m.video",
+                                MediaKind::Audio => "m.audio",
+                                MediaKind::Other => "m.file",
+                            },
+                            body: filename.to_string(),
+                            info: Some(MediaInfo {
+                                mimetype: attachment.mime_type.clone(),
+                                size: bytes.len(),
+                            }),
+                        },
+                        media_file: Some(path),
+                    });
+                }
+            }
+
+            // Me first, as the room's creator, then everyone else by Matrix ID
+            let me = members
+                .remove_entry(&self.users.me)
+                .unwrap_or_else(|| (self.users.me.clone(), "Me".to_string()));
+            let room = Room {
+                name: display_name(chat_key).to_string(),
+                is_direct: chat_key.starts_with("Direct: "),
+                members: std::iter::once(me)
+                    .chain(members)
+                    .map(|(user_id, displayname)| Member {
+                        user_id,
+                        displayname,
+                    })
+                    .collect(),
+                events,
+            };
+            output.write(
+                &format!("{}/{}.json", ROOMS_FOLDER, stem),
+                serde_json::to_string_pretty(&room)?.as_bytes(),
+            )?;
+
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `value` looks like a Matrix user ID, `@localpart:server`
+fn is_mxid(value: &str) -> bool {
+    value
+        .strip_prefix('@')
+        .and_then(|rest| rest.split_once(':'))
+        .is_some_and(|(localpart, server)| !localpart.is_empty() && !server.is_empty())
+}
+
+/// `identifier` as a Matrix localpart: lowercase, with any character Matrix doesn't
+/// allow written as `=xx`, the escaping the Matrix spec suggests for mapping other IDs
+fn localpart(identifier: &str) -> String {
+    let mut localpart = String::new();
+    for c in identifier.to_lowercase().chars() {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-' | '/') {
+            localpart.push(c);
+        } else {
+            let mut buffer = [0; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                localpart.push_str(&format!("={:02x}", byte));
+            }
+        }
+    }
+    localpart
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_localpart() {
+        assert_eq!(localpart("+15555550100"), "=2b15555550100");
+        assert_eq!(localpart("Alex@Example.com"), "alex=40example.com");
+    }
+
+    #[test]
+    fn test_parse_users() {
+        let users = MatrixUsers::parse(
+            "me = \"@dana:example.org\"\n\"alex@example.com\" = \"@alex:example.org\"\n",
+            None,
+            country::Id::US,
+        )
+        .unwrap();
+        assert_eq!(users.me, "@dana:example.org");
+        assert_eq!(users.users["alex@example.com"], "@alex:example.org");
+
+        assert!(MatrixUsers::parse("me = \"dana\"\n", None, country::Id::US).is_err());
+        assert!(MatrixUsers::parse("\"a@b.c\" = \"@a:b.c\"\n", None, country::Id::US).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::attachment_store::read_for_export;
+use crate::clean_message::CleanMessage;
+use crate::error_log::ErrorLog;
+use crate::events::ExportEvent;
+use crate::filename::chat_stems;
+use crate::markup::double_quoted;
+use crate::message_store::group_messages_by_chat;
+use crate::output_target::OutputTarget;
+use crate::resolved_handle::ResolvedHandle;
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::fmt::Write;
+
+/// Domain used to turn handles that aren't email addresses into mailbox addresses
+const PLACEHOLDER_DOMAIN: &str = "imessage.invalid";
+
+/// Longest line of base64 in a MIME part, per RFC 2045
+const BASE64_LINE_LENGTH: usize = 76;
+
+/// Writes each chat as an mbox mailbox of RFC 2822 messages so the export can be
+/// imported into mail clients, mail archivers and eDiscovery tools.
+pub struct MboxOutput {
+    messages: Vec<CleanMessage>,
+    /// Remove EXIF, GPS and device metadata from attached files
+    strip_metadata: bool,
+}
+
+impl MboxOutput {
+    pub fn new(messages: Vec<CleanMessage>, strip_metadata: bool) -> Self {
+        Self {
+            messages,
+            strip_metadata,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget, errors: &mut ErrorLog) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let chat_stems = chat_stems(grouped_messages.keys());
+
+        for (chat_key, chat_messages) in &grouped_messages {
+            let is_group = !chat_key.starts_with("Direct: ");
+            let subdir = if is_group { "groups" } else { "direct" };
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+            let mbox = self.build_mbox(chat_key, chat_messages, errors)?;
+            let output_path = format!("{}/{}.mbox", subdir, chat_stems[chat_key]);
+            output.write(&output_path, mbox.as_bytes())?;
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        Ok(())
+    }
+
+    fn build_mbox(
+        &self,
+        chat_name: &str,
+        messages: &[&CleanMessage],
+        errors: &mut ErrorLog,
+    ) -> Result<String> {
+        // Everyone other than me who sent something to this chat
+        let mut participants: Vec<&ResolvedHandle> = Vec::new();
+        for message in messages {
+            if !message.from.is_me() && !participants.contains(&&message.from) {
+                participants.push(&message.from);
+            }
+        }
+
+        let mut mbox = String::new();
+        for message in messages {
+            let recipients: Vec<String> = if message.from.is_me() {
+                participants.iter().map(|h| mailbox(h)).collect()
+            } else {
+                std::iter::once(me_mailbox())
+                    .chain(
+                        participants
+                            .iter()
+                            .filter(|h| **h != &message.from)
+                            .map(|h| mailbox(h)),
+                    )
+                    .collect()
+            };
+
+            self.write_message(&mut mbox, chat_name, message, &recipients, errors)?;
+        }
+
+        Ok(mbox)
+    }
+
+    fn write_message(
+        &self,
+        mbox: &mut String,
+        chat_name: &str,
+        message: &CleanMessage,
+        recipients: &[String],
+        errors: &mut ErrorLog,
+    ) -> Result<()> {
+        let from = if message.from.is_me() {
+            me_mailbox()
+        } else {
+            mailbox(&message.from)
+        };
+
+        // mbox separator line, then the RFC 2822 headers
+        writeln!(
+            mbox,
+            "From {} {}",
+            addr_spec(&message.from),
+            message.date.format("%a %b %e %H:%M:%S %Y")
+        )?;
+        writeln!(mbox, "From: {}", from)?;
+        if !recipients.is_empty() {
+            writeln!(mbox, "To: {}", recipients.join(", "))?;
+        }
+        writeln!(mbox, "Date: {}", message.date.to_rfc2822())?;
+        writeln!(mbox, "Subject: {}", encode_header(chat_name))?;
+        writeln!(
+            mbox,
+            "Message-ID: <{}@{}>",
+            message.guid, PLACEHOLDER_DOMAIN
+        )?;
+        writeln!(mbox, "X-iMessage-Service: {}", message.service)?;
+        writeln!(mbox, "MIME-Version: 1.0")?;
+
+        let body = quote_from_lines(&message_body(message));
+        let attachments: Vec<_> = message
+            .attachments
+            .iter()
+            .filter_map(|attachment| {
+                attachment
+                    .filename
+                    .as_deref()
+                    .map(|name| (attachment, name))
+            })
+            .collect();
+
+        if attachments.is_empty() {
+            writeln!(mbox, "Content-Type: text/plain; charset=utf-8")?;
+            writeln!(mbox, "Content-Transfer-Encoding: 8bit")?;
+            writeln!(mbox)?;
+            writeln!(mbox, "{}", body)?;
+            writeln!(mbox)?;
+            return Ok(());
+        }
+
+        let boundary = format!("=_{}", message.guid);
+        writeln!(
+            mbox,
+            "Content-Type: multipart/mixed; boundary=\"{}\"",
+            boundary
+        )?;
+        writeln!(mbox)?;
+        writeln!(mbox, "--{}", boundary)?;
+        writeln!(mbox, "Content-Type: text/plain; charset=utf-8")?;
+        writeln!(mbox, "Content-Transfer-Encoding: 8bit")?;
+        writeln!(mbox)?;
+        writeln!(mbox, "{}", body)?;
+
+        for (attachment, filename) in attachments {
+            let Some(bytes) = read_for_export(attachment, self.strip_metadata, errors)? else {
+                continue;
+            };
+
+            let mime_type = attachment
+                .mime_type
+                .as_deref()
+                .unwrap_or("application/octet-stream");
+            let filename = encode_header(filename);
+
+            writeln!(mbox, "--{}", boundary)?;
+            writeln!(mbox, "Content-Type: {}; name=\"{}\"", mime_type, filename)?;
+            writeln!(
+                mbox,
+                "Content-Disposition: attachment; filename=\"{}\"",
+                filename
+            )?;
+            writeln!(mbox, "Content-Transfer-Encoding: base64")?;
+            writeln!(mbox)?;
+            let encoded = BASE64.encode(&bytes);
+            for line in encoded.as_bytes().chunks(BASE64_LINE_LENGTH) {
+                writeln!(mbox, "{}", String::from_utf8_lossy(line))?;
+            }
+        }
+
+        writeln!(mbox, "--{}--", boundary)?;
+        writeln!(mbox)?;
+        Ok(())
+    }
+}
+
+/// Plain-text body of a message, falling back to a description for calls and
+/// unsent messages, which have no text of their own
+pub fn message_body(message: &CleanMessage) -> String {
+    if let Some(call) = &message.call {
+        return call.description();
+    }
+    if message.unsent {
+        return "(unsent message)".to_string();
+    }
+
+    // Attachments are marked with U+FFFC in the text; they become MIME parts instead
+    let mut body = message.text.replace('\u{FFFC}', "").trim().to_string();
+    for skipped in &message.skipped_attachments {
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body.push_str(&format!("[{}]", skipped.description()));
+    }
+    body
+}
+
+/// mboxrd escaping: body lines starting with "From " (after any '>'s) get one more '>'
+fn quote_from_lines(body: &str) -> String {
+    body.lines()
+        .map(|line| {
+            if line.trim_start_matches('>').starts_with("From ") {
+                format!(">{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn me_mailbox() -> String {
+    format!("Me <me@{}>", PLACEHOLDER_DOMAIN)
+}
+
+/// `"Display Name" <address>` for a handle
+fn mailbox(handle: &ResolvedHandle) -> String {
+    format!(
+        "{} <{}>",
+        encode_display_name(&handle.to_string()),
+        addr_spec(handle)
+    )
+}
+
+/// Email address for a handle. Phone numbers become `+15555550100@imessage.invalid`.
+fn addr_spec(handle: &ResolvedHandle) -> String {
+    if handle.is_me() {
+        return format!("me@{}", PLACEHOLDER_DOMAIN);
+    }
+
+    match handle.identifier() {
+        Some(identifier) if identifier.contains('@') => identifier.to_string(),
+        Some(identifier) => {
+            let local_part: String = identifier
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '+')
+                .collect();
+            format!("{}@{}", local_part, PLACEHOLDER_DOMAIN)
+        }
+        None => format!("unknown@{}", PLACEHOLDER_DOMAIN),
+    }
+}
+
+fn encode_display_name(name: &str) -> String {
+    if name.is_ascii() {
+        double_quoted(name)
+    } else {
+        encode_header(name)
+    }
+}
+
+/// RFC 2047 encoded word for header values that aren't plain ASCII
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", BASE64.encode(value))
+    }
+}
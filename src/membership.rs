@@ -0,0 +1,156 @@
+use crate::clean_message::CleanMessage;
+use crate::contacts::ContactMap;
+use crate::recently_deleted::apple_timestamp_to_local;
+use crate::resolved_handle::ResolvedHandle;
+use crate::text_fallback;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use imessage_database::tables::table::{CHAT, CHAT_MESSAGE_JOIN, MESSAGE};
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+/// `item_type` of messages that add someone to or remove someone from a group chat
+const MEMBER_CHANGE_ITEM_TYPE: i32 = 1;
+/// `item_type` of group changes, which include someone leaving on their own
+const GROUP_CHANGE_ITEM_TYPE: i32 = 3;
+
+/// Someone joining or leaving a group chat, as recorded by its system messages
+#[derive(Clone, Debug)]
+pub struct MembershipChange {
+    pub date: DateTime<Local>,
+    /// Contact name of who joined or left, "Me" for me
+    pub member: String,
+    pub joined: bool,
+}
+
+/// Reads every group chat's membership changes, keyed by chat GUID
+pub fn membership_changes(
+    db: &Connection,
+    handle_cache: &HashMap<i32, String>,
+    contact_map: &ContactMap,
+) -> Result<HashMap<String, Vec<MembershipChange>>> {
+    let mut statement = db.prepare(&format!(
+        "SELECT c.guid, m.date, m.item_type, COALESCE(m.group_action_type, 0),
+                COALESCE(m.handle_id, 0), COALESCE(m.other_handle, 0), COALESCE(m.is_from_me, 0)
+         FROM {} m
+         JOIN {} j ON j.message_id = m.ROWID
+         JOIN {} c ON c.ROWID = j.chat_id
+         WHERE m.item_type IN ({}, {})",
+        MESSAGE, CHAT_MESSAGE_JOIN, CHAT, MEMBER_CHANGE_ITEM_TYPE, GROUP_CHANGE_ITEM_TYPE
+    ))?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i32>(2)?,
+            row.get::<_, i32>(3)?,
+            row.get::<_, i32>(4)?,
+            row.get::<_, i32>(5)?,
+            row.get::<_, bool>(6)?,
+        ))
+    })?;
+
+    let name = |handle_id: i32| {
+        if handle_cache
+            .get(&handle_id)
+            .is_some_and(|identifier| contact_map.is_me(identifier))
+        {
+            "Me".to_string()
+        } else {
+            ResolvedHandle::resolve_handle_to_name(&handle_id, handle_cache, contact_map)
+        }
+    };
+
+    let database_tz_offset = imessage_database::util::dates::get_offset();
+    let mut changes: HashMap<String, Vec<MembershipChange>> = HashMap::new();
+    for row in rows {
+        let (chat_guid, date, item_type, action, handle_id, other_handle, is_from_me) = row?;
+        let Some(date) = apple_timestamp_to_local(date, database_tz_offset) else {
+            continue;
+        };
+        let (member, joined) = match (item_type, action) {
+            // Added (0) or removed (1) by another member
+            (MEMBER_CHANGE_ITEM_TYPE, 0 | 1) if other_handle != 0 => {
+                (name(other_handle), action == 0)
+            }
+            // Left the chat themselves
+            (GROUP_CHANGE_ITEM_TYPE, 0) if is_from_me => ("Me".to_string(), false),
+            (GROUP_CHANGE_ITEM_TYPE, 0) if handle_id != 0 => (name(handle_id), false),
+            _ => continue,
+        };
+        changes
+            .entry(chat_guid)
+            .or_default()
+            .push(MembershipChange {
+                date,
+                member,
+                joined,
+            });
+    }
+    Ok(changes)
+}
+
+/// One person's time in a group chat
+#[derive(Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    /// When they were first added or, failing that, first wrote
+    pub since: DateTime<Local>,
+    /// When they left, unless they're still in the chat
+    pub until: Option<DateTime<Local>>,
+    pub message_count: usize,
+}
+
+/// Everyone who was ever in a chat, in the order they joined, from its membership
+/// changes and who wrote its (date-ordered) messages. Someone whose last change is
+/// leaving, with nothing written after it, is no longer in the chat. The chat's own
+/// system messages, which have nothing to show, don't count as written.
+pub fn members(messages: &[&CleanMessage], changes: &[MembershipChange]) -> Vec<Member> {
+    let mut members: HashMap<String, Member> = HashMap::new();
+    let mut last_message: HashMap<String, DateTime<Local>> = HashMap::new();
+
+    let written = messages.iter().filter(|message| {
+        !text_fallback::is_blank(&message.text)
+            || !message.attachments.is_empty()
+            || message.balloon.is_some()
+    });
+    for message in written {
+        let name = message.from.to_string();
+        last_message.insert(name.clone(), message.date);
+        members
+            .entry(name.clone())
+            .and_modify(|member| member.message_count += 1)
+            .or_insert(Member {
+                name,
+                since: message.date,
+                until: None,
+                message_count: 1,
+            });
+    }
+
+    let mut changes: Vec<&MembershipChange> = changes.iter().collect();
+    changes.sort_by_key(|change| change.date);
+    for change in changes {
+        let member = members
+            .entry(change.member.clone())
+            .or_insert_with(|| Member {
+                name: change.member.clone(),
+                since: change.date,
+                until: None,
+                message_count: 0,
+            });
+        if change.joined {
+            member.since = member.since.min(change.date);
+            member.until = None;
+        } else if last_message
+            .get(&change.member)
+            .is_none_or(|last| *last <= change.date)
+        {
+            member.until = Some(change.date);
+        }
+    }
+
+    let mut members: Vec<Member> = members.into_values().collect();
+    members.sort_by(|a, b| a.since.cmp(&b.since).then_with(|| a.name.cmp(&b.name)));
+    members
+}
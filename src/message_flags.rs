@@ -0,0 +1,67 @@
+use anyhow::Result;
+use imessage_database::tables::table::MESSAGE;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+
+/// Status flags from a message's row that the database library doesn't expose
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MessageFlags {
+    /// A received message has been read on one of my devices
+    pub is_read: bool,
+    /// A sent message reached the recipient
+    pub is_delivered: bool,
+    /// Sending as iMessage failed and the message went as SMS instead
+    pub was_downgraded: bool,
+    /// Recorded with the microphone button rather than sent as an audio file
+    pub is_audio_message: bool,
+    /// Another message was sent as a reply to this one
+    pub has_replies: bool,
+}
+
+/// Reads every message's flags, keyed by message ROWID
+pub fn message_flags(db: &Connection) -> Result<HashMap<i32, MessageFlags>> {
+    let replied = replied_guids(db);
+
+    let mut statement = db.prepare(&format!(
+        "SELECT ROWID, guid, COALESCE(is_read, 0), COALESCE(is_delivered, 0),
+                COALESCE(was_downgraded, 0), COALESCE(is_audio_message, 0)
+         FROM {}",
+        MESSAGE
+    ))?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, i32>(0)?,
+            row.get::<_, String>(1)?,
+            MessageFlags {
+                is_read: row.get(2)?,
+                is_delivered: row.get(3)?,
+                was_downgraded: row.get(4)?,
+                is_audio_message: row.get(5)?,
+                has_replies: false,
+            },
+        ))
+    })?;
+
+    let mut flags = HashMap::new();
+    for row in rows {
+        let (rowid, guid, mut message_flags) = row?;
+        message_flags.has_replies = replied.contains(&guid);
+        flags.insert(rowid, message_flags);
+    }
+    Ok(flags)
+}
+
+/// GUIDs of messages that start a reply thread. Databases from before replies existed
+/// have no thread column, and so no replies.
+fn replied_guids(db: &Connection) -> HashSet<String> {
+    let Ok(mut statement) = db.prepare(&format!(
+        "SELECT DISTINCT thread_originator_guid FROM {} WHERE thread_originator_guid IS NOT NULL",
+        MESSAGE
+    )) else {
+        return HashSet::new();
+    };
+    statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default()
+}
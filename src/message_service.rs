@@ -0,0 +1,62 @@
+use imessage_database::tables::messages::models::Service;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageService {
+    IMessage,
+    Sms,
+    Rcs,
+    Satellite,
+    Other(String),
+    Unknown,
+}
+
+impl MessageService {
+    pub fn from_message_service(service: Service) -> Self {
+        match service {
+            Service::iMessage => MessageService::IMessage,
+            Service::SMS => MessageService::Sms,
+            Service::RCS => MessageService::Rcs,
+            Service::Satellite => MessageService::Satellite,
+            Service::Other(other) => MessageService::Other(other.to_string()),
+            Service::Unknown => MessageService::Unknown,
+        }
+    }
+
+    /// CSS class for the bubble color: blue for iMessage, green for carrier messages
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            MessageService::Sms | MessageService::Rcs => "service-sms",
+            _ => "service-imessage",
+        }
+    }
+}
+
+impl std::str::FromStr for MessageService {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "imessage" => Ok(MessageService::IMessage),
+            "sms" => Ok(MessageService::Sms),
+            "rcs" => Ok(MessageService::Rcs),
+            "satellite" => Ok(MessageService::Satellite),
+            _ => Err(format!(
+                "unknown service '{}' (expected imessage, sms, rcs or satellite)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for MessageService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageService::IMessage => write!(f, "iMessage"),
+            MessageService::Sms => write!(f, "SMS"),
+            MessageService::Rcs => write!(f, "RCS"),
+            MessageService::Satellite => write!(f, "Satellite"),
+            MessageService::Other(other) => write!(f, "{}", other),
+            MessageService::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
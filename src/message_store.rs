@@ -1,17 +1,81 @@
 use super::clean_message::CleanMessage;
+use super::group_photo::{GroupPhoto, GroupPhotoChange};
+use super::jsonl_output::JsonlWriter;
+use super::membership::MembershipChange;
 use super::resolved_handle::ResolvedHandle;
-use imessage_database::message_types::variants::{Tapback, TapbackAction};
-use std::collections::HashMap;
+use super::tapback_emoji::TapbackEmoji;
+use anyhow::Result;
+use imessage_database::message_types::variants::TapbackAction;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 
-pub struct MessageStore(HashMap<String, CleanMessage>);
+#[derive(Default)]
+pub struct MessageStore {
+    /// Messages by GUID
+    messages: HashMap<String, CleanMessage>,
+    /// The latest photo change of each group chat, by chat GUID
+    group_photos: HashMap<String, GroupPhotoChange>,
+    /// Group chats' membership changes, by chat GUID
+    membership_changes: HashMap<String, Vec<MembershipChange>>,
+    /// Where messages are written as they're added instead of being kept, for
+    /// `--format jsonl`
+    stream: Option<JsonlWriter>,
+    /// GUIDs of the messages already written to `stream`
+    written: HashSet<String>,
+}
 
 impl MessageStore {
     pub fn new() -> Self {
-        MessageStore(HashMap::new())
+        Self::default()
     }
 
-    pub fn insert(&mut self, message: CleanMessage) {
-        self.0.insert(message.guid.clone(), message);
+    /// A store that writes each message to `writer` as it's added rather than keeping it.
+    /// A message in more than one database is written once, as first read, and its
+    /// tapbacks are lost.
+    pub fn streaming(writer: JsonlWriter) -> Self {
+        Self {
+            stream: Some(writer),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a message. If a message with the same GUID was already added from another
+    /// database, keeps whichever copy has more of its attachment files on disk, along
+    /// with the tapbacks of both.
+    pub fn insert(&mut self, mut message: CleanMessage) -> Result<()> {
+        if let Some(stream) = &mut self.stream {
+            if !self.written.insert(message.guid.clone()) {
+                return Ok(());
+            }
+            return stream.write(&message);
+        }
+        match self.messages.entry(message.guid.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert(message);
+            }
+            Entry::Occupied(mut entry) => {
+                let existing = entry.get_mut();
+                if message.attachments_on_disk() > existing.attachments_on_disk() {
+                    for (handle, tapback) in existing.tapbacks.drain() {
+                        message.tapbacks.entry(handle).or_insert(tapback);
+                    }
+                    *existing = message;
+                } else {
+                    for (handle, tapback) in message.tapbacks {
+                        existing.tapbacks.entry(handle).or_insert(tapback);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes writing a streaming store, returning how many messages it wrote
+    pub fn finish_stream(self) -> Result<usize> {
+        match self.stream {
+            Some(stream) => stream.finish(),
+            None => Ok(0),
+        }
     }
 
     pub fn tapback(
@@ -19,11 +83,11 @@ impl MessageStore {
         message_id: String,
         tapback_action: TapbackAction,
         tapback_handle: ResolvedHandle,
-        tapback: Tapback,
+        tapback_emoji: TapbackEmoji,
     ) {
-        match self.0.get_mut(&message_id) {
+        match self.messages.get_mut(&message_id) {
             None => (),
-            Some(message) => message.tapback(tapback_action, tapback_handle, tapback),
+            Some(message) => message.tapback(tapback_action, tapback_handle, tapback_emoji),
         }
     }
 
@@ -34,9 +98,129 @@ impl MessageStore {
     //     }
     // }
 
+    /// Records a change to a group chat's photo, keeping the latest one when the chat is
+    /// in more than one database
+    pub fn group_photo_change(&mut self, chat_guid: String, change: GroupPhotoChange) {
+        match self.group_photos.entry(chat_guid) {
+            Entry::Vacant(entry) => {
+                entry.insert(change);
+            }
+            Entry::Occupied(mut entry) => {
+                if change.date >= entry.get().date {
+                    entry.insert(change);
+                }
+            }
+        }
+    }
+
+    /// Adds the membership changes read from one database, by chat GUID
+    pub fn add_membership_changes(&mut self, changes: HashMap<String, Vec<MembershipChange>>) {
+        for (chat_guid, changes) in changes {
+            self.membership_changes
+                .entry(chat_guid)
+                .or_default()
+                .extend(changes);
+        }
+    }
+
+    pub fn take_membership_changes(&mut self) -> HashMap<String, Vec<MembershipChange>> {
+        std::mem::take(&mut self.membership_changes)
+    }
+
+    /// The current photo of each group chat that has one, by chat GUID
+    pub fn take_group_photos(&mut self) -> HashMap<String, GroupPhoto> {
+        self.group_photos
+            .drain()
+            .filter_map(|(chat_guid, change)| Some((chat_guid, change.photo?)))
+            .collect()
+    }
+
     pub fn drain_to_sorted_vector(mut self) -> Vec<CleanMessage> {
-        let mut vec = self.0.drain().map(|(_, m)| m).collect::<Vec<_>>();
+        let mut vec = self.messages.drain().map(|(_, m)| m).collect::<Vec<_>>();
         vec.sort_by(|a, b| a.date.cmp(&b.date));
         vec
     }
 }
+
+/// Groups messages into chats, keyed by chat name. Direct messages without a chat name
+/// are keyed `Direct: <participants>` so threads with the same people are merged.
+pub fn group_messages_by_chat(messages: &[CleanMessage]) -> HashMap<String, Vec<&CleanMessage>> {
+    let mut grouped: HashMap<String, Vec<&CleanMessage>> = HashMap::new();
+
+    // First pass: collect all chat_ids that are used for direct messages (no chat name)
+    let mut direct_chat_ids: std::collections::HashSet<i32> = std::collections::HashSet::new();
+    for message in messages {
+        if message.chat_name.is_none() {
+            if let Some(chat_id) = message.chat_id {
+                direct_chat_ids.insert(chat_id);
+            }
+        }
+    }
+
+    // Second pass: for each direct chat_id, find all unique participants (excluding "Me")
+    let mut chat_id_to_participants: HashMap<i32, Vec<String>> = HashMap::new();
+    for chat_id in &direct_chat_ids {
+        let mut participants: Vec<String> = messages
+            .iter()
+            .filter(|m| m.chat_id == Some(*chat_id))
+            .map(|m| m.from.to_string())
+            .filter(|name| name != "Me")
+            .collect();
+        participants.sort();
+        participants.dedup();
+        chat_id_to_participants.insert(*chat_id, participants);
+    }
+
+    // Third pass: create a mapping from participant set to canonical chat key
+    let mut participant_set_to_key: HashMap<Vec<String>, String> = HashMap::new();
+    for (_chat_id, participants) in &chat_id_to_participants {
+        if !participants.is_empty() {
+            participant_set_to_key
+                .entry(participants.clone())
+                .or_insert_with(|| {
+                    if participants.len() == 1 {
+                        format!("Direct: {}", participants[0])
+                    } else {
+                        format!("Direct: {}", participants.join(", "))
+                    }
+                });
+        }
+    }
+
+    // Fourth pass: group messages using participant-based keys for direct messages
+    for message in messages {
+        let chat_key = match &message.chat_name {
+            Some(name) => name.clone(),
+            None => {
+                // For direct messages, find participants and use that as the key
+                if let Some(chat_id) = message.chat_id {
+                    if let Some(participants) = chat_id_to_participants.get(&chat_id) {
+                        if let Some(key) = participant_set_to_key.get(participants) {
+                            key.clone()
+                        } else {
+                            format!("Direct: Unknown ({})", chat_id)
+                        }
+                    } else {
+                        format!("Direct: Unknown ({})", chat_id)
+                    }
+                } else {
+                    // Fallback for messages with no chat_id
+                    if !message.from.is_me() {
+                        format!("Direct: {}", message.from)
+                    } else {
+                        "Direct: Unknown".to_string()
+                    }
+                }
+            }
+        };
+
+        grouped.entry(chat_key).or_default().push(message);
+    }
+
+    grouped
+}
+
+/// A chat's name without the "Direct: " prefix of one-on-one chats
+pub fn display_name(chat_key: &str) -> &str {
+    chat_key.strip_prefix("Direct: ").unwrap_or(chat_key)
+}
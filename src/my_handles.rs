@@ -0,0 +1,48 @@
+use anyhow::Result;
+use imessage_database::tables::table::{CHAT, MESSAGE};
+use rusqlite::Connection;
+use std::collections::HashSet;
+
+/// Reads the user's own phone numbers and emails from a database: the accounts chats are
+/// signed in with and the addresses messages were sent from.
+///
+/// Messages synced from another device sometimes lose their `is_from_me` flag and carry
+/// one of these as their sender instead.
+pub fn account_handles(db: &Connection) -> Result<HashSet<String>> {
+    let mut handles = HashSet::new();
+
+    // account_login is prefixed with the account type, e.g. `E:me@icloud.com`
+    let mut statement = db.prepare(&format!(
+        "SELECT DISTINCT account_login FROM {} WHERE account_login IS NOT NULL",
+        CHAT
+    ))?;
+    let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+    for row in rows {
+        handles.extend(strip_account_prefix(&row?));
+    }
+
+    let mut statement = db.prepare(&format!(
+        "SELECT DISTINCT destination_caller_id FROM {} WHERE is_from_me = 1 AND destination_caller_id IS NOT NULL",
+        MESSAGE
+    ))?;
+    let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+    for row in rows {
+        handles.extend(strip_account_prefix(&row?));
+    }
+
+    Ok(handles)
+}
+
+/// `E:me@icloud.com` or `P:+15555550100` without the prefix; `None` for empty accounts
+fn strip_account_prefix(account: &str) -> Option<String> {
+    let handle = match account.split_once(':') {
+        Some((prefix, handle))
+            if prefix.eq_ignore_ascii_case("e") || prefix.eq_ignore_ascii_case("p") =>
+        {
+            handle
+        }
+        _ => account,
+    };
+    let handle = handle.trim();
+    (!handle.is_empty()).then(|| handle.to_lowercase())
+}
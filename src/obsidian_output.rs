@@ -0,0 +1,292 @@
+use crate::attachment_store::read_for_export;
+use crate::attachment_timestamp::AttachmentTimestamp;
+use crate::clean_attachment::MediaKind;
+use crate::clean_message::CleanMessage;
+use crate::error_log::ErrorLog;
+use crate::events::ExportEvent;
+use crate::filename::FilenameAllocator;
+use crate::markup::double_quoted;
+use crate::mbox_output::message_body;
+use crate::message_store::{display_name, group_messages_by_chat};
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use chrono::Datelike;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+const CHATS_FOLDER: &str = "Chats";
+const PEOPLE_FOLDER: &str = "People";
+const ASSETS_FOLDER: &str = "assets";
+
+/// Writes a Markdown vault for Obsidian or Logseq: a note per chat per month under
+/// `Chats/`, a note per chat listing its months, a note per person under `People/`
+/// linking to their chats, and attachments under `assets/`, all joined by wiki-links
+pub struct ObsidianOutput {
+    messages: Vec<CleanMessage>,
+    timestamp: AttachmentTimestamp,
+    strip_metadata: bool,
+}
+
+/// What a person's note lists
+#[derive(Default)]
+struct Person {
+    message_count: usize,
+    /// Messages they sent in each chat, by chat key
+    chats: BTreeMap<String, usize>,
+}
+
+impl ObsidianOutput {
+    pub fn new(
+        messages: Vec<CleanMessage>,
+        timestamp: AttachmentTimestamp,
+        strip_metadata: bool,
+    ) -> Self {
+        Self {
+            messages,
+            timestamp,
+            strip_metadata,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget, errors: &mut ErrorLog) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+
+        // Sorted so notes keep their names from one export to the next
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+        let mut chat_allocator = FilenameAllocator::default();
+        let chat_notes: HashMap<&String, String> = chat_keys
+            .iter()
+            .map(|chat_key| {
+                (
+                    *chat_key,
+                    chat_allocator.stem(&note_name(display_name(chat_key))),
+                )
+            })
+            .collect();
+
+        let mut people: BTreeMap<String, Person> = BTreeMap::new();
+        for (chat_key, chat_messages) in &grouped_messages {
+            for message in chat_messages.iter().filter(|m| !m.from.is_me()) {
+                let person = people.entry(message.from.to_string()).or_default();
+                person.message_count += 1;
+                *person.chats.entry(chat_key.clone()).or_default() += 1;
+            }
+        }
+        let mut people_allocator = FilenameAllocator::default();
+        let person_notes: HashMap<&String, String> = people
+            .keys()
+            .map(|name| (name, people_allocator.stem(&note_name(name))))
+            .collect();
+        let person_link =
+            |name: &String| format!("[[{}/{}|{}]]", PEOPLE_FOLDER, person_notes[name], name);
+
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            let chat_note = &chat_notes[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+
+            let months: Vec<&[&CleanMessage]> = chat_messages
+                .chunk_by(|a, b| (a.date.year(), a.date.month()) == (b.date.year(), b.date.month()))
+                .collect();
+            let month_links: Vec<String> = months
+                .iter()
+                .map(|month| {
+                    format!(
+                        "[[{}/{}/{} {}|{}]]",
+                        CHATS_FOLDER,
+                        chat_note,
+                        chat_note,
+                        month[0].date.format("%Y-%m"),
+                        month[0].date.format("%B %Y")
+                    )
+                })
+                .collect();
+
+            let mut assets = FilenameAllocator::default();
+            for (index, month) in months.iter().enumerate() {
+                let mut note = String::new();
+                writeln!(note, "---")?;
+                writeln!(note, "chat: {}", double_quoted(display_name(chat_key)))?;
+                writeln!(note, "month: {}", month[0].date.format("%Y-%m"))?;
+                write_participants(&mut note, month, &person_link)?;
+                writeln!(note, "message_count: {}", month.len())?;
+                writeln!(note, "---")?;
+                writeln!(note)?;
+                writeln!(
+                    note,
+                    "# {} — {}",
+                    display_name(chat_key),
+                    month[0].date.format("%B %Y")
+                )?;
+                writeln!(note)?;
+                let mut navigation = vec![format!(
+                    "[[{}/{}|{}]]",
+                    CHATS_FOLDER,
+                    chat_note,
+                    display_name(chat_key)
+                )];
+                if index > 0 {
+                    navigation.push(format!("← {}", month_links[index - 1]));
+                }
+                if let Some(next) = month_links.get(index + 1) {
+                    navigation.push(format!("{} →", next));
+                }
+                writeln!(note, "{}", navigation.join(" · "))?;
+
+                let mut last_day = String::new();
+                for message in month.iter() {
+                    let day = message.date.format("%A, %B %-d, %Y").to_string();
+                    if day != last_day {
+                        writeln!(note)?;
+                        writeln!(note, "## {}", day)?;
+                        last_day = day;
+                    }
+                    let sender = if message.from.is_me() {
+                        "Me".to_string()
+                    } else {
+                        person_link(&message.from.to_string())
+                    };
+                    writeln!(note)?;
+                    writeln!(note, "**{}** · {}", sender, message.date.format("%I:%M %p"))?;
+                    let body = message_body(message);
+                    if !body.is_empty() {
+                        writeln!(note, "{}", body)?;
+                    }
+                    self.write_attachments(
+                        output,
+                        &mut note,
+                        &mut assets,
+                        chat_note,
+                        message,
+                        errors,
+                    )?;
+                }
+
+                let path = format!(
+                    "{}/{}/{} {}.md",
+                    CHATS_FOLDER,
+                    chat_note,
+                    chat_note,
+                    month[0].date.format("%Y-%m")
+                );
+                output.write(&path, note.as_bytes())?;
+            }
+
+            // The chat's own note, listing its months
+            let mut note = String::new();
+            writeln!(note, "---")?;
+            writeln!(note, "type: chat")?;
+            write_participants(&mut note, chat_messages, &person_link)?;
+            writeln!(note, "message_count: {}", chat_messages.len())?;
+            if let (Some(first), Some(last)) = (chat_messages.first(), chat_messages.last()) {
+                writeln!(note, "first_message: {}", first.date.format("%Y-%m-%d"))?;
+                writeln!(note, "last_message: {}", last.date.format("%Y-%m-%d"))?;
+            }
+            writeln!(note, "---")?;
+            writeln!(note)?;
+            writeln!(note, "# {}", display_name(chat_key))?;
+            writeln!(note)?;
+            for (month, link) in months.iter().zip(&month_links) {
+                writeln!(note, "- {} ({} messages)", link, month.len())?;
+            }
+            output.write(
+                &format!("{}/{}.md", CHATS_FOLDER, chat_note),
+                note.as_bytes(),
+            )?;
+
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        for (name, person) in &people {
+            let mut note = String::new();
+            writeln!(note, "---")?;
+            writeln!(note, "type: person")?;
+            writeln!(note, "message_count: {}", person.message_count)?;
+            writeln!(note, "---")?;
+            writeln!(note)?;
+            writeln!(note, "# {}", name)?;
+            writeln!(note)?;
+            for (chat_key, count) in &person.chats {
+                writeln!(
+                    note,
+                    "- [[{}/{}|{}]] ({} messages)",
+                    CHATS_FOLDER,
+                    chat_notes[chat_key],
+                    display_name(chat_key),
+                    count
+                )?;
+            }
+            output.write(
+                &format!("{}/{}.md", PEOPLE_FOLDER, person_notes[name]),
+                note.as_bytes(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves a message's attachments under `assets/<chat>/` and embeds them in `note`:
+    /// images, videos and audio inline, other files as links
+    fn write_attachments(
+        &self,
+        output: &mut OutputTarget,
+        note: &mut String,
+        assets: &mut FilenameAllocator,
+        chat_note: &str,
+        message: &CleanMessage,
+        errors: &mut ErrorLog,
+    ) -> Result<()> {
+        for attachment in &message.attachments {
+            let Some(filename) = attachment.filename.as_deref() else {
+                continue;
+            };
+            let path = format!("{}/{}/{}", ASSETS_FOLDER, chat_note, assets.file(filename));
+
+            if !output.resume(&path) {
+                let Some(bytes) = read_for_export(attachment, self.strip_metadata, errors)? else {
+                    writeln!(note, "*(attachment not available: {})*", filename)?;
+                    continue;
+                };
+                output.write_modified(
+                    &path,
+                    &bytes,
+                    self.timestamp.resolve(message, attachment),
+                )?;
+            }
+
+            let embed = attachment.kind != MediaKind::Other;
+            writeln!(note, "{}[[{}]]", if embed { "!" } else { "" }, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The frontmatter list of everyone other than me who wrote one of `messages`
+fn write_participants(
+    note: &mut String,
+    messages: &[&CleanMessage],
+    person_link: &impl Fn(&String) -> String,
+) -> Result<()> {
+    let mut participants: Vec<String> = messages
+        .iter()
+        .filter(|m| !m.from.is_me())
+        .map(|m| m.from.to_string())
+        .collect();
+    participants.sort();
+    participants.dedup();
+
+    writeln!(note, "participants:")?;
+    for participant in &participants {
+        writeln!(note, "  - {}", double_quoted(&person_link(participant)))?;
+    }
+    Ok(())
+}
+
+/// `name` without the characters that end or split a wiki-link
+fn note_name(name: &str) -> String {
+    name.replace(['[', ']', '#', '^', '|'], "_")
+}
@@ -0,0 +1,107 @@
+use crate::clean_attachment::MediaKind;
+use crate::clean_message::CleanMessage;
+use crate::filename::sanitize_filename;
+use crate::snapshot::DatabaseSnapshot;
+use crate::swift_helper::SwiftHelper;
+use anyhow::{Result, bail};
+use std::process::{Command, Stdio};
+
+const SWIFT_SCRIPT: &str = include_str!("../ocr_helper.swift");
+
+/// Recognizes text in images, with macOS's Vision framework when the Swift helper can be
+/// compiled and with `tesseract` otherwise
+pub struct TextRecognizer {
+    engine: Engine,
+}
+
+enum Engine {
+    /// The compiled `ocr_helper.swift`
+    Vision(SwiftHelper),
+    Tesseract,
+}
+
+impl TextRecognizer {
+    /// Compiles the Vision helper, falling back to `tesseract`. Fails when neither is
+    /// available.
+    pub fn new() -> Result<Self> {
+        if let Some(helper) = SwiftHelper::compile("ocr_helper.swift", SWIFT_SCRIPT) {
+            return Ok(Self {
+                engine: Engine::Vision(helper),
+            });
+        }
+
+        let tesseract = Command::new("tesseract")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if tesseract.is_ok_and(|status| status.success()) {
+            return Ok(Self {
+                engine: Engine::Tesseract,
+            });
+        }
+
+        bail!(
+            "--ocr needs the Xcode Command Line Tools (`xcode-select --install`) or tesseract (`brew install tesseract`)"
+        )
+    }
+
+    /// Text in an image, with lines joined by newlines; `None` when there is none or the
+    /// image can't be read
+    pub fn recognize(&self, bytes: &[u8], filename: &str) -> Option<String> {
+        let image = DatabaseSnapshot::from_bytes(filename, bytes, "ocr").ok()?;
+        let mut command = match &self.engine {
+            Engine::Vision(helper) => helper.command(),
+            Engine::Tesseract => Command::new("tesseract"),
+        };
+        command.arg(image.path());
+        if let Engine::Tesseract = self.engine {
+            command.arg("stdout");
+        }
+
+        let output = command
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let text = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+/// Recognizes the text in each message's image attachments, storing it in
+/// `recognized_text`. Returns the number of images text was found in.
+pub fn recognize_attachments(
+    messages: &mut [CleanMessage],
+    recognizer: &TextRecognizer,
+) -> Result<usize> {
+    let mut count = 0;
+    for message in messages {
+        for attachment in &message.attachments {
+            if attachment.kind != MediaKind::Image {
+                continue;
+            }
+            let Some(filename) = attachment.filename.as_deref() else {
+                continue;
+            };
+            let Some(bytes) = attachment.read()? else {
+                continue;
+            };
+            let filename = sanitize_filename(filename);
+            if let Some(text) = recognizer.recognize(&bytes, &filename) {
+                message.recognized_text.insert(attachment.rowid, text);
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
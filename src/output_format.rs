@@ -0,0 +1,69 @@
+/// What the exporter writes to the output directory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Browsable HTML pages plus attachments
+    #[default]
+    Html,
+    /// One plain text transcript per chat
+    Txt,
+    /// One mbox mailbox per chat, each message an RFC 2822 email
+    Mbox,
+    /// One EPUB "conversation book" per chat
+    Epub,
+    /// A Markdown vault for Obsidian or Logseq, one note per chat per month
+    Obsidian,
+    /// A Day One JSON import archive, one journal entry per chat per day
+    DayOne,
+    /// Matrix room files and media for importing into a homeserver
+    Matrix,
+    /// Chunks of each chat as JSON lines, for embedding
+    Corpus,
+    /// One line of JSON per message, streamed to stdout or a file
+    Jsonl,
+    /// A columnar table of every message, for DuckDB and Polars
+    Parquet,
+    /// A Concordance/Relativity load file with Bates-numbered images, text and natives
+    LoadFile,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "html" => Ok(OutputFormat::Html),
+            "txt" => Ok(OutputFormat::Txt),
+            "mbox" => Ok(OutputFormat::Mbox),
+            "epub" => Ok(OutputFormat::Epub),
+            "obsidian" => Ok(OutputFormat::Obsidian),
+            "dayone" => Ok(OutputFormat::DayOne),
+            "matrix" => Ok(OutputFormat::Matrix),
+            "corpus" => Ok(OutputFormat::Corpus),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "loadfile" => Ok(OutputFormat::LoadFile),
+            _ => Err(format!(
+                "unknown format '{}' (expected html, txt, mbox, epub, obsidian, dayone, matrix, corpus, jsonl, parquet or loadfile)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Html => write!(f, "html"),
+            OutputFormat::Txt => write!(f, "txt"),
+            OutputFormat::Mbox => write!(f, "mbox"),
+            OutputFormat::Epub => write!(f, "epub"),
+            OutputFormat::Obsidian => write!(f, "obsidian"),
+            OutputFormat::DayOne => write!(f, "dayone"),
+            OutputFormat::Matrix => write!(f, "matrix"),
+            OutputFormat::Corpus => write!(f, "corpus"),
+            OutputFormat::Jsonl => write!(f, "jsonl"),
+            OutputFormat::Parquet => write!(f, "parquet"),
+            OutputFormat::LoadFile => write!(f, "loadfile"),
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use crate::clean_message::CleanMessage;
+use crate::encryption::Encryption;
+use crate::events::{ExportEvent, ExportObserver};
+use crate::manifest::ManifestFile;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use std::collections::HashMap;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use zip::write::{SimpleFileOptions, StreamWriter};
+use zip::{CompressionMethod, ZipWriter};
+
+/// Extensions of files that are already compressed and gain nothing from deflate
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "heif", "mov", "mp4", "m4v", "m4a", "mp3", "aac",
+    "caf", "amr", "zip", "epub", "gz", "zst", "pdf",
+];
+
+/// zstd level used for tar.zst archives
+const ZSTD_LEVEL: i32 = 3;
+
+/// Log of the files a directory export has finished writing, removed once the export
+/// completes. Its presence marks an interrupted export that can be resumed.
+pub const CHECKPOINT_FILENAME: &str = ".checkpoint.jsonl";
+
+/// Where an export is written: a directory, or a single archive that files are
+/// streamed into as they are generated. Keeps a checksum of every file written for
+/// the manifest.
+pub struct OutputTarget {
+    destination: Destination,
+    files: Vec<ManifestFile>,
+    observer: Option<Arc<dyn ExportObserver>>,
+}
+
+enum Destination {
+    /// With encryption, every file is written as a separate `<name>.age`
+    Directory {
+        root: PathBuf,
+        encryption: Option<Encryption>,
+        /// Append-only log: the export's arguments, then one `ManifestFile` per line
+        checkpoint: File,
+        /// Files an interrupted run already wrote, by path
+        completed: HashMap<String, ManifestFile>,
+    },
+    Zip(Box<ZipWriter<StreamWriter<ArchiveFile>>>),
+    TarZst(tar::Builder<zstd::Encoder<'static, ArchiveFile>>),
+}
+
+/// The archive file itself, optionally encrypted as a single age stream
+enum ArchiveFile {
+    Plain(File),
+    Encrypted(age::stream::StreamWriter<File>),
+}
+
+impl ArchiveFile {
+    fn finish(self) -> Result<()> {
+        match self {
+            ArchiveFile::Plain(file) => file.sync_all()?,
+            ArchiveFile::Encrypted(stream) => stream.finish()?.sync_all()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for ArchiveFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            ArchiveFile::Plain(file) => file.write(buf),
+            ArchiveFile::Encrypted(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            ArchiveFile::Plain(file) => file.flush(),
+            ArchiveFile::Encrypted(stream) => stream.flush(),
+        }
+    }
+}
+
+impl OutputTarget {
+    /// Writes into the directory at `path`. If a checkpoint from an interrupted export
+    /// with the same arguments is there, the files it finished can be skipped with
+    /// `resume`.
+    pub fn directory(path: &Path, encryption: Option<Encryption>) -> Result<Self> {
+        let arguments: Vec<String> = std::env::args().skip(1).collect();
+        let checkpoint_path = path.join(CHECKPOINT_FILENAME);
+
+        let completed = if checkpoint_path.exists() {
+            read_checkpoint(&checkpoint_path, &arguments)?
+        } else {
+            fs::create_dir_all(path)?;
+            fs::write(
+                &checkpoint_path,
+                format!("{}\n", serde_json::to_string(&arguments)?),
+            )?;
+            HashMap::new()
+        };
+        let checkpoint = OpenOptions::new().append(true).open(&checkpoint_path)?;
+
+        Ok(Self::new(Destination::Directory {
+            root: path.to_path_buf(),
+            encryption,
+            checkpoint,
+            completed,
+        }))
+    }
+
+    /// True when `path` is a directory holding an interrupted export
+    pub fn has_checkpoint(path: &Path) -> bool {
+        path.join(CHECKPOINT_FILENAME).exists()
+    }
+
+    /// Creates an archive at `path`; `.zip` for zip, `.tar.zst` or `.tzst` for zstd
+    /// compressed tar. With encryption the whole archive is one age stream, so the
+    /// path should end in `.age`.
+    pub fn archive(path: &Path, encryption: Option<Encryption>) -> Result<Self> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let name = name.strip_suffix(".age").unwrap_or(&name);
+
+        let open = || -> Result<ArchiveFile> {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create archive {}", path.display()))?;
+            Ok(match &encryption {
+                Some(encryption) => ArchiveFile::Encrypted(encryption.wrap(file)?),
+                None => ArchiveFile::Plain(file),
+            })
+        };
+
+        if name.ends_with(".zip") {
+            Ok(Self::new(Destination::Zip(Box::new(
+                ZipWriter::new_stream(open()?),
+            ))))
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            let encoder = zstd::Encoder::new(open()?, ZSTD_LEVEL)?;
+            Ok(Self::new(Destination::TarZst(tar::Builder::new(encoder))))
+        } else {
+            bail!(
+                "Unsupported archive '{}' (expected a .zip or .tar.zst file)",
+                path.display()
+            )
+        }
+    }
+
+    fn new(destination: Destination) -> Self {
+        Self {
+            destination,
+            files: Vec::new(),
+            observer: None,
+        }
+    }
+
+    /// Returns true if an interrupted run already wrote the file at `relative_path`, in
+    /// which case it is kept as is and recorded as written.
+    pub fn resume(&mut self, relative_path: &str) -> bool {
+        let Destination::Directory {
+            root,
+            encryption,
+            completed,
+            ..
+        } = &mut self.destination
+        else {
+            return false;
+        };
+        let Some(file) = completed.remove(relative_path) else {
+            return false;
+        };
+
+        let path = root.join(relative_path);
+        let intact = match encryption {
+            None => fs::metadata(&path).is_ok_and(|metadata| metadata.len() == file.size),
+            Some(_) => {
+                let mut encrypted_path = path.into_os_string();
+                encrypted_path.push(".age");
+                Path::new(&encrypted_path).exists()
+            }
+        };
+        if intact {
+            self.files.push(file);
+        }
+        intact
+    }
+
+    /// Sends the export's progress to `observer`: the output formats' chat and message
+    /// events, and each attachment saved
+    pub fn set_observer(&mut self, observer: Arc<dyn ExportObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn notify(&self, event: ExportEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event);
+        }
+    }
+
+    /// `MessageProcessed` for each message of a chat that has been written
+    pub fn notify_messages(&self, chat: &str, messages: &[&CleanMessage]) {
+        if let Some(observer) = &self.observer {
+            for message in messages {
+                observer.on_event(&ExportEvent::MessageProcessed {
+                    chat: chat.to_string(),
+                    guid: message.guid.clone(),
+                });
+            }
+        }
+    }
+
+    /// Every file written so far, with its size and checksum
+    pub fn files(&self) -> &[ManifestFile] {
+        &self.files
+    }
+
+    /// Writes a file at `relative_path` (using `/` separators) inside the export
+    pub fn write(&mut self, relative_path: &str, bytes: &[u8]) -> Result<()> {
+        self.write_file(relative_path, bytes, None)
+    }
+
+    /// Writes an attachment file like `write`, with its modification time (and, on macOS
+    /// and Windows, its creation time) set to `modified`
+    pub fn write_modified(
+        &mut self,
+        relative_path: &str,
+        bytes: &[u8],
+        modified: DateTime<Local>,
+    ) -> Result<()> {
+        self.write_file(relative_path, bytes, Some(modified))?;
+        self.notify(ExportEvent::AttachmentSaved {
+            path: relative_path.to_string(),
+            size: bytes.len() as u64,
+        });
+        Ok(())
+    }
+
+    fn write_file(
+        &mut self,
+        relative_path: &str,
+        bytes: &[u8],
+        modified: Option<DateTime<Local>>,
+    ) -> Result<()> {
+        let file = ManifestFile::new(relative_path, bytes);
+
+        match &mut self.destination {
+            Destination::Directory {
+                root,
+                encryption,
+                checkpoint,
+                ..
+            } => {
+                let path = root.join(relative_path);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let written = match encryption {
+                    None => {
+                        fs::write(&path, bytes)?;
+                        path
+                    }
+                    Some(encryption) => {
+                        let mut encrypted_path = path.into_os_string();
+                        encrypted_path.push(".age");
+                        let mut stream = encryption.wrap(File::create(&encrypted_path)?)?;
+                        stream.write_all(bytes)?;
+                        stream.finish()?;
+                        PathBuf::from(encrypted_path)
+                    }
+                };
+                if let Some(modified) = modified {
+                    let modified = SystemTime::from(modified);
+                    let times = FileTimes::new()
+                        .set_accessed(modified)
+                        .set_modified(modified);
+                    // Finder sorts by creation date, which only macOS and Windows can set
+                    #[cfg(target_os = "macos")]
+                    let times = std::os::macos::fs::FileTimesExt::set_created(times, modified);
+                    #[cfg(windows)]
+                    let times = std::os::windows::fs::FileTimesExt::set_created(times, modified);
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&written)?
+                        .set_times(times)?;
+                }
+
+                writeln!(checkpoint, "{}", serde_json::to_string(&file)?)?;
+            }
+            Destination::Zip(zip) => {
+                let compression = if is_compressed(relative_path) {
+                    CompressionMethod::Stored
+                } else {
+                    CompressionMethod::Deflated
+                };
+                let mut options = SimpleFileOptions::default()
+                    .compression_method(compression)
+                    .large_file(bytes.len() as u64 >= u32::MAX as u64);
+                // Zip timestamps can't represent dates before 1980 or after 2107
+                if let Some(modified) = modified
+                    && let Ok(modified) = zip::DateTime::from_date_and_time(
+                        modified.year() as u16,
+                        modified.month() as u8,
+                        modified.day() as u8,
+                        modified.hour() as u8,
+                        modified.minute() as u8,
+                        modified.second() as u8,
+                    )
+                {
+                    options = options.last_modified_time(modified);
+                }
+                zip.start_file(relative_path, options)?;
+                zip.write_all(bytes)?;
+            }
+            Destination::TarZst(tar) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(bytes.len() as u64);
+                header.set_mode(0o644);
+                let modified = modified.map_or_else(|| Local::now().timestamp(), |m| m.timestamp());
+                header.set_mtime(modified.max(0) as u64);
+                tar.append_data(&mut header, relative_path, bytes)?;
+            }
+        }
+
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// Completes the archive. Must be called, or the archive is left truncated.
+    pub fn finish(self) -> Result<()> {
+        match self.destination {
+            Destination::Directory { root, .. } => {
+                fs::remove_file(root.join(CHECKPOINT_FILENAME))?;
+            }
+            Destination::Zip(zip) => {
+                zip.finish()?.into_inner().finish()?;
+            }
+            Destination::TarZst(tar) => {
+                tar.into_inner()?.finish()?.finish()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_compressed(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Reads the files an interrupted export finished, checking it was run with the same
+/// arguments. A truncated last line, from being killed mid-write, is ignored.
+fn read_checkpoint(path: &Path, arguments: &[String]) -> Result<HashMap<String, ManifestFile>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+
+    let recorded: Vec<String> = match lines.next() {
+        Some(line) => serde_json::from_str(&line?).context("Corrupt export checkpoint")?,
+        None => Vec::new(),
+    };
+    if recorded != arguments {
+        bail!(
+            "The interrupted export in '{}' was started with different options ({}); rerun with the same options to resume it",
+            path.parent().unwrap_or(path).display(),
+            recorded.join(" ")
+        );
+    }
+
+    let mut completed = HashMap::new();
+    for line in lines {
+        if let Ok(file) = serde_json::from_str::<ManifestFile>(&line?) {
+            completed.insert(file.path.clone(), file);
+        }
+    }
+    Ok(completed)
+}
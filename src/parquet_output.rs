@@ -0,0 +1,148 @@
+use crate::clean_attachment::MediaKind;
+use crate::clean_message::CleanMessage;
+use crate::events::ExportEvent;
+use crate::mbox_output::message_body;
+use crate::message_store::group_messages_by_chat;
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use arrow::array::{ArrayRef, BooleanArray, StringArray, TimestampMicrosecondArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::sync::Arc;
+
+pub const PARQUET_FILENAME: &str = "messages.parquet";
+
+/// Messages per row group, which bounds how many are held as columns at once
+const ROWS_PER_BATCH: usize = 65_536;
+
+/// Writes every message as a row of `messages.parquet`, a columnar table DuckDB, Polars
+/// or pandas can query directly, e.g. `SELECT sender, count(*) FROM 'messages.parquet'
+/// GROUP BY sender`
+pub struct ParquetOutput {
+    messages: Vec<CleanMessage>,
+}
+
+impl ParquetOutput {
+    pub fn new(messages: Vec<CleanMessage>) -> Self {
+        Self { messages }
+    }
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("chat", DataType::Utf8, false),
+            Field::new("chat_guid", DataType::Utf8, true),
+            Field::new("guid", DataType::Utf8, false),
+            Field::new("sender", DataType::Utf8, false),
+            Field::new("sender_id", DataType::Utf8, true),
+            Field::new("is_from_me", DataType::Boolean, false),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                false,
+            ),
+            Field::new("text", DataType::Utf8, false),
+            Field::new("service", DataType::Utf8, false),
+            Field::new("attachment_count", DataType::UInt32, false),
+            Field::new("image_count", DataType::UInt32, false),
+            Field::new("video_count", DataType::UInt32, false),
+            Field::new("audio_count", DataType::UInt32, false),
+            Field::new("unsent", DataType::Boolean, false),
+            Field::new("deleted", DataType::Boolean, false),
+        ]))
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+
+        let schema = Self::schema();
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut buffer = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buffer, schema.clone(), Some(properties))?;
+
+        let mut rows: Vec<(&str, &CleanMessage)> = Vec::new();
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+            for message in chat_messages {
+                rows.push((chat_key.as_str(), *message));
+                if rows.len() == ROWS_PER_BATCH {
+                    writer.write(&record_batch(&schema, &rows)?)?;
+                    rows.clear();
+                }
+            }
+            output.notify_messages(chat_key, chat_messages);
+        }
+        if !rows.is_empty() {
+            writer.write(&record_batch(&schema, &rows)?)?;
+        }
+        writer.close()?;
+
+        output.write(PARQUET_FILENAME, &buffer)
+    }
+}
+
+/// The columns of `rows`, each a chat key and one of its messages
+fn record_batch(schema: &SchemaRef, rows: &[(&str, &CleanMessage)]) -> Result<RecordBatch> {
+    let string_column = |value: &dyn Fn(&CleanMessage) -> String| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|(_, message)| value(message)),
+        ))
+    };
+    let bool_column = |value: &dyn Fn(&CleanMessage) -> bool| -> ArrayRef {
+        Arc::new(BooleanArray::from_iter(
+            rows.iter().map(|(_, message)| Some(value(message))),
+        ))
+    };
+    let count_column = |matches: &dyn Fn(MediaKind) -> bool| -> ArrayRef {
+        Arc::new(UInt32Array::from_iter_values(rows.iter().map(
+            |(_, message)| {
+                message
+                    .attachments
+                    .iter()
+                    .filter(|attachment| matches(attachment.kind))
+                    .count() as u32
+            },
+        )))
+    };
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|(chat_key, _)| *chat_key),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|(_, message)| message.chat_guid.as_deref()),
+        )),
+        string_column(&|message| message.guid.clone()),
+        string_column(&|message| message.from.to_string()),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|(_, message)| message.from.identifier()),
+        )),
+        bool_column(&|message| message.from.is_me()),
+        Arc::new(
+            TimestampMicrosecondArray::from_iter_values(
+                rows.iter()
+                    .map(|(_, message)| message.date.timestamp_micros()),
+            )
+            .with_timezone("UTC"),
+        ),
+        string_column(&message_body),
+        string_column(&|message| message.service.to_string()),
+        count_column(&|_| true),
+        count_column(&|kind| kind == MediaKind::Image),
+        count_column(&|kind| kind == MediaKind::Video),
+        count_column(&|kind| kind == MediaKind::Audio),
+        bool_column(&|message| message.unsent),
+        bool_column(&|message| message.deleted),
+    ];
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
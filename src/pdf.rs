@@ -0,0 +1,228 @@
+use std::fmt::Write;
+
+/// US Letter, in points
+const PAGE_WIDTH: usize = 612;
+const PAGE_HEIGHT: usize = 792;
+const MARGIN: usize = 54;
+const FONT_SIZE: usize = 10;
+const LEADING: usize = 12;
+
+/// Characters of 10 pt Courier, 6 pt wide each, that fit between the margins
+pub const LINE_CHARS: usize = (PAGE_WIDTH - 2 * MARGIN) / 6;
+
+/// Lines that fit on a page above its stamp
+pub const PAGE_LINES: usize = (PAGE_HEIGHT - 2 * MARGIN) / LEADING - 2;
+
+/// Printed next to the stamp of a page with characters Courier can't show
+const REPLACED_NOTE: &str = "Characters shown as ? are in the text file";
+
+/// A page of monospaced text, with a line stamped in its bottom right corner
+pub struct PdfPage {
+    pub lines: Vec<String>,
+    pub stamp: String,
+}
+
+/// `text` cut into lines of at most [`LINE_CHARS`] characters, at spaces where it can
+pub fn wrap(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let line_chars = line.chars().count();
+            let word_chars = word.chars().count();
+            if line_chars > 0 && line_chars + 1 + word_chars > LINE_CHARS {
+                lines.push(std::mem::take(&mut line));
+            } else if line_chars > 0 {
+                line.push(' ');
+            }
+            line.push_str(word);
+            // A word longer than a line is broken wherever it has to be
+            while line.chars().count() > LINE_CHARS {
+                let split = line.char_indices().nth(LINE_CHARS).map(|(i, _)| i).unwrap();
+                let rest = line.split_off(split);
+                lines.push(std::mem::replace(&mut line, rest));
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// How many characters of `text` a PDF page shows as `?`, because Courier has no glyph
+/// for them
+pub fn replaced_chars(text: &str) -> usize {
+    text.chars()
+        .filter(|c| !c.is_control() && win_ansi_byte(*c).is_none())
+        .count()
+}
+
+/// A PDF of `pages` in Courier, one of the fonts every PDF reader has, so nothing is
+/// embedded. Courier covers Windows-1252; other characters, like emoji, show as `?`,
+/// and a page with any says so at its bottom left.
+pub fn text_pdf(pages: &[PdfPage]) -> Vec<u8> {
+    // Objects 1 and 2 are the catalog and page tree, 3 the font, then each page and
+    // its content stream
+    let page_ids: Vec<usize> = (0..pages.len()).map(|index| 4 + 2 * index).collect();
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids
+                .iter()
+                .map(|id| format!("{} 0 R", id))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_vec(),
+    ];
+    for (page, id) in pages.iter().zip(&page_ids) {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                id + 1
+            )
+            .into_bytes(),
+        );
+
+        let mut content = format!(
+            "BT /F1 {} Tf {} TL {} {} Td\n",
+            FONT_SIZE,
+            LEADING,
+            MARGIN,
+            PAGE_HEIGHT - MARGIN
+        )
+        .into_bytes();
+        for line in &page.lines {
+            content.extend(pdf_string(line));
+            content.extend(b" Tj T*\n");
+        }
+        content.extend(b"ET\n");
+        let stamp_x = (PAGE_WIDTH - MARGIN).saturating_sub(6 * page.stamp.chars().count());
+        content.extend(format!("BT /F1 {} Tf {} {} Td ", FONT_SIZE, stamp_x, MARGIN / 2).bytes());
+        content.extend(pdf_string(&page.stamp));
+        content.extend(b" Tj ET\n");
+        if page.lines.iter().any(|line| replaced_chars(line) > 0) {
+            content
+                .extend(format!("BT /F1 {} Tf {} {} Td ", FONT_SIZE, MARGIN, MARGIN / 2).bytes());
+            content.extend(pdf_string(REPLACED_NOTE));
+            content.extend(b" Tj ET\n");
+        }
+
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", index + 1).bytes());
+        pdf.extend(object);
+        pdf.extend(b"\nendobj\n");
+    }
+    let xref_offset = pdf.len();
+    let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(xref, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        xref,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_offset
+    );
+    pdf.extend(xref.bytes());
+    pdf
+}
+
+/// `text` as a PDF string literal in Windows-1252
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = vec![b'('];
+    for c in text.chars() {
+        let byte = match c {
+            '(' | ')' | '\\' => {
+                bytes.push(b'\\');
+                c as u8
+            }
+            '\t' => b' ',
+            c if c.is_control() => continue,
+            c => win_ansi_byte(c).unwrap_or(b'?'),
+        };
+        bytes.push(byte);
+    }
+    bytes.push(b')');
+    bytes
+}
+
+/// `c` in Windows-1252, or `None` when it has no code there
+fn win_ansi_byte(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => Some(c as u8),
+        '€' => Some(0x80),
+        '…' => Some(0x85),
+        '‘' => Some(0x91),
+        '’' => Some(0x92),
+        '“' => Some(0x93),
+        '”' => Some(0x94),
+        '•' => Some(0x95),
+        '–' => Some(0x96),
+        '—' => Some(0x97),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        let long_word = "x".repeat(LINE_CHARS + 5);
+        let lines = wrap(&format!("short line\n\n{} tail", long_word));
+        assert_eq!(lines[0], "short line");
+        assert_eq!(lines[1], "");
+        assert_eq!(lines[2], "x".repeat(LINE_CHARS));
+        assert_eq!(lines[3], "xxxxx tail");
+    }
+
+    #[test]
+    fn test_pdf_string() {
+        assert_eq!(pdf_string("a(b)\\"), b"(a\\(b\\)\\\\)");
+        assert_eq!(pdf_string("café ’👍"), b"(caf\xe9 \x92?)");
+        assert_eq!(replaced_chars("café ’👍 ok\t"), 1);
+    }
+
+    #[test]
+    fn test_replaced_note() {
+        let page = |line: &str| PdfPage {
+            lines: vec![line.to_string()],
+            stamp: "IMSG00000001".to_string(),
+        };
+        let has_note = |pdf: Vec<u8>| {
+            pdf.windows(REPLACED_NOTE.len())
+                .any(|w| w == REPLACED_NOTE.as_bytes())
+        };
+        assert!(has_note(text_pdf(&[page("Sounds good 👍")])));
+        assert!(!has_note(text_pdf(&[page("Sounds good")])));
+    }
+
+    #[test]
+    fn test_text_pdf_xref() {
+        let pdf = text_pdf(&[PdfPage {
+            lines: vec!["Hello".to_string()],
+            stamp: "IMSG00000001".to_string(),
+        }]);
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        // The first xref entry after the free one points at object 1
+        let xref = pdf.windows(6).rposition(|w| w == b"\nxref\n").unwrap() + 1;
+        let table = std::str::from_utf8(&pdf[xref..]).unwrap();
+        let offset: usize = table.lines().nth(3).unwrap()[..10].parse().unwrap();
+        assert!(pdf[offset..].starts_with(b"1 0 obj"));
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeZone};
+use imessage_database::tables::table::RECENTLY_DELETED;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+/// Timestamps above this are nanoseconds since 2001, below it seconds
+const NANOSECOND_TIMESTAMP_THRESHOLD: i64 = 100_000_000_000;
+
+/// Reads when each recoverable message was deleted, keyed by message ROWID.
+///
+/// Databases from before the "Recently Deleted" feature don't have the table; those
+/// simply have no recoverable messages.
+pub fn deletion_dates(db: &Connection) -> Result<HashMap<i32, DateTime<Local>>> {
+    let mut statement = match db.prepare(&format!(
+        "SELECT message_id, delete_date FROM {}",
+        RECENTLY_DELETED
+    )) {
+        Ok(statement) => statement,
+        Err(_) => return Ok(HashMap::new()),
+    };
+
+    let database_tz_offset = imessage_database::util::dates::get_offset();
+    let rows = statement.query_map([], |row| {
+        Ok((row.get::<_, i32>(0)?, row.get::<_, Option<i64>>(1)?))
+    })?;
+
+    let mut dates = HashMap::new();
+    for row in rows {
+        let (message_id, delete_date) = row?;
+        if let Some(delete_date) = delete_date
+            && let Some(date) = apple_timestamp_to_local(delete_date, database_tz_offset)
+        {
+            dates.insert(message_id, date);
+        }
+    }
+
+    Ok(dates)
+}
+
+/// Converts a timestamp from the database, in seconds or nanoseconds since 2001
+pub fn apple_timestamp_to_local(timestamp: i64, offset: i64) -> Option<DateTime<Local>> {
+    let seconds = if timestamp > NANOSECOND_TIMESTAMP_THRESHOLD {
+        timestamp / 1_000_000_000
+    } else {
+        timestamp
+    };
+    Local.timestamp_opt(seconds + offset, 0).single()
+}
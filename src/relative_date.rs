@@ -0,0 +1,119 @@
+use chrono::{Datelike, Days, Months, NaiveDate};
+
+/// A start date given relative to today with `--since`, so recurring exports don't have
+/// to compute dates: `30d`, `2w`, `6m`, `1y` (or `30 days ago`), `today`, `yesterday`,
+/// `this week`/`month`/`year`, `last week`/`month`/`year`, or a `YYYY-MM-DD` date.
+///
+/// `30d`-style amounts count back from today; `this` and `last` periods start on their
+/// first day (weeks start on Monday), so `last year` on any day of 2025 is 2024-01-01.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelativeDate {
+    Date(NaiveDate),
+    Ago(u32, Period),
+    /// Start of the current period; `This(Period::Day)` is today
+    This(Period),
+    /// Start of the previous period; `Last(Period::Day)` is yesterday
+    Last(Period),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Period {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "d" | "day" | "days" => Some(Period::Day),
+            "w" | "week" | "weeks" => Some(Period::Week),
+            "m" | "month" | "months" => Some(Period::Month),
+            "y" | "year" | "years" => Some(Period::Year),
+            _ => None,
+        }
+    }
+
+    /// `today` moved back by `count` of this period
+    fn back(&self, today: NaiveDate, count: u32) -> Option<NaiveDate> {
+        match self {
+            Period::Day => today.checked_sub_days(Days::new(count.into())),
+            Period::Week => today.checked_sub_days(Days::new(u64::from(count) * 7)),
+            Period::Month => today.checked_sub_months(Months::new(count)),
+            Period::Year => today.checked_sub_months(Months::new(count.saturating_mul(12))),
+        }
+    }
+
+    /// First day of the period `today` is in
+    fn start(&self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Period::Day => Some(today),
+            Period::Week => {
+                today.checked_sub_days(Days::new(today.weekday().num_days_from_monday().into()))
+            }
+            Period::Month => today.with_day(1),
+            Period::Year => today.with_ordinal(1),
+        }
+    }
+}
+
+impl RelativeDate {
+    /// The concrete date this stands for on `today`
+    pub fn resolve(&self, today: NaiveDate) -> NaiveDate {
+        let date = match self {
+            RelativeDate::Date(date) => Some(*date),
+            RelativeDate::Ago(count, period) => period.back(today, *count),
+            RelativeDate::This(period) => period.start(today),
+            RelativeDate::Last(period) => period
+                .back(today, 1)
+                .and_then(|earlier| period.start(earlier)),
+        };
+        // Further back than dates go means everything
+        date.unwrap_or(NaiveDate::MIN)
+    }
+}
+
+impl std::str::FromStr for RelativeDate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            format!(
+                "invalid date '{}' (expected e.g. 30d, 6m, 1y, yesterday, last month, this year or 2024-01-31)",
+                s
+            )
+        };
+        let lowercase = s.trim().to_lowercase();
+        let words: Vec<&str> = lowercase.split_whitespace().collect();
+
+        if let Ok(date) = NaiveDate::parse_from_str(&lowercase, "%Y-%m-%d") {
+            return Ok(RelativeDate::Date(date));
+        }
+        match words.as_slice() {
+            ["today"] => return Ok(RelativeDate::This(Period::Day)),
+            ["yesterday"] => return Ok(RelativeDate::Last(Period::Day)),
+            ["this", period] => {
+                return Period::parse(period)
+                    .map(RelativeDate::This)
+                    .ok_or_else(error);
+            }
+            ["last", period] => {
+                return Period::parse(period)
+                    .map(RelativeDate::Last)
+                    .ok_or_else(error);
+            }
+            _ => {}
+        }
+
+        // "30d", "30 days" or "30 days ago"
+        let amount = words.strip_suffix(&["ago"]).unwrap_or(&words).concat();
+        let split = amount
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(error)?;
+        let (count, period) = amount.split_at(split);
+        let count: u32 = count.parse().map_err(|_| error())?;
+        let period = Period::parse(period).ok_or_else(error)?;
+        Ok(RelativeDate::Ago(count, period))
+    }
+}
@@ -6,10 +6,12 @@ use std::collections::HashMap;
 pub struct ResolvedHandle {
     id: i32,
     display: String,
+    /// Phone number or email of the handle; `None` for me and unknown senders
+    identifier: Option<String>,
 }
 
 impl ResolvedHandle {
-    fn resolve_handle_to_name(
+    pub fn resolve_handle_to_name(
         handle_id: &i32,
         handle_cache: &HashMap<i32, String>,
         contact_map: &ContactMap,
@@ -17,13 +19,11 @@ impl ResolvedHandle {
         let unknown = "Unknown";
 
         match handle_cache.get(handle_id) {
-            None => unknown,
-            Some(contact_string) => match contact_map.get(contact_string) {
-                None => contact_string,
-                Some(better_contact_string) => better_contact_string,
-            },
+            None => unknown.to_owned(),
+            Some(contact_string) => contact_map
+                .get(contact_string)
+                .unwrap_or_else(|| contact_string.to_owned()),
         }
-        .to_owned()
     }
 
     pub fn from_message_sender(
@@ -31,22 +31,83 @@ impl ResolvedHandle {
         handle_cache: &HashMap<i32, String>,
         contact_map: &ContactMap,
     ) -> ResolvedHandle {
-        let (id, display) = if message.is_from_me {
-            (0, "Me".to_owned())
-        } else if let Some(handle_id) = message.handle_id {
+        let (id, display, identifier) = if message.is_from_me
+            || ResolvedHandle::sent_from_my_handle(message, handle_cache, contact_map)
+        {
+            (0, "Me".to_owned(), None)
+        } else if let Some(handle_id) = message.handle_id.filter(|handle_id| *handle_id != 0) {
             (
                 handle_id,
                 ResolvedHandle::resolve_handle_to_name(&handle_id, handle_cache, contact_map),
+                handle_cache.get(&handle_id).cloned(),
             )
         } else {
-            // When is_from_me is false but handle_id is None, this might be a bug
+            // When is_from_me is false but handle_id is None (or 0), this might be a bug
             // in the database where messages from me aren't properly marked.
             // In this case, we'll mark it as from an unknown sender rather than
             // incorrectly assuming it's from me.
-            (-1, "Unknown".to_owned())
+            (-1, "Unknown".to_owned(), None)
         };
 
-        ResolvedHandle { id, display }
+        ResolvedHandle {
+            id,
+            display,
+            identifier,
+        }
+    }
+
+    /// Whether a message not flagged as from me was sent from one of my own handles: its
+    /// sender is my number or email, or it has no sender and went out through my account
+    fn sent_from_my_handle(
+        message: &Message,
+        handle_cache: &HashMap<i32, String>,
+        contact_map: &ContactMap,
+    ) -> bool {
+        match message
+            .handle_id
+            .and_then(|handle_id| handle_cache.get(&handle_id))
+        {
+            Some(sender) => contact_map.is_me(sender),
+            None => message
+                .destination_caller_id
+                .as_deref()
+                .is_some_and(|account| contact_map.is_me(account)),
+        }
+    }
+
+    pub fn me() -> ResolvedHandle {
+        ResolvedHandle {
+            id: 0,
+            display: "Me".to_owned(),
+            identifier: None,
+        }
+    }
+
+    /// The caller of a call history entry. Calls aren't tied to a message handle, so the
+    /// other party gets the same id as an unknown sender.
+    pub fn from_call(outgoing: bool, display: &str, identifier: &str) -> ResolvedHandle {
+        if outgoing {
+            ResolvedHandle::me()
+        } else {
+            ResolvedHandle {
+                id: -1,
+                display: display.to_owned(),
+                identifier: Some(identifier.to_owned()),
+            }
+        }
+    }
+
+    pub fn is_me(&self) -> bool {
+        self.id == 0
+    }
+
+    /// A sender without a handle, neither me nor anyone the database can name
+    pub fn is_unknown(&self) -> bool {
+        !self.is_me() && self.identifier.is_none()
+    }
+
+    pub fn identifier(&self) -> Option<&str> {
+        self.identifier.as_deref()
     }
 }
 
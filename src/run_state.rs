@@ -0,0 +1,197 @@
+use crate::error::ExportError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Held while an export runs, so an overlapping run stops instead of writing into it
+pub const LOCK_FILENAME: &str = ".export.lock";
+
+/// What the last completed export was made from, for `--if-changed`
+pub const RUN_STATE_FILENAME: &str = ".run-state.json";
+
+/// Where an export's lock or run state file lives: inside a directory export, and next
+/// to an archive, named after it (`export.zip.run-state.json`)
+pub fn state_path(output: &Path, is_archive: bool, filename: &str) -> PathBuf {
+    if is_archive {
+        let archive_name = output
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        output.with_file_name(format!("{}{}", archive_name, filename))
+    } else {
+        output.join(filename)
+    }
+}
+
+/// True when `output` holds an export: the archive exists, or the directory has
+/// anything besides a lock file
+pub fn has_export(output: &Path, is_archive: bool) -> bool {
+    if is_archive {
+        return output.exists();
+    }
+    match fs::read_dir(output) {
+        Ok(entries) => entries
+            .flatten()
+            .any(|entry| entry.file_name() != LOCK_FILENAME),
+        Err(_) => output.exists(),
+    }
+}
+
+/// Deletes the export a previous run left at `output`, keeping this run's lock file
+pub fn remove_export(output: &Path, is_archive: bool) -> Result<()> {
+    let remove = |path: &Path| -> Result<()> {
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    };
+
+    if is_archive {
+        remove(output)?;
+        return remove(&state_path(output, true, RUN_STATE_FILENAME));
+    }
+    for entry in fs::read_dir(output)? {
+        let entry = entry?;
+        if entry.file_name() != LOCK_FILENAME {
+            remove(&entry.path())
+                .with_context(|| format!("Unable to remove {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Stops a second export into the same output, e.g. when launchd or cron starts a run
+/// while a slow one is still going. The lock file holds the process ID, so a lock left
+/// by a killed run is taken over. Removed when dropped.
+pub struct ExportLock {
+    path: PathBuf,
+}
+
+impl ExportLock {
+    pub fn acquire(path: PathBuf) -> Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id())?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let pid = fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|contents| contents.trim().parse::<u32>().ok());
+                    match pid {
+                        Some(pid) if !is_running(pid) => fs::remove_file(&path)?,
+                        _ => {
+                            return Err(ExportError::OutputLocked {
+                                lock_file: path,
+                                pid,
+                            }
+                            .into());
+                        }
+                    }
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Unable to create {}", path.display()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ExportLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether a process with this ID exists; assumed so where that can't be checked
+fn is_running(pid: u32) -> bool {
+    if !cfg!(unix) {
+        return true;
+    }
+    match Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(status) => status.success(),
+        Err(_) => true,
+    }
+}
+
+/// The command line and database files an export was made from, saved once it
+/// completes so the next `--if-changed` run can skip an unchanged database
+#[derive(Serialize, Deserialize)]
+pub struct RunState {
+    /// When the databases were looked at, before the export started
+    pub started: String,
+    pub arguments: Vec<String>,
+    pub files: Vec<FileState>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    pub path: String,
+    pub size: u64,
+    /// RFC 3339, to the nanosecond
+    pub modified: String,
+}
+
+impl RunState {
+    /// The state of `databases` now. Each database's write-ahead log is included, since
+    /// Messages writes new messages there long before they reach the database file.
+    pub fn current(databases: &[PathBuf]) -> Result<Self> {
+        let mut files = Vec::new();
+        for database in databases {
+            let mut wal = database.clone().into_os_string();
+            wal.push("-wal");
+            for path in [database.clone(), PathBuf::from(wal)] {
+                let Ok(metadata) = fs::metadata(&path) else {
+                    continue;
+                };
+                files.push(FileState {
+                    path: path.to_string_lossy().into_owned(),
+                    size: metadata.len(),
+                    modified: DateTime::<Utc>::from(metadata.modified()?)
+                        .to_rfc3339_opts(SecondsFormat::Nanos, true),
+                });
+            }
+        }
+
+        Ok(Self {
+            started: Local::now().to_rfc3339(),
+            arguments: std::env::args().skip(1).collect(),
+            files,
+        })
+    }
+
+    /// The state saved at `path`, or `None` if there is none or it can't be read
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    /// True when an export with these arguments was made from the databases as they are
+    pub fn is_unchanged(&self, current: &RunState) -> bool {
+        self.arguments == current.arguments && self.files == current.files
+    }
+}
@@ -0,0 +1,102 @@
+use crate::clean_message::CleanMessage;
+use crate::message_store::group_messages_by_chat;
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Which messages `--sample` and `--max-messages-per-chat` keep
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SampleFrom {
+    /// A spread picked by hashing each GUID, the same on every run
+    #[default]
+    Random,
+    /// The oldest messages
+    Head,
+    /// The newest messages
+    Tail,
+}
+
+impl SampleFrom {
+    /// Picks `count` of `messages` (in date order), returning their GUIDs
+    fn pick<'a>(&self, messages: &[&'a CleanMessage], count: usize) -> Vec<&'a str> {
+        let guids = messages.iter().map(|m| m.guid.as_str());
+        match self {
+            SampleFrom::Head => guids.take(count).collect(),
+            SampleFrom::Tail => guids.skip(messages.len().saturating_sub(count)).collect(),
+            SampleFrom::Random => {
+                let mut hashed: Vec<(u64, &str)> = guids
+                    .map(|guid| {
+                        let mut hasher = DefaultHasher::new();
+                        guid.hash(&mut hasher);
+                        (hasher.finish(), guid)
+                    })
+                    .collect();
+                hashed.sort_unstable();
+                hashed
+                    .into_iter()
+                    .take(count)
+                    .map(|(_, guid)| guid)
+                    .collect()
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for SampleFrom {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(SampleFrom::Random),
+            "head" => Ok(SampleFrom::Head),
+            "tail" => Ok(SampleFrom::Tail),
+            _ => Err(format!(
+                "unknown sample '{}' (expected random, head or tail)",
+                s
+            )),
+        }
+    }
+}
+
+/// Leaves out the chats with fewer than `min` messages
+pub fn drop_small_chats(messages: Vec<CleanMessage>, min: usize) -> Vec<CleanMessage> {
+    let keep: HashSet<String> = group_messages_by_chat(&messages)
+        .values()
+        .filter(|chat_messages| chat_messages.len() >= min)
+        .flatten()
+        .map(|message| message.guid.clone())
+        .collect();
+
+    retain_guids(messages, &keep)
+}
+
+/// Keeps at most `max` messages of each chat
+pub fn limit_per_chat(
+    messages: Vec<CleanMessage>,
+    max: usize,
+    from: SampleFrom,
+) -> Vec<CleanMessage> {
+    let keep: HashSet<String> = group_messages_by_chat(&messages)
+        .values()
+        .flat_map(|chat_messages| from.pick(chat_messages, max))
+        .map(str::to_string)
+        .collect();
+
+    retain_guids(messages, &keep)
+}
+
+/// Keeps `count` messages from the whole export
+pub fn sample(messages: Vec<CleanMessage>, count: usize, from: SampleFrom) -> Vec<CleanMessage> {
+    let all: Vec<&CleanMessage> = messages.iter().collect();
+    let keep: HashSet<String> = from
+        .pick(&all, count)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+
+    retain_guids(messages, &keep)
+}
+
+fn retain_guids(mut messages: Vec<CleanMessage>, keep: &HashSet<String>) -> Vec<CleanMessage> {
+    messages.retain(|message| keep.contains(&message.guid));
+    messages
+}
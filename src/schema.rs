@@ -0,0 +1,157 @@
+use anyhow::Result;
+use imessage_database::tables::table::{MESSAGE, RECENTLY_DELETED};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Optional parts of `chat.db` that newer macOS and iOS releases added, each read only
+/// when the database has it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaFeature {
+    /// `message.attributedBody`: bold, italic, mentions and links in message text
+    StyledText,
+    /// `message.thread_originator_guid`: inline replies
+    Replies,
+    /// `message.message_summary_info`: edit history and unsent messages
+    EditedMessages,
+    /// The `chat_recoverable_message_join` table: "Recently Deleted"
+    RecentlyDeleted,
+}
+
+impl SchemaFeature {
+    pub const ALL: [SchemaFeature; 4] = [
+        SchemaFeature::StyledText,
+        SchemaFeature::Replies,
+        SchemaFeature::EditedMessages,
+        SchemaFeature::RecentlyDeleted,
+    ];
+
+    fn is_in(self, message_columns: &HashSet<String>, tables: &HashSet<String>) -> bool {
+        match self {
+            SchemaFeature::StyledText => message_columns.contains("attributedBody"),
+            SchemaFeature::Replies => message_columns.contains("thread_originator_guid"),
+            SchemaFeature::EditedMessages => message_columns.contains("message_summary_info"),
+            SchemaFeature::RecentlyDeleted => tables.contains(RECENTLY_DELETED),
+        }
+    }
+
+    /// What an export of a database without this feature does instead
+    pub fn note(self) -> &'static str {
+        match self {
+            SchemaFeature::StyledText => {
+                "Styled text is not supported on this OS version; message text is exported plain"
+            }
+            SchemaFeature::Replies => {
+                "Replies are not supported on this OS version (macOS 11 / iOS 14 or later); replies are exported as ordinary messages"
+            }
+            SchemaFeature::EditedMessages => {
+                "Edited and unsent messages are not supported on this OS version (macOS 13 / iOS 16 or later); messages are exported as last saved, without edit history"
+            }
+            SchemaFeature::RecentlyDeleted => {
+                "--include-deleted: Recently Deleted is not supported on this OS version (macOS 13 / iOS 16 or later); no deleted messages are exported"
+            }
+        }
+    }
+}
+
+/// A database's schema version, the OS release it likely came from, and the optional
+/// features it lacks
+#[derive(Clone, Debug, Serialize)]
+pub struct DatabaseSchema {
+    pub path: String,
+    /// `PRAGMA user_version`, which Messages raises when it migrates the schema
+    pub schema_version: i64,
+    /// OS releases whose schema matches, estimated from the features present
+    pub os_version: &'static str,
+    pub unsupported: Vec<SchemaFeature>,
+}
+
+impl DatabaseSchema {
+    /// Reads the schema of `db`, the database at `path`
+    pub fn detect(db: &Connection, path: &Path) -> Result<Self> {
+        let schema_version = db.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let message_columns = names(db, "SELECT name FROM pragma_table_info(?1)", Some(MESSAGE))?;
+        let tables = names(
+            db,
+            "SELECT name FROM sqlite_master WHERE type = 'table'",
+            None,
+        )?;
+        let unsupported: Vec<SchemaFeature> = SchemaFeature::ALL
+            .into_iter()
+            .filter(|feature| !feature.is_in(&message_columns, &tables))
+            .collect();
+
+        Ok(Self {
+            path: path.display().to_string(),
+            schema_version,
+            os_version: os_version(&unsupported),
+            unsupported,
+        })
+    }
+
+    pub fn supports(&self, feature: SchemaFeature) -> bool {
+        !self.unsupported.contains(&feature)
+    }
+
+    /// Notes on what this export leaves out because the schema lacks it; Recently
+    /// Deleted only matters with `--include-deleted`
+    pub fn notes(&self, include_deleted: bool) -> Vec<&'static str> {
+        self.unsupported
+            .iter()
+            .filter(|feature| include_deleted || **feature != SchemaFeature::RecentlyDeleted)
+            .map(|feature| feature.note())
+            .collect()
+    }
+}
+
+fn names(db: &Connection, query: &str, table: Option<&str>) -> Result<HashSet<String>> {
+    let mut statement = db.prepare(query)?;
+    let rows = statement.query_map(rusqlite::params_from_iter(table), |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// The releases a schema lacking `unsupported` came from: edit history and Recently
+/// Deleted arrived together in macOS 13 and iOS 16, replies in macOS 11 and iOS 14
+fn os_version(unsupported: &[SchemaFeature]) -> &'static str {
+    if !unsupported.contains(&SchemaFeature::EditedMessages)
+        && !unsupported.contains(&SchemaFeature::RecentlyDeleted)
+    {
+        "macOS 13 / iOS 16 or later"
+    } else if !unsupported.contains(&SchemaFeature::Replies) {
+        "macOS 11-12 / iOS 14-15"
+    } else {
+        "macOS 10.15 / iOS 13 or earlier"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_old_schema() {
+        let dir = std::env::temp_dir().join(format!(
+            "imessage_extractor-{}-schema-test",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(
+            "CREATE TABLE message (ROWID INTEGER PRIMARY KEY, text TEXT, attributedBody BLOB);
+             PRAGMA user_version = 12345;",
+        )
+        .unwrap();
+
+        let schema = DatabaseSchema::detect(&db, &path).unwrap();
+        assert_eq!(schema.schema_version, 12345);
+        assert!(schema.supports(SchemaFeature::StyledText));
+        assert!(!schema.supports(SchemaFeature::EditedMessages));
+        assert_eq!(schema.os_version, "macOS 10.15 / iOS 13 or earlier");
+        assert_eq!(schema.notes(false).len(), 2);
+        assert_eq!(schema.notes(true).len(), 3);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
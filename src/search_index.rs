@@ -0,0 +1,77 @@
+use crate::clean_message::CleanMessage;
+use crate::mbox_output::message_body;
+use crate::message_store::{display_name, group_messages_by_chat};
+use anyhow::{Context, Result};
+use rusqlite::{Connection, params};
+use std::fs;
+use std::path::Path;
+
+pub const SEARCH_DB_FILENAME: &str = "search.db";
+
+/// The full-text table. `date` is local time as `YYYY-MM-DD HH:MM:SS`, so it sorts and
+/// compares as text; it and `guid` aren't tokenized.
+const SCHEMA: &str = "CREATE VIRTUAL TABLE messages USING fts5(
+    chat,
+    sender,
+    date UNINDEXED,
+    text,
+    attachments,
+    guid UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);";
+
+/// Builds `search.db`, an SQLite database with an FTS5 table of every message's chat,
+/// sender, date, text and attachment names, for queries like
+/// `SELECT date, chat, sender, text FROM messages WHERE messages MATCH 'sender:alice pizza'`
+pub fn build(messages: &[CleanMessage]) -> Result<Vec<u8>> {
+    // SQLite builds databases in files; this one is read back and removed
+    let path = std::env::temp_dir().join(format!(
+        "imessage_extractor-{}-{}",
+        std::process::id(),
+        SEARCH_DB_FILENAME
+    ));
+    let result = write_index(&path, messages).and_then(|()| {
+        fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
+    });
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn write_index(path: &Path, messages: &[CleanMessage]) -> Result<()> {
+    let _ = fs::remove_file(path);
+    let mut db =
+        Connection::open(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    db.execute_batch(SCHEMA)
+        .context("Failed to create the search table; SQLite needs FTS5")?;
+
+    let transaction = db.transaction()?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO messages (chat, sender, date, text, attachments, guid)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for (chat_key, chat_messages) in group_messages_by_chat(messages) {
+            let chat = display_name(&chat_key);
+            for message in chat_messages {
+                let attachments: Vec<&str> = message
+                    .attachments
+                    .iter()
+                    .filter_map(|attachment| attachment.filename.as_deref())
+                    .collect();
+                insert.execute(params![
+                    chat,
+                    message.from.to_string(),
+                    message.date.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    message_body(message),
+                    attachments.join("\n"),
+                    message.guid,
+                ])?;
+            }
+        }
+    }
+    transaction.commit()?;
+
+    // Merge the index into one b-tree, the smallest and fastest form to ship
+    db.execute("INSERT INTO messages (messages) VALUES ('optimize')", [])?;
+    Ok(())
+}
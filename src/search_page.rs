@@ -0,0 +1,246 @@
+use crate::heatmap::PageMessages;
+use crate::theme::Theme;
+use serde::Serialize;
+
+/// Script with every exported message, shared by search.html and the index page's search
+pub const SEARCH_DATA_FILENAME: &str = "search-data.js";
+
+/// Most results listed at once, so a common word doesn't render thousands of rows
+const MAX_RESULTS: usize = 200;
+
+/// Messages containing every one of `terms` in their text, sender, or the text recognized
+/// in their images and videos, newest first. Used by search.html and the index page.
+pub const MATCH_SCRIPT: &str = r#"
+        function searchTerms(value) {
+            return value.toLowerCase().split(/\s+/).filter(Boolean);
+        }
+
+        function matchingMessages(terms) {
+            const matches = [];
+            for (let i = SEARCH_DATA.messages.length - 1; i >= 0; i--) {
+                const message = SEARCH_DATA.messages[i];
+                const haystack = (message.text + '\n' + message.sender + '\n' + message.media_text).toLowerCase();
+                if (terms.every(function(term) { return haystack.includes(term); })) {
+                    matches.push(message);
+                }
+            }
+            return matches;
+        }
+"#;
+
+/// Lists the messages matching what's typed newest first, each linking to the message in
+/// its chat page. A `?q=` in the page's URL is searched for on opening.
+const SEARCH_SCRIPT: &str = r#"
+        function search() {
+            const terms = searchTerms(document.getElementById('searchInput').value);
+            const results = document.getElementById('results');
+            const status = document.getElementById('status');
+            results.innerHTML = '';
+            if (terms.length === 0) {
+                status.textContent = SEARCH_DATA.messages.length + ' messages';
+                return;
+            }
+
+            const matches = matchingMessages(terms);
+
+            status.textContent = matches.length + ' matching messages' +
+                (matches.length > __MAX_RESULTS__ ? ', showing the newest __MAX_RESULTS__' : '');
+            matches.slice(0, __MAX_RESULTS__).forEach(function(message) {
+                const link = document.createElement('a');
+                link.className = 'result';
+                link.href = message.file + '#msg-' + message.guid;
+
+                const header = document.createElement('div');
+                header.className = 'result-header';
+                header.textContent = SEARCH_DATA.chats[message.chat] + ' · ' + message.sender + ' · ' + message.date;
+                link.appendChild(header);
+
+                const text = document.createElement('div');
+                text.className = 'result-text';
+                text.textContent = message.text;
+                link.appendChild(text);
+
+                const textMatches = terms.every(function(term) {
+                    return (message.text + '\n' + message.sender).toLowerCase().includes(term);
+                });
+                if (message.media_text && !textMatches) {
+                    const mediaText = document.createElement('div');
+                    mediaText.className = 'result-media-text';
+                    mediaText.textContent = 'In attachment: ' + message.media_text;
+                    link.appendChild(mediaText);
+                }
+
+                results.appendChild(link);
+            });
+        }
+
+        const query = new URLSearchParams(window.location.search).get('q');
+        if (query) {
+            document.getElementById('searchInput').value = query;
+        }
+        search();
+"#;
+
+#[derive(Serialize)]
+struct SearchData<'a> {
+    /// Chat display names, which messages refer to by index
+    chats: Vec<&'a str>,
+    messages: Vec<SearchMessage<'a>>,
+}
+
+#[derive(Serialize)]
+struct SearchMessage<'a> {
+    chat: usize,
+    /// Transcript page, relative to the export root
+    file: &'a str,
+    guid: &'a str,
+    #[serde(skip)]
+    timestamp: i64,
+    date: String,
+    sender: String,
+    text: &'a str,
+    /// Text `--ocr` recognized in the message's images and speech `--transcribe-videos`
+    /// recognized in its videos
+    media_text: String,
+}
+
+/// search-data.js: every exported message, for `chats` given as `(display name, pages)`,
+/// as a script defining `SEARCH_DATA`. A script rather than JSON so the pages searching
+/// it work when opened straight from disk.
+pub fn search_data_js(chats: &[(&str, Vec<PageMessages>)]) -> String {
+    let mut data = SearchData {
+        chats: chats.iter().map(|(name, _)| *name).collect(),
+        messages: Vec::new(),
+    };
+    for (chat, (_, pages)) in chats.iter().enumerate() {
+        for (file, messages) in pages {
+            for message in *messages {
+                let media_text: Vec<&str> = message
+                    .attachments
+                    .iter()
+                    .flat_map(|attachment| {
+                        [&message.recognized_text, &message.transcripts]
+                            .into_iter()
+                            .filter_map(|texts| texts.get(&attachment.rowid))
+                    })
+                    .map(String::as_str)
+                    .collect();
+                data.messages.push(SearchMessage {
+                    chat,
+                    file,
+                    guid: &message.guid,
+                    timestamp: message.date.timestamp(),
+                    date: message.date.format("%b %d, %Y %-I:%M %p").to_string(),
+                    sender: message.from.to_string(),
+                    text: &message.text,
+                    media_text: media_text.join("\n"),
+                });
+            }
+        }
+    }
+    // Newest last, so the script can walk backwards for newest first
+    data.messages.sort_by_key(|message| message.timestamp);
+
+    let json = serde_json::to_string(&data)
+        .unwrap_or_else(|_| r#"{"chats":[],"messages":[]}"#.to_string());
+    format!("const SEARCH_DATA = {};\n", json)
+}
+
+/// search.html: a full-text search over every exported message in search-data.js
+pub fn search_page_html(theme: &Theme) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Search Messages</title>
+    <style>
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Helvetica, Arial, sans-serif;
+            max-width: 900px;
+            margin: 0 auto;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }}
+
+        h1 {{
+            text-align: center;
+            color: #333;
+            border-bottom: 2px solid #007aff;
+            padding-bottom: 10px;
+            margin-bottom: 20px;
+        }}
+
+        .back-link {{
+            display: inline-block;
+            margin-bottom: 20px;
+            color: #007aff;
+            text-decoration: none;
+        }}
+
+        #searchInput {{
+            width: 100%;
+            padding: 10px;
+            font-size: 1em;
+            border: 2px solid #e5e5ea;
+            border-radius: 8px;
+            box-sizing: border-box;
+        }}
+
+        #status {{
+            text-align: center;
+            margin: 12px 0 20px;
+            color: #666;
+            font-size: 0.9em;
+        }}
+
+        .result {{
+            display: block;
+            padding: 12px 16px;
+            margin-bottom: 8px;
+            background: white;
+            border-radius: 12px;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.1);
+            text-decoration: none;
+            color: inherit;
+        }}
+
+        .result-header {{
+            font-size: 0.85em;
+            color: #666;
+            margin-bottom: 4px;
+        }}
+
+        .result-text {{
+            white-space: pre-wrap;
+        }}
+
+        .result-media-text {{
+            font-size: 0.85em;
+            font-style: italic;
+            color: #666;
+            margin-top: 4px;
+            white-space: pre-wrap;
+        }}
+    </style>
+{}</head>
+<body>
+{}    <a href="index.html" class="back-link">← Back to Chats</a>
+    <h1>Search Messages</h1>
+    <input type="text" id="searchInput" placeholder="Search message text..." oninput="search()" autofocus>
+    <div id="status"></div>
+    <div id="results"></div>
+    <script src="{}"></script>
+    <script>
+{}{}    </script>
+</body>
+</html>
+"#,
+        theme.head_html(),
+        theme.toggle_html(),
+        SEARCH_DATA_FILENAME,
+        MATCH_SCRIPT,
+        SEARCH_SCRIPT.replace("__MAX_RESULTS__", &MAX_RESULTS.to_string())
+    )
+}
@@ -0,0 +1,82 @@
+use imessage_database::message_types::expressives::{BubbleEffect, Expressive, ScreenEffect};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SendEffect {
+    Slam,
+    Loud,
+    Gentle,
+    InvisibleInk,
+    Confetti,
+    Echo,
+    Fireworks,
+    Balloons,
+    Heart,
+    Lasers,
+    ShootingStar,
+    Sparkles,
+    Spotlight,
+    Other(String),
+}
+
+impl SendEffect {
+    /// Returns `None` for messages sent without an effect
+    pub fn from_expressive(expressive: Expressive) -> Option<Self> {
+        let effect = match expressive {
+            Expressive::Bubble(bubble) => match bubble {
+                BubbleEffect::Slam => SendEffect::Slam,
+                BubbleEffect::Loud => SendEffect::Loud,
+                BubbleEffect::Gentle => SendEffect::Gentle,
+                BubbleEffect::InvisibleInk => SendEffect::InvisibleInk,
+            },
+            Expressive::Screen(screen) => match screen {
+                ScreenEffect::Confetti => SendEffect::Confetti,
+                ScreenEffect::Echo => SendEffect::Echo,
+                ScreenEffect::Fireworks => SendEffect::Fireworks,
+                ScreenEffect::Balloons => SendEffect::Balloons,
+                ScreenEffect::Heart => SendEffect::Heart,
+                ScreenEffect::Lasers => SendEffect::Lasers,
+                ScreenEffect::ShootingStar => SendEffect::ShootingStar,
+                ScreenEffect::Sparkles => SendEffect::Sparkles,
+                ScreenEffect::Spotlight => SendEffect::Spotlight,
+            },
+            Expressive::Unknown(style) => SendEffect::Other(style.to_string()),
+            Expressive::None => return None,
+        };
+        Some(effect)
+    }
+
+    /// CSS class used to animate an approximation of the effect on the bubble
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            SendEffect::Slam => "effect-slam",
+            SendEffect::Loud => "effect-loud",
+            SendEffect::Gentle => "effect-gentle",
+            SendEffect::InvisibleInk => "effect-invisible-ink",
+            SendEffect::Other(_) => "effect-other",
+            // Full-screen effects are approximated with a glow around the bubble
+            _ => "effect-screen",
+        }
+    }
+}
+
+impl std::fmt::Display for SendEffect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SendEffect::Slam => "Slam",
+            SendEffect::Loud => "Loud",
+            SendEffect::Gentle => "Gentle",
+            SendEffect::InvisibleInk => "Invisible Ink",
+            SendEffect::Confetti => "Confetti",
+            SendEffect::Echo => "Echo",
+            SendEffect::Fireworks => "Fireworks",
+            SendEffect::Balloons => "Balloons",
+            SendEffect::Heart => "Love",
+            SendEffect::Lasers => "Lasers",
+            SendEffect::ShootingStar => "Shooting Star",
+            SendEffect::Sparkles => "Celebration",
+            SendEffect::Spotlight => "Spotlight",
+            SendEffect::Other(style) => style,
+        };
+        write!(f, "{}", name)
+    }
+}
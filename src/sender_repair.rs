@@ -0,0 +1,39 @@
+use crate::clean_message::CleanMessage;
+use crate::resolved_handle::ResolvedHandle;
+use std::collections::HashSet;
+
+/// Applies `--repair-sender-heuristics`: in chats where no message is marked as from me
+/// (the database bug `debug_messages` looks for), messages that were probably sent by me
+/// are reassigned to me and flagged with `sender_repaired`. A message counts as mine when
+///
+/// - it has no sender handle at all, which only outgoing rows lack, or
+/// - it has a delivery receipt, which Messages only records for outgoing messages.
+///
+/// Returns the number of messages repaired.
+pub fn repair_senders(messages: &mut [CleanMessage]) -> usize {
+    let mut chats_with_me = HashSet::new();
+    for message in messages.iter() {
+        if let Some(chat_id) = message.chat_id
+            && message.from.is_me()
+        {
+            chats_with_me.insert(chat_id);
+        }
+    }
+
+    let mut repaired = 0;
+    for message in messages.iter_mut() {
+        let Some(chat_id) = message.chat_id else {
+            continue;
+        };
+        if chats_with_me.contains(&chat_id) || message.call.is_some() {
+            continue;
+        }
+        if message.from.is_unknown() || message.date_delivered.is_some() {
+            message.from = ResolvedHandle::me();
+            message.sender_repaired = true;
+            repaired += 1;
+        }
+    }
+
+    repaired
+}
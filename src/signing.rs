@@ -0,0 +1,133 @@
+use crate::manifest::ManifestFile;
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Detached, ASCII-armored GPG signature of `manifest.json`
+pub const SIGNATURE_FILENAME: &str = "manifest.json.asc";
+
+/// Signs `manifest` with the GPG key `key_id`, returning an ASCII-armored detached
+/// signature. The signature records when it was made, so it attests to the export's
+/// creation time as well as its contents.
+pub fn sign(manifest: &[u8], key_id: &str) -> Result<Vec<u8>> {
+    let mut child = Command::new("gpg")
+        .args([
+            "--batch",
+            "--yes",
+            "--armor",
+            "--detach-sign",
+            "--local-user",
+        ])
+        .arg(key_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                anyhow::anyhow!("--sign-key needs GnuPG; gpg was not found on the PATH")
+            }
+            _ => anyhow::Error::new(e).context("Failed to spawn gpg"),
+        })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(manifest)
+            .context("Failed to write the manifest to gpg")?;
+    }
+    let output = child.wait_with_output().context("Failed to wait for gpg")?;
+    if !output.status.success() {
+        bail!(
+            "gpg couldn't sign with key {}: {}",
+            key_id,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Checks `signature` is a good signature of `manifest`, printing what gpg reports
+/// about the signer and signing time
+pub fn verify(manifest: &Path, signature: &Path) -> Result<bool> {
+    let output = Command::new("gpg")
+        .arg("--verify")
+        .arg(signature)
+        .arg(manifest)
+        .output()
+        .context("Failed to run gpg to check the manifest's signature")?;
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        println!("  {}", line);
+    }
+    Ok(output.status.success())
+}
+
+/// Merkle root over the export's files, as lowercase hex. The leaves are the files
+/// sorted by path, each `SHA-256(0x00 || path || 0x00 || sha256)` with the file's
+/// hex checksum; each node is `SHA-256(0x01 || left || right)`, and a node without a
+/// partner moves up a level as it is. An export with no files has the hash of nothing.
+pub fn merkle_root(files: &[ManifestFile]) -> String {
+    let mut files: Vec<&ManifestFile> = files.iter().collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut level: Vec<Vec<u8>> = files
+        .iter()
+        .map(|file| {
+            let mut hasher = Sha256::new();
+            hasher.update([0u8]);
+            hasher.update(file.path.as_bytes());
+            hasher.update([0u8]);
+            hasher.update(file.sha256.as_bytes());
+            hasher.finalize().to_vec()
+        })
+        .collect();
+    if level.is_empty() {
+        return format!("{:x}", Sha256::digest(b""));
+    }
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut hasher = Sha256::new();
+                    hasher.update([1u8]);
+                    hasher.update(left);
+                    hasher.update(right);
+                    hasher.finalize().to_vec()
+                }
+                [single] => single.clone(),
+                _ => unreachable!("chunks of two"),
+            })
+            .collect();
+    }
+    level[0]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> ManifestFile {
+        ManifestFile::new(path, path.as_bytes())
+    }
+
+    #[test]
+    fn test_merkle_root_ignores_file_order() {
+        let files = [file("a.html"), file("b.html"), file("c.html")];
+        let reversed = [file("c.html"), file("b.html"), file("a.html")];
+        assert_eq!(merkle_root(&files), merkle_root(&reversed));
+    }
+
+    #[test]
+    fn test_merkle_root_changes_with_a_file() {
+        let files = [file("a.html"), file("b.html")];
+        let changed = [file("a.html"), ManifestFile::new("b.html", b"changed")];
+        assert_ne!(merkle_root(&files), merkle_root(&changed));
+        assert_eq!(merkle_root(&[]).len(), 64);
+    }
+}
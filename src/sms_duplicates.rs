@@ -0,0 +1,49 @@
+use crate::clean_message::CleanMessage;
+use crate::message_service::MessageService;
+use chrono::{DateTime, Local, TimeDelta};
+use std::collections::HashMap;
+
+/// Longest gap between a message and its copy sent over another service, in seconds
+const DUPLICATE_WINDOW_SECONDS: i64 = 60;
+
+/// Removes the second copy of messages delivered twice, once over iMessage and again
+/// over SMS when Messages fell back to it (or the other way around). A message is a
+/// copy when an earlier message in the same conversation
+///
+/// - came from the same sender,
+/// - has the same text, ignoring surrounding whitespace,
+/// - went over a different service, and
+/// - was sent at most a minute before it.
+///
+/// Calls and messages without text are never treated as copies. `messages` must be in
+/// date order. Returns the number of messages removed.
+pub fn remove_sms_duplicates(messages: &mut Vec<CleanMessage>) -> usize {
+    let mut duplicates = vec![false; messages.len()];
+    // The last message kept for each conversation, sender and text
+    let mut latest: HashMap<(&str, String, &str), (DateTime<Local>, &MessageService)> =
+        HashMap::new();
+    for (index, message) in messages.iter().enumerate() {
+        let text = message.text.trim();
+        if text.is_empty() || message.call.is_some() {
+            continue;
+        }
+        let key = (
+            message.chat_name.as_deref().unwrap_or_default(),
+            message.from.to_string(),
+            text,
+        );
+        if let Some((date, service)) = latest.get(&key)
+            && *service != &message.service
+            && message.date - *date <= TimeDelta::seconds(DUPLICATE_WINDOW_SECONDS)
+        {
+            duplicates[index] = true;
+            continue;
+        }
+        latest.insert(key, (message.date, &message.service));
+    }
+
+    let removed = duplicates.iter().filter(|duplicate| **duplicate).count();
+    let mut duplicates = duplicates.into_iter();
+    messages.retain(|_| !duplicates.next().unwrap_or(false));
+    removed
+}
@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// SQLite sidecar files that hold changes not yet checkpointed into the database
+const SIDECAR_SUFFIXES: &[&str] = &["-wal", "-shm"];
+
+/// A private copy of a database and its WAL files, read instead of the live database
+/// so Messages.app can keep writing to it during the export. Deleted when dropped.
+pub struct DatabaseSnapshot {
+    dir: TempDir,
+    path: PathBuf,
+}
+
+impl DatabaseSnapshot {
+    /// Copies `database_path` and any `-wal`/`-shm` files next to it into a new
+    /// temporary directory. `index` distinguishes snapshots taken by the same process.
+    pub fn create(database_path: &Path, index: usize) -> Result<Self> {
+        let dir = snapshot_dir(&index.to_string())?;
+        let file_name = database_path
+            .file_name()
+            .context("Database path has no file name")?;
+        let path = dir.path().join(file_name);
+        let snapshot = Self { dir, path };
+
+        // Copy the database first and the WAL right after, so the pair is as close to a
+        // consistent state as a file copy can get
+        fs::copy(database_path, &snapshot.path)
+            .with_context(|| format!("Failed to copy {}", database_path.display()))?;
+        for suffix in SIDECAR_SUFFIXES {
+            let sidecar = with_suffix(database_path, suffix);
+            if sidecar.exists() {
+                fs::copy(&sidecar, with_suffix(&snapshot.path, suffix))
+                    .with_context(|| format!("Failed to copy {}", sidecar.display()))?;
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Writes `bytes`, such as a database decrypted in memory, to `file_name` in a new
+    /// temporary directory. `label` distinguishes snapshots taken by the same process.
+    pub fn from_bytes(file_name: &str, bytes: &[u8], label: &str) -> Result<Self> {
+        let dir = snapshot_dir(label)?;
+        let path = dir.path().join(file_name);
+        let snapshot = Self { dir, path };
+        fs::write(&snapshot.path, bytes)
+            .with_context(|| format!("Failed to write {}", snapshot.path.display()))?;
+        Ok(snapshot)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Creates a new directory, readable only by this user on Unix, under a random name, so
+/// the snapshot never lands in a directory that already exists or was planted by someone
+/// else. Removed with its contents when dropped.
+fn snapshot_dir(label: &str) -> Result<TempDir> {
+    tempfile::Builder::new()
+        .prefix(&format!("imessage_extractor-{}-", label))
+        .tempdir()
+        .context("Failed to create snapshot directory")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
@@ -0,0 +1,50 @@
+use crate::locale::Locale;
+use chrono::{DateTime, Local};
+
+/// How each chat's transcript is split into pages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitBy {
+    /// One page per calendar year
+    Year,
+    /// One page per calendar month
+    Month,
+}
+
+impl SplitBy {
+    /// Page name for the period containing `date`, e.g. `2021` or `2021-05`
+    pub fn period(&self, date: &DateTime<Local>) -> String {
+        match self {
+            SplitBy::Year => date.format("%Y").to_string(),
+            SplitBy::Month => date.format("%Y-%m").to_string(),
+        }
+    }
+
+    /// Human-readable name for the period containing `date`, e.g. `2021` or `May 2021`
+    pub fn label(&self, date: &DateTime<Local>, locale: Locale) -> String {
+        match self {
+            SplitBy::Year => date.format("%Y").to_string(),
+            SplitBy::Month => locale.month_year(date),
+        }
+    }
+}
+
+impl std::str::FromStr for SplitBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "year" => Ok(SplitBy::Year),
+            "month" => Ok(SplitBy::Month),
+            _ => Err(format!("unknown split '{}' (expected year or month)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for SplitBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitBy::Year => write!(f, "year"),
+            SplitBy::Month => write!(f, "month"),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::clean_message::CleanMessage;
+use crate::manifest::{ManifestChat, ManifestFile};
+use crate::schema::DatabaseSchema;
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Name of the run summary written at the root of every export
+pub const SUMMARY_FILENAME: &str = "summary.json";
+
+/// What a run exported, for scripts to check after each run
+#[derive(Default, Serialize)]
+pub struct RunSummary {
+    pub chat_count: usize,
+    pub message_count: usize,
+    pub chats: Vec<SummaryChat>,
+    /// Schema version of each database read and the optional features it lacks
+    pub databases: Vec<DatabaseSchema>,
+    pub attachments: AttachmentCounts,
+    /// Messages that could not be exported completely, as listed in `errors.log`
+    pub warnings: usize,
+    pub duration_seconds: f64,
+    /// Total size in bytes of the exported files, not counting this summary and the
+    /// manifest
+    pub output_size: u64,
+    /// Chats `--skip-automated` left out as verification code or marketing texts
+    pub automated_chats: Vec<String>,
+    /// Second copies of messages delivered over both iMessage and SMS, left out unless
+    /// `--keep-sms-duplicates` is given
+    pub sms_duplicates: usize,
+    /// Messages with no readable text, exported with fallback text instead
+    pub text_fallbacks: usize,
+}
+
+#[derive(Serialize)]
+pub struct SummaryChat {
+    pub name: String,
+    pub message_count: usize,
+}
+
+#[derive(Default, Serialize)]
+pub struct AttachmentCounts {
+    /// Attachments whose file was found and exported
+    pub copied: usize,
+    /// Attachments left out by `--max-attachment-size` or `--skip-videos`
+    pub skipped: usize,
+    /// Attachments whose file isn't on this computer, e.g. offloaded to iCloud
+    pub missing: usize,
+}
+
+impl AttachmentCounts {
+    pub fn from_messages(messages: &[CleanMessage]) -> Self {
+        let mut counts = Self::default();
+        for message in messages {
+            counts.skipped += message.skipped_attachments.len();
+            for attachment in &message.attachments {
+                if attachment.filename.is_some() && attachment.is_on_disk() {
+                    counts.copied += 1;
+                } else {
+                    counts.missing += 1;
+                }
+            }
+        }
+        counts
+    }
+}
+
+impl RunSummary {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        chats: &[ManifestChat],
+        databases: Vec<DatabaseSchema>,
+        attachments: AttachmentCounts,
+        warnings: usize,
+        duration: Duration,
+        files: &[ManifestFile],
+        automated_chats: Vec<String>,
+        sms_duplicates: usize,
+        text_fallbacks: usize,
+    ) -> Self {
+        Self {
+            chat_count: chats.len(),
+            message_count: chats.iter().map(|chat| chat.message_count).sum(),
+            chats: chats
+                .iter()
+                .map(|chat| SummaryChat {
+                    name: chat.name.clone(),
+                    message_count: chat.message_count,
+                })
+                .collect(),
+            databases,
+            attachments,
+            warnings,
+            duration_seconds: duration.as_secs_f64(),
+            output_size: files.iter().map(|file| file.size).sum(),
+            automated_chats,
+            sms_duplicates,
+            text_fallbacks,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_summary() {
+        let chats = [ManifestChat {
+            name: "Family".to_string(),
+            message_count: 3,
+            first_message: None,
+            last_message: None,
+            messages: Vec::new(),
+        }];
+        let files = [ManifestFile {
+            path: "Family.html".to_string(),
+            size: 1200,
+            sha256: String::new(),
+        }];
+        let summary = RunSummary::new(
+            &chats,
+            Vec::new(),
+            AttachmentCounts::default(),
+            1,
+            Duration::from_secs(2),
+            &files,
+            vec!["Verification Codes".to_string()],
+            4,
+            2,
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&summary.to_json().unwrap()).unwrap();
+        assert_eq!(json["message_count"], 3);
+        assert_eq!(json["output_size"], 1200);
+        assert_eq!(json["sms_duplicates"], 4);
+        assert_eq!(json["text_fallbacks"], 2);
+    }
+}
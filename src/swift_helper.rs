@@ -0,0 +1,34 @@
+use crate::snapshot::DatabaseSnapshot;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// A Swift script compiled once with `swiftc`, so running it for each file doesn't pay
+/// for the Swift compiler every time
+pub struct SwiftHelper {
+    binary: PathBuf,
+    /// Temporary directory the helper was compiled in, deleted when dropped
+    _dir: DatabaseSnapshot,
+}
+
+impl SwiftHelper {
+    /// Compiles `source`; `None` when `swiftc` isn't installed or the script doesn't
+    /// compile on this system
+    pub fn compile(file_name: &str, source: &str) -> Option<Self> {
+        let dir = DatabaseSnapshot::from_bytes(file_name, source.as_bytes(), file_name).ok()?;
+        let binary = dir.path().with_extension("");
+        let status = Command::new("swiftc")
+            .arg("-O")
+            .arg(dir.path())
+            .arg("-o")
+            .arg(&binary)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok()?;
+        (status.success() && binary.exists()).then_some(Self { binary, _dir: dir })
+    }
+
+    pub fn command(&self) -> Command {
+        Command::new(&self.binary)
+    }
+}
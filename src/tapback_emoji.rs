@@ -1,6 +1,24 @@
+use crate::database_source::{AttachmentFile, DatabaseSource};
+use crate::filename::sanitize_filename;
 use imessage_database::message_types::variants::Tapback;
+use imessage_database::tables::attachment::{Attachment, MediaType};
+use imessage_database::tables::messages::Message;
+use rusqlite::Connection;
 
-pub struct TapbackEmoji(String);
+pub struct TapbackEmoji {
+    emoji: String,
+    /// The sticker or custom emoji a reaction was made with, when its image is available
+    image: Option<TapbackImage>,
+}
+
+/// Image of a sticker or custom emoji tapback, which is stored as an attachment of the
+/// tapback message
+pub struct TapbackImage {
+    /// GUID of the tapback message, which the image is saved under
+    pub guid: String,
+    pub filename: String,
+    pub file: AttachmentFile,
+}
 
 impl TapbackEmoji {
     pub fn from_message_tapback(tapback: Tapback) -> Self {
@@ -15,12 +33,50 @@ impl TapbackEmoji {
             Emoji(emoji) => emoji.unwrap_or_default(),
             Sticker => "🎨",
         };
-        Self(emoji.to_string())
+        Self {
+            emoji: emoji.to_string(),
+            image: None,
+        }
+    }
+
+    /// Adds the image a sticker or custom emoji tapback carries, when its file is on this
+    /// computer
+    pub fn with_image(
+        mut self,
+        db: &Connection,
+        source: &DatabaseSource,
+        message: &Message,
+    ) -> Self {
+        self.image = TapbackImage::from_message(db, source, message);
+        self
+    }
+
+    pub fn image(&self) -> Option<&TapbackImage> {
+        self.image.as_ref()
+    }
+}
+
+impl TapbackImage {
+    fn from_message(db: &Connection, source: &DatabaseSource, message: &Message) -> Option<Self> {
+        if !message.has_attachments() {
+            return None;
+        }
+        let attachment = Attachment::from_message(db, message)
+            .ok()?
+            .into_iter()
+            .find(|attachment| matches!(attachment.mime_type(), MediaType::Image(_)))?;
+        let filename = sanitize_filename(attachment.filename()?);
+        let file = source.attachment_file(&attachment).ok()??;
+        Some(Self {
+            guid: message.guid.clone(),
+            filename,
+            file,
+        })
     }
 }
 
 impl std::fmt::Display for TapbackEmoji {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.emoji)
     }
 }
@@ -0,0 +1,66 @@
+use imessage_database::message_types::app::AppMessage;
+use imessage_database::message_types::url::URLMessage;
+use imessage_database::message_types::variants::URLOverride;
+use imessage_database::tables::messages::Message;
+use imessage_database::util::plist::parse_ns_keyed_archiver;
+use imessage_database::util::streamtyped;
+use rusqlite::Connection;
+
+/// Text shown for a message that has nothing readable at all
+pub const UNSUPPORTED_MESSAGE: &str = "[unsupported message]";
+
+/// Where the text of a message without a usable `text` column came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextFallback {
+    /// The legacy `streamtyped` decoding of `attributedBody`
+    AttributedBody,
+    /// The caption or summary an iMessage app stored in the payload
+    Summary,
+    /// The title, summary or URL of a link payload
+    Payload,
+    /// Nothing readable; the text is `UNSUPPORTED_MESSAGE`
+    Unsupported,
+}
+
+/// Whether decoded text has no readable characters, only attachment placeholders
+pub fn is_blank(text: &str) -> bool {
+    text.trim_matches(|c: char| c == '\u{FFFC}' || c.is_whitespace())
+        .is_empty()
+}
+
+/// Text for a message whose decoded text is blank, trying the raw `attributedBody`, then
+/// the app payload's summary, then the link payload's description
+pub fn fallback_text(db: &Connection, message: &Message) -> (String, TextFallback) {
+    if let Some(text) = message
+        .attributed_body(db)
+        .and_then(|body| streamtyped::parse(body).ok())
+        .filter(|text| !is_blank(text))
+    {
+        return (text, TextFallback::AttributedBody);
+    }
+
+    if let Some(parsed) = message
+        .payload_data(db)
+        .and_then(|payload| parse_ns_keyed_archiver(&payload).ok())
+    {
+        if let Ok(app) = AppMessage::from_map(&parsed)
+            && let Some(summary) = [app.ldtext, app.caption, app.subcaption]
+                .into_iter()
+                .flatten()
+                .find(|text| !is_blank(text))
+        {
+            return (summary.to_string(), TextFallback::Summary);
+        }
+
+        if let Ok(URLOverride::Normal(url)) = URLMessage::get_url_message_override(&parsed)
+            && let Some(description) = [url.title, url.summary, url.url, url.original_url]
+                .into_iter()
+                .flatten()
+                .find(|text| !is_blank(text))
+        {
+            return (description.to_string(), TextFallback::Payload);
+        }
+    }
+
+    (UNSUPPORTED_MESSAGE.to_string(), TextFallback::Unsupported)
+}
@@ -0,0 +1,142 @@
+use super::contacts::ContactMap;
+use imessage_database::message_types::text_effects::{Animation, Style, TextEffect};
+use imessage_database::tables::messages::models::BubbleComponent;
+
+/// A run of message text along with the formatting iMessage applied to it
+pub struct TextSpan {
+    pub text: String,
+    /// Display name of the person @-mentioned by this span, if it is a mention
+    pub mention: Option<String>,
+    /// The person mentioned is me
+    pub mentions_me: bool,
+    /// Bold, italic, underline and strikethrough applied to this span
+    pub styles: Vec<Style>,
+    pub animation: Option<Animation>,
+}
+
+impl TextSpan {
+    /// Splits a message's text into spans using the attribute ranges parsed from its
+    /// `attributedBody`. Returns an empty list when the message has no text ranges.
+    pub fn from_components(
+        text: &str,
+        components: &[BubbleComponent],
+        contact_map: &ContactMap,
+    ) -> Vec<TextSpan> {
+        let mut spans = Vec::new();
+        // End of the text the spans so far cover
+        let mut covered = 0;
+
+        for component in components {
+            if let BubbleComponent::Text(attributes) = component {
+                for attribute in attributes {
+                    let Some(span_text) = text.get(attribute.start..attribute.end) else {
+                        // A range that splits a character or runs past the end keeps its
+                        // text, unstyled
+                        let (start, end) = char_range(text, attribute.start, attribute.end);
+                        let start = start.max(covered);
+                        if start < end {
+                            spans.push(TextSpan {
+                                text: text[start..end].to_string(),
+                                mention: None,
+                                mentions_me: false,
+                                styles: Vec::new(),
+                                animation: None,
+                            });
+                            covered = end;
+                        }
+                        continue;
+                    };
+                    covered = attribute.end;
+
+                    let mentioned = attribute.effects.iter().find_map(|effect| match effect {
+                        TextEffect::Mention(identifier) => Some(identifier),
+                        _ => None,
+                    });
+                    let mention = mentioned.map(|identifier| {
+                        contact_map
+                            .get(identifier)
+                            .unwrap_or_else(|| identifier.clone())
+                    });
+                    let mentions_me =
+                        mentioned.is_some_and(|identifier| contact_map.is_me(identifier));
+
+                    let styles = attribute
+                        .effects
+                        .iter()
+                        .filter_map(|effect| match effect {
+                            TextEffect::Styles(styles) => Some(styles.clone()),
+                            _ => None,
+                        })
+                        .flatten()
+                        .collect();
+
+                    let animation = attribute.effects.iter().find_map(|effect| match effect {
+                        TextEffect::Animated(animation) => Some(animation.clone()),
+                        _ => None,
+                    });
+
+                    spans.push(TextSpan {
+                        text: span_text.to_string(),
+                        mention,
+                        mentions_me,
+                        styles,
+                        animation,
+                    });
+                }
+            }
+        }
+
+        spans
+    }
+}
+
+/// Bytes `start..end` of `text` widened to whole characters and cut off at its end
+fn char_range(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let mut end = end.min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    let mut start = start.min(end);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    (start, end)
+}
+
+/// HTML element used to render a text style
+pub fn style_tag(style: &Style) -> &'static str {
+    match style {
+        Style::Bold => "strong",
+        Style::Italic => "em",
+        Style::Underline => "u",
+        Style::Strikethrough => "s",
+    }
+}
+
+/// CSS class used to render a text animation
+pub fn animation_class(animation: &Animation) -> &'static str {
+    match animation {
+        Animation::Big => "text-big",
+        Animation::Small => "text-small",
+        Animation::Shake => "text-shake",
+        Animation::Nod => "text-nod",
+        Animation::Explode => "text-explode",
+        Animation::Ripple => "text-ripple",
+        Animation::Bloom => "text-bloom",
+        Animation::Jitter => "text-jitter",
+        Animation::Unknown(_) => "text-animated",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_range() {
+        // "é" is bytes 1..3
+        assert_eq!(char_range("héllo", 2, 4), (1, 4));
+        assert_eq!(char_range("héllo", 3, 99), (3, 6));
+        assert_eq!(char_range("héllo", 99, 99), (6, 6));
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+/// Dark overrides for the built-in page styles, applied when `<html data-theme="dark">`
+const DARK_CSS: &str = r#"
+        html[data-theme="dark"] body {
+            background-color: #000;
+            color: #e5e5e7;
+        }
+
+        html[data-theme="dark"] h1 {
+            color: #f5f5f7;
+        }
+
+        html[data-theme="dark"] .search-box,
+        html[data-theme="dark"] .chat-list,
+        html[data-theme="dark"] .participants,
+        html[data-theme="dark"] .result {
+            background: #1c1c1e;
+            box-shadow: 0 2px 8px rgba(0, 0, 0, 0.5);
+        }
+
+        html[data-theme="dark"] #searchInput {
+            background-color: #2c2c2e;
+            color: #f5f5f7;
+            border-color: #3a3a3c;
+        }
+
+        html[data-theme="dark"] .stats,
+        html[data-theme="dark"] #status,
+        html[data-theme="dark"] .result-header,
+        html[data-theme="dark"] .result-media-text,
+        html[data-theme="dark"] .chat-info,
+        html[data-theme="dark"] .date-separator,
+        html[data-theme="dark"] .media-date {
+            color: #98989d;
+        }
+
+        html[data-theme="dark"] .category-header {
+            background-color: #2c2c2e;
+            color: #f5f5f7;
+            border-bottom-color: #3a3a3c;
+        }
+
+        html[data-theme="dark"] .chat-item {
+            border-bottom-color: #3a3a3c;
+        }
+
+        html[data-theme="dark"] .chat-item:hover {
+            background-color: #2c2c2e;
+        }
+
+        html[data-theme="dark"] .chat-name,
+        html[data-theme="dark"] .participants-header {
+            color: #f5f5f7;
+        }
+
+        html[data-theme="dark"] .chat-members,
+        html[data-theme="dark"] .merged-chats {
+            color: #8e8e93;
+        }
+
+        html[data-theme="dark"] .participant {
+            background-color: #3a3a3c;
+            color: #f5f5f7;
+        }
+
+        html[data-theme="dark"] .message.from-others {
+            background-color: #3a3a3c;
+            color: #f5f5f7;
+        }
+
+        html[data-theme="dark"] .message.from-others .message-header {
+            color: rgba(255, 255, 255, 0.6);
+        }
+
+        html[data-theme="dark"] .attachment-link,
+        html[data-theme="dark"] .tapback,
+        html[data-theme="dark"] .app-card {
+            background-color: rgba(255, 255, 255, 0.1);
+        }
+
+        .theme-toggle {
+            position: fixed;
+            top: 12px;
+            right: 12px;
+            padding: 6px 10px;
+            font-size: 1.1em;
+            border: none;
+            border-radius: 8px;
+            background-color: rgba(120, 120, 128, 0.2);
+            cursor: pointer;
+        }
+"#;
+
+/// Picks the initial color scheme before the page renders and handles the toggle button.
+/// A choice made with the toggle is remembered in localStorage across pages.
+const THEME_SCRIPT: &str = r#"
+        (function() {
+            var theme = localStorage.getItem('imessage-theme') || '__DEFAULT_THEME__';
+            if (theme === 'auto') {
+                theme = window.matchMedia('(prefers-color-scheme: dark)').matches ? 'dark' : 'light';
+            }
+            document.documentElement.setAttribute('data-theme', theme);
+        })();
+
+        function toggleTheme() {
+            var current = document.documentElement.getAttribute('data-theme');
+            var next = current === 'dark' ? 'light' : 'dark';
+            document.documentElement.setAttribute('data-theme', next);
+            localStorage.setItem('imessage-theme', next);
+        }
+"#;
+
+/// Color scheme and optional custom styling for the generated HTML
+#[derive(Default)]
+pub enum Theme {
+    /// Follow the browser's `prefers-color-scheme`
+    #[default]
+    Auto,
+    Light,
+    Dark,
+    /// User-supplied CSS, injected after the built-in styles on every page
+    Custom(String),
+}
+
+impl Theme {
+    /// Parses a `--theme` argument: either a theme name (`auto`, `light`, `dark`)
+    /// or the path to a CSS file.
+    pub fn from_arg(arg: &str) -> Result<Self> {
+        match arg {
+            "auto" => Ok(Theme::Auto),
+            "light" => Ok(Theme::Light),
+            "dark" => Ok(Theme::Dark),
+            path => {
+                let css = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read theme CSS file {}", path))?;
+                Ok(Theme::Custom(css))
+            }
+        }
+    }
+
+    fn default_scheme(&self) -> &'static str {
+        match self {
+            Theme::Auto | Theme::Custom(_) => "auto",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+
+    /// Styles and script to place at the end of every page's `<head>`
+    pub fn head_html(&self) -> String {
+        let mut html = format!(
+            "    <style>{}    </style>\n    <script>{}    </script>\n",
+            DARK_CSS,
+            THEME_SCRIPT.replace("__DEFAULT_THEME__", self.default_scheme())
+        );
+
+        if let Theme::Custom(css) = self {
+            html.push_str(&format!("    <style>\n{}\n    </style>\n", css));
+        }
+
+        html
+    }
+
+    /// The manual light/dark toggle, placed at the start of every page's `<body>`
+    pub fn toggle_html(&self) -> &'static str {
+        r#"    <button class="theme-toggle" onclick="toggleTheme()" title="Toggle dark mode">🌓</button>
+"#
+    }
+}
@@ -0,0 +1,43 @@
+use crate::clean_message::CleanMessage;
+use chrono::{DateTime, Local};
+
+/// Which of a message's times it is shown with and sorted by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// When the message was sent, which keeps a conversation in the order it was written
+    #[default]
+    Sent,
+    /// When it was delivered
+    Delivered,
+    /// When it was read
+    Read,
+}
+
+impl TimestampSource {
+    /// The chosen time of `message`, falling back to the sent time when it isn't known
+    /// (e.g. no read receipt)
+    pub fn resolve(&self, message: &CleanMessage) -> DateTime<Local> {
+        let date = match self {
+            TimestampSource::Sent => None,
+            TimestampSource::Delivered => message.date_delivered,
+            TimestampSource::Read => message.date_read,
+        };
+        date.unwrap_or(message.date_sent)
+    }
+}
+
+impl std::str::FromStr for TimestampSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sent" => Ok(TimestampSource::Sent),
+            "delivered" => Ok(TimestampSource::Delivered),
+            "read" => Ok(TimestampSource::Read),
+            _ => Err(format!(
+                "unknown timestamp source '{}' (expected sent, delivered or read)",
+                s
+            )),
+        }
+    }
+}
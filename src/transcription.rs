@@ -0,0 +1,144 @@
+use crate::attachment_filter::ByteSize;
+use crate::audio;
+use crate::clean_attachment::MediaKind;
+use crate::clean_message::CleanMessage;
+use crate::filename::sanitize_filename;
+use crate::snapshot::DatabaseSnapshot;
+use crate::swift_helper::SwiftHelper;
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const SWIFT_SCRIPT: &str = include_str!("../speech_helper.swift");
+
+/// Largest video transcribed unless `--transcribe-max-size` says otherwise
+pub const DEFAULT_MAX_SIZE: ByteSize = ByteSize(200 << 20);
+
+/// Longest video transcribed unless `--transcribe-max-minutes` says otherwise
+pub const DEFAULT_MAX_MINUTES: u64 = 10;
+
+/// Transcribes the speech in videos, with macOS's Speech framework when the Swift helper
+/// can be compiled and with OpenAI's `whisper` command otherwise
+pub struct Transcriber {
+    engine: Engine,
+    /// Videos larger than this are left untranscribed
+    max_size: ByteSize,
+    /// Videos longer than this are left untranscribed
+    max_duration: Duration,
+}
+
+enum Engine {
+    /// The compiled `speech_helper.swift`
+    Speech(SwiftHelper),
+    Whisper,
+}
+
+impl Transcriber {
+    /// Compiles the Speech helper, falling back to `whisper`. Fails when neither is
+    /// available.
+    pub fn new(max_size: ByteSize, max_duration: Duration) -> Result<Self> {
+        let engine = if let Some(helper) = SwiftHelper::compile("speech_helper.swift", SWIFT_SCRIPT)
+        {
+            Engine::Speech(helper)
+        } else if Command::new("whisper")
+            .arg("--help")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+        {
+            Engine::Whisper
+        } else {
+            bail!(
+                "--transcribe-videos needs the Xcode Command Line Tools (`xcode-select --install`) or whisper (`pip install openai-whisper`)"
+            )
+        };
+
+        Ok(Self {
+            engine,
+            max_size,
+            max_duration,
+        })
+    }
+
+    /// The speech in a video; `None` when there is none or it can't be recognized
+    pub fn transcribe(&self, bytes: &[u8], filename: &str) -> Option<String> {
+        let video = DatabaseSnapshot::from_bytes(filename, bytes, "transcription").ok()?;
+        let text = match &self.engine {
+            Engine::Speech(helper) => {
+                let output = helper
+                    .command()
+                    .arg(video.path())
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::null())
+                    .output()
+                    .ok()?;
+                if !output.status.success() {
+                    return None;
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+            Engine::Whisper => transcribe_with_whisper(video.path())?,
+        };
+
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+/// Transcribes the speech in each message's video attachments within the size and
+/// length limits, storing it in `transcripts`. Returns the number of videos transcribed.
+pub fn transcribe_attachments(
+    messages: &mut [CleanMessage],
+    transcriber: &Transcriber,
+) -> Result<usize> {
+    let mut count = 0;
+    for message in messages {
+        for attachment in &message.attachments {
+            if attachment.kind != MediaKind::Video {
+                continue;
+            }
+            let Some(filename) = attachment.filename.as_deref() else {
+                continue;
+            };
+            if ByteSize(attachment.size) > transcriber.max_size {
+                continue;
+            }
+            let Some(bytes) = attachment.read()? else {
+                continue;
+            };
+            // Videos whose length can't be read are skipped rather than risking an hour
+            // of recognition
+            if !audio::duration(&bytes).is_some_and(|duration| duration <= transcriber.max_duration)
+            {
+                continue;
+            }
+            if let Some(text) = transcriber.transcribe(&bytes, &sanitize_filename(filename)) {
+                message.transcripts.insert(attachment.rowid, text);
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Runs `whisper`, which extracts the audio itself with `ffmpeg`, writing the transcript
+/// as a text file next to the video
+fn transcribe_with_whisper(path: &Path) -> Option<String> {
+    let dir = path.parent()?;
+    let status = Command::new("whisper")
+        .arg(path)
+        .args(["--model", "base", "--output_format", "txt", "--output_dir"])
+        .arg(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .ok()?;
+    if !status.success() {
+        return None;
+    }
+    fs::read_to_string(path.with_extension("txt")).ok()
+}
@@ -0,0 +1,72 @@
+use crate::attachment_store::ExtractedAttachments;
+use crate::clean_message::CleanMessage;
+use crate::events::ExportEvent;
+use crate::layout::{Layout, join};
+use crate::mbox_output::message_body;
+use crate::message_store::group_messages_by_chat;
+use crate::output_target::OutputTarget;
+use anyhow::Result;
+use std::fmt::Write;
+
+/// Writes each chat as a plain text transcript, in imessage-exporter's txt style: a
+/// line with the date, a line with the sender, then the message and the paths of its
+/// attachments, with a blank line between messages
+pub struct TxtOutput {
+    messages: Vec<CleanMessage>,
+    layout: Layout,
+    /// Attachments copied into the same export, listed under their messages
+    attachments: ExtractedAttachments,
+}
+
+impl TxtOutput {
+    pub fn new(
+        messages: Vec<CleanMessage>,
+        layout: Layout,
+        attachments: ExtractedAttachments,
+    ) -> Self {
+        Self {
+            messages,
+            layout,
+            attachments,
+        }
+    }
+
+    pub fn generate(&self, output: &mut OutputTarget) -> Result<()> {
+        let grouped_messages = group_messages_by_chat(&self.messages);
+        let mut chat_keys: Vec<&String> = grouped_messages.keys().collect();
+        chat_keys.sort();
+        let chat_stems = self.layout.chat_stems(grouped_messages.keys());
+
+        for chat_key in chat_keys {
+            let chat_messages = &grouped_messages[chat_key];
+            output.notify(ExportEvent::ChatStarted {
+                chat: chat_key.clone(),
+                message_count: chat_messages.len(),
+            });
+
+            let chat_dir = self.layout.chat_dir(chat_key);
+            // Attachment paths are relative to the transcript
+            let root_prefix = if chat_dir.is_empty() { "" } else { "../" };
+
+            let mut text = String::new();
+            for message in chat_messages {
+                writeln!(text, "{}", message.date.format("%b %d, %Y %l:%M:%S %p"))?;
+                writeln!(text, "{}", message.from)?;
+                let body = message_body(message);
+                if !body.is_empty() {
+                    writeln!(text, "{}", body)?;
+                }
+                for copy in self.attachments.of_message(&message.guid) {
+                    writeln!(text, "{}{}", root_prefix, copy.path)?;
+                }
+                writeln!(text)?;
+            }
+
+            let path = join(chat_dir, &format!("{}.txt", chat_stems[chat_key]));
+            output.write(&path, text.as_bytes())?;
+            output.notify_messages(chat_key, chat_messages);
+        }
+
+        Ok(())
+    }
+}
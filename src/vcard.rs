@@ -0,0 +1,153 @@
+/// The parts of a shared contact card (`.vcf`) shown in a transcript
+#[derive(Debug, Default, PartialEq)]
+pub struct VCard {
+    pub name: Option<String>,
+    pub organization: Option<String>,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+}
+
+impl VCard {
+    /// Whether `filename` is a contact card
+    pub fn is_vcard(filename: &str) -> bool {
+        let lower = filename.to_lowercase();
+        lower.ends_with(".vcf") || lower.ends_with(".vcard")
+    }
+
+    /// Reads the first card in a vCard 2.1, 3.0 or 4.0 file. Returns `None` when there
+    /// is no card or it has nothing to show.
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut card = VCard::default();
+        let mut structured_name = None;
+        let mut in_card = false;
+
+        for line in unfold(contents) {
+            let Some((property, value)) = line.split_once(':') else {
+                continue;
+            };
+            // Parameters (`TEL;TYPE=CELL`) and groups (`item1.EMAIL`) aren't shown
+            let name = property.split(';').next().unwrap_or_default();
+            let name = name.rsplit('.').next().unwrap_or_default().to_uppercase();
+            let value = unescape(value.trim());
+
+            match name.as_str() {
+                "BEGIN" if value.eq_ignore_ascii_case("vcard") => in_card = true,
+                "END" if value.eq_ignore_ascii_case("vcard") => break,
+                _ if !in_card || value.is_empty() => {}
+                "FN" => card.name = Some(value),
+                "N" => structured_name = Some(value),
+                "ORG" => card.organization = Some(value.trim_end_matches(';').replace(';', ", ")),
+                "TEL" => card
+                    .phones
+                    .push(value.trim_start_matches("tel:").to_string()),
+                "EMAIL" => card.emails.push(value),
+                _ => {}
+            }
+        }
+
+        // `N` is family;given;additional;prefix;suffix
+        if card.name.is_none()
+            && let Some(structured_name) = structured_name
+        {
+            let parts: Vec<&str> = structured_name.split(';').collect();
+            let ordered = [3, 1, 2, 0, 4]
+                .iter()
+                .filter_map(|&index| parts.get(index))
+                .filter(|part| !part.is_empty())
+                .copied()
+                .collect::<Vec<_>>();
+            if !ordered.is_empty() {
+                card.name = Some(ordered.join(" "));
+            }
+        }
+
+        (card != VCard::default()).then_some(card)
+    }
+
+    /// Reads every card in a file that may hold several, as address book exports do
+    pub fn parse_all(contents: &str) -> Vec<Self> {
+        let mut cards = Vec::new();
+        let mut card = String::new();
+        for line in contents.lines() {
+            if line.trim().eq_ignore_ascii_case("BEGIN:VCARD") && !card.is_empty() {
+                cards.extend(VCard::parse(&card));
+                card.clear();
+            }
+            card.push_str(line);
+            card.push('\n');
+        }
+        cards.extend(VCard::parse(&card));
+        cards
+    }
+}
+
+/// Lines of a vCard with folded continuation lines (starting with a space or tab) joined
+fn unfold(contents: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Undoes the backslash escapes of vCard 3.0 and later text values
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some(escaped @ (',' | '\\'))) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vcard() {
+        let card = VCard::parse(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Appleseed;Jane;;;\r\nORG:Example\\, Inc.;\r\nitem1.TEL;type=CELL;type=pref:(555) 555-01\r\n 16\r\nEMAIL;type=INTERNET:jane@example.com\r\nEND:VCARD\r\n",
+        )
+        .expect("Failed to parse vCard");
+
+        assert_eq!(
+            card,
+            VCard {
+                name: Some("Jane Appleseed".to_string()),
+                organization: Some("Example, Inc.".to_string()),
+                phones: vec!["(555) 555-0116".to_string()],
+                emails: vec!["jane@example.com".to_string()],
+            }
+        );
+
+        assert_eq!(VCard::parse("BEGIN:VCARD\nVERSION:4.0\nEND:VCARD\n"), None);
+        assert_eq!(VCard::parse("not a card"), None);
+    }
+
+    #[test]
+    fn test_parse_all_vcards() {
+        let cards = VCard::parse_all(
+            "BEGIN:VCARD\nFN:Jane Appleseed\nTEL:555-555-0118\nEND:VCARD\nBEGIN:VCARD\nVERSION:4.0\nEND:VCARD\nBEGIN:VCARD\nFN:John Appleseed\nEMAIL:john@example.com\nEND:VCARD\n",
+        );
+
+        let names: Vec<_> = cards
+            .iter()
+            .filter_map(|card| card.name.as_deref())
+            .collect();
+        assert_eq!(names, ["Jane Appleseed", "John Appleseed"]);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::manifest::{MANIFEST_FILENAME, Manifest, ManifestChat};
+use crate::run_state::{LOCK_FILENAME, RUN_STATE_FILENAME};
+use crate::signing::{self, SIGNATURE_FILENAME};
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Compares an export against its manifest and against the chats currently in the
+/// database, printing a diff-style report: `-` for what the export has that the
+/// database doesn't, `+` for what the database has that the export is missing, and
+/// `!` for files that changed since the export. Returns the number of problems found.
+pub fn verify_export(
+    export_dir: &Path,
+    manifest: &Manifest,
+    database_chats: &[ManifestChat],
+) -> Result<usize> {
+    let sources: Vec<&str> = manifest.sources.iter().map(|s| s.path.as_str()).collect();
+    println!(
+        "Verifying {} (exported {} from {})",
+        export_dir.display(),
+        manifest.generated_at,
+        sources.join(", ")
+    );
+
+    let problems = verify_attestation(export_dir, manifest)?
+        + verify_files(export_dir, manifest)?
+        + verify_chats(manifest, database_chats);
+
+    if problems == 0 {
+        println!(
+            "OK: {} chats, {} messages, {} files",
+            manifest.chats.len(),
+            manifest
+                .chats
+                .iter()
+                .map(|chat| chat.message_count)
+                .sum::<usize>(),
+            manifest.files.len()
+        );
+    } else {
+        println!("{} problem(s) found", problems);
+    }
+
+    Ok(problems)
+}
+
+/// Checks the manifest's `--sign-key` signature and `--merkle-root`, when it has them
+fn verify_attestation(export_dir: &Path, manifest: &Manifest) -> Result<usize> {
+    let mut problems = 0;
+
+    let signature = export_dir.join(SIGNATURE_FILENAME);
+    if signature.exists() {
+        println!("Checking {}:", SIGNATURE_FILENAME);
+        if !signing::verify(&export_dir.join(MANIFEST_FILENAME), &signature)? {
+            println!("! {}: bad signature", MANIFEST_FILENAME);
+            problems += 1;
+        }
+    }
+
+    if let Some(merkle_root) = &manifest.merkle_root
+        && *merkle_root != signing::merkle_root(&manifest.files)
+    {
+        println!(
+            "! {}: Merkle root doesn't match its files",
+            MANIFEST_FILENAME
+        );
+        problems += 1;
+    }
+
+    Ok(problems)
+}
+
+/// Checks every file in the manifest is present with the same checksum, and that no
+/// unlisted files were added
+fn verify_files(export_dir: &Path, manifest: &Manifest) -> Result<usize> {
+    let mut problems = 0;
+
+    for file in &manifest.files {
+        let path = export_dir.join(&file.path);
+        match fs::read(&path) {
+            Err(_) => {
+                println!("- {}: missing from export", file.path);
+                problems += 1;
+            }
+            Ok(bytes) => {
+                let sha256 = format!("{:x}", Sha256::digest(&bytes));
+                if sha256 != file.sha256 {
+                    println!("! {}: checksum changed", file.path);
+                    problems += 1;
+                }
+            }
+        }
+    }
+
+    let listed: HashSet<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    let mut unlisted = BTreeSet::new();
+    collect_files(export_dir, export_dir, &mut unlisted)?;
+    for path in unlisted {
+        let bookkeeping = [
+            MANIFEST_FILENAME,
+            SIGNATURE_FILENAME,
+            RUN_STATE_FILENAME,
+            LOCK_FILENAME,
+        ];
+        if !bookkeeping.contains(&path.as_str()) && !listed.contains(path.as_str()) {
+            println!("+ {}: not in manifest", path);
+            problems += 1;
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Compares the messages recorded per chat with what the database has now
+fn verify_chats(manifest: &Manifest, database_chats: &[ManifestChat]) -> usize {
+    let mut problems = 0;
+    let database_by_name: HashMap<&str, &ManifestChat> = database_chats
+        .iter()
+        .map(|chat| (chat.name.as_str(), chat))
+        .collect();
+    let exported_names: HashSet<&str> = manifest.chats.iter().map(|c| c.name.as_str()).collect();
+
+    for exported in &manifest.chats {
+        let Some(current) = database_by_name.get(exported.name.as_str()) else {
+            println!(
+                "- chat \"{}\": {} messages exported, chat no longer in database",
+                exported.name, exported.message_count
+            );
+            problems += 1;
+            continue;
+        };
+
+        let exported_guids: HashSet<&str> = exported.messages.iter().map(String::as_str).collect();
+        let current_guids: HashSet<&str> = current.messages.iter().map(String::as_str).collect();
+
+        if exported.message_count != exported.messages.len() {
+            println!(
+                "! chat \"{}\": manifest counts {} messages but lists {}",
+                exported.name,
+                exported.message_count,
+                exported.messages.len()
+            );
+            problems += 1;
+        }
+        if exported.message_count != current.message_count {
+            println!(
+                "! chat \"{}\": {} messages exported, {} in database",
+                exported.name, exported.message_count, current.message_count
+            );
+            problems += 1;
+        }
+
+        for guid in &exported.messages {
+            if !current_guids.contains(guid.as_str()) {
+                println!(
+                    "- chat \"{}\": message {} not in database",
+                    exported.name, guid
+                );
+                problems += 1;
+            }
+        }
+        for guid in &current.messages {
+            if !exported_guids.contains(guid.as_str()) {
+                println!(
+                    "+ chat \"{}\": message {} missing from export",
+                    exported.name, guid
+                );
+                problems += 1;
+            }
+        }
+    }
+
+    for current in database_chats {
+        if !exported_names.contains(current.name.as_str()) {
+            println!(
+                "+ chat \"{}\": {} messages in database, missing from export",
+                current.name, current.message_count
+            );
+            problems += 1;
+        }
+    }
+
+    problems
+}
+
+/// Relative `/`-separated paths of every file under `dir`
+fn collect_files(root: &Path, dir: &Path, files: &mut BTreeSet<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let relative: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            files.insert(relative.join("/"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use crate::clean_message::CleanMessage;
+use crate::emoji::emoji_in;
+use crate::markup::csv_field;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+pub const WORDCLOUD_JSON_FILENAME: &str = "wordcloud.json";
+pub const WORDCLOUD_CSV_FILENAME: &str = "wordcloud.csv";
+
+/// Most words and emoji `--wordcloud` lists for each chat and for all of them
+const WORDCLOUD_TERMS: usize = 200;
+
+/// Common English words left out of the word counts
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "again", "all", "also", "am", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "before", "being", "but", "by", "can", "could", "did", "do", "does",
+    "doing", "don't", "for", "from", "get", "got", "had", "has", "have", "having", "he", "her",
+    "here", "hers", "him", "his", "how", "i", "i'm", "if", "in", "into", "is", "it", "it's", "its",
+    "just", "me", "more", "my", "no", "not", "now", "of", "off", "on", "one", "only", "or", "our",
+    "out", "over", "so", "some", "than", "that", "that's", "the", "their", "them", "then", "there",
+    "these", "they", "this", "those", "to", "too", "up", "us", "was", "we", "were", "what", "when",
+    "where", "which", "who", "why", "will", "with", "would", "you", "you're", "your",
+];
+
+/// How often each word and emoji was used
+#[derive(Default)]
+pub struct Frequencies {
+    words: HashMap<String, usize>,
+    emoji: HashMap<String, usize>,
+}
+
+impl Frequencies {
+    /// Counts the words and emoji of `messages`' text
+    pub fn of<'a>(messages: impl IntoIterator<Item = &'a CleanMessage>) -> Self {
+        let mut frequencies = Self::default();
+        for message in messages {
+            frequencies.add(&message.text);
+        }
+        frequencies
+    }
+
+    pub fn add(&mut self, text: &str) {
+        for word in words(text) {
+            *self.words.entry(word).or_default() += 1;
+        }
+        for emoji in emoji_in(text) {
+            *self.emoji.entry(emoji.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Frequencies) {
+        for (word, count) in &other.words {
+            *self.words.entry(word.clone()).or_default() += count;
+        }
+        for (emoji, count) in &other.emoji {
+            *self.emoji.entry(emoji.clone()).or_default() += count;
+        }
+    }
+
+    /// The `n` most used words, most used first
+    pub fn top_words(&self, n: usize) -> Vec<(String, usize)> {
+        top(&self.words, n)
+    }
+
+    /// The `n` most used emoji, most used first
+    pub fn top_emoji(&self, n: usize) -> Vec<(String, usize)> {
+        top(&self.emoji, n)
+    }
+}
+
+fn top(counts: &HashMap<String, usize>, n: usize) -> Vec<(String, usize)> {
+    let mut top: Vec<(String, usize)> = counts
+        .iter()
+        .map(|(term, count)| (term.clone(), *count))
+        .collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top.truncate(n);
+    top
+}
+
+/// The lowercase words of `text` worth counting: not links, numbers, single letters or
+/// stopwords
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split_whitespace()
+        .filter(|token| !token.contains("://") && !token.starts_with("www."))
+        .flat_map(|token| token.split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’'))
+        .map(|word| {
+            word.trim_matches(|c| c == '\'' || c == '’')
+                .replace('’', "'")
+                .to_lowercase()
+        })
+        .filter(|word| {
+            word.chars().count() > 1
+                && !word.chars().all(|c| c.is_numeric())
+                && !STOPWORDS.contains(&word.as_str())
+        })
+}
+
+/// The `--wordcloud` terms of every chat and of all of them, as `[term, count]` pairs
+/// most used first, the shape word cloud libraries like wordcloud2.js take
+#[derive(Serialize)]
+struct Wordcloud<'a> {
+    words: Vec<(String, usize)>,
+    emoji: Vec<(String, usize)>,
+    chats: BTreeMap<&'a str, WordcloudChat>,
+}
+
+#[derive(Serialize)]
+struct WordcloudChat {
+    words: Vec<(String, usize)>,
+    emoji: Vec<(String, usize)>,
+}
+
+/// `wordcloud.json` and `wordcloud.csv` for `--wordcloud`, from each chat's frequencies;
+/// the CSV has a row per chat, kind (`word` or `emoji`) and term, with an empty chat for
+/// the totals
+pub fn wordcloud_files(chats: &[(String, Frequencies)]) -> Result<(String, String)> {
+    let mut all = Frequencies::default();
+    for (_, frequencies) in chats {
+        all.merge(frequencies);
+    }
+
+    let wordcloud = Wordcloud {
+        words: all.top_words(WORDCLOUD_TERMS),
+        emoji: all.top_emoji(WORDCLOUD_TERMS),
+        chats: chats
+            .iter()
+            .map(|(chat, frequencies)| {
+                (
+                    chat.as_str(),
+                    WordcloudChat {
+                        words: frequencies.top_words(WORDCLOUD_TERMS),
+                        emoji: frequencies.top_emoji(WORDCLOUD_TERMS),
+                    },
+                )
+            })
+            .collect(),
+    };
+
+    let mut csv = String::from("chat,kind,term,count\n");
+    let mut write_rows = |chat: &str, kind: &str, terms: &[(String, usize)]| -> Result<()> {
+        for (term, count) in terms {
+            writeln!(
+                csv,
+                "{},{},{},{}",
+                csv_field(chat),
+                kind,
+                csv_field(term),
+                count
+            )?;
+        }
+        Ok(())
+    };
+    write_rows("", "word", &wordcloud.words)?;
+    write_rows("", "emoji", &wordcloud.emoji)?;
+    for (chat, terms) in &wordcloud.chats {
+        write_rows(chat, "word", &terms.words)?;
+        write_rows(chat, "emoji", &terms.emoji)?;
+    }
+
+    Ok((serde_json::to_string_pretty(&wordcloud)?, csv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        let counted: Vec<String> =
+            words("I'm at the café, don’t wait! See https://example.com 2024 x").collect();
+        assert_eq!(counted, vec!["café", "wait", "see"]);
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let mut frequencies = Frequencies::default();
+        frequencies.add("Pizza tonight? 🍕🍕");
+        frequencies.add("pizza again 😂");
+        assert_eq!(
+            frequencies.top_words(2),
+            vec![("pizza".to_string(), 2), ("again".to_string(), 1)]
+        );
+        assert_eq!(frequencies.top_emoji(1), vec![("🍕".to_string(), 2)]);
+    }
+}
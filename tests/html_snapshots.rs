@@ -0,0 +1,114 @@
+//! Golden-file tests of the exporter's output. Each test exports the synthetic
+//! database from `imessage_extractor::fixture` with the real binary and compares every
+//! page with its snapshot in `tests/snapshots`. After an intended change to the output,
+//! review and accept the differences with `cargo insta review`.
+
+use imessage_extractor::fixture;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Names for the fixture's handles, since Contacts isn't read
+const ALIASES: &str = "+15555550101,Alice\nbob@example.com,Bob\n";
+
+/// A day the fixture has messages on, so `on-this-day.html` doesn't depend on today
+const ON_THIS_DAY: &str = "2025-03-01";
+
+/// Files that record when and where the export ran rather than what it holds
+const UNSTABLE_FILES: &[&str] = &[
+    "manifest.json",
+    "summary.json",
+    ".run-state.json",
+    ".export.lock",
+];
+
+/// Exports the fixture with `args` into a fresh directory, returning the directory
+fn export(name: &str, args: &[&str]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "imessage_extractor-{}-snapshot-{}",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&directory);
+    let fixture = fixture::generate(&directory.join("fixture")).unwrap();
+    let aliases = directory.join("aliases.csv");
+    fs::write(&aliases, ALIASES).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_imessage_extractor"))
+        .current_dir(&directory)
+        .env("TZ", "UTC")
+        .arg("--database-path")
+        .arg(&fixture.database)
+        .arg("--attachment-root")
+        .arg(&fixture.attachment_root)
+        .arg("--aliases")
+        .arg(&aliases)
+        .args(["--platform", "macos", "--no-contacts"])
+        .args(["--output-directory", "output"])
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "export failed: {}", status);
+    directory
+}
+
+/// Every text file under `root`, by path relative to it, in order
+fn text_files(root: &Path) -> Vec<(String, String)> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                directories.push(path);
+                continue;
+            }
+            let relative = path
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            if UNSTABLE_FILES.contains(&relative.as_str()) {
+                continue;
+            }
+            if let Ok(contents) = fs::read_to_string(&path) {
+                files.push((relative, contents));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// `contents` with the export's temporary directory replaced, so snapshots match on
+/// every machine
+fn redact(contents: &str, directory: &Path) -> String {
+    contents.replace(&directory.display().to_string(), "[export]")
+}
+
+fn assert_snapshots(directory: &Path, root: &Path, prefix: &str) {
+    let files = text_files(root);
+    assert!(!files.is_empty(), "nothing exported to {}", root.display());
+    for (relative, contents) in files {
+        let name = format!("{}__{}", prefix, relative.replace(['/', '.'], "_"));
+        insta::assert_snapshot!(name, redact(&contents, directory));
+    }
+}
+
+#[test]
+fn test_html_export() {
+    let directory = export("html", &["--on-this-day", ON_THIS_DAY]);
+    assert_snapshots(&directory, &directory.join("output"), "html");
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_jsonl_export() {
+    let directory = export(
+        "jsonl",
+        &["--format", "jsonl", "--output-file", "messages.jsonl"],
+    );
+    let contents = fs::read_to_string(directory.join("messages.jsonl")).unwrap();
+    insta::assert_snapshot!("jsonl", redact(&contents, &directory));
+    let _ = fs::remove_dir_all(&directory);
+}